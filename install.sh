@@ -66,6 +66,13 @@ install_binary() {
     chmod +x "$INSTALL_DIR/$binary_name"
 
     echo "Installed $binary_name to $INSTALL_DIR/$binary_name"
+
+    # Older release archives have no `ralph` entry point
+    if [ -f "$tmpdir/ralph" ]; then
+        mv "$tmpdir/ralph" "$INSTALL_DIR/"
+        chmod +x "$INSTALL_DIR/ralph"
+        echo "Installed ralph to $INSTALL_DIR/ralph"
+    fi
 }
 
 main() {
@@ -0,0 +1,115 @@
+//! Throughput of `JsonEventMonitor` on the output of a high-output agent.
+//!
+//! A load generator produces Claude stream-json the way a busy session
+//! emits it: assistant turns with usage, tool results with large payloads
+//! and the occasional event of an unknown type. The monitor has to keep up
+//! with it, or the kill at the context limit arrives late.
+//!
+//! Run with `cargo bench --bench monitor_throughput`.
+
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ralph_loop::config::Config;
+use ralph_loop::monitor::{JsonEventMonitor, ProcessCommand};
+use ralph_loop::state::SharedState;
+use serde_json::json;
+use tokio::io::BufReader;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+
+/// Stream of `events` events whose tool results carry `payload` bytes each.
+/// With `over_limit`, the final result reports more tokens than the limit.
+fn stream(events: usize, payload: usize, over_limit: bool) -> String {
+    let tool_output = "x".repeat(payload);
+    let mut out = String::new();
+    out.push_str(r#"{"type":"system","subtype":"init","session_id":"bench"}"#);
+    out.push('\n');
+    for i in 0..events {
+        let event = match i % 4 {
+            0 => json!({
+                "type": "assistant",
+                "message": {
+                    "content": [{ "type": "text", "text": format!("Step {i}: running the tests") }],
+                    "usage": { "input_tokens": 20_000, "output_tokens": 50 },
+                },
+            }),
+            1 => json!({
+                "type": "assistant",
+                "message": {
+                    "content": [{ "type": "tool_use", "id": format!("t{i}"), "name": "Bash", "input": { "command": "cargo test" } }],
+                },
+            }),
+            2 => json!({
+                "type": "user",
+                "message": {
+                    "content": [{ "type": "tool_result", "tool_use_id": format!("t{}", i - 1), "content": tool_output }],
+                },
+            }),
+            _ => json!({ "type": "rate_limit_event", "seq": i }),
+        };
+        out.push_str(&event.to_string());
+        out.push('\n');
+    }
+    let total = if over_limit { 500_000 } else { 20_000 };
+    out.push_str(&format!(
+        r#"{{"type":"result","session_id":"bench","usage":{{"input_tokens":{total},"output_tokens":0}}}}"#
+    ));
+    out.push('\n');
+    out
+}
+
+fn config(redaction: bool) -> Arc<Config> {
+    let mut config = Config {
+        prompt: "bench".to_string(),
+        ..Config::default()
+    };
+    config.redaction.enabled = redaction;
+    Arc::new(config)
+}
+
+/// Feed `input` through a fresh monitor, returning the command it sent, if any
+async fn monitor(config: &Arc<Config>, input: &str) -> Option<ProcessCommand> {
+    let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+    let mut monitor =
+        JsonEventMonitor::new(Arc::clone(config), SharedState::new_shared(), cmd_tx).unwrap();
+    let mut reader = BufReader::new(input.as_bytes());
+    monitor.monitor_stream(&mut reader).await.unwrap();
+    cmd_rx.try_recv().ok()
+}
+
+fn throughput(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("monitor_throughput");
+    for (name, events, payload) in [
+        ("small_events", 10_000, 200),
+        ("large_tool_results", 400, 256 * 1024),
+    ] {
+        let input = stream(events, payload, false);
+        group.throughput(Throughput::Bytes(input.len() as u64));
+        for redaction in [false, true] {
+            let config = config(redaction);
+            let id = BenchmarkId::new(name, if redaction { "redacted" } else { "plain" });
+            group.bench_with_input(id, &input, |b, input| {
+                b.iter(|| rt.block_on(monitor(&config, input)));
+            });
+        }
+    }
+    group.finish();
+}
+
+/// Time from the start of a long stream until the kill at its end is sent
+fn kill_latency(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let config = config(false);
+    let input = stream(5_000, 16 * 1024, true);
+    c.bench_function("kill_after_5000_events", |b| {
+        b.iter(|| {
+            let command = rt.block_on(monitor(&config, &input));
+            assert!(matches!(command, Some(ProcessCommand::Kill)));
+        });
+    });
+}
+
+criterion_group!(benches, throughput, kill_latency);
+criterion_main!(benches);
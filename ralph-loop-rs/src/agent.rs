@@ -1,15 +1,30 @@
 use std::sync::Arc;
+use std::time::Instant;
 
 use async_trait::async_trait;
+use chrono::Utc;
 use tokio::sync::mpsc;
 use tracing::{debug, info, trace, warn};
 
-use crate::config::Config;
+use crate::api_agent::ApiAgent;
+use crate::audit::SubprocessRecord;
+use crate::commands::AgentCommand;
+use crate::config::{AgentProvider, Config, ContextLimitConfig};
 use crate::error::Result;
 use crate::json_events::TokenUsage;
-use crate::monitor::{spawn_monitors, MonitorResult, ProcessCommand};
+use crate::monitor::{
+    spawn_monitors, MonitorResult, ProcessCommand, SubAgentUsage, UnknownEvent, UnparsedLine,
+};
+use crate::ollama_agent::OllamaAgent;
 use crate::process::AgentProcess;
+use crate::resources;
 use crate::state::SharedState;
+use crate::status_line::StatusLine;
+use crate::timeline::TimelineEntry;
+use crate::token_counter::TokenCounter;
+use crate::tool_spans::ToolSpan;
+use crate::tools::ToolCall;
+use crate::transcript::IterationTimings;
 
 /// The reason an agent invocation ended
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -18,8 +33,14 @@ pub enum ExitReason {
     Natural,
     /// Process was killed due to context limit
     ContextLimit,
+    /// Process was stopped because the completion promise was found
+    PromiseFound,
+    /// Process was stopped because the agent asked for a fresh context
+    RestartRequested,
     /// Process was killed due to shutdown signal
     Shutdown,
+    /// Process exited with a failure status
+    Error,
 }
 
 /// Result of a single agent invocation
@@ -37,6 +58,42 @@ pub struct AgentResult {
     pub session_id: Option<String>,
     /// Detailed token usage from the agent backend
     pub token_usage: Option<TokenUsage>,
+    /// Cost of the invocation reported by the agent backend
+    pub cost_usd: Option<f64>,
+    /// Timing of the invocation's phases
+    pub timings: Option<IterationTimings>,
+    /// The subprocess spawned for this invocation, for the audit log
+    pub subprocess: Option<SubprocessRecord>,
+    /// Task progress last reported by the agent, in percent
+    pub progress: Option<u8>,
+    /// Commands the agent sent for the loop controller
+    pub commands: Vec<AgentCommand>,
+    /// Assistant text of the invocation
+    pub text: String,
+    /// Tool calls an API backend asked ralph to execute
+    pub tool_calls: Vec<ToolCall>,
+    /// Captured stderr of the agent process, capped at `[agent] stderr_max_bytes`
+    pub stderr: String,
+    /// Count of output lines parsed as events
+    pub event_count: u64,
+    /// Output lines that couldn't be parsed as events, up to a cap
+    pub unparsed: Vec<UnparsedLine>,
+    /// Output lines that couldn't be parsed as events but weren't kept
+    pub unparsed_dropped: u64,
+    /// Events of types ralph-loop doesn't handle
+    pub unknown: Vec<UnknownEvent>,
+    /// Assistant messages that contained the promise
+    pub promise_mentions: u32,
+    /// Summary the agent gave with the promise, `<promise name="...">summary</promise>`
+    pub promise_summary: Option<String>,
+    /// Milestones reached in the invocation, in order
+    pub milestones: Vec<String>,
+    /// Decisions the monitor made, for the run's timeline
+    pub timeline: Vec<TimelineEntry>,
+    /// The agent's tool calls, each paired with its result
+    pub tool_spans: Vec<ToolSpan>,
+    /// Usage of the sub-agents the agent started, not included in `token_usage`
+    pub subagent_usage: Option<SubAgentUsage>,
 }
 
 impl AgentResult {
@@ -49,6 +106,24 @@ impl AgentResult {
             exit_reason: ExitReason::Natural,
             session_id: None,
             token_usage: None,
+            cost_usd: None,
+            timings: None,
+            subprocess: None,
+            progress: None,
+            commands: Vec::new(),
+            text: String::new(),
+            tool_calls: Vec::new(),
+            stderr: String::new(),
+            event_count: 0,
+            unparsed: Vec::new(),
+            unparsed_dropped: 0,
+            unknown: Vec::new(),
+            promise_mentions: 0,
+            promise_summary: None,
+            milestones: Vec::new(),
+            timeline: Vec::new(),
+            tool_spans: Vec::new(),
+            subagent_usage: None,
         }
     }
 
@@ -61,6 +136,24 @@ impl AgentResult {
             exit_reason: ExitReason::Natural,
             session_id: None,
             token_usage: None,
+            cost_usd: None,
+            timings: None,
+            subprocess: None,
+            progress: None,
+            commands: Vec::new(),
+            text: String::new(),
+            tool_calls: Vec::new(),
+            stderr: String::new(),
+            event_count: 0,
+            unparsed: Vec::new(),
+            unparsed_dropped: 0,
+            unknown: Vec::new(),
+            promise_mentions: 0,
+            promise_summary: None,
+            milestones: Vec::new(),
+            timeline: Vec::new(),
+            tool_spans: Vec::new(),
+            subagent_usage: None,
         }
     }
 
@@ -69,10 +162,74 @@ impl AgentResult {
         self.promise_found.is_some()
     }
 
+    /// Combine this result with the one of a follow-up request made in the
+    /// same iteration. Usage and cost add up; the token count, exit reason
+    /// and promise are those of the latest request.
+    pub fn followed_by(mut self, next: AgentResult) -> Self {
+        self.output.push_str(&next.output);
+        self.promise_found = next.promise_found.or(self.promise_found);
+        self.token_count = next.token_count;
+        self.exit_reason = next.exit_reason;
+        self.session_id = self.session_id.or(next.session_id);
+        self.token_usage = match (self.token_usage, next.token_usage) {
+            (Some(mut usage), Some(next)) => {
+                usage.add(&next);
+                Some(usage)
+            }
+            (usage, next) => usage.or(next),
+        };
+        self.cost_usd = match (self.cost_usd, next.cost_usd) {
+            (Some(cost), Some(next)) => Some(cost + next),
+            (cost, next) => cost.or(next),
+        };
+        self.progress = next.progress.or(self.progress);
+        self.commands.extend(next.commands);
+        if !next.text.is_empty() {
+            if !self.text.is_empty() {
+                self.text.push('\n');
+            }
+            self.text.push_str(&next.text);
+        }
+        self.tool_calls = next.tool_calls;
+        self.stderr.push_str(&next.stderr);
+        self.event_count += next.event_count;
+        self.unparsed.extend(next.unparsed);
+        self.unparsed_dropped += next.unparsed_dropped;
+        self.unknown.extend(next.unknown);
+        self.promise_mentions += next.promise_mentions;
+        self.promise_summary = next.promise_summary.or(self.promise_summary);
+        self.milestones.extend(next.milestones);
+        self.timeline.extend(next.timeline);
+        self.tool_spans.extend(next.tool_spans);
+        self.subagent_usage = match (self.subagent_usage, next.subagent_usage) {
+            (Some(mut usage), Some(next)) => {
+                usage.add(&next);
+                Some(usage)
+            }
+            (usage, next) => usage.or(next),
+        };
+        self
+    }
+
     /// Apply monitor result to this agent result
     pub fn with_monitor_result(mut self, monitor_result: MonitorResult) -> Self {
         self.session_id = monitor_result.session_id;
         self.token_usage = monitor_result.token_usage;
+        self.cost_usd = monitor_result.cost_usd;
+        self.progress = monitor_result.progress;
+        self.commands = monitor_result.commands;
+        self.text = monitor_result.text;
+        self.tool_calls = monitor_result.tool_calls;
+        self.event_count = monitor_result.event_count;
+        self.unparsed = monitor_result.unparsed;
+        self.unparsed_dropped = monitor_result.unparsed_dropped;
+        self.unknown = monitor_result.unknown;
+        self.promise_mentions = monitor_result.promise_mentions;
+        self.promise_summary = monitor_result.promise_summary;
+        self.milestones = monitor_result.milestones;
+        self.timeline = monitor_result.timeline;
+        self.tool_spans = monitor_result.tool_spans;
+        self.subagent_usage = monitor_result.subagent_usage;
         self
     }
 }
@@ -82,6 +239,25 @@ impl AgentResult {
 pub trait Agent: Send + Sync {
     /// Run the agent with the given prompt
     async fn run(&self, prompt: &str) -> Result<AgentResult>;
+
+    /// Run the agent with extra environment variables for its subprocess.
+    /// Agents without a subprocess ignore them.
+    async fn run_with_env(&self, prompt: &str, env: &[(String, String)]) -> Result<AgentResult> {
+        let _ = env;
+        self.run(prompt).await
+    }
+
+    /// Run the agent for an iteration whose settings differ from the
+    /// configured ones. Agents that don't monitor their output ignore them.
+    async fn run_iteration(
+        &self,
+        prompt: &str,
+        env: &[(String, String)],
+        settings: &IterationSettings,
+    ) -> Result<AgentResult> {
+        let _ = settings;
+        self.run_with_env(prompt, env).await
+    }
 }
 
 /// Production implementation of Agent that spawns a configured CLI subprocess
@@ -99,94 +275,275 @@ impl CliAgent {
 #[async_trait]
 impl Agent for CliAgent {
     async fn run(&self, prompt: &str) -> Result<AgentResult> {
-        info!("Agent::run() starting");
-        let state = SharedState::new_shared();
-
-        // Create command channel for monitors to send kill commands
-        let (cmd_tx, mut cmd_rx) = mpsc::channel::<ProcessCommand>(1);
-
-        // Spawn configured agent process with stdin (for headless mode)
-        let agent_path = self.config.agent_path();
-        let agent_args = self.config.agent_args();
-        debug!("Spawning agent process: {} {:?}", agent_path, agent_args);
-        let mut process = AgentProcess::spawn_with_stdin(&agent_path, &agent_args, prompt).await?;
-
-        let pid = process.id();
-        info!("Agent process spawned with PID: {:?}", pid);
-
-        // Take stdout and stderr for monitoring
-        let stdout = process.stdout.take().expect("stdout not available");
-        let stderr = process.stderr.take().expect("stderr not available");
-        debug!("Captured stdout and stderr handles");
-
-        // Spawn monitor tasks
-        debug!("Spawning stdout and stderr monitor tasks");
-        let (stdout_handle, stderr_handle) = spawn_monitors(
-            Arc::clone(&self.config),
-            Arc::clone(&state),
-            stdout,
-            stderr,
-            cmd_tx,
-        );
-        debug!("Monitor tasks spawned successfully");
-
-        // Wait for process to exit or kill command
-        debug!("Entering select! loop - waiting for process exit or kill command");
-        let exit_reason = tokio::select! {
-            // Wait for process to exit naturally
-            status = process.wait() => {
-                match status {
-                    Ok(s) => {
-                        info!("Agent process exited with status: {:?}", s);
-                        ExitReason::Natural
-                    }
-                    Err(e) => {
-                        warn!("Error waiting for agent process: {}", e);
-                        ExitReason::Natural
-                    }
+        self.run_with_env(prompt, &[]).await
+    }
+
+    async fn run_with_env(&self, prompt: &str, env: &[(String, String)]) -> Result<AgentResult> {
+        self.run_iteration(prompt, env, &IterationSettings::from_config(&self.config))
+            .await
+    }
+
+    async fn run_iteration(
+        &self,
+        prompt: &str,
+        env: &[(String, String)],
+        settings: &IterationSettings,
+    ) -> Result<AgentResult> {
+        run_subprocess(
+            &for_iteration(&self.config, settings),
+            self.config.agent_path(),
+            self.config.agent_args(),
+            prompt,
+            env,
+            &settings.token_counter,
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl Agent for Box<dyn Agent> {
+    async fn run(&self, prompt: &str) -> Result<AgentResult> {
+        (**self).run(prompt).await
+    }
+
+    async fn run_with_env(&self, prompt: &str, env: &[(String, String)]) -> Result<AgentResult> {
+        (**self).run_with_env(prompt, env).await
+    }
+
+    async fn run_iteration(
+        &self,
+        prompt: &str,
+        env: &[(String, String)],
+        settings: &IterationSettings,
+    ) -> Result<AgentResult> {
+        (**self).run_iteration(prompt, env, settings).await
+    }
+}
+
+/// What the loop controller decides per iteration instead of the config
+#[derive(Debug, Clone)]
+pub struct IterationSettings {
+    /// Context limits of the iteration
+    pub limits: ContextLimitConfig,
+    /// Milestones not reached in earlier iterations, in order
+    pub milestones: Vec<String>,
+    /// Counter for the usage estimates of the run's monitors
+    pub token_counter: Arc<TokenCounter>,
+}
+
+impl IterationSettings {
+    /// The settings of an iteration that starts the run
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            limits: config.context_limit.clone(),
+            milestones: config.milestones.clone(),
+            token_counter: Arc::new(TokenCounter::new(config.context_limit.estimation_method)),
+        }
+    }
+}
+
+/// `config` with its context limits and milestones replaced by `settings`
+pub(crate) fn for_iteration(config: &Arc<Config>, settings: &IterationSettings) -> Arc<Config> {
+    Arc::new(Config {
+        context_limit: settings.limits.clone(),
+        milestones: settings.milestones.clone(),
+        ..(**config).clone()
+    })
+}
+
+/// The agent for the configured provider
+pub fn from_config(config: Arc<Config>) -> Result<Box<dyn Agent>> {
+    Ok(match config.agent_provider() {
+        AgentProvider::Openai => Box::new(ApiAgent::new(config)),
+        AgentProvider::Ollama => Box::new(OllamaAgent::new(config)),
+        #[cfg(feature = "anthropic-api")]
+        AgentProvider::Anthropic => Box::new(crate::anthropic_agent::AnthropicAgent::new(config)),
+        #[cfg(not(feature = "anthropic-api"))]
+        AgentProvider::Anthropic => {
+            return Err(crate::error::RalphError::ConfigError(
+                "the anthropic provider requires building ralph-loop with \
+                 `--features anthropic-api`"
+                    .to_string(),
+            ))
+        }
+        AgentProvider::Claude | AgentProvider::Codex => Box::new(CliAgent::new(config)),
+    })
+}
+
+/// Spawn `agent_path` with `stdin` as its input, monitor its stdout as agent
+/// events until it exits or is stopped, and collect the result. Usage the
+/// agent doesn't report is estimated with `token_counter`.
+pub(crate) async fn run_subprocess(
+    config: &Arc<Config>,
+    agent_path: String,
+    agent_args: Vec<String>,
+    stdin: &str,
+    env: &[(String, String)],
+    token_counter: &Arc<TokenCounter>,
+) -> Result<AgentResult> {
+    info!("Agent::run() starting");
+    let state = SharedState::new_shared();
+
+    // Create command channel for monitors to send kill commands
+    let (cmd_tx, mut cmd_rx) = mpsc::channel::<ProcessCommand>(1);
+
+    // Spawn configured agent process with stdin (for headless mode)
+    let (agent_path, agent_args) =
+        resources::wrap_command(&config.agent.resources, agent_path, agent_args);
+    debug!("Spawning agent process: {} {:?}", agent_path, agent_args);
+    let started_at = Utc::now();
+    let spawn_started = Instant::now();
+    let mut process = AgentProcess::spawn_with_stdin(&agent_path, &agent_args, stdin, env).await?;
+    let spawn_ms = elapsed_ms(spawn_started, Instant::now());
+
+    let pid = process.id();
+    info!("Agent process spawned with PID: {:?}", pid);
+
+    // Take stdout and stderr for monitoring
+    let stdout = process.stdout.take().expect("stdout not available");
+    let stderr = process.stderr.take().expect("stderr not available");
+    debug!("Captured stdout and stderr handles");
+
+    // Spawn monitor tasks
+    debug!("Spawning stdout and stderr monitor tasks");
+    let monitors = spawn_monitors(
+        Arc::clone(config),
+        Arc::clone(&state),
+        Arc::clone(token_counter),
+        stdout,
+        stderr,
+        cmd_tx,
+    );
+    let (stdout_handle, stderr_handle) = match monitors {
+        Ok(handles) => handles,
+        Err(e) => {
+            process.kill().await?;
+            return Err(e);
+        }
+    };
+    debug!("Monitor tasks spawned successfully");
+    let status_line = StatusLine::start(config, &state);
+
+    // Wait for process to exit or kill command
+    debug!("Entering select! loop - waiting for process exit or kill command");
+    let exit_reason = tokio::select! {
+        // Wait for process to exit naturally
+        status = process.wait() => {
+            match status {
+                Ok(s) if !s.success() => {
+                    warn!("Agent process failed with status: {:?}", s);
+                    ExitReason::Error
+                }
+                Ok(s) => {
+                    info!("Agent process exited with status: {:?}", s);
+                    ExitReason::Natural
+                }
+                Err(e) => {
+                    warn!("Error waiting for agent process: {}", e);
+                    ExitReason::Natural
                 }
             }
-            // Or receive kill command from monitor (context limit)
-            Some(cmd) = cmd_rx.recv() => {
-                match cmd {
-                    ProcessCommand::Kill => {
-                        info!("Killing agent process due to context limit");
-                        let _ = process.kill().await;
-                        ExitReason::ContextLimit
-                    }
+        }
+        // Or receive kill command from monitor (context limit or promise)
+        Some(cmd) = cmd_rx.recv() => {
+            match cmd {
+                ProcessCommand::Kill => {
+                    info!("Stopping agent process due to context limit");
+                    let _ = process.stop(&config.agent.stop).await;
+                    ExitReason::ContextLimit
+                }
+                ProcessCommand::StopOnPromise => {
+                    info!("Stopping agent process, promise found");
+                    let _ = process.stop(&config.agent.stop).await;
+                    ExitReason::PromiseFound
+                }
+                ProcessCommand::Restart => {
+                    info!("Stopping agent process, restart requested by agent");
+                    let _ = process.stop(&config.agent.stop).await;
+                    ExitReason::RestartRequested
                 }
             }
-        };
-        debug!("Exited select! loop with reason: {:?}", exit_reason);
-
-        // Wait for monitors to finish and get results
-        debug!("Waiting for monitor tasks to complete...");
-        let (stdout_result, _) = tokio::join!(stdout_handle, stderr_handle);
-        debug!("Monitor tasks completed");
-        let monitor_result = stdout_result.unwrap_or_default();
-
-        // Build result
-        let output = state.get_output().await;
-        let token_count = state.get_token_count().await;
-        let promise_found = state.get_promise_text().await;
-
-        info!(
-            "Agent::run() complete - token_count: {}, promise_found: {:?}, exit_reason: {:?}",
-            token_count,
-            promise_found.is_some(),
-            exit_reason
-        );
-        trace!("Promise text: {:?}", promise_found);
-
-        Ok(AgentResult {
-            output,
-            promise_found,
-            token_count,
-            exit_reason,
-            session_id: monitor_result.session_id,
-            token_usage: monitor_result.token_usage,
-        })
-    }
+        }
+    };
+    debug!("Exited select! loop with reason: {:?}", exit_reason);
+    let exited_at = Instant::now();
+    let exit_code = match process.try_wait() {
+        Ok(Some(status)) => status.code(),
+        _ => None,
+    };
+    let subprocess = SubprocessRecord {
+        kind: "agent".to_string(),
+        program: agent_path,
+        args: agent_args,
+        pid,
+        started_at,
+        exit_code,
+        duration_ms: elapsed_ms(spawn_started, exited_at),
+    };
+
+    // Wait for monitors to finish and get results
+    debug!("Waiting for monitor tasks to complete...");
+    let (stdout_result, stderr_result) = tokio::join!(stdout_handle, stderr_handle);
+    debug!("Monitor tasks completed");
+    drop(status_line);
+    let monitor_result = stdout_result.unwrap_or_default();
+    let timings = IterationTimings {
+        spawn_ms,
+        first_event_ms: monitor_result
+            .first_event_at
+            .map(|at| elapsed_ms(spawn_started, at)),
+        last_event_ms: monitor_result
+            .last_event_at
+            .map(|at| elapsed_ms(spawn_started, at)),
+        drain_ms: elapsed_ms(exited_at, Instant::now()),
+    };
+    debug!("Iteration timings: {:?}", timings);
+
+    // Build result
+    let output = state.take_output().await;
+    let snapshot = state.snapshot().await;
+    let token_count = snapshot.token_count;
+    let promise_found = snapshot.promise;
+
+    info!(
+        "Agent::run() complete - token_count: {}, promise_found: {:?}, exit_reason: {:?}",
+        token_count,
+        promise_found.is_some(),
+        exit_reason
+    );
+    trace!("Promise text: {:?}", promise_found);
+
+    Ok(AgentResult {
+        output,
+        promise_found,
+        token_count,
+        exit_reason,
+        session_id: monitor_result.session_id,
+        token_usage: monitor_result.token_usage,
+        cost_usd: monitor_result.cost_usd,
+        timings: Some(timings),
+        subprocess: Some(subprocess),
+        progress: monitor_result.progress,
+        commands: monitor_result.commands,
+        text: monitor_result.text,
+        tool_calls: monitor_result.tool_calls,
+        stderr: stderr_result.unwrap_or_default(),
+        event_count: monitor_result.event_count,
+        unparsed: monitor_result.unparsed,
+        unparsed_dropped: monitor_result.unparsed_dropped,
+        unknown: monitor_result.unknown,
+        promise_mentions: monitor_result.promise_mentions,
+        promise_summary: monitor_result.promise_summary,
+        milestones: monitor_result.milestones,
+        timeline: monitor_result.timeline,
+        tool_spans: monitor_result.tool_spans,
+        subagent_usage: monitor_result.subagent_usage,
+    })
+}
+
+fn elapsed_ms(from: Instant, to: Instant) -> u64 {
+    to.saturating_duration_since(from).as_millis() as u64
 }
 
 #[cfg(test)]
@@ -0,0 +1,161 @@
+//! Agent backed by the Anthropic Messages API.
+//!
+//! With `[agent] provider = "anthropic"`, each iteration streams a response
+//! from `<base_url>/messages` instead of spawning the Claude CLI, so the loop
+//! works where the CLI isn't installed. The stream reports input tokens up
+//! front and output tokens as they are generated, which lets the monitor
+//! enforce the context limit while the response is still streaming.
+//! Requires the `anthropic-api` cargo feature.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::agent::{Agent, AgentResult, IterationSettings};
+use crate::api_agent::{api_key, run_conversation, ChatApi};
+use crate::config::Config;
+use crate::error::Result;
+use crate::tools::{ToolCall, ToolDefinition, ToolOutput};
+
+/// Version of the Messages API the requests are written against
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Agent that streams responses from the Anthropic Messages API
+pub struct AnthropicAgent {
+    config: Arc<Config>,
+}
+
+impl AnthropicAgent {
+    /// Create a new AnthropicAgent with the given configuration
+    pub fn new(config: Arc<Config>) -> Self {
+        Self { config }
+    }
+}
+
+impl ChatApi for AnthropicAgent {
+    fn config(&self) -> &Arc<Config> {
+        &self.config
+    }
+
+    fn endpoint(&self) -> String {
+        format!(
+            "{}/messages",
+            self.config.anthropic.base_url.trim_end_matches('/')
+        )
+    }
+
+    fn headers(&self) -> Result<Vec<String>> {
+        let key = api_key(&self.config.anthropic.key_env)?;
+        Ok(vec![
+            format!("x-api-key: {key}"),
+            format!("anthropic-version: {ANTHROPIC_VERSION}"),
+        ])
+    }
+
+    fn request_body(&self, messages: &[Value], tools: &[ToolDefinition]) -> Result<Value> {
+        let anthropic = &self.config.anthropic;
+        let mut body = json!({
+            "model": anthropic.model,
+            "max_tokens": anthropic.max_tokens,
+            "messages": messages,
+            "stream": true,
+        });
+        if let Some(ref system_prompt) = anthropic.system_prompt {
+            body["system"] = json!(system_prompt);
+        }
+        if !tools.is_empty() {
+            body["tools"] = tools
+                .iter()
+                .map(|tool| {
+                    json!({
+                        "name": tool.name,
+                        "description": tool.description,
+                        "input_schema": tool.parameters,
+                    })
+                })
+                .collect();
+        }
+        Ok(body)
+    }
+
+    fn assistant_message(&self, text: &str, calls: &[ToolCall]) -> Value {
+        let mut content = Vec::with_capacity(calls.len() + 1);
+        if !text.is_empty() {
+            content.push(json!({ "type": "text", "text": text }));
+        }
+        content.extend(calls.iter().map(|call| {
+            json!({ "type": "tool_use", "id": call.id, "name": call.name, "input": call.input() })
+        }));
+        json!({ "role": "assistant", "content": content })
+    }
+
+    fn tool_results(&self, results: &[(ToolCall, ToolOutput)]) -> Vec<Value> {
+        let content: Vec<Value> = results
+            .iter()
+            .map(|(call, output)| {
+                json!({
+                    "type": "tool_result",
+                    "tool_use_id": call.id,
+                    "content": output.content,
+                    "is_error": output.is_error,
+                })
+            })
+            .collect();
+        vec![json!({ "role": "user", "content": content })]
+    }
+}
+
+#[async_trait]
+impl Agent for AnthropicAgent {
+    async fn run(&self, prompt: &str) -> Result<AgentResult> {
+        self.run_with_env(prompt, &[]).await
+    }
+
+    async fn run_with_env(&self, prompt: &str, env: &[(String, String)]) -> Result<AgentResult> {
+        run_conversation(
+            self,
+            prompt,
+            env,
+            &IterationSettings::from_config(&self.config),
+        )
+        .await
+    }
+
+    async fn run_iteration(
+        &self,
+        prompt: &str,
+        env: &[(String, String)],
+        settings: &IterationSettings,
+    ) -> Result<AgentResult> {
+        run_conversation(self, prompt, env, settings).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AnthropicConfig;
+
+    #[test]
+    fn builds_streaming_request() {
+        let agent = AnthropicAgent::new(Arc::new(Config {
+            anthropic: AnthropicConfig {
+                base_url: "http://localhost:8080/v1/".to_string(),
+                system_prompt: Some("Be brief".to_string()),
+                ..AnthropicConfig::default()
+            },
+            ..Config::default()
+        }));
+
+        let body = agent
+            .request_body(&[json!({ "role": "user", "content": "Fix the bug" })], &[])
+            .unwrap();
+
+        assert_eq!(agent.endpoint(), "http://localhost:8080/v1/messages");
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["max_tokens"], 8192);
+        assert_eq!(body["system"], "Be brief");
+        assert_eq!(body["messages"][0]["content"], "Fix the bug");
+    }
+}
@@ -0,0 +1,485 @@
+//! Agents backed by HTTP APIs instead of a coding agent CLI.
+//!
+//! With `[agent] provider = "openai"`, each iteration sends the prompt to an
+//! OpenAI-compatible `<base_url>/chat/completions` endpoint. The requests are
+//! made with curl and their streamed responses are monitored like any other
+//! agent output. When the model calls one of the local tools (see
+//! [`crate::tools`]), ralph executes it and sends the result back in a
+//! follow-up request, until the model answers without calling a tool.
+//! The Anthropic and Ollama agents share this conversation loop.
+
+use std::io::Write;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tempfile::NamedTempFile;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::agent::{
+    for_iteration, run_subprocess, Agent, AgentResult, ExitReason, IterationSettings,
+};
+use crate::config::Config;
+use crate::error::{RalphError, Result};
+use crate::json_events::LocalToolEvent;
+use crate::monitor::JsonEventMonitor;
+use crate::state::SharedState;
+use crate::token_counter::TokenCounter;
+use crate::tools::{ToolCall, ToolDefinition, ToolExecutor, ToolOutput};
+
+/// How a chat API encodes requests, tool calls and tool results
+pub(crate) trait ChatApi: Send + Sync {
+    fn config(&self) -> &Arc<Config>;
+
+    /// URL the requests are sent to
+    fn endpoint(&self) -> String;
+
+    /// Headers sent with every request, including credentials
+    fn headers(&self) -> Result<Vec<String>>;
+
+    /// JSON body of a request continuing `messages`
+    fn request_body(&self, messages: &[Value], tools: &[ToolDefinition]) -> Result<Value>;
+
+    /// The assistant turn that requested `calls`
+    fn assistant_message(&self, text: &str, calls: &[ToolCall]) -> Value;
+
+    /// Messages handing the tool results back to the model
+    fn tool_results(&self, results: &[(ToolCall, ToolOutput)]) -> Vec<Value>;
+}
+
+/// Send `prompt` and keep executing the tool calls the model makes, one
+/// request per turn, until it answers without calling a tool. Each request
+/// is monitored with `settings`, less the milestones reached in earlier
+/// requests. The executed tool calls are monitored across the requests, so
+/// the context growth between them can schedule a restart.
+pub(crate) async fn run_conversation(
+    api: &impl ChatApi,
+    prompt: &str,
+    env: &[(String, String)],
+    settings: &IterationSettings,
+) -> Result<AgentResult> {
+    let mut settings = settings.clone();
+    let mut config = for_iteration(api.config(), &settings);
+    let executor = if config.tools.enabled {
+        let root = std::env::current_dir().map_err(RalphError::ProcessIoError)?;
+        Some(
+            ToolExecutor::new(&root, config.permissions, config.tools.clone())
+                .hiding_env([config.api.key_env.clone(), config.anthropic.key_env.clone()]),
+        )
+    } else {
+        None
+    };
+    let tools = executor
+        .as_ref()
+        .map(ToolExecutor::definitions)
+        .unwrap_or_default();
+    let headers = api.headers()?;
+    let endpoint = api.endpoint();
+    let (tool_tx, mut tool_rx) = mpsc::channel(1);
+    let tool_state = SharedState::new_shared();
+    let mut tool_monitor =
+        JsonEventMonitor::for_tool_calls(Arc::clone(&config), Arc::clone(&tool_state), tool_tx)?
+            .with_token_counter(Arc::clone(&settings.token_counter));
+
+    let mut messages = vec![json!({ "role": "user", "content": prompt })];
+    let mut combined: Option<AgentResult> = None;
+    for turn in 1..=config.tools.max_turns.max(1) {
+        let body = api.request_body(&messages, &tools)?;
+        let mut result = stream_request(
+            &config,
+            &endpoint,
+            &headers,
+            &body,
+            env,
+            &settings.token_counter,
+        )
+        .await?;
+        let calls = std::mem::take(&mut result.tool_calls);
+        if !result.milestones.is_empty() {
+            settings.milestones.drain(..result.milestones.len());
+            config = for_iteration(api.config(), &settings);
+        }
+        // Each request is monitored on its own, so confirmations add up here
+        let mentions =
+            combined.as_ref().map_or(0, |c| c.promise_mentions) + result.promise_mentions;
+        if result.promise_found.is_none()
+            && settings.milestones.is_empty()
+            && result.promise_mentions > 0
+            && mentions >= config.promise_confirmations.max(1)
+        {
+            info!(
+                "Promise confirmed across requests: {}",
+                config.completion_promise
+            );
+            result.promise_found = Some(config.completion_promise.clone());
+        }
+
+        let finished = result.exit_reason != ExitReason::Natural
+            || result.promise_found.is_some()
+            || calls.is_empty();
+        let executor = match executor {
+            Some(ref executor) if !finished => executor,
+            _ => {
+                combined = Some(followed_by(combined, result));
+                break;
+            }
+        };
+        if turn == config.tools.max_turns {
+            warn!(
+                "Model still calling tools after {} requests, ending the iteration",
+                turn
+            );
+            combined = Some(followed_by(combined, result));
+            break;
+        }
+
+        messages.push(api.assistant_message(&result.text, &calls));
+        // Recorded like the tool events of the CLI agents' streams
+        let requested = LocalToolEvent::RalphToolUse {
+            calls: calls.clone(),
+            usage: result.token_usage.clone(),
+        };
+        tool_monitor.process_line(&requested.to_line()).await?;
+        let mut results = Vec::with_capacity(calls.len());
+        let mut stopped = false;
+        for call in calls {
+            info!("Tool call: {}", call.name);
+            debug!("Tool call arguments: {}", call.arguments);
+            let output = executor.execute(&call, env).await;
+            if output.is_error {
+                debug!("Tool {} failed: {}", call.name, output.content);
+            }
+            let finished = LocalToolEvent::RalphToolResult {
+                tool_call_id: call.id.clone(),
+                name: call.name.clone(),
+                output: output.clone(),
+            };
+            tool_monitor.process_line(&finished.to_line()).await?;
+            results.push((call, output));
+            // The only command the tool monitor sends is the restart
+            // scheduled before the context limit
+            if tool_rx.try_recv().is_ok() {
+                stopped = true;
+                break;
+            }
+        }
+        result.output.push_str(&tool_state.take_output().await);
+        if stopped {
+            result.exit_reason = ExitReason::ContextLimit;
+            combined = Some(followed_by(combined, result));
+            break;
+        }
+        messages.extend(api.tool_results(&results));
+        combined = Some(followed_by(combined, result));
+    }
+
+    let mut combined =
+        combined.ok_or_else(|| RalphError::ConfigError("no request was made".to_string()))?;
+    let tool_calls = tool_monitor.result();
+    combined.timeline.extend(tool_calls.timeline);
+    combined.tool_spans.extend(tool_calls.tool_spans);
+    Ok(combined)
+}
+
+fn followed_by(previous: Option<AgentResult>, next: AgentResult) -> AgentResult {
+    match previous {
+        Some(previous) => previous.followed_by(next),
+        None => next,
+    }
+}
+
+/// Agent that streams chat completions from an OpenAI-compatible API
+pub struct ApiAgent {
+    config: Arc<Config>,
+}
+
+impl ApiAgent {
+    /// Create a new ApiAgent with the given configuration
+    pub fn new(config: Arc<Config>) -> Self {
+        Self { config }
+    }
+}
+
+impl ChatApi for ApiAgent {
+    fn config(&self) -> &Arc<Config> {
+        &self.config
+    }
+
+    fn endpoint(&self) -> String {
+        format!(
+            "{}/chat/completions",
+            self.config.api.base_url.trim_end_matches('/')
+        )
+    }
+
+    fn headers(&self) -> Result<Vec<String>> {
+        let key = api_key(&self.config.api.key_env)?;
+        Ok(vec![format!("Authorization: Bearer {key}")])
+    }
+
+    fn request_body(&self, messages: &[Value], tools: &[ToolDefinition]) -> Result<Value> {
+        let api = &self.config.api;
+        let model = api.model.as_deref().ok_or_else(|| {
+            RalphError::ConfigError("[api] model must be set for the openai provider".to_string())
+        })?;
+
+        let mut all_messages = Vec::with_capacity(messages.len() + 1);
+        if let Some(ref system_prompt) = api.system_prompt {
+            all_messages.push(json!({ "role": "system", "content": system_prompt }));
+        }
+        all_messages.extend_from_slice(messages);
+
+        let mut body = json!({
+            "model": model,
+            "messages": all_messages,
+            "stream": true,
+            "stream_options": { "include_usage": true },
+        });
+        if let Some(max_tokens) = api.max_tokens {
+            body["max_tokens"] = json!(max_tokens);
+        }
+        if !tools.is_empty() {
+            body["tools"] = function_tools(tools);
+        }
+        Ok(body)
+    }
+
+    fn assistant_message(&self, text: &str, calls: &[ToolCall]) -> Value {
+        let tool_calls: Vec<Value> = calls
+            .iter()
+            .map(|call| {
+                json!({
+                    "id": call.id,
+                    "type": "function",
+                    "function": { "name": call.name, "arguments": call.arguments },
+                })
+            })
+            .collect();
+        json!({
+            "role": "assistant",
+            "content": (!text.is_empty()).then_some(text),
+            "tool_calls": tool_calls,
+        })
+    }
+
+    fn tool_results(&self, results: &[(ToolCall, ToolOutput)]) -> Vec<Value> {
+        results
+            .iter()
+            .map(|(call, output)| {
+                json!({ "role": "tool", "tool_call_id": call.id, "content": output.content })
+            })
+            .collect()
+    }
+}
+
+/// Tools in the `{"type": "function", ...}` format of OpenAI and Ollama
+pub(crate) fn function_tools(tools: &[ToolDefinition]) -> Value {
+    tools
+        .iter()
+        .map(|tool| {
+            json!({
+                "type": "function",
+                "function": {
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": tool.parameters,
+                },
+            })
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Agent for ApiAgent {
+    async fn run(&self, prompt: &str) -> Result<AgentResult> {
+        self.run_with_env(prompt, &[]).await
+    }
+
+    async fn run_with_env(&self, prompt: &str, env: &[(String, String)]) -> Result<AgentResult> {
+        run_conversation(
+            self,
+            prompt,
+            env,
+            &IterationSettings::from_config(&self.config),
+        )
+        .await
+    }
+
+    async fn run_iteration(
+        &self,
+        prompt: &str,
+        env: &[(String, String)],
+        settings: &IterationSettings,
+    ) -> Result<AgentResult> {
+        run_conversation(self, prompt, env, settings).await
+    }
+}
+
+/// Read the API key from `key_env`
+pub(crate) fn api_key(key_env: &str) -> Result<String> {
+    std::env::var(key_env)
+        .ok()
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| {
+            RalphError::ConfigError(format!("{key_env} is not set; it must hold the API key"))
+        })
+}
+
+/// POST `body` to `url` with curl and monitor the streamed response.
+/// `headers` are written to a file only the current user can read, so keys
+/// never show up in the process list.
+pub(crate) async fn stream_request(
+    config: &Arc<Config>,
+    url: &str,
+    headers: &[String],
+    body: &Value,
+    env: &[(String, String)],
+    token_counter: &Arc<TokenCounter>,
+) -> Result<AgentResult> {
+    let header_file = write_header_file(headers)?;
+
+    let mut args: Vec<String> = ["-sS", "-N", "--fail-with-body", "-X", "POST"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    args.extend([
+        "-H".to_string(),
+        "Content-Type: application/json".to_string(),
+        "-H".to_string(),
+        format!("@{}", header_file.path().display()),
+        "--data-binary".to_string(),
+        "@-".to_string(),
+    ]);
+    args.extend(config.agent_args());
+    args.push(url.to_string());
+
+    // The header file is removed once curl has exited and it goes out of scope
+    run_subprocess(
+        config,
+        config.agent_path(),
+        args,
+        &body.to_string(),
+        env,
+        token_counter,
+    )
+    .await
+}
+
+/// Write `headers` to a new temporary file only the current user can read,
+/// removed when the returned handle is dropped
+fn write_header_file(headers: &[String]) -> Result<NamedTempFile> {
+    let mut file = NamedTempFile::with_prefix("ralph-api-").map_err(RalphError::ProcessIoError)?;
+    for header in headers {
+        writeln!(file, "{header}").map_err(RalphError::ProcessIoError)?;
+    }
+    file.flush().map_err(RalphError::ProcessIoError)?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AgentProvider, ApiConfig, ToolsConfig};
+
+    fn agent(api: ApiConfig) -> ApiAgent {
+        let mut config = Config {
+            api,
+            ..Config::default()
+        };
+        config.agent.provider = AgentProvider::Openai;
+        ApiAgent::new(Arc::new(config))
+    }
+
+    fn prompt(text: &str) -> Vec<Value> {
+        vec![json!({ "role": "user", "content": text })]
+    }
+
+    #[test]
+    fn builds_streaming_request() {
+        let agent = agent(ApiConfig {
+            base_url: "http://localhost:8080/v1/".to_string(),
+            model: Some("gpt-test".to_string()),
+            max_tokens: Some(256),
+            system_prompt: Some("Be brief".to_string()),
+            ..ApiConfig::default()
+        });
+
+        let body = agent.request_body(&prompt("Fix the bug"), &[]).unwrap();
+
+        assert_eq!(
+            agent.endpoint(),
+            "http://localhost:8080/v1/chat/completions"
+        );
+        assert_eq!(body["model"], "gpt-test");
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["max_tokens"], 256);
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["content"], "Fix the bug");
+        assert!(body.get("tools").is_none());
+    }
+
+    #[test]
+    fn missing_model_is_a_config_error() {
+        let error = agent(ApiConfig::default()).request_body(&prompt("prompt"), &[]);
+
+        assert!(matches!(error, Err(RalphError::ConfigError(_))));
+    }
+
+    #[test]
+    fn encodes_tool_calls_and_results() {
+        let agent = agent(ApiConfig {
+            model: Some("gpt-test".to_string()),
+            ..ApiConfig::default()
+        });
+        let executor = ToolExecutor::new(
+            std::path::Path::new("."),
+            crate::config::PermissionMode::Skip,
+            ToolsConfig::default(),
+        );
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            name: "bash".to_string(),
+            arguments: r#"{"command":"ls"}"#.to_string(),
+        };
+        let output = ToolOutput {
+            content: "Cargo.toml".to_string(),
+            is_error: false,
+        };
+
+        let body = agent
+            .request_body(&prompt("List files"), &executor.definitions())
+            .unwrap();
+        let assistant = agent.assistant_message("", std::slice::from_ref(&call));
+        let results = agent.tool_results(&[(call, output)]);
+
+        assert_eq!(body["tools"][2]["function"]["name"], "bash");
+        assert_eq!(assistant["content"], Value::Null);
+        assert_eq!(
+            assistant["tool_calls"][0]["function"]["arguments"],
+            r#"{"command":"ls"}"#
+        );
+        assert_eq!(results[0]["tool_call_id"], "call_1");
+        assert_eq!(results[0]["content"], "Cargo.toml");
+    }
+
+    #[test]
+    fn header_file_is_private_and_removed_when_dropped() {
+        let file = write_header_file(&["Authorization: Bearer secret".to_string()]).unwrap();
+        let path = file.path().to_path_buf();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "Authorization: Bearer secret\n"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        drop(file);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,104 @@
+//! Append-only audit trail of subprocesses ralph itself spawned for a run.
+//!
+//! Each run directory gets an `audit.jsonl` with one line per subprocess,
+//! recording what was executed, how it ended and how long it took. Recorded
+//! are the agent, the summary, the classifier, the completion probe and git;
+//! checks of the machine's state and uploads of finished runs are not.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{RalphError, Result};
+
+/// Name of the audit log inside each run directory
+pub const AUDIT_FILE: &str = "audit.jsonl";
+
+/// A subprocess spawned by ralph
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubprocessRecord {
+    /// What the subprocess was for, e.g. `agent`
+    pub kind: String,
+    /// Executable that was run
+    pub program: String,
+    /// Arguments passed to the executable
+    pub args: Vec<String>,
+    /// Process ID, if the process was spawned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// When the process was started
+    pub started_at: DateTime<Utc>,
+    /// Exit code; `None` when the process was terminated by a signal
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// Wall-clock runtime in milliseconds
+    pub duration_ms: u64,
+}
+
+/// One line of `audit.jsonl`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Iteration the subprocess belonged to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iteration: Option<u32>,
+    #[serde(flatten)]
+    pub record: SubprocessRecord,
+}
+
+/// Append an entry to the audit log in `run_dir`
+pub fn append(run_dir: &Path, entry: &AuditEntry) -> Result<()> {
+    let line = serde_json::to_string(entry)
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(run_dir.join(AUDIT_FILE))
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+    writeln!(file, "{line}").map_err(|e| RalphError::TranscriptWriteError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entry(iteration: u32, exit_code: Option<i32>) -> AuditEntry {
+        AuditEntry {
+            iteration: Some(iteration),
+            record: SubprocessRecord {
+                kind: "agent".to_string(),
+                program: "claude".to_string(),
+                args: vec!["--print".to_string()],
+                pid: Some(42),
+                started_at: Utc::now(),
+                exit_code,
+                duration_ms: 1500,
+            },
+        }
+    }
+
+    #[test]
+    fn appends_one_line_per_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let first = entry(1, Some(0));
+        let second = entry(2, None);
+
+        append(temp_dir.path(), &first).unwrap();
+        append(temp_dir.path(), &second).unwrap();
+
+        let content = std::fs::read_to_string(temp_dir.path().join(AUDIT_FILE)).unwrap();
+        let entries: Vec<AuditEntry> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries, vec![first, second]);
+        assert!(content
+            .lines()
+            .next()
+            .unwrap()
+            .contains(r#""program":"claude""#));
+    }
+}
@@ -0,0 +1,164 @@
+//! Exit banner rendering and terminal alerts.
+
+use std::io::Write;
+use std::time::Duration;
+
+use crate::config::BannerConfig;
+use crate::time_display::TimeDisplay;
+
+/// Default success banner, matching the historical output
+pub const DEFAULT_TEMPLATE: &str = "Promise '{promise}' fulfilled after {iterations} iteration(s)";
+
+/// Values available to the banner template
+#[derive(Debug, Clone)]
+pub struct BannerContext<'a> {
+    pub promise: &'a str,
+    pub iterations: u32,
+    pub duration: Duration,
+    pub cost_usd: Option<f64>,
+    /// How `{duration}` is formatted
+    pub time: TimeDisplay,
+}
+
+/// Render the success banner from the configured (or default) template
+pub fn render(config: &BannerConfig, context: &BannerContext<'_>) -> String {
+    let template = config.template.as_deref().unwrap_or(DEFAULT_TEMPLATE);
+    let cost = match context.cost_usd {
+        Some(cost) => format!("${cost:.2}"),
+        None => "n/a".to_string(),
+    };
+
+    template
+        .replace("{promise}", context.promise)
+        .replace("{iterations}", &context.iterations.to_string())
+        .replace("{duration}", &context.time.duration(context.duration))
+        .replace("{cost}", &cost)
+}
+
+/// Format a duration as e.g. `1h 02m 03s`, `4m 05s` or `7s`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes:02}m {seconds:02}s")
+    } else if minutes > 0 {
+        format!("{minutes}m {seconds:02}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+/// Escape sequences to emit when the run ends, per the configuration.
+/// Inside tmux the notification is wrapped in a passthrough sequence, which
+/// tmux only forwards to the terminal with `allow-passthrough` on.
+pub fn alert_sequence(config: &BannerConfig, message: &str, tmux: bool) -> String {
+    let mut sequence = String::new();
+    if config.osc9 {
+        // OSC 9 is picked up by iTerm2, WezTerm and kitty
+        let osc9 = format!("\x1b]9;{}\x07", message.replace(['\x07', '\x1b'], ""));
+        if tmux {
+            sequence.push_str(&format!(
+                "\x1bPtmux;{}\x1b\\",
+                osc9.replace('\x1b', "\x1b\x1b")
+            ));
+        } else {
+            sequence.push_str(&osc9);
+        }
+    }
+    if config.bell {
+        sequence.push('\x07');
+    }
+    sequence
+}
+
+/// Emit the configured terminal alerts on stdout
+pub fn alert(config: &BannerConfig, message: &str) {
+    let tmux = std::env::var_os("TMUX").is_some();
+    let sequence = alert_sequence(config, message, tmux);
+    if sequence.is_empty() {
+        return;
+    }
+    let mut stdout = std::io::stdout();
+    let _ = stdout.write_all(sequence.as_bytes());
+    let _ = stdout.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> BannerContext<'static> {
+        BannerContext {
+            promise: "DONE",
+            iterations: 3,
+            duration: Duration::from_secs(3723),
+            cost_usd: Some(1.234),
+            time: TimeDisplay::default(),
+        }
+    }
+
+    #[test]
+    fn default_template_matches_historical_banner() {
+        let banner = render(&BannerConfig::default(), &context());
+        assert_eq!(banner, "Promise 'DONE' fulfilled after 3 iteration(s)");
+    }
+
+    #[test]
+    fn custom_template_substitutes_all_placeholders() {
+        let config = BannerConfig {
+            template: Some("{iterations} iters, {duration}, {cost} ({promise})".to_string()),
+            ..BannerConfig::default()
+        };
+        assert_eq!(
+            render(&config, &context()),
+            "3 iters, 1h 02m 03s, $1.23 (DONE)"
+        );
+    }
+
+    #[test]
+    fn missing_cost_renders_as_not_available() {
+        let config = BannerConfig {
+            template: Some("{cost}".to_string()),
+            ..BannerConfig::default()
+        };
+        let context = BannerContext {
+            cost_usd: None,
+            ..context()
+        };
+        assert_eq!(render(&config, &context), "n/a");
+    }
+
+    #[test]
+    fn formats_short_durations() {
+        assert_eq!(format_duration(Duration::from_secs(7)), "7s");
+        assert_eq!(format_duration(Duration::from_secs(245)), "4m 05s");
+    }
+
+    #[test]
+    fn alert_sequence_respects_toggles() {
+        assert_eq!(alert_sequence(&BannerConfig::default(), "done", false), "");
+
+        let config = BannerConfig {
+            bell: true,
+            osc9: true,
+            ..BannerConfig::default()
+        };
+        assert_eq!(
+            alert_sequence(&config, "done", false),
+            "\x1b]9;done\x07\x07"
+        );
+    }
+
+    #[test]
+    fn alert_sequence_passes_through_tmux() {
+        let config = BannerConfig {
+            bell: true,
+            osc9: true,
+            ..BannerConfig::default()
+        };
+        assert_eq!(
+            alert_sequence(&config, "done", true),
+            "\x1bPtmux;\x1b\x1b]9;done\x07\x1b\\\x07"
+        );
+    }
+}
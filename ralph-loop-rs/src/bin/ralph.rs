@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use ralph_loop::umbrella::{self, Invocation, LOOP_BINARY};
+
+/// `ralph-loop` next to this binary, or on the `PATH`
+fn loop_binary() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .map(|exe| exe.with_file_name(format!("{LOOP_BINARY}{}", std::env::consts::EXE_SUFFIX)))
+        .filter(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from(LOOP_BINARY))
+}
+
+fn main() {
+    let args = match umbrella::invocation(std::env::args_os().skip(1).collect()) {
+        Invocation::Help => {
+            println!("{}", umbrella::USAGE);
+            return;
+        }
+        Invocation::Loop(args) => args,
+    };
+    let binary = loop_binary();
+    let mut command = Command::new(&binary);
+    command.args(args);
+
+    #[cfg(unix)]
+    let error = {
+        use std::os::unix::process::CommandExt;
+        command.exec()
+    };
+    #[cfg(not(unix))]
+    let error = match command.status() {
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(e) => e,
+    };
+    eprintln!("ralph: failed to run {}: {}", binary.display(), error);
+    std::process::exit(1);
+}
@@ -0,0 +1,72 @@
+//! What this binary was built from.
+//!
+//! Printed by `ralph-loop --build-info` and stored in the metadata of each
+//! run, so a bug report says which build it came from, whether it was
+//! installed from a release archive, Homebrew or `cargo install`.
+
+use serde::{Deserialize, Serialize};
+
+/// Cargo features that change what the binary can do
+const FEATURES: &[(&str, bool)] = &[
+    ("self-update", cfg!(feature = "self-update")),
+    ("anthropic-api", cfg!(feature = "anthropic-api")),
+    (
+        "desktop-notifications",
+        cfg!(feature = "desktop-notifications"),
+    ),
+];
+
+/// Version, commit and configuration of a build
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    /// Crate version
+    pub version: String,
+    /// Commit the binary was built from, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_sha: Option<String>,
+    /// Day of the build, `YYYY-MM-DD` in UTC
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_date: Option<String>,
+    /// Enabled cargo features
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Target triple, e.g. `x86_64-unknown-linux-gnu`
+    pub target: String,
+}
+
+impl BuildInfo {
+    /// Build information of this binary
+    pub fn current() -> Self {
+        let known = |value: &str| (!value.is_empty()).then(|| value.to_string());
+        Self {
+            version: crate::VERSION.to_string(),
+            git_sha: known(env!("RALPH_GIT_SHA")),
+            build_date: known(env!("RALPH_BUILD_DATE")),
+            features: FEATURES
+                .iter()
+                .filter(|(_, enabled)| *enabled)
+                .map(|(name, _)| name.to_string())
+                .collect(),
+            target: env!("RALPH_TARGET").to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_this_build() {
+        let info = BuildInfo::current();
+        assert_eq!(info.version, crate::VERSION);
+        assert!(!info.target.is_empty());
+        assert_eq!(
+            info.features.contains(&"self-update".to_string()),
+            cfg!(feature = "self-update")
+        );
+        let date = info.build_date.unwrap();
+        assert_eq!(date.len(), 10);
+        assert_eq!(&date[4..5], "-");
+    }
+}
@@ -0,0 +1,135 @@
+//! Cancellation of a running loop from another process.
+//!
+//! `ralph-loop cancel <run-id>` drops a marker file into the run directory that
+//! the loop checks between iterations. With `--now` the agent subprocess is
+//! killed as well, so the current iteration ends immediately.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use crate::error::RalphError;
+use crate::transcript::{RunMetadata, RunStatus, CANCEL_FILE};
+
+/// Ask the run `run_id` under `output_dir` to stop after its current iteration
+pub fn cancel_run(output_dir: &Path, run_id: &str, now: bool) -> Result<String, RalphError> {
+    let run_dir = output_dir.join("runs").join(run_id);
+    if !run_dir.is_dir() {
+        return Err(RalphError::CancelError(format!(
+            "run '{run_id}' not found in {}",
+            output_dir.join("runs").display()
+        )));
+    }
+
+    let metadata = RunMetadata::load(&run_dir)?;
+    if metadata.status != RunStatus::Running {
+        return Err(RalphError::CancelError(format!(
+            "run '{run_id}' is not running (status: {:?})",
+            metadata.status
+        )));
+    }
+
+    let pid = metadata.pid.ok_or_else(|| {
+        RalphError::CancelError(format!("run '{run_id}' did not record a process ID"))
+    })?;
+    if !process_alive(pid) {
+        return Err(RalphError::CancelError(format!(
+            "process {pid} for run '{run_id}' is no longer alive"
+        )));
+    }
+
+    fs::write(
+        run_dir.join(CANCEL_FILE),
+        if now { "now" } else { "graceful" },
+    )
+    .map_err(|e| RalphError::CancelError(e.to_string()))?;
+
+    if now {
+        kill_children(pid)?;
+        return Ok(format!(
+            "killed agent of run '{run_id}'; loop will stop and mark the run interrupted"
+        ));
+    }
+
+    Ok(format!(
+        "run '{run_id}' will stop after its current iteration"
+    ))
+}
+
+/// Whether process `pid` exists
+pub(crate) fn process_alive(pid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// Hard-kill the agent subprocess(es) spawned by the ralph-loop process `pid`
+fn kill_children(pid: u32) -> Result<(), RalphError> {
+    let status = Command::new("pkill")
+        .args(["-KILL", "-P", &pid.to_string()])
+        .status()
+        .map_err(|e| RalphError::CancelError(e.to_string()))?;
+
+    // pkill exits 1 when nothing matched, which just means no agent is running
+    match status.code() {
+        Some(0) | Some(1) => Ok(()),
+        _ => Err(RalphError::CancelError(format!(
+            "failed to kill agent process of pid {pid} ({status})"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AgentProvider;
+    use crate::transcript::{ExitReason, TranscriptWriter};
+    use tempfile::TempDir;
+
+    fn writer(output_dir: &Path, run_id: &str) -> TranscriptWriter {
+        TranscriptWriter::new(
+            output_dir,
+            output_dir,
+            "Test prompt",
+            None,
+            AgentProvider::Claude,
+            "TASK COMPLETE".to_string(),
+            Some(run_id.to_string()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn graceful_cancel_writes_marker_for_running_run() {
+        let temp_dir = TempDir::new().unwrap();
+        let writer = writer(temp_dir.path(), "run-cancel");
+
+        let message = cancel_run(temp_dir.path(), "run-cancel", false).unwrap();
+
+        assert!(message.contains("after its current iteration"));
+        assert!(writer.cancel_requested());
+    }
+
+    #[test]
+    fn rejects_unknown_run() {
+        let temp_dir = TempDir::new().unwrap();
+        let error = cancel_run(temp_dir.path(), "missing", false).unwrap_err();
+        assert!(error.to_string().contains("not found"));
+    }
+
+    #[test]
+    fn rejects_finished_run() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut writer = writer(temp_dir.path(), "run-done");
+        writer
+            .complete(ExitReason::PromiseFulfilled, "promise found".to_string())
+            .unwrap();
+
+        let error = cancel_run(temp_dir.path(), "run-done", false).unwrap_err();
+
+        assert!(error.to_string().contains("not running"));
+        assert!(!writer.cancel_requested());
+    }
+}
@@ -0,0 +1,220 @@
+//! Compatibility with the installed Claude CLI.
+//!
+//! Before a run, `claude --help` is parsed to find out which flags the
+//! installed CLI supports. The agent arguments are adapted where an
+//! equivalent exists (`--print` vs `-p`, dropping optional flags), and the
+//! run fails fast with a clear message when the CLI can't do what ralph needs.
+
+use std::process::{Command, Stdio};
+
+use regex::Regex;
+use tracing::{debug, info, warn};
+
+use crate::config::{AgentProvider, Config};
+use crate::error::{RalphError, Result};
+
+/// Flags of the installed Claude CLI that ralph cares about
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClaudeCapabilities {
+    /// Output of `claude --version`
+    pub version: Option<String>,
+    /// `--print` is accepted
+    pub print_long: bool,
+    /// `-p` is accepted
+    pub print_short: bool,
+    /// `--output-format stream-json` is available
+    pub stream_json: bool,
+    /// `--include-partial-messages` is available
+    pub include_partial_messages: bool,
+    /// `--permission-mode` is available
+    pub permission_mode: bool,
+    /// `--dangerously-skip-permissions` is available
+    pub skip_permissions: bool,
+}
+
+impl ClaudeCapabilities {
+    /// Parse the output of `claude --help`. Returns `None` if it doesn't
+    /// look like help output at all.
+    pub fn parse_help(help: &str) -> Option<Self> {
+        let short_print = Regex::new(r"(?m)(^|\s)-p[,\s]").expect("Invalid -p regex");
+        let capabilities = Self {
+            version: None,
+            print_long: help.contains("--print"),
+            print_short: short_print.is_match(help),
+            stream_json: help.contains("stream-json"),
+            include_partial_messages: help.contains("--include-partial-messages"),
+            permission_mode: help.contains("--permission-mode"),
+            skip_permissions: help.contains("--dangerously-skip-permissions"),
+        };
+        (help.contains("Usage") || capabilities.print_long || capabilities.print_short)
+            .then_some(capabilities)
+    }
+
+    /// Run `path --help` and `path --version`. Returns `None` if the help
+    /// output can't be interpreted.
+    pub fn probe(path: &str) -> Result<Option<Self>> {
+        let help = run(path, "--help").map_err(|e| {
+            RalphError::IncompatibleAgent(format!("failed to run `{path} --help`: {e}"))
+        })?;
+        let Some(mut capabilities) = Self::parse_help(&help) else {
+            return Ok(None);
+        };
+        capabilities.version = run(path, "--version")
+            .ok()
+            .and_then(|out| out.lines().next().map(|line| line.trim().to_string()))
+            .filter(|line| !line.is_empty());
+        Ok(Some(capabilities))
+    }
+
+    fn describe(&self) -> String {
+        match self.version {
+            Some(ref version) => format!("the installed claude CLI ({version})"),
+            None => "the installed claude CLI".to_string(),
+        }
+    }
+
+    /// Adapt `args` to this CLI, or explain why it can't be used
+    pub fn adapt_args(&self, args: &[String]) -> Result<Vec<String>> {
+        let unsupported = |flag: &str| {
+            RalphError::IncompatibleAgent(format!(
+                "{} does not support {flag}, which ralph-loop needs; \
+                 upgrade it with `npm install -g @anthropic-ai/claude-code`",
+                self.describe()
+            ))
+        };
+
+        let mut adapted = Vec::with_capacity(args.len());
+        let mut args_iter = args.iter().peekable();
+        while let Some(arg) = args_iter.next() {
+            match arg.as_str() {
+                "--print" if !self.print_long => {
+                    if !self.print_short {
+                        return Err(unsupported("--print"));
+                    }
+                    adapted.push("-p".to_string());
+                }
+                "-p" if !self.print_short => {
+                    if !self.print_long {
+                        return Err(unsupported("-p"));
+                    }
+                    adapted.push("--print".to_string());
+                }
+                "--output-format" if args_iter.peek().is_some_and(|f| *f == "stream-json") => {
+                    if !self.stream_json {
+                        return Err(unsupported("--output-format stream-json"));
+                    }
+                    adapted.push(arg.clone());
+                }
+                "--include-partial-messages" if !self.include_partial_messages => {
+                    warn!(
+                        "{} does not support --include-partial-messages, dropping it",
+                        self.describe()
+                    );
+                }
+                "--permission-mode" if !self.permission_mode => {
+                    return Err(unsupported("--permission-mode"));
+                }
+                "--dangerously-skip-permissions" if !self.skip_permissions => {
+                    return Err(unsupported("--dangerously-skip-permissions"));
+                }
+                _ => adapted.push(arg.clone()),
+            }
+        }
+        Ok(adapted)
+    }
+}
+
+fn run(path: &str, flag: &str) -> std::io::Result<String> {
+    let output = Command::new(path).arg(flag).stdin(Stdio::null()).output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Probe the configured Claude CLI and adapt the agent arguments to it.
+/// Does nothing for other providers or when probing is disabled.
+pub fn adapt_config(config: &mut Config) -> Result<()> {
+    if config.agent_provider() != AgentProvider::Claude || !config.agent.probe {
+        return Ok(());
+    }
+    let path = config.agent_path();
+    let Some(capabilities) = ClaudeCapabilities::probe(&path)? else {
+        warn!(
+            "Could not interpret `{} --help`, using the agent arguments unchanged",
+            path
+        );
+        return Ok(());
+    };
+    debug!("Claude CLI capabilities: {:?}", capabilities);
+
+    let args = config.agent_args();
+    let adapted = capabilities.adapt_args(&args)?;
+    if adapted != args {
+        info!("Adapted agent arguments to {}", capabilities.describe());
+        config.agent.args = Some(adapted);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELP: &str = "Usage: claude [options] [command] [prompt]\n\
+        Options:\n\
+        \x20 -p, --print                  Print response and exit\n\
+        \x20 --output-format <format>     \"text\", \"json\" or \"stream-json\"\n\
+        \x20 --verbose                    Override verbose mode\n\
+        \x20 --permission-mode <mode>     Permission mode\n\
+        \x20 --dangerously-skip-permissions  Bypass all permission checks\n";
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_help_output() {
+        let capabilities = ClaudeCapabilities::parse_help(HELP).unwrap();
+
+        assert!(capabilities.print_long && capabilities.print_short);
+        assert!(capabilities.stream_json);
+        assert!(!capabilities.include_partial_messages);
+        assert!(ClaudeCapabilities::parse_help("{\"type\":\"system\"}").is_none());
+    }
+
+    #[test]
+    fn adapts_print_flag_and_drops_optional_flags() {
+        let capabilities = ClaudeCapabilities {
+            print_short: true,
+            stream_json: true,
+            ..ClaudeCapabilities::default()
+        };
+
+        assert_eq!(
+            capabilities
+                .adapt_args(&args(&[
+                    "--print",
+                    "--output-format",
+                    "stream-json",
+                    "--include-partial-messages",
+                ]))
+                .unwrap(),
+            args(&["-p", "--output-format", "stream-json"])
+        );
+    }
+
+    #[test]
+    fn missing_stream_json_fails_with_version() {
+        let capabilities = ClaudeCapabilities {
+            version: Some("0.2.9 (Claude Code)".to_string()),
+            print_long: true,
+            ..ClaudeCapabilities::default()
+        };
+
+        let error = capabilities
+            .adapt_args(&args(&["--print", "--output-format", "stream-json"]))
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("0.2.9 (Claude Code)"), "{error}");
+        assert!(error.contains("stream-json"), "{error}");
+    }
+}
@@ -0,0 +1,117 @@
+//! Checkpoint of the loop state for `ralph-loop resume`.
+//!
+//! After every iteration the controller writes `runs/<id>/checkpoint.json`
+//! with the state the run metadata doesn't cover: the iteration budget
+//! including extensions granted by the agent, and the prompt. A run stopped
+//! by Ctrl+C, a crash or a reboot can then continue in the same run
+//! directory. The prompt is only stored if redaction leaves it unchanged;
+//! otherwise it has to be passed to `resume` again.
+
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{RalphError, Result};
+use crate::redact::{redact_opt, Redactor};
+
+/// Name of the checkpoint file inside each run directory
+pub const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+/// Loop state after the last completed iteration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// When the checkpoint was written
+    pub saved_at: DateTime<Utc>,
+    /// Last completed iteration
+    pub iteration: u32,
+    /// Iteration budget, including extensions requested by the agent
+    pub max_iterations: Option<u32>,
+    /// The prompt, unless it contains something redaction would hide
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+}
+
+impl Checkpoint {
+    /// Checkpoint after `iteration`, keeping the prompt only if it is safe to store
+    pub fn new(
+        iteration: u32,
+        max_iterations: Option<u32>,
+        prompt: &str,
+        redactor: Option<&Redactor>,
+    ) -> Self {
+        let redacted = redact_opt(redactor, prompt);
+        Self {
+            saved_at: Utc::now(),
+            iteration,
+            max_iterations,
+            prompt: (redacted == prompt).then(|| prompt.to_string()),
+        }
+    }
+}
+
+/// Write `checkpoint` to `run_dir`, replacing the previous one atomically
+pub fn save(run_dir: &Path, checkpoint: &Checkpoint) -> Result<()> {
+    let json = serde_json::to_string_pretty(checkpoint)
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+    let tmp = run_dir.join(format!("{CHECKPOINT_FILE}.tmp"));
+    std::fs::write(&tmp, json).map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+    std::fs::rename(&tmp, run_dir.join(CHECKPOINT_FILE))
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))
+}
+
+/// Read the checkpoint of the run in `run_dir`, if one was written
+pub fn load(run_dir: &Path) -> Result<Option<Checkpoint>> {
+    let path = run_dir.join(CHECKPOINT_FILE);
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(RalphError::ResumeError(format!("{}: {e}", path.display()))),
+    };
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|e| RalphError::ResumeError(format!("{}: {e}", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RedactionConfig;
+    use tempfile::TempDir;
+
+    #[test]
+    fn round_trips_through_run_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(load(temp_dir.path()).unwrap(), None);
+
+        let checkpoint = Checkpoint::new(3, Some(12), "Fix the tests", None);
+        save(temp_dir.path(), &checkpoint).unwrap();
+
+        assert_eq!(load(temp_dir.path()).unwrap(), Some(checkpoint));
+        assert!(!temp_dir
+            .path()
+            .join(format!("{CHECKPOINT_FILE}.tmp"))
+            .exists());
+    }
+
+    #[test]
+    fn omits_prompt_with_secrets() {
+        let redactor = Redactor::from_config(&RedactionConfig {
+            enabled: true,
+            ..RedactionConfig::default()
+        })
+        .unwrap()
+        .unwrap();
+
+        let safe = Checkpoint::new(1, None, "Fix the tests", Some(&redactor));
+        let secret = Checkpoint::new(
+            1,
+            None,
+            "Use sk-ant-REDACTED to call the API",
+            Some(&redactor),
+        );
+
+        assert_eq!(safe.prompt.as_deref(), Some("Fix the tests"));
+        assert_eq!(secret.prompt, None);
+    }
+}
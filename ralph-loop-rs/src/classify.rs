@@ -0,0 +1,233 @@
+//! Iteration classification by a user-provided shell command.
+//!
+//! With `classify_command` set, the agent's output of each iteration is
+//! written to `runs/<id>/output/iteration_NNN.jsonl` and the command is run
+//! through `sh -c` with that path as `$1`. How the iteration ended is passed
+//! in environment variables. The last line the command prints is its
+//! verdict: `continue`, `done`, `abort` or `retry`. A command that fails or
+//! prints anything else leaves the decision to the built-in checks.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use tokio::process::Command;
+use tracing::warn;
+
+use crate::agent::ExitReason;
+use crate::audit::SubprocessRecord;
+use crate::error::{RalphError, Result};
+
+/// Name of the directory holding the agent output of each iteration
+pub const OUTPUT_DIR: &str = "output";
+
+/// How long the command may take to reach a verdict
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What the loop should do after an iteration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Start the next iteration, even if the promise was found
+    Continue,
+    /// The task is complete, even if the promise wasn't found
+    Done,
+    /// Stop the run as failed
+    Abort,
+    /// Run another iteration that doesn't count toward `max_iterations`
+    Retry,
+}
+
+impl FromStr for Verdict {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "continue" => Ok(Self::Continue),
+            "done" => Ok(Self::Done),
+            "abort" => Ok(Self::Abort),
+            "retry" => Ok(Self::Retry),
+            other => Err(format!(
+                "unknown verdict \"{other}\", expected continue, done, abort or retry"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Continue => "continue",
+            Self::Done => "done",
+            Self::Abort => "abort",
+            Self::Retry => "retry",
+        })
+    }
+}
+
+/// How an iteration ended, as passed to the command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IterationOutcome {
+    pub iteration: u32,
+    pub exit_reason: ExitReason,
+    /// Exit code of the agent process, if it exited on its own
+    pub exit_code: Option<i32>,
+    pub promise_found: bool,
+}
+
+impl IterationOutcome {
+    fn env(&self, run_dir: &Path, output: &Path) -> Vec<(&'static str, String)> {
+        let exit_reason = match self.exit_reason {
+            ExitReason::Natural => "natural",
+            ExitReason::ContextLimit => "context_limit",
+            ExitReason::PromiseFound => "promise_found",
+            ExitReason::RestartRequested => "restart_requested",
+            ExitReason::Shutdown => "shutdown",
+            ExitReason::Error => "error",
+        };
+        vec![
+            ("RALPH_RUN_DIR", run_dir.to_string_lossy().to_string()),
+            ("RALPH_OUTPUT_FILE", output.to_string_lossy().to_string()),
+            ("RALPH_ITERATION", self.iteration.to_string()),
+            ("RALPH_EXIT_REASON", exit_reason.to_string()),
+            (
+                "RALPH_EXIT_CODE",
+                self.exit_code.map(|c| c.to_string()).unwrap_or_default(),
+            ),
+            ("RALPH_PROMISE_FOUND", self.promise_found.to_string()),
+        ]
+    }
+}
+
+/// Name of the kept agent output of `iteration` inside [`OUTPUT_DIR`]
+pub fn iteration_file(iteration: u32) -> String {
+    format!("iteration_{iteration:03}.jsonl")
+}
+
+/// Write the agent output of `iteration` into `run_dir`
+pub fn write_output(run_dir: &Path, iteration: u32, output: &str) -> Result<PathBuf> {
+    let dir = run_dir.join(OUTPUT_DIR);
+    std::fs::create_dir_all(&dir).map_err(RalphError::OutputDirError)?;
+    let path = dir.join(iteration_file(iteration));
+    std::fs::write(&path, output).map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+    Ok(path)
+}
+
+/// Run `command` for the iteration whose output is in `output`. Returns the
+/// verdict, if the command reached one, and the subprocess for the audit log.
+pub async fn classify(
+    command: &str,
+    run_dir: &Path,
+    output: &Path,
+    outcome: &IterationOutcome,
+) -> (Option<Verdict>, SubprocessRecord) {
+    let args = vec![
+        "-c".to_string(),
+        command.to_string(),
+        "classify".to_string(),
+        output.to_string_lossy().to_string(),
+    ];
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let result = tokio::time::timeout(
+        TIMEOUT,
+        Command::new("sh")
+            .args(&args)
+            .envs(outcome.env(run_dir, output))
+            .stdin(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await;
+    let subprocess = SubprocessRecord {
+        kind: "classify".to_string(),
+        program: "sh".to_string(),
+        args,
+        pid: None,
+        started_at,
+        exit_code: match result {
+            Ok(Ok(ref output)) => output.status.code(),
+            _ => None,
+        },
+        duration_ms: started.elapsed().as_millis() as u64,
+    };
+
+    let verdict = match result {
+        Ok(Ok(output)) if output.status.success() => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let last_line = stdout.lines().rev().find(|line| !line.trim().is_empty());
+            match last_line.unwrap_or_default().parse() {
+                Ok(verdict) => Some(verdict),
+                Err(e) => {
+                    warn!("classify_command: {}", e);
+                    None
+                }
+            }
+        }
+        Ok(Ok(output)) => {
+            warn!(
+                "classify_command exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            None
+        }
+        Ok(Err(e)) => {
+            warn!("Failed to run classify_command: {}", e);
+            None
+        }
+        Err(_) => {
+            warn!("classify_command timed out after {:?}", TIMEOUT);
+            None
+        }
+    };
+    (verdict, subprocess)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn outcome() -> IterationOutcome {
+        IterationOutcome {
+            iteration: 2,
+            exit_reason: ExitReason::Error,
+            exit_code: Some(1),
+            promise_found: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn command_sees_output_and_exit_info() {
+        let temp_dir = TempDir::new().unwrap();
+        let output = write_output(temp_dir.path(), 2, "{\"type\":\"result\"}\n").unwrap();
+        let command = r#"
+            echo "checking $1"
+            grep -q result "$1" && [ "$RALPH_EXIT_REASON" = error ] \
+                && [ "$RALPH_EXIT_CODE" = 1 ] && [ "$RALPH_ITERATION" = 2 ] && echo RETRY
+        "#;
+
+        let (verdict, subprocess) = classify(command, temp_dir.path(), &output, &outcome()).await;
+
+        assert_eq!(
+            output,
+            temp_dir.path().join("output").join("iteration_002.jsonl")
+        );
+        assert_eq!(verdict, Some(Verdict::Retry));
+        assert_eq!(subprocess.kind, "classify");
+        assert_eq!(subprocess.exit_code, Some(0));
+    }
+
+    #[tokio::test]
+    async fn failure_or_unknown_output_gives_no_verdict() {
+        let temp_dir = TempDir::new().unwrap();
+        let output = write_output(temp_dir.path(), 1, "").unwrap();
+
+        for command in ["echo done; exit 1", "echo maybe"] {
+            let (verdict, _) = classify(command, temp_dir.path(), &output, &outcome()).await;
+            assert_eq!(verdict, None, "{command}");
+        }
+    }
+}
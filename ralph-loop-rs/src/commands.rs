@@ -0,0 +1,119 @@
+//! Commands the agent sends to ralph with `<ralph:cmd>...</ralph:cmd>` sentinels.
+//!
+//! The sentinel body is a command name followed by its argument, e.g.
+//! `<ralph:cmd>extend-iterations 5</ralph:cmd>`. Each command can be switched
+//! off in `[agent_commands]`; disabled and unknown commands are ignored.
+
+use regex::Regex;
+use tracing::{info, warn};
+
+use crate::config::AgentCommandsConfig;
+
+/// A command requested by the agent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgentCommand {
+    /// End the iteration now and continue with fresh context
+    Restart,
+    /// Raise the max iteration budget by the given amount
+    ExtendIterations(u32),
+    /// Record a note in the iteration metadata
+    Note(String),
+}
+
+impl AgentCommand {
+    /// Parse a sentinel body such as `note tests are flaky`
+    pub fn parse(body: &str) -> Option<Self> {
+        let body = body.trim();
+        let (name, arg) = body.split_once(char::is_whitespace).unwrap_or((body, ""));
+        let arg = arg.trim();
+        match name {
+            "restart" if arg.is_empty() => Some(Self::Restart),
+            "extend-iterations" => arg.parse().ok().map(Self::ExtendIterations),
+            "note" if !arg.is_empty() => Some(Self::Note(arg.to_string())),
+            _ => None,
+        }
+    }
+
+    /// Whether the configuration allows this command
+    pub fn enabled(&self, config: &AgentCommandsConfig) -> bool {
+        match self {
+            Self::Restart => config.restart,
+            Self::ExtendIterations(_) => config.extend_iterations,
+            Self::Note(_) => config.note,
+        }
+    }
+}
+
+/// Finds command sentinels in assistant text and filters them by configuration
+#[derive(Debug, Clone)]
+pub struct CommandDispatcher {
+    config: AgentCommandsConfig,
+    sentinel_regex: Regex,
+}
+
+impl CommandDispatcher {
+    /// Create a dispatcher honouring the per-command toggles in `config`
+    pub fn new(config: AgentCommandsConfig) -> Self {
+        Self {
+            config,
+            sentinel_regex: Regex::new(r"(?s)<ralph:cmd>(.*?)</ralph:cmd>")
+                .expect("Invalid command sentinel regex"),
+        }
+    }
+
+    /// The enabled commands in `text`, in order of appearance
+    pub fn dispatch(&self, text: &str) -> Vec<AgentCommand> {
+        self.sentinel_regex
+            .captures_iter(text)
+            .filter_map(|caps| {
+                let body = &caps[1];
+                let Some(command) = AgentCommand::parse(body) else {
+                    warn!("Ignoring unknown agent command: {}", body.trim());
+                    return None;
+                };
+                if !command.enabled(&self.config) {
+                    warn!("Ignoring disabled agent command: {:?}", command);
+                    return None;
+                }
+                info!("Agent command: {:?}", command);
+                Some(command)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_known_commands() {
+        assert_eq!(AgentCommand::parse("restart"), Some(AgentCommand::Restart));
+        assert_eq!(
+            AgentCommand::parse(" extend-iterations 5 "),
+            Some(AgentCommand::ExtendIterations(5))
+        );
+        assert_eq!(
+            AgentCommand::parse("note tests are flaky"),
+            Some(AgentCommand::Note("tests are flaky".to_string()))
+        );
+        assert_eq!(AgentCommand::parse("extend-iterations many"), None);
+        assert_eq!(AgentCommand::parse("note"), None);
+        assert_eq!(AgentCommand::parse("rm -rf /"), None);
+    }
+
+    #[test]
+    fn dispatch_drops_disabled_commands() {
+        let dispatcher = CommandDispatcher::new(AgentCommandsConfig::default());
+        let text = "done for now <ralph:cmd>note split the parser</ralph:cmd>\n\
+                    <ralph:cmd>extend-iterations 3</ralph:cmd><ralph:cmd>restart</ralph:cmd>";
+
+        assert_eq!(
+            dispatcher.dispatch(text),
+            vec![
+                AgentCommand::Note("split the parser".to_string()),
+                AgentCommand::Restart,
+            ]
+        );
+    }
+}
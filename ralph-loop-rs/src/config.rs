@@ -1,6 +1,29 @@
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::disk_space::LowDiskSpaceAction;
+use crate::quiet_hours::QuietHours;
+
+/// Name of the per-project config file discovered upward from the CWD
+pub const PROJECT_CONFIG_FILE: &str = ".ralph.toml";
+
+/// Find the nearest ancestor of `start` (inclusive) containing `.ralph.toml`
+pub fn find_project_config(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|candidate| candidate.is_file())
+}
+
+/// The project root for `cwd`: the directory holding the discovered
+/// `.ralph.toml`, or `cwd` itself when there is none
+pub fn project_root(cwd: &Path) -> PathBuf {
+    find_project_config(cwd)
+        .and_then(|config| config.parent().map(Path::to_path_buf))
+        .unwrap_or_else(|| cwd.to_path_buf())
+}
 
 /// Token estimation method for context tracking
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
@@ -9,40 +32,161 @@ pub enum TokenEstimationMethod {
     /// Use tiktoken with cl100k_base encoding (most accurate)
     #[default]
     Tiktoken,
+    /// Approximate Claude's tokenizer by scaling the cl100k_base count
+    Claude,
     /// Estimate as text.len() / 4
     ByteRatio,
     /// Estimate as text.chars().count() / 4
     CharRatio,
 }
 
+/// Which tokens of reported usage count toward the context limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenAccounting {
+    /// Uncached input and output tokens, as billed at the full rate
+    #[default]
+    Billed,
+    /// Everything occupying the context: input and output, plus cache reads
+    /// and writes
+    Context,
+}
+
 /// Context limit configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ContextLimitConfig {
     /// Maximum tokens before killing process
     #[serde(default = "default_max_tokens")]
     pub max_tokens: usize,
-    /// Token count at which to emit a warning
-    #[serde(default = "default_warning_threshold")]
-    pub warning_threshold: usize,
+    /// Token count at which to emit a warning, absolute or as a share of
+    /// `max_tokens` (None = 150000, or 90% of `max_tokens` if that is lower)
+    #[serde(default)]
+    pub warning_threshold: Option<WarningThreshold>,
     /// Method for estimating token count
     #[serde(default)]
     pub estimation_method: TokenEstimationMethod,
+    /// Restart after the current tool call when the next turn is projected to exceed `max_tokens`
+    #[serde(default = "default_true")]
+    pub adaptive_restart: bool,
+    /// `max_tokens` of the first iteration of a run, which often reads much of
+    /// the repository and needs more headroom than the ones after it
+    #[serde(default)]
+    pub first_iteration_max_tokens: Option<usize>,
+    /// Which tokens of the reported usage are checked against the limit
+    #[serde(default)]
+    pub accounting: TokenAccounting,
+    /// Model whose context window `max_tokens` is derived from, if not the
+    /// one the agent is configured with
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Context windows by model name prefix, added to or overriding the
+    /// built-in ones
+    #[serde(default)]
+    pub windows: BTreeMap<String, usize>,
 }
 
 fn default_max_tokens() -> usize {
     180_000
 }
 
-fn default_warning_threshold() -> usize {
-    150_000
+const DEFAULT_WARNING_TOKENS: usize = 150_000;
+
+/// Token count at which the context limit warning is emitted: a number of
+/// tokens, or a percentage of `max_tokens` such as `"85%"`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawWarningThreshold", into = "RawWarningThreshold")]
+pub enum WarningThreshold {
+    Tokens(usize),
+    Percent(f64),
+}
+
+impl WarningThreshold {
+    /// The threshold in tokens for a limit of `max_tokens`
+    pub fn tokens(&self, max_tokens: usize) -> usize {
+        match *self {
+            WarningThreshold::Tokens(tokens) => tokens,
+            WarningThreshold::Percent(percent) => (max_tokens as f64 * percent / 100.0) as usize,
+        }
+    }
+}
+
+/// `warning_threshold` as written in the config file
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawWarningThreshold {
+    Tokens(usize),
+    Text(String),
+}
+
+impl TryFrom<RawWarningThreshold> for WarningThreshold {
+    type Error = String;
+
+    fn try_from(raw: RawWarningThreshold) -> Result<Self, Self::Error> {
+        let text = match raw {
+            RawWarningThreshold::Tokens(tokens) => return Ok(WarningThreshold::Tokens(tokens)),
+            RawWarningThreshold::Text(text) => text,
+        };
+        let invalid = || {
+            format!("invalid warning_threshold \"{text}\", expected a token count or a percentage like \"85%\"")
+        };
+        let percent: f64 = text
+            .trim()
+            .strip_suffix('%')
+            .ok_or_else(invalid)?
+            .trim()
+            .parse()
+            .map_err(|_| invalid())?;
+        if !(percent > 0.0 && percent < 100.0) {
+            return Err(format!(
+                "warning_threshold \"{text}\" must be more than 0% and less than 100%"
+            ));
+        }
+        Ok(WarningThreshold::Percent(percent))
+    }
+}
+
+impl From<WarningThreshold> for RawWarningThreshold {
+    fn from(threshold: WarningThreshold) -> Self {
+        match threshold {
+            WarningThreshold::Tokens(tokens) => RawWarningThreshold::Tokens(tokens),
+            WarningThreshold::Percent(percent) => RawWarningThreshold::Text(format!("{percent}%")),
+        }
+    }
 }
 
 impl Default for ContextLimitConfig {
     fn default() -> Self {
         Self {
             max_tokens: default_max_tokens(),
-            warning_threshold: default_warning_threshold(),
+            warning_threshold: None,
             estimation_method: TokenEstimationMethod::default(),
+            adaptive_restart: true,
+            first_iteration_max_tokens: None,
+            accounting: TokenAccounting::default(),
+            model: None,
+            windows: BTreeMap::new(),
+        }
+    }
+}
+
+impl ContextLimitConfig {
+    /// Token count at which to warn, resolved against `max_tokens`
+    pub fn warning_tokens(&self) -> usize {
+        match self.warning_threshold {
+            Some(threshold) => threshold.tokens(self.max_tokens),
+            None => DEFAULT_WARNING_TOKENS.min(self.max_tokens / 10 * 9),
+        }
+    }
+
+    /// The limits that apply to the `first` iteration of a run, or to the ones after it
+    pub fn for_iteration(&self, first: bool) -> Self {
+        match self.first_iteration_max_tokens {
+            Some(max_tokens) if first => Self {
+                max_tokens,
+                ..self.clone()
+            },
+            _ => self.clone(),
         }
     }
 }
@@ -56,10 +200,38 @@ pub enum AgentProvider {
     Claude,
     /// OpenAI Codex CLI
     Codex,
+    /// OpenAI-compatible chat completions API (`[api]`)
+    Openai,
+    /// Anthropic Messages API (`[anthropic]`, requires the `anthropic-api` feature)
+    Anthropic,
+    /// Local Ollama server (`[ollama]`)
+    Ollama,
+}
+
+impl AgentProvider {
+    /// Whether the provider is an HTTP API rather than a CLI
+    pub fn is_api(self) -> bool {
+        matches!(self, Self::Openai | Self::Anthropic | Self::Ollama)
+    }
+}
+
+/// How much the agent may do without asking for permission
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "camelCase")]
+pub enum PermissionMode {
+    /// Skip all permission checks (the agent may run any command)
+    #[default]
+    Skip,
+    /// Auto-approve file edits, keep prompting for everything else
+    #[value(alias = "acceptEdits")]
+    AcceptEdits,
+    /// The agent's own default permission handling
+    Default,
 }
 
 /// Agent execution configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AgentConfig {
     /// Which coding agent backend to invoke
     #[serde(default)]
@@ -70,6 +242,18 @@ pub struct AgentConfig {
     /// Additional arguments to pass to the agent CLI
     #[serde(default)]
     pub args: Option<Vec<String>>,
+    /// Probe the Claude CLI's flags before a run and adapt the arguments to it
+    #[serde(default = "default_true")]
+    pub probe: bool,
+    /// Bytes of the agent's stderr kept per iteration (0 disables capturing)
+    #[serde(default = "default_stderr_max_bytes")]
+    pub stderr_max_bytes: usize,
+    /// Scheduling priority and resource caps of the agent subprocess
+    #[serde(default)]
+    pub resources: ResourceLimitsConfig,
+    /// How the agent subprocess is stopped when ralph-loop ends an iteration
+    #[serde(default)]
+    pub stop: StopConfig,
 }
 
 impl Default for AgentConfig {
@@ -78,10 +262,604 @@ impl Default for AgentConfig {
             provider: AgentProvider::Claude,
             path: None,
             args: None,
+            probe: true,
+            stderr_max_bytes: default_stderr_max_bytes(),
+            resources: ResourceLimitsConfig::default(),
+            stop: StopConfig::default(),
+        }
+    }
+}
+
+/// Limits applied to the agent subprocess so it can't starve the host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceLimitsConfig {
+    /// Niceness the agent runs with, from -20 (highest priority) to 19
+    #[serde(default)]
+    pub nice: Option<i32>,
+    /// Memory cap in MiB
+    #[serde(default)]
+    pub max_memory_mb: Option<u64>,
+    /// CPU cap in percent of one core, e.g. 200 for two cores (cgroup only)
+    #[serde(default)]
+    pub max_cpu_percent: Option<u32>,
+    /// Enforce the caps with a transient systemd scope (cgroup) instead of
+    /// a ulimit on the agent's address space
+    #[serde(default = "default_true")]
+    pub cgroup: bool,
+}
+
+impl Default for ResourceLimitsConfig {
+    fn default() -> Self {
+        Self {
+            nice: None,
+            max_memory_mb: None,
+            max_cpu_percent: None,
+            cgroup: true,
+        }
+    }
+}
+
+/// Grace periods of stopping the agent: SIGINT first so it can write its
+/// result event, then SIGTERM, then SIGKILL
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StopConfig {
+    /// Seconds to wait for the agent to exit after SIGINT (0 skips SIGINT)
+    #[serde(default = "default_stop_grace_secs")]
+    pub interrupt_grace_secs: u64,
+    /// Seconds to wait for the agent to exit after SIGTERM (0 skips SIGTERM)
+    #[serde(default = "default_stop_grace_secs")]
+    pub terminate_grace_secs: u64,
+}
+
+impl Default for StopConfig {
+    fn default() -> Self {
+        Self {
+            interrupt_grace_secs: default_stop_grace_secs(),
+            terminate_grace_secs: default_stop_grace_secs(),
+        }
+    }
+}
+
+fn default_stop_grace_secs() -> u64 {
+    5
+}
+
+/// Desktop notification settings, with a toggle per run outcome
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DesktopNotificationConfig {
+    /// Whether desktop notifications are sent at all
+    #[serde(default)]
+    pub enabled: bool,
+    /// Notify when the completion promise is found
+    #[serde(default = "default_true")]
+    pub on_promise: bool,
+    /// Notify when max iterations are exhausted without the promise
+    #[serde(default = "default_true")]
+    pub on_max_iterations: bool,
+    /// Notify when an error aborts the run
+    #[serde(default = "default_true")]
+    pub on_error: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for DesktopNotificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            on_promise: true,
+            on_max_iterations: true,
+            on_error: true,
+        }
+    }
+}
+
+/// Notification configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationsConfig {
+    /// Desktop notifications (`[notifications.desktop]`)
+    #[serde(default)]
+    pub desktop: DesktopNotificationConfig,
+}
+
+/// End-of-run banner and terminal alert settings
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BannerConfig {
+    /// Template for the success banner.
+    /// Placeholders: `{promise}`, `{iterations}`, `{duration}`, `{cost}`
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Ring the terminal bell when the run ends
+    #[serde(default)]
+    pub bell: bool,
+    /// Emit an OSC 9 terminal notification when the run ends
+    #[serde(default)]
+    pub osc9: bool,
+}
+
+/// Redaction of secrets in stored output (`[redaction]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactionConfig {
+    /// Whether output is redacted before it is stored
+    #[serde(default)]
+    pub enabled: bool,
+    /// Apply the built-in patterns (API keys, tokens, emails)
+    #[serde(default = "default_true")]
+    pub builtin_patterns: bool,
+    /// Additional regex patterns to redact
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            builtin_patterns: true,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+/// Where single-shot job mode publishes run artifacts (`[job]`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JobConfig {
+    /// Directory (e.g. a mounted volume) the run directory is copied to
+    #[serde(default)]
+    pub artifacts_dir: Option<PathBuf>,
+    /// S3 URL (`s3://bucket/prefix`) the run directory is uploaded to
+    #[serde(default)]
+    pub s3_url: Option<String>,
+    /// Endpoint of an S3-compatible service such as MinIO
+    #[serde(default)]
+    pub s3_endpoint_url: Option<String>,
+}
+
+/// Markdown context in which a promise is ignored (`promise_ignore_contexts`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromiseContext {
+    /// A block fenced with ``` or ~~~
+    CodeFence,
+    /// A `backtick` code span
+    InlineCode,
+    /// A line starting with `>`
+    BlockQuote,
+}
+
+/// Who writes the run summary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SummaryGenerator {
+    /// Render `[summary] template`
+    #[default]
+    Template,
+    /// Ask the configured agent, falling back to the template
+    Agent,
+}
+
+/// Summary generated when a run succeeds (`[summary]`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SummaryConfig {
+    /// Whether a summary is generated
+    #[serde(default)]
+    pub enabled: bool,
+    /// Who writes the summary
+    #[serde(default)]
+    pub generator: SummaryGenerator,
+    /// Template for the summary.
+    /// Placeholders: `{task}`, `{promise}`, `{iterations}`, `{notes}`
+    #[serde(default)]
+    pub template: Option<String>,
+}
+
+/// Completion probe asked after each iteration (`[probe]`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProbeConfig {
+    /// Whether the probe is asked
+    #[serde(default)]
+    pub enabled: bool,
+    /// Model the probe asks; `haiku` for Claude, the agent's default for Codex
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Per-iteration scratch directories (`[scratch]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScratchConfig {
+    /// Give each iteration a scratch directory
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Number of most recent scratch directories kept (0 keeps all)
+    #[serde(default = "default_scratch_keep")]
+    pub keep: usize,
+}
+
+impl Default for ScratchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            keep: default_scratch_keep(),
+        }
+    }
+}
+
+fn default_scratch_keep() -> usize {
+    3
+}
+
+/// Free disk space required before each iteration (`[disk_space]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiskSpaceConfig {
+    /// MiB that must be free in the output and project directories (0 disables the check)
+    #[serde(default = "default_min_free_mb")]
+    pub min_free_mb: u64,
+    /// Abort the run or wait for space to be freed
+    #[serde(default)]
+    pub action: LowDiskSpaceAction,
+}
+
+impl Default for DiskSpaceConfig {
+    fn default() -> Self {
+        Self {
+            min_free_mb: default_min_free_mb(),
+            action: LowDiskSpaceAction::default(),
+        }
+    }
+}
+
+fn default_prompt_preview_chars() -> usize {
+    crate::text::DEFAULT_PROMPT_PREVIEW_CHARS
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_min_free_mb() -> u64 {
+    256
+}
+
+/// Waiting out network outages (`[network]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Wait for the network instead of counting iterations that failed without it
+    #[serde(default = "default_true")]
+    pub wait_when_offline: bool,
+    /// URL probed for connectivity (default: the agent's API)
+    #[serde(default)]
+    pub probe_url: Option<String>,
+    /// Seconds between probes while offline
+    #[serde(default = "default_probe_interval_secs")]
+    pub probe_interval_secs: u64,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            wait_when_offline: true,
+            probe_url: None,
+            probe_interval_secs: default_probe_interval_secs(),
+        }
+    }
+}
+
+fn default_probe_interval_secs() -> u64 {
+    30
+}
+
+/// Git history handling at the end of a run (`[git]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GitConfig {
+    /// Squash the commits made during a successful run into one
+    #[serde(default)]
+    pub squash_on_success: bool,
+    /// Prefix of the branch keeping the original commits
+    #[serde(default = "default_git_branch_prefix")]
+    pub branch_prefix: String,
+}
+
+impl Default for GitConfig {
+    fn default() -> Self {
+        Self {
+            squash_on_success: false,
+            branch_prefix: default_git_branch_prefix(),
+        }
+    }
+}
+
+fn default_git_branch_prefix() -> String {
+    "ralph/".to_string()
+}
+
+/// Environment snapshot recorded at run start (`[environment]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvironmentConfig {
+    /// Whether the snapshot is captured
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Version probes by name, e.g. `rustc = "rustc --version"`
+    #[serde(default = "default_environment_probes")]
+    pub probes: BTreeMap<String, String>,
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            probes: default_environment_probes(),
+        }
+    }
+}
+
+fn default_environment_probes() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("rustc".to_string(), "rustc --version".to_string()),
+        ("node".to_string(), "node --version".to_string()),
+    ])
+}
+
+/// Central endpoint finished runs are reported to (`[reporter]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReporterConfig {
+    /// URL the run report is POSTed to; reporting is off when unset
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Environment variable holding the bearer token sent with each report
+    #[serde(default = "default_reporter_token_env")]
+    pub token_env: String,
+    /// Retries after a failed POST before the report is queued
+    #[serde(default = "default_reporter_retries")]
+    pub retries: u32,
+    /// File unsent reports are queued in (default: `<output-dir>/reporter-queue.jsonl`)
+    #[serde(default)]
+    pub queue_file: Option<PathBuf>,
+}
+
+impl Default for ReporterConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            token_env: default_reporter_token_env(),
+            retries: default_reporter_retries(),
+            queue_file: None,
+        }
+    }
+}
+
+fn default_reporter_token_env() -> String {
+    "RALPH_REPORTER_TOKEN".to_string()
+}
+
+fn default_reporter_retries() -> u32 {
+    3
+}
+
+/// OpenAI-compatible chat completions endpoint used by the `openai` provider (`[api]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiConfig {
+    /// Base URL of the API; `/chat/completions` is appended
+    #[serde(default = "default_api_base_url")]
+    pub base_url: String,
+    /// Model to request
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Environment variable holding the API key
+    #[serde(default = "default_api_key_env")]
+    pub key_env: String,
+    /// Maximum number of tokens to generate per iteration
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    /// System message sent before the prompt
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            base_url: default_api_base_url(),
+            model: None,
+            key_env: default_api_key_env(),
+            max_tokens: None,
+            system_prompt: None,
+        }
+    }
+}
+
+fn default_api_base_url() -> String {
+    "https://api.openai.com/v1".to_string()
+}
+
+fn default_api_key_env() -> String {
+    "OPENAI_API_KEY".to_string()
+}
+
+/// Anthropic Messages API used by the `anthropic` provider (`[anthropic]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AnthropicConfig {
+    /// Base URL of the API; `/messages` is appended
+    #[serde(default = "default_anthropic_base_url")]
+    pub base_url: String,
+    /// Model to request
+    #[serde(default = "default_anthropic_model")]
+    pub model: String,
+    /// Environment variable holding the API key
+    #[serde(default = "default_anthropic_key_env")]
+    pub key_env: String,
+    /// Maximum number of tokens to generate per iteration
+    #[serde(default = "default_anthropic_max_tokens")]
+    pub max_tokens: u32,
+    /// System prompt sent with the request
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+}
+
+impl Default for AnthropicConfig {
+    fn default() -> Self {
+        Self {
+            base_url: default_anthropic_base_url(),
+            model: default_anthropic_model(),
+            key_env: default_anthropic_key_env(),
+            max_tokens: default_anthropic_max_tokens(),
+            system_prompt: None,
+        }
+    }
+}
+
+fn default_anthropic_base_url() -> String {
+    "https://api.anthropic.com/v1".to_string()
+}
+
+fn default_anthropic_model() -> String {
+    "claude-sonnet-4-5".to_string()
+}
+
+fn default_anthropic_key_env() -> String {
+    "ANTHROPIC_API_KEY".to_string()
+}
+
+fn default_anthropic_max_tokens() -> u32 {
+    8192
+}
+
+/// Local Ollama server used by the `ollama` provider (`[ollama]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OllamaConfig {
+    /// Base URL of the server; `/api/chat` is appended
+    #[serde(default = "default_ollama_base_url")]
+    pub base_url: String,
+    /// Model to run, which must already be pulled
+    #[serde(default = "default_ollama_model")]
+    pub model: String,
+    /// Context window to request (`num_ctx`), if not the model's default
+    #[serde(default)]
+    pub num_ctx: Option<u32>,
+    /// System message sent before the prompt
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+}
+
+impl Default for OllamaConfig {
+    fn default() -> Self {
+        Self {
+            base_url: default_ollama_base_url(),
+            model: default_ollama_model(),
+            num_ctx: None,
+            system_prompt: None,
         }
     }
 }
 
+fn default_ollama_base_url() -> String {
+    "http://localhost:11434".to_string()
+}
+
+fn default_ollama_model() -> String {
+    "llama3.2".to_string()
+}
+
+/// Local tools offered to the API-based agents (`[tools]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolsConfig {
+    /// Offer tools at all; without them the model can only answer in text
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Requests per iteration before ralph stops answering tool calls
+    #[serde(default = "default_tools_max_turns")]
+    pub max_turns: u32,
+    /// Seconds a `bash` call may run
+    #[serde(default = "default_tools_bash_timeout_secs")]
+    pub bash_timeout_secs: u64,
+    /// Tool output handed back to the model is cut at this many bytes
+    #[serde(default = "default_tools_max_output_bytes")]
+    pub max_output_bytes: usize,
+}
+
+impl Default for ToolsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_turns: default_tools_max_turns(),
+            bash_timeout_secs: default_tools_bash_timeout_secs(),
+            max_output_bytes: default_tools_max_output_bytes(),
+        }
+    }
+}
+
+fn default_tools_max_turns() -> u32 {
+    50
+}
+
+fn default_tools_bash_timeout_secs() -> u64 {
+    120
+}
+
+fn default_tools_max_output_bytes() -> usize {
+    30_000
+}
+
+/// Commands the agent may send with `<ralph:cmd>...</ralph:cmd>` (`[agent_commands]`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentCommandsConfig {
+    /// Allow `restart`: end the iteration now and continue with fresh context
+    #[serde(default = "default_true")]
+    pub restart: bool,
+    /// Allow `extend-iterations N`: raise the max iteration budget by N
+    #[serde(default)]
+    pub extend_iterations: bool,
+    /// Allow `note TEXT`: record a note in the iteration metadata
+    #[serde(default = "default_true")]
+    pub note: bool,
+}
+
+impl Default for AgentCommandsConfig {
+    fn default() -> Self {
+        Self {
+            restart: true,
+            extend_iterations: false,
+            note: true,
+        }
+    }
+}
+
+/// Tool events left out of the stored agent output (`[event_filter]`).
+///
+/// Tools are Claude tool names such as `Read`, or Codex item types such as
+/// `command_execution`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventFilterConfig {
+    /// Tools whose calls and results are left out entirely
+    #[serde(default)]
+    pub mute_tools: Vec<String>,
+    /// Tools of which only every Nth call and its result are kept
+    #[serde(default)]
+    pub sample_tools: BTreeMap<String, u32>,
+}
+
 /// CLI-provided config overrides
 #[derive(Debug, Clone, Default)]
 pub struct CliOverrides {
@@ -93,10 +871,45 @@ pub struct CliOverrides {
     pub agent_provider: Option<AgentProvider>,
     pub agent_path: Option<String>,
     pub agent_args: Option<Vec<String>>,
+    pub global_store: Option<bool>,
+    pub permissions: Option<PermissionMode>,
+    pub plan_file: Option<PathBuf>,
+    pub inject_promise_instructions: Option<bool>,
+    pub log_thinking: Option<bool>,
+    pub tags: Option<Vec<String>>,
+}
+
+impl CliOverrides {
+    /// Dotted config keys of the overrides that are set
+    fn keys(&self) -> Vec<&'static str> {
+        [
+            ("prompt", self.prompt.is_some()),
+            ("max_iterations", self.max_iterations.is_some()),
+            ("completion_promise", self.completion_promise.is_some()),
+            ("output_dir", self.output_dir.is_some()),
+            ("context_limit.max_tokens", self.context_limit.is_some()),
+            ("agent.provider", self.agent_provider.is_some()),
+            ("agent.path", self.agent_path.is_some()),
+            ("agent.args", self.agent_args.is_some()),
+            ("global_store", self.global_store.is_some()),
+            ("permissions", self.permissions.is_some()),
+            ("plan_file", self.plan_file.is_some()),
+            (
+                "inject_promise_instructions",
+                self.inject_promise_instructions.is_some(),
+            ),
+            ("log_thinking", self.log_thinking.is_some()),
+            ("tags", self.tags.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(key, set)| set.then_some(key))
+        .collect()
+    }
 }
 
 /// Main configuration for the ralph-loop application
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     /// The prompt to send to the configured coding agent
     #[serde(default)]
@@ -104,18 +917,160 @@ pub struct Config {
     /// Maximum number of iterations (None = infinite loop)
     #[serde(default)]
     pub max_iterations: Option<u32>,
+    /// Characters of the prompt shown as its preview in run listings
+    #[serde(default = "default_prompt_preview_chars")]
+    pub prompt_preview_chars: usize,
     /// Text to look for to consider the task complete
     #[serde(default = "default_completion_promise")]
     pub completion_promise: String,
     /// Context limit configuration
     #[serde(default)]
     pub context_limit: ContextLimitConfig,
+    /// Kill the agent as soon as the promise is detected instead of waiting for it to exit
+    #[serde(default = "default_true")]
+    pub stop_on_promise: bool,
+    /// Assistant messages that must contain the promise before it counts, so
+    /// a model quoting it while describing its plan doesn't end the run. The
+    /// promise in the final result text always counts.
+    #[serde(default = "default_promise_confirmations")]
+    pub promise_confirmations: u32,
+    /// Promises the agent gives on the way, in order; the completion promise
+    /// only counts once each of them was given, in this or an earlier iteration
+    #[serde(default)]
+    pub milestones: Vec<String>,
+    /// Markdown contexts in which the promise doesn't count, e.g. an echoed
+    /// prompt explaining the convention in a code block
+    #[serde(default)]
+    pub promise_ignore_contexts: Vec<PromiseContext>,
+    /// Append instructions for giving the promise, with the iterations left,
+    /// to the prompt of every iteration
+    #[serde(default)]
+    pub inject_promise_instructions: bool,
+    /// Template replacing the default promise instructions
+    #[serde(default)]
+    pub promise_instructions: Option<String>,
+    /// Log each assistant message at info level, so the log shows what the agent says
+    #[serde(default)]
+    pub log_assistant_text: bool,
+    /// Characters of each assistant message logged with `log_assistant_text`
+    #[serde(default = "default_log_assistant_text_max_chars")]
+    pub log_assistant_text_max_chars: usize,
+    /// Log the agent's extended reasoning (thinking blocks) at info level,
+    /// dimmed and shortened like assistant messages
+    #[serde(default)]
+    pub log_thinking: bool,
+    /// Markdown checklist read between iterations; the run completes once every item is checked
+    #[serde(default)]
+    pub plan_file: Option<PathBuf>,
     /// Directory for output files
     #[serde(default = "default_output_dir")]
     pub output_dir: PathBuf,
+    /// Store runs in the global run store (`RALPH_HOME`) instead of `output_dir`
+    #[serde(default)]
+    pub global_store: bool,
+    /// Show a status line while the agent runs, when stderr is a terminal
+    #[serde(default = "default_true")]
+    pub progress: bool,
+    /// Seconds between writes of the run's heartbeat file (0 disables it)
+    #[serde(default = "default_heartbeat_interval_secs")]
+    pub heartbeat_interval_secs: u64,
+    /// Seconds to wait between iterations
+    #[serde(default)]
+    pub iteration_delay_secs: u64,
+    /// Up to this many seconds are randomly added to the delay between iterations
+    #[serde(default)]
+    pub iteration_delay_jitter_secs: u64,
+    /// Local time window (`HH:MM-HH:MM`) during which no iteration is started
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHours>,
+    /// Abort the run after this many iterations in a row ended in an error (None = never)
+    #[serde(default)]
+    pub max_consecutive_errors: Option<u32>,
+    /// Shell command deciding after each iteration whether to continue, finish, abort or retry
+    #[serde(default)]
+    pub classify_command: Option<String>,
+    /// Retry verdicts per run that don't count toward `max_iterations`;
+    /// later ones count like `continue`
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Keep the agent output of each iteration in `output/iteration_NNN.jsonl`,
+    /// so `ralph-loop replay` can re-run the loop's decisions on it
+    #[serde(default)]
+    pub keep_output: bool,
+    /// Directories searched for iteration transcripts after the run directory
+    /// and Claude's project directory, e.g. output copied from CI
+    #[serde(default)]
+    pub transcript_search_path: Vec<PathBuf>,
+    /// Tags recorded with each run, e.g. the task category, for grouping
+    /// runs in `ralph-loop stats`
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Abort the run once more than this fraction of the agent's output lines couldn't be parsed (None = never)
+    #[serde(default)]
+    pub max_unparsed_ratio: Option<f64>,
     /// Coding agent execution settings
     #[serde(default)]
     pub agent: AgentConfig,
+    /// Chat completions endpoint for the `openai` provider
+    #[serde(default)]
+    pub api: ApiConfig,
+    /// Messages API settings for the `anthropic` provider
+    #[serde(default)]
+    pub anthropic: AnthropicConfig,
+    /// Local server settings for the `ollama` provider
+    #[serde(default)]
+    pub ollama: OllamaConfig,
+    /// Local tools executed for the API-based agents
+    #[serde(default)]
+    pub tools: ToolsConfig,
+    /// Permission profile mapped to the agent's permission flags
+    #[serde(default)]
+    pub permissions: PermissionMode,
+    /// Whether running with permission checks disabled was confirmed for this project
+    #[serde(default)]
+    pub skip_permissions_consent: bool,
+    /// Notifications sent when a run ends
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    /// Exit banner and terminal alerts
+    #[serde(default)]
+    pub banner: BannerConfig,
+    /// Redaction of secrets in stored output
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+    /// Commands the agent may send to ralph
+    #[serde(default)]
+    pub agent_commands: AgentCommandsConfig,
+    /// Tool events left out of the stored agent output
+    #[serde(default)]
+    pub event_filter: EventFilterConfig,
+    /// Artifact publishing in job mode
+    #[serde(default)]
+    pub job: JobConfig,
+    /// Summary stored in the run metadata when a run succeeds
+    #[serde(default)]
+    pub summary: SummaryConfig,
+    /// Second opinion on whether the task is complete, from a small model
+    #[serde(default)]
+    pub probe: ProbeConfig,
+    /// Scratch directories for the agent's temporary files
+    #[serde(default)]
+    pub scratch: ScratchConfig,
+    /// Free disk space required before each iteration
+    #[serde(default)]
+    pub disk_space: DiskSpaceConfig,
+    /// Waiting for the network when iterations fail for lack of it
+    #[serde(default)]
+    pub network: NetworkConfig,
+    /// Squashing the commits made during a run
+    #[serde(default)]
+    pub git: GitConfig,
+    /// Environment snapshot recorded in the run metadata
+    #[serde(default)]
+    pub environment: EnvironmentConfig,
+    /// Reporting finished runs to a central server
+    #[serde(default)]
+    pub reporter: ReporterConfig,
     /// Legacy Claude CLI path setting kept for backward compatibility
     #[serde(default)]
     pub claude_path: Option<String>,
@@ -128,10 +1083,26 @@ fn default_completion_promise() -> String {
     "TASK COMPLETE".to_string()
 }
 
+fn default_promise_confirmations() -> u32 {
+    1
+}
+
 fn default_output_dir() -> PathBuf {
     PathBuf::from(".ralph-loop-output")
 }
 
+fn default_heartbeat_interval_secs() -> u64 {
+    5
+}
+
+fn default_log_assistant_text_max_chars() -> usize {
+    500
+}
+
+fn default_stderr_max_bytes() -> usize {
+    64 * 1024
+}
+
 fn default_claude_path() -> String {
     "claude".to_string()
 }
@@ -140,25 +1111,81 @@ fn default_codex_path() -> String {
     "codex".to_string()
 }
 
-fn default_claude_args() -> Vec<String> {
-    vec![
+fn default_claude_args(permissions: PermissionMode) -> Vec<String> {
+    let mut args = vec![
         "--print".to_string(),
         "--verbose".to_string(),
         "--output-format".to_string(),
         "stream-json".to_string(),
-        "--dangerously-skip-permissions".to_string(),
-    ]
+    ];
+    match permissions {
+        PermissionMode::Skip => args.push(CLAUDE_SKIP_PERMISSIONS_FLAG.to_string()),
+        PermissionMode::AcceptEdits => {
+            args.push("--permission-mode".to_string());
+            args.push("acceptEdits".to_string());
+        }
+        PermissionMode::Default => {}
+    }
+    args
 }
 
+/// Flags that disable the agent's permission checks entirely
+const CLAUDE_SKIP_PERMISSIONS_FLAG: &str = "--dangerously-skip-permissions";
+const CODEX_SKIP_PERMISSIONS_FLAG: &str = "--dangerously-bypass-approvals-and-sandbox";
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             prompt: String::new(),
             max_iterations: None,
+            prompt_preview_chars: default_prompt_preview_chars(),
             completion_promise: default_completion_promise(),
             context_limit: ContextLimitConfig::default(),
+            stop_on_promise: true,
+            promise_confirmations: default_promise_confirmations(),
+            milestones: Vec::new(),
+            promise_ignore_contexts: Vec::new(),
+            inject_promise_instructions: false,
+            promise_instructions: None,
+            log_assistant_text: false,
+            log_assistant_text_max_chars: default_log_assistant_text_max_chars(),
+            log_thinking: false,
+            plan_file: None,
             output_dir: default_output_dir(),
+            global_store: false,
+            progress: true,
+            heartbeat_interval_secs: default_heartbeat_interval_secs(),
+            iteration_delay_secs: 0,
+            iteration_delay_jitter_secs: 0,
+            quiet_hours: None,
+            max_consecutive_errors: None,
+            classify_command: None,
+            max_retries: default_max_retries(),
+            keep_output: false,
+            transcript_search_path: Vec::new(),
+            tags: Vec::new(),
+            max_unparsed_ratio: None,
             agent: AgentConfig::default(),
+            api: ApiConfig::default(),
+            anthropic: AnthropicConfig::default(),
+            ollama: OllamaConfig::default(),
+            tools: ToolsConfig::default(),
+            permissions: PermissionMode::default(),
+            skip_permissions_consent: false,
+            notifications: NotificationsConfig::default(),
+            banner: BannerConfig::default(),
+            redaction: RedactionConfig::default(),
+            agent_commands: AgentCommandsConfig::default(),
+            event_filter: EventFilterConfig::default(),
+            job: JobConfig::default(),
+            summary: SummaryConfig::default(),
+            probe: ProbeConfig::default(),
+            scratch: ScratchConfig::default(),
+            disk_space: DiskSpaceConfig::default(),
+            network: NetworkConfig::default(),
+            git: GitConfig::default(),
+            environment: EnvironmentConfig::default(),
+            reporter: ReporterConfig::default(),
             claude_path: None,
             claude_args: None,
         }
@@ -167,15 +1194,84 @@ impl Default for Config {
 
 impl Config {
     /// Load configuration from a TOML file
-    pub fn from_file(path: &std::path::Path) -> crate::error::Result<Self> {
-        let content = std::fs::read_to_string(path)
-            .map_err(|e| crate::error::RalphError::ConfigError(e.to_string()))?;
-        let mut config: Self = toml::from_str(&content)
+    ///
+    /// Unknown keys are rejected, so a typo like `max_iteration` is reported
+    /// with its line instead of being silently ignored.
+    pub fn from_file(path: &Path) -> crate::error::Result<Self> {
+        let mut config = from_table(read_layer(path)?)
             .map_err(|e| crate::error::RalphError::ConfigError(e.to_string()))?;
         config.apply_legacy_defaults();
         Ok(config)
     }
 
+    /// Reject settings that contradict each other, checked once the config
+    /// file and CLI arguments are merged
+    pub fn validate(&self) -> crate::error::Result<()> {
+        let invalid = |message: String| Err(crate::error::RalphError::ConfigError(message));
+        if self.max_iterations == Some(0) {
+            return invalid("max_iterations must be at least 1".to_string());
+        }
+        if self.completion_promise.trim().is_empty() {
+            return invalid("completion_promise must not be empty".to_string());
+        }
+        for (i, milestone) in self.milestones.iter().enumerate() {
+            if milestone.trim().is_empty() {
+                return invalid("milestones must not be empty".to_string());
+            }
+            if *milestone == self.completion_promise {
+                return invalid(format!(
+                    "milestone \"{milestone}\" is the completion_promise, which always comes last"
+                ));
+            }
+            if self.milestones[..i].contains(milestone) {
+                return invalid(format!("milestone \"{milestone}\" is listed twice"));
+            }
+        }
+        for (name, limits) in [
+            ("max_tokens", self.context_limit.for_iteration(false)),
+            (
+                "first_iteration_max_tokens",
+                self.context_limit.for_iteration(true),
+            ),
+        ] {
+            if limits.warning_tokens() >= limits.max_tokens {
+                return invalid(format!(
+                    "context_limit.warning_threshold ({} tokens) must be below {name} ({})",
+                    limits.warning_tokens(),
+                    limits.max_tokens
+                ));
+            }
+        }
+        if self.prompt_preview_chars == 0 {
+            return invalid("prompt_preview_chars must be at least 1".to_string());
+        }
+        if self.network.wait_when_offline && self.network.probe_interval_secs == 0 {
+            return invalid("network.probe_interval_secs must be at least 1".to_string());
+        }
+        let resources = &self.agent.resources;
+        if resources
+            .nice
+            .is_some_and(|nice| !(-20..=19).contains(&nice))
+        {
+            return invalid("agent.resources.nice must be between -20 and 19".to_string());
+        }
+        if resources.max_memory_mb == Some(0) || resources.max_cpu_percent == Some(0) {
+            return invalid("agent.resources caps must be at least 1".to_string());
+        }
+        if resources.max_cpu_percent.is_some() && !resources.cgroup {
+            return invalid(
+                "agent.resources.max_cpu_percent can only be enforced with cgroup = true"
+                    .to_string(),
+            );
+        }
+        if !cfg!(target_os = "linux")
+            && (resources.max_memory_mb.is_some() || resources.max_cpu_percent.is_some())
+        {
+            return invalid("agent.resources caps are only supported on Linux".to_string());
+        }
+        Ok(())
+    }
+
     /// Merge CLI arguments into this configuration
     /// CLI arguments take precedence over config file values
     pub fn merge_cli_args(&mut self, overrides: CliOverrides) {
@@ -203,9 +1299,40 @@ impl Config {
         if let Some(args) = overrides.agent_args {
             self.agent.args = Some(args);
         }
+        if let Some(global) = overrides.global_store {
+            self.global_store = global;
+        }
+        if let Some(permissions) = overrides.permissions {
+            self.permissions = permissions;
+        }
+        if let Some(plan_file) = overrides.plan_file {
+            self.plan_file = Some(plan_file);
+        }
+        if let Some(inject) = overrides.inject_promise_instructions {
+            self.inject_promise_instructions = inject;
+        }
+        if let Some(log_thinking) = overrides.log_thinking {
+            self.log_thinking = log_thinking;
+        }
+        if let Some(tags) = overrides.tags {
+            self.tags = tags;
+        }
         self.apply_legacy_defaults();
     }
 
+    /// The directory runs are written to, honouring the global store setting
+    pub fn runs_output_dir(&self) -> crate::error::Result<PathBuf> {
+        if !self.global_store {
+            return Ok(self.output_dir.clone());
+        }
+        crate::registry::ralph_home().ok_or_else(|| {
+            crate::error::RalphError::ConfigError(
+                "global store enabled but neither RALPH_HOME nor a data directory is available"
+                    .to_string(),
+            )
+        })
+    }
+
     /// The effective configured agent provider
     pub fn agent_provider(&self) -> AgentProvider {
         self.agent.provider
@@ -216,10 +1343,13 @@ impl Config {
         self.agent
             .path
             .clone()
-            .or_else(|| self.claude_path.clone())
+            .or_else(|| self.legacy(&self.claude_path))
             .unwrap_or_else(|| match self.agent.provider {
                 AgentProvider::Claude => default_claude_path(),
                 AgentProvider::Codex => default_codex_path(),
+                AgentProvider::Openai | AgentProvider::Anthropic | AgentProvider::Ollama => {
+                    "curl".to_string()
+                }
             })
     }
 
@@ -228,16 +1358,76 @@ impl Config {
         if let Some(args) = self.agent.args.clone() {
             return args;
         }
-        if let Some(args) = self.claude_args.clone() {
+        if let Some(args) = self.legacy(&self.claude_args) {
             return args;
         }
         match self.agent.provider {
-            AgentProvider::Claude => default_claude_args(),
-            AgentProvider::Codex => default_codex_args(),
+            AgentProvider::Claude => default_claude_args(self.permissions),
+            AgentProvider::Codex => default_codex_args(self.permissions),
+            // The API agents build their own requests
+            AgentProvider::Openai | AgentProvider::Anthropic | AgentProvider::Ollama => Vec::new(),
+        }
+    }
+
+    /// The model the agent runs: `context_limit.model`, the model of an API
+    /// provider, or the `--model` in the arguments of a CLI agent
+    pub fn model(&self) -> Option<String> {
+        if let Some(ref model) = self.context_limit.model {
+            return Some(model.clone());
+        }
+        match self.agent.provider {
+            AgentProvider::Claude | AgentProvider::Codex => {
+                crate::context_window::model_in_args(&self.agent_args())
+            }
+            AgentProvider::Openai => self.api.model.clone(),
+            AgentProvider::Anthropic => Some(self.anthropic.model.clone()),
+            AgentProvider::Ollama => Some(self.ollama.model.clone()),
+        }
+    }
+
+    /// The context window of the model the agent runs, if known. Ollama
+    /// models get the `num_ctx` they are asked for.
+    pub fn context_window(&self) -> Option<(String, usize)> {
+        let model = self.model()?;
+        if self.agent.provider == AgentProvider::Ollama && self.context_limit.model.is_none() {
+            if let Some(num_ctx) = self.ollama.num_ctx {
+                return Some((model, num_ctx as usize));
+            }
+        }
+        let window = crate::context_window::lookup(&model, &self.context_limit.windows)?;
+        Some((model, window))
+    }
+
+    /// Whether the effective agent arguments disable permission checks
+    pub fn skips_permissions(&self) -> bool {
+        if self.agent_provider().is_api() {
+            // The local `bash` tool is offered without restrictions
+            return self.tools.enabled && self.permissions == PermissionMode::Skip;
         }
+        self.agent_args()
+            .iter()
+            .any(|arg| arg == CLAUDE_SKIP_PERMISSIONS_FLAG || arg == CODEX_SKIP_PERMISSIONS_FLAG)
+    }
+
+    /// A legacy Claude CLI setting, unless an API provider is configured
+    fn legacy<T: Clone>(&self, setting: &Option<T>) -> Option<T> {
+        if self.agent.provider.is_api() {
+            return None;
+        }
+        setting.clone()
     }
 
     fn apply_legacy_defaults(&mut self) {
+        // Settings copied while a CLI provider was configured don't apply to curl
+        if self.agent.provider.is_api() {
+            if self.agent.path.is_some() && self.agent.path == self.claude_path {
+                self.agent.path = None;
+            }
+            if self.agent.args.is_some() && self.agent.args == self.claude_args {
+                self.agent.args = None;
+            }
+            return;
+        }
         if self.agent.path.is_none() {
             self.agent.path = self.claude_path.clone();
         }
@@ -247,11 +1437,838 @@ impl Config {
     }
 }
 
-fn default_codex_args() -> Vec<String> {
-    vec![
-        "exec".to_string(),
-        "--json".to_string(),
-        "--dangerously-bypass-approvals-and-sandbox".to_string(),
-        "-".to_string(),
-    ]
+/// Config file read below the user's and the project's
+pub const SYSTEM_CONFIG_FILE: &str = "/etc/ralph/config.toml";
+
+/// Prefix of the environment variables that set config keys
+const ENV_PREFIX: &str = "RALPH_";
+
+/// Environment variables of run options whose names don't follow the keys
+const ENV_ALIASES: [(&str, &str); 3] = [
+    ("RALPH_CONTEXT_LIMIT", "context_limit.max_tokens"),
+    ("RALPH_AGENT_PROVIDER", "agent.provider"),
+    ("RALPH_AGENT_PATH", "agent.path"),
+];
+
+/// Where an effective config value came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigOrigin {
+    Default,
+    System(PathBuf),
+    User(PathBuf),
+    Project(PathBuf),
+    /// The environment variable that set it
+    Env(String),
+    Cli,
+    /// Derived from the context window of the model
+    ContextWindow(String),
+}
+
+impl std::fmt::Display for ConfigOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigOrigin::Default => write!(f, "default"),
+            ConfigOrigin::System(path) => write!(f, "system ({})", path.display()),
+            ConfigOrigin::User(path) => write!(f, "user ({})", path.display()),
+            ConfigOrigin::Project(path) => write!(f, "project ({})", path.display()),
+            ConfigOrigin::Env(name) => write!(f, "env {name}"),
+            ConfigOrigin::Cli => write!(f, "command line"),
+            ConfigOrigin::ContextWindow(model) => write!(f, "context window of {model}"),
+        }
+    }
+}
+
+/// The config files layered below the environment and the command line
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSources {
+    pub system: Option<PathBuf>,
+    pub user: Option<PathBuf>,
+    /// `--config`, or the discovered `.ralph.toml`
+    pub project: Option<PathBuf>,
+    /// Directory of the discovered `.ralph.toml`, which relative paths in it
+    /// are resolved against
+    pub project_root: Option<PathBuf>,
+}
+
+impl ConfigSources {
+    /// The system and user config files that exist, and `explicit` or the
+    /// `.ralph.toml` found upward from `start`
+    pub fn discover(start: &Path, explicit: Option<&Path>) -> Self {
+        let user = dirs::config_dir().map(|dir| dir.join("ralph").join("config.toml"));
+        let (project, project_root) = match explicit {
+            Some(path) => (Some(path.to_path_buf()), None),
+            None => {
+                let project = find_project_config(start);
+                let root = project
+                    .as_ref()
+                    .and_then(|path| path.parent().map(Path::to_path_buf));
+                (project, root)
+            }
+        };
+        Self {
+            system: Some(PathBuf::from(SYSTEM_CONFIG_FILE)).filter(|path| path.is_file()),
+            user: user.filter(|path| path.is_file()),
+            project,
+            project_root,
+        }
+    }
+}
+
+/// The effective config and where each of its values came from.
+///
+/// Layers, each overriding the ones before it: defaults, the system config,
+/// the user config, the project config, `RALPH_*` environment variables and
+/// the command line. Tables are merged key by key; any other value, arrays
+/// included, replaces the one below it.
+#[derive(Debug, Clone)]
+pub struct LayeredConfig {
+    pub config: Config,
+    /// Origin per dotted key; keys not listed are defaults
+    origins: BTreeMap<String, ConfigOrigin>,
+}
+
+impl LayeredConfig {
+    /// Merge the config files of `sources` and the `RALPH_*` variables in `env`
+    pub fn load(
+        sources: &ConfigSources,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> crate::error::Result<Self> {
+        let mut merged = toml::Table::new();
+        let mut origins = BTreeMap::new();
+        let files = [
+            (
+                &sources.system,
+                ConfigOrigin::System as fn(PathBuf) -> ConfigOrigin,
+            ),
+            (&sources.user, ConfigOrigin::User),
+            (&sources.project, ConfigOrigin::Project),
+        ];
+        for (path, origin) in files {
+            if let Some(path) = path {
+                let layer = read_layer(path)?;
+                merge_layer(&mut merged, layer, &origin(path.clone()), "", &mut origins);
+            }
+        }
+        let mut env: Vec<(String, String)> = env.into_iter().collect();
+        env.sort();
+        for (name, value) in env {
+            if let Some((key, value)) = env_setting(&name, &value)? {
+                let layer = table_at(&key, value);
+                merge_layer(
+                    &mut merged,
+                    layer,
+                    &ConfigOrigin::Env(name),
+                    "",
+                    &mut origins,
+                );
+            }
+        }
+
+        let mut config =
+            from_table(merged).map_err(|e| crate::error::RalphError::ConfigError(e.to_string()))?;
+        if !config.agent.provider.is_api() {
+            for (key, legacy) in [("agent.path", "claude_path"), ("agent.args", "claude_args")] {
+                if let (None, Some(origin)) = (origins.get(key), origins.get(legacy).cloned()) {
+                    origins.insert(key.to_string(), origin);
+                }
+            }
+        }
+        config.apply_legacy_defaults();
+
+        // Relative paths of the discovered project config are relative to
+        // its directory, as is the default output directory
+        if let Some(ref root) = sources.project_root {
+            let from_project =
+                |key: &str| matches!(origins.get(key), Some(ConfigOrigin::Project(_)));
+            if from_project("output_dir") || !origins.contains_key("output_dir") {
+                config.output_dir = root.join(&config.output_dir);
+            }
+            if from_project("plan_file") {
+                config.plan_file = config.plan_file.map(|plan| root.join(plan));
+            }
+        }
+        Ok(Self { config, origins })
+    }
+
+    /// Merge CLI arguments, which take precedence over every other layer
+    pub fn merge_cli_args(&mut self, overrides: CliOverrides) {
+        for key in overrides.keys() {
+            self.origins.insert(key.to_string(), ConfigOrigin::Cli);
+        }
+        self.config.merge_cli_args(overrides);
+    }
+
+    /// Derive the context limit and warning threshold that aren't set in any
+    /// layer from the context window of the agent's model. Returns the model
+    /// and its window if the limit was derived.
+    pub fn calibrate_context_limit(&mut self) -> Option<(String, usize)> {
+        if !matches!(
+            self.origin("context_limit.max_tokens"),
+            ConfigOrigin::Default
+        ) {
+            return None;
+        }
+        let (model, window) = self.config.context_window()?;
+        let origin = ConfigOrigin::ContextWindow(model.clone());
+        self.config.context_limit.max_tokens = window * crate::context_window::LIMIT_PERCENT / 100;
+        self.origins
+            .insert("context_limit.max_tokens".to_string(), origin.clone());
+        if matches!(
+            self.origin("context_limit.warning_threshold"),
+            ConfigOrigin::Default
+        ) {
+            self.config.context_limit.warning_threshold = Some(WarningThreshold::Tokens(
+                window * crate::context_window::WARNING_PERCENT / 100,
+            ));
+            self.origins
+                .insert("context_limit.warning_threshold".to_string(), origin);
+        }
+        Some((model, window))
+    }
+
+    /// Where the value of the dotted `key` came from
+    pub fn origin(&self, key: &str) -> &ConfigOrigin {
+        // Entries of a map like `event_filter.sample_tools` may have been set
+        // as a whole
+        let mut key = key;
+        loop {
+            if let Some(origin) = self.origins.get(key) {
+                return origin;
+            }
+            match key.rsplit_once('.') {
+                Some((parent, _)) => key = parent,
+                None => return &ConfigOrigin::Default,
+            }
+        }
+    }
+
+    /// The effective value of the dotted `key`, or None if it isn't set
+    pub fn get(&self, key: &str) -> crate::error::Result<Option<toml::Value>> {
+        // Unset optional values aren't serialized, so ask the schema
+        if let Ok(None) = parse_setting(key, "") {
+            return Err(crate::error::RalphError::ConfigError(format!(
+                "unknown config key `{key}`"
+            )));
+        }
+        let mut value = toml::Value::try_from(&self.config)
+            .map_err(|e| crate::error::RalphError::ConfigError(e.to_string()))?;
+        for part in key.split('.') {
+            match value.get(part) {
+                Some(child) => value = child.clone(),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(value))
+    }
+
+    /// Every effective value by dotted key, with its origin
+    pub fn values(&self) -> crate::error::Result<Vec<(String, toml::Value, &ConfigOrigin)>> {
+        let table = toml::Table::try_from(&self.config)
+            .map_err(|e| crate::error::RalphError::ConfigError(e.to_string()))?;
+        let mut values = Vec::new();
+        flatten(table, "", &mut values);
+        Ok(values
+            .into_iter()
+            .map(|(key, value)| {
+                let origin = self.origin(&key);
+                (key, value, origin)
+            })
+            .collect())
+    }
+}
+
+/// Read a config file as a table, reporting unknown keys with their line
+fn read_layer(path: &Path) -> crate::error::Result<toml::Table> {
+    let error = |e: &dyn std::fmt::Display| {
+        crate::error::RalphError::ConfigError(format!("{}: {e}", path.display()))
+    };
+    let content = std::fs::read_to_string(path).map_err(|e| error(&e))?;
+    // Deserialize on its own first, as only the text has line numbers
+    toml::from_str::<Config>(&content).map_err(|e| error(&e))?;
+    toml::from_str(&content).map_err(|e| error(&e))
+}
+
+fn from_table(table: toml::Table) -> Result<Config, toml::de::Error> {
+    toml::Value::Table(table).try_into()
+}
+
+/// Merge `layer` into `merged`, recording `origin` for every value it sets
+fn merge_layer(
+    merged: &mut toml::Table,
+    layer: toml::Table,
+    origin: &ConfigOrigin,
+    prefix: &str,
+    origins: &mut BTreeMap<String, ConfigOrigin>,
+) {
+    for (name, value) in layer {
+        let key = format!("{prefix}{name}");
+        let nested = format!("{key}.");
+        match value {
+            toml::Value::Table(table) => {
+                if !matches!(merged.get(&name), Some(toml::Value::Table(_))) {
+                    origins.remove(&key);
+                    merged.insert(name.clone(), toml::Value::Table(toml::Table::new()));
+                }
+                let Some(toml::Value::Table(below)) = merged.get_mut(&name) else {
+                    unreachable!("inserted above");
+                };
+                merge_layer(below, table, origin, &nested, origins);
+            }
+            value => {
+                origins.retain(|set, _| !set.starts_with(&nested));
+                origins.insert(key, origin.clone());
+                merged.insert(name, value);
+            }
+        }
+    }
+}
+
+/// The dotted key and value set by the environment variable `name`, if it
+/// names a config key
+fn env_setting(name: &str, value: &str) -> crate::error::Result<Option<(String, toml::Value)>> {
+    let key = match ENV_ALIASES.iter().find(|(alias, _)| *alias == name) {
+        Some((_, key)) => key.to_string(),
+        None => match name.strip_prefix(ENV_PREFIX) {
+            Some(rest) if !rest.is_empty() => rest.to_lowercase().replace("__", "."),
+            _ => return Ok(None),
+        },
+    };
+    // Other RALPH_ variables, like RALPH_HOME, aren't config keys
+    parse_setting(&key, value)
+        .map(|value| value.map(|value| (key, value)))
+        .map_err(|e| crate::error::RalphError::ConfigError(format!("{name}: {e}")))
+}
+
+/// `value` for the dotted `key`, or None if `key` isn't a config key.
+/// Values are read as TOML where the key accepts that, e.g. `false` or
+/// `["exec", "--json"]`, and as strings otherwise.
+fn parse_setting(key: &str, value: &str) -> Result<Option<toml::Value>, toml::de::Error> {
+    let literal = toml::from_str::<toml::Table>(&format!("value = {value}"))
+        .ok()
+        .filter(|table| table.len() == 1)
+        .and_then(|mut table| table.remove("value"));
+    let mut last_error = None;
+    for candidate in literal
+        .into_iter()
+        .chain([toml::Value::String(value.to_string())])
+    {
+        match from_table(table_at(key, candidate.clone())) {
+            Ok(_) => return Ok(Some(candidate)),
+            Err(e) if e.to_string().contains("unknown field") => return Ok(None),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.expect("a candidate was tried"))
+}
+
+/// Set the dotted `key` to `value` in the config file at `path`, creating
+/// the file if needed and keeping its comments and formatting
+pub fn set_config_value(path: &Path, key: &str, value: &str) -> crate::error::Result<toml::Value> {
+    let error = |e: &dyn std::fmt::Display| {
+        crate::error::RalphError::ConfigError(format!("{}: {e}", path.display()))
+    };
+    let value = parse_setting(key, value)
+        .map_err(|e| crate::error::RalphError::ConfigError(e.to_string()))?
+        .ok_or_else(|| {
+            crate::error::RalphError::ConfigError(format!("unknown config key `{key}`"))
+        })?;
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(error(&e)),
+    };
+    let mut document: toml_edit::DocumentMut = content.parse().map_err(|e| error(&e))?;
+    let edit_value: toml_edit::Value = value.to_string().parse().map_err(|e| error(&e))?;
+    let parts: Vec<&str> = key.split('.').collect();
+    set_item(document.as_table_mut(), false, &parts, edit_value)
+        .map_err(|e| error(&format!("can't set `{key}`: {e}")))?;
+
+    let content = document.to_string();
+    let config: Config = toml::from_str(&content).map_err(|e| error(&e))?;
+    config.validate()?;
+    std::fs::write(path, content).map_err(|e| error(&e))?;
+    Ok(value)
+}
+
+/// Set `value` at the key `parts` below `table`, creating missing tables
+fn set_item(
+    table: &mut dyn toml_edit::TableLike,
+    inline: bool,
+    parts: &[&str],
+    mut value: toml_edit::Value,
+) -> Result<(), String> {
+    let (name, rest) = parts.split_first().expect("key is not empty");
+    if rest.is_empty() {
+        match table.get_mut(name).and_then(toml_edit::Item::as_value_mut) {
+            // Keep the comments around the value being replaced
+            Some(existing) => {
+                *value.decor_mut() = existing.decor().clone();
+                *existing = value;
+            }
+            None => {
+                table.insert(name, toml_edit::Item::Value(value));
+            }
+        }
+        if inline {
+            table.fmt();
+        }
+        return Ok(());
+    }
+    if table.get(name).is_none() {
+        let new = if inline {
+            toml_edit::Item::Value(toml_edit::InlineTable::new().into())
+        } else {
+            let mut new = toml_edit::Table::new();
+            new.set_implicit(true);
+            toml_edit::Item::Table(new)
+        };
+        table.insert(name, new);
+    }
+    let item = table.get_mut(name).expect("inserted above");
+    let inline = inline || item.is_inline_table();
+    match item.as_table_like_mut() {
+        Some(child) => set_item(child, inline, rest, value),
+        None => Err(format!("`{name}` is not a table")),
+    }
+}
+
+/// A table with `value` at the dotted `key`
+fn table_at(key: &str, value: toml::Value) -> toml::Table {
+    let mut parts = key.rsplit('.');
+    let last = parts.next().expect("key is not empty");
+    let mut table = toml::Table::from_iter([(last.to_string(), value)]);
+    for parent in parts {
+        table = toml::Table::from_iter([(parent.to_string(), toml::Value::Table(table))]);
+    }
+    table
+}
+
+/// The values of `table` by dotted key; arrays are single values
+fn flatten(table: toml::Table, prefix: &str, values: &mut Vec<(String, toml::Value)>) {
+    for (name, value) in table {
+        let key = format!("{prefix}{name}");
+        match value {
+            toml::Value::Table(table) => flatten(table, &format!("{key}."), values),
+            value => values.push((key, value)),
+        }
+    }
+}
+
+fn default_codex_args(permissions: PermissionMode) -> Vec<String> {
+    let mut args = vec!["exec".to_string(), "--json".to_string()];
+    match permissions {
+        PermissionMode::Skip => args.push(CODEX_SKIP_PERMISSIONS_FLAG.to_string()),
+        PermissionMode::AcceptEdits => args.push("--full-auto".to_string()),
+        PermissionMode::Default => {}
+    }
+    // Read the prompt from stdin
+    args.push("-".to_string());
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn finds_project_config_in_ancestor() {
+        let temp_dir = TempDir::new().unwrap();
+        let nested = temp_dir.path().join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(temp_dir.path().join(PROJECT_CONFIG_FILE), "").unwrap();
+
+        assert_eq!(
+            find_project_config(&nested),
+            Some(temp_dir.path().join(PROJECT_CONFIG_FILE))
+        );
+        assert_eq!(project_root(&nested), temp_dir.path());
+    }
+
+    #[test]
+    fn nearest_project_config_wins() {
+        let temp_dir = TempDir::new().unwrap();
+        let nested = temp_dir.path().join("sub");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(temp_dir.path().join(PROJECT_CONFIG_FILE), "").unwrap();
+        std::fs::write(nested.join(PROJECT_CONFIG_FILE), "").unwrap();
+
+        assert_eq!(project_root(&nested), nested);
+    }
+
+    #[test]
+    fn permission_modes_map_to_claude_flags() {
+        let mut config = Config::default();
+        assert!(config
+            .agent_args()
+            .contains(&CLAUDE_SKIP_PERMISSIONS_FLAG.to_string()));
+        assert!(config.skips_permissions());
+
+        config.permissions = PermissionMode::AcceptEdits;
+        let args = config.agent_args();
+        assert!(args.ends_with(&["--permission-mode".to_string(), "acceptEdits".to_string()]));
+        assert!(!config.skips_permissions());
+
+        config.permissions = PermissionMode::Default;
+        assert!(!config.agent_args().iter().any(|a| a.contains("permission")));
+    }
+
+    #[test]
+    fn permission_modes_map_to_codex_flags() {
+        let mut config = Config::default();
+        config.agent.provider = AgentProvider::Codex;
+        assert_eq!(
+            config.agent_args(),
+            vec!["exec", "--json", CODEX_SKIP_PERMISSIONS_FLAG, "-"]
+        );
+
+        config.permissions = PermissionMode::AcceptEdits;
+        assert_eq!(
+            config.agent_args(),
+            vec!["exec", "--json", "--full-auto", "-"]
+        );
+    }
+
+    #[test]
+    fn explicit_args_are_checked_for_skip_flags() {
+        let mut config: Config = toml::from_str(r#"permissions = "acceptEdits""#).unwrap();
+        assert_eq!(config.permissions, PermissionMode::AcceptEdits);
+
+        config.agent.args = Some(vec![CLAUDE_SKIP_PERMISSIONS_FLAG.to_string()]);
+        assert!(config.skips_permissions());
+    }
+
+    #[test]
+    fn legacy_claude_settings_are_ignored_by_api_providers() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "claude_path = \"/opt/claude\"\nclaude_args = [\"--verbose\"]\n",
+        )
+        .unwrap();
+
+        let mut config = Config::from_file(&path).unwrap();
+        assert_eq!(config.agent_path(), "/opt/claude");
+        assert_eq!(config.agent_args(), ["--verbose"]);
+
+        config.merge_cli_args(CliOverrides {
+            agent_provider: Some(AgentProvider::Openai),
+            ..Default::default()
+        });
+        assert_eq!(config.agent_path(), "curl");
+        assert!(config.agent_args().is_empty());
+    }
+
+    #[test]
+    fn project_root_defaults_to_cwd() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(find_project_config(temp_dir.path()), None);
+        assert_eq!(project_root(temp_dir.path()), temp_dir.path());
+    }
+
+    #[test]
+    fn warning_threshold_accepts_tokens_or_a_percentage() {
+        let config: Config = toml::from_str(
+            "[context_limit]\nmax_tokens = 200000\nwarning_threshold = \"85%\"\nfirst_iteration_max_tokens = 300000\n",
+        )
+        .unwrap();
+        let limits = &config.context_limit;
+        assert_eq!(
+            limits.warning_threshold,
+            Some(WarningThreshold::Percent(85.0))
+        );
+        assert_eq!(limits.warning_tokens(), 170_000);
+        // The percentage follows the larger limit of the first iteration
+        assert_eq!(limits.for_iteration(true).warning_tokens(), 255_000);
+
+        let config: Config =
+            toml::from_str("[context_limit]\nwarning_threshold = 120000\n").unwrap();
+        assert_eq!(config.context_limit.warning_tokens(), 120_000);
+
+        for invalid in ["\"85\"", "\"much%\"", "\"0%\"", "\"100%\""] {
+            let error = toml::from_str::<Config>(&format!(
+                "[context_limit]\nwarning_threshold = {invalid}\n"
+            ))
+            .unwrap_err();
+            assert!(error.to_string().contains("warning_threshold"), "{error}");
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_reported_with_their_line() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(PROJECT_CONFIG_FILE);
+        std::fs::write(&path, "prompt = \"fix\"\nmax_iteration = 3\n").unwrap();
+
+        let error = Config::from_file(&path).unwrap_err().to_string();
+        assert!(error.contains(&path.display().to_string()), "{error}");
+        assert!(error.contains("line 2"), "{error}");
+        assert!(error.contains("unknown field `max_iteration`"), "{error}");
+
+        let error = toml::from_str::<Config>("[context_limit]\nmax_token = 1\n").unwrap_err();
+        assert!(
+            error.to_string().contains("unknown field `max_token`"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn contradicting_settings_are_rejected() {
+        assert!(Config::default().validate().is_ok());
+
+        let invalid = |config: Config| config.validate().unwrap_err().to_string();
+        let error = invalid(Config {
+            max_iterations: Some(0),
+            ..Config::default()
+        });
+        assert!(error.contains("max_iterations"), "{error}");
+
+        let error = invalid(Config {
+            completion_promise: "  ".to_string(),
+            ..Config::default()
+        });
+        assert!(error.contains("completion_promise"), "{error}");
+
+        let milestones = |milestones: &[&str]| Config {
+            milestones: milestones.iter().map(|m| m.to_string()).collect(),
+            ..Config::default()
+        };
+        assert!(milestones(&["PLAN READY", "TESTS PASS"]).validate().is_ok());
+        let error = invalid(milestones(&["PLAN READY", "PLAN READY"]));
+        assert!(error.contains("listed twice"), "{error}");
+        let error = invalid(milestones(&["TASK COMPLETE"]));
+        assert!(error.contains("always comes last"), "{error}");
+
+        let mut config = Config::default();
+        config.context_limit.max_tokens = 100_000;
+        // The default threshold follows a lower limit
+        assert_eq!(config.context_limit.warning_tokens(), 90_000);
+        assert!(config.validate().is_ok());
+        config.context_limit.warning_threshold = Some(WarningThreshold::Tokens(150_000));
+        let error = invalid(config.clone());
+        assert!(
+            error.contains("(150000 tokens) must be below max_tokens (100000)"),
+            "{error}"
+        );
+
+        config.context_limit.warning_threshold = Some(WarningThreshold::Percent(80.0));
+        config.context_limit.first_iteration_max_tokens = Some(200_000);
+        assert!(config.validate().is_ok());
+        config.context_limit.warning_threshold = Some(WarningThreshold::Tokens(90_000));
+        config.context_limit.first_iteration_max_tokens = Some(50_000);
+        let error = invalid(config);
+        assert!(
+            error.contains("first_iteration_max_tokens (50000)"),
+            "{error}"
+        );
+
+        let mut config = Config::default();
+        config.agent.resources.nice = Some(20);
+        let error = invalid(config.clone());
+        assert!(error.contains("between -20 and 19"), "{error}");
+        config.agent.resources.nice = Some(10);
+        config.agent.resources.max_cpu_percent = Some(150);
+        config.agent.resources.cgroup = false;
+        let error = invalid(config);
+        assert!(error.contains("with cgroup = true"), "{error}");
+    }
+
+    #[test]
+    fn layers_override_each_other_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = temp_dir.path().join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        };
+        let sources = ConfigSources {
+            system: Some(write(
+                "system.toml",
+                "max_iterations = 1\ncompletion_promise = \"SYSTEM\"\n[agent]\nprovider = \"codex\"\n",
+            )),
+            user: Some(write(
+                "user.toml",
+                "max_iterations = 2\nstop_on_promise = false\n",
+            )),
+            project: Some(write(
+                PROJECT_CONFIG_FILE,
+                "max_iterations = 3\nplan_file = \"PLAN.md\"\n[agent]\npath = \"my-codex\"\n",
+            )),
+            project_root: Some(temp_dir.path().to_path_buf()),
+        };
+        let env = [
+            ("RALPH_MAX_ITERATIONS", "4"),
+            ("RALPH_COMPLETION_PROMISE", "true"),
+            ("RALPH_CONTEXT_LIMIT", "200000"),
+            ("RALPH_CONTEXT_LIMIT__WARNING_THRESHOLD", "85%"),
+            ("RALPH_HOME", "/somewhere"),
+            ("OTHER", "1"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        let mut layered = LayeredConfig::load(&sources, env).unwrap();
+        let config = &layered.config;
+        assert_eq!(config.max_iterations, Some(4));
+        // A value the key doesn't accept as TOML is taken as a string
+        assert_eq!(config.completion_promise, "true");
+        assert_eq!(config.context_limit.max_tokens, 200_000);
+        assert_eq!(config.context_limit.warning_tokens(), 170_000);
+        assert!(!config.stop_on_promise);
+        // Tables are merged key by key
+        assert_eq!(config.agent.provider, AgentProvider::Codex);
+        assert_eq!(config.agent.path.as_deref(), Some("my-codex"));
+        assert_eq!(config.plan_file, Some(temp_dir.path().join("PLAN.md")));
+        assert_eq!(
+            config.output_dir,
+            temp_dir.path().join(default_output_dir())
+        );
+
+        let project = sources.project.clone().unwrap();
+        assert_eq!(
+            layered.origin("max_iterations"),
+            &ConfigOrigin::Env("RALPH_MAX_ITERATIONS".to_string())
+        );
+        assert_eq!(
+            layered.origin("context_limit.max_tokens"),
+            &ConfigOrigin::Env("RALPH_CONTEXT_LIMIT".to_string())
+        );
+        assert_eq!(
+            layered.origin("agent.provider"),
+            &ConfigOrigin::System(sources.system.clone().unwrap())
+        );
+        assert_eq!(
+            layered.origin("stop_on_promise"),
+            &ConfigOrigin::User(sources.user.clone().unwrap())
+        );
+        assert_eq!(
+            layered.origin("agent.path"),
+            &ConfigOrigin::Project(project)
+        );
+        assert_eq!(layered.origin("agent.args"), &ConfigOrigin::Default);
+
+        layered.merge_cli_args(CliOverrides {
+            max_iterations: Some(5),
+            ..CliOverrides::default()
+        });
+        assert_eq!(layered.config.max_iterations, Some(5));
+        assert_eq!(layered.origin("max_iterations"), &ConfigOrigin::Cli);
+        let values = layered.values().unwrap();
+        assert!(values
+            .iter()
+            .any(|(key, value, origin)| key == "max_iterations"
+                && value.as_integer() == Some(5)
+                && **origin == ConfigOrigin::Cli));
+    }
+
+    #[test]
+    fn invalid_environment_values_name_the_variable() {
+        let env = [("RALPH_STOP_ON_PROMISE".to_string(), "maybe".to_string())];
+        let error = LayeredConfig::load(&ConfigSources::default(), env)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("RALPH_STOP_ON_PROMISE"), "{error}");
+        assert!(error.contains("expected a boolean"), "{error}");
+    }
+
+    #[test]
+    fn set_config_value_keeps_the_rest_of_the_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(PROJECT_CONFIG_FILE);
+        std::fs::write(
+            &path,
+            "# project\nmax_iterations = 4  # keep small\n\n[event_filter]\nsample_tools = { Grep = 3 }\n",
+        )
+        .unwrap();
+
+        set_config_value(&path, "max_iterations", "6").unwrap();
+        set_config_value(&path, "context_limit.max_tokens", "150000").unwrap();
+        set_config_value(&path, "event_filter.sample_tools.Read", "5").unwrap();
+        let value = set_config_value(&path, "completion_promise", "ALL DONE").unwrap();
+        assert_eq!(value, toml::Value::String("ALL DONE".to_string()));
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            content.starts_with("# project\nmax_iterations = 6  # keep small\n"),
+            "{content}"
+        );
+        assert!(
+            content.contains("sample_tools = { Grep = 3, Read = 5 }"),
+            "{content}"
+        );
+        let config = Config::from_file(&path).unwrap();
+        assert_eq!(config.context_limit.max_tokens, 150_000);
+        assert_eq!(config.completion_promise, "ALL DONE");
+
+        for (key, value, expected) in [
+            ("max_iteration", "3", "unknown config key `max_iteration`"),
+            ("stop_on_promise", "maybe", "expected a boolean"),
+            ("max_iterations", "0", "max_iterations must be at least 1"),
+        ] {
+            let error = set_config_value(&path, key, value).unwrap_err().to_string();
+            assert!(error.contains(expected), "{error}");
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), content);
+    }
+
+    #[test]
+    fn context_limit_follows_the_model_unless_set() {
+        let load = |env: &[(&str, &str)]| {
+            let env = env
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()));
+            LayeredConfig::load(&ConfigSources::default(), env).unwrap()
+        };
+
+        let mut layered = load(&[("RALPH_AGENT__PROVIDER", "ollama")]);
+        layered.config.ollama.model = "qwen2.5-coder:7b".to_string();
+        assert_eq!(
+            layered.calibrate_context_limit(),
+            Some(("qwen2.5-coder:7b".to_string(), 32_768))
+        );
+        assert_eq!(layered.config.context_limit.max_tokens, 31_129);
+        assert_eq!(layered.config.context_limit.warning_tokens(), 26_214);
+        assert_eq!(
+            layered.origin("context_limit.max_tokens"),
+            &ConfigOrigin::ContextWindow("qwen2.5-coder:7b".to_string())
+        );
+
+        // The Claude CLI's model is only known if it is passed
+        let mut layered = load(&[]);
+        assert_eq!(layered.calibrate_context_limit(), None);
+        assert_eq!(layered.config.context_limit.max_tokens, 180_000);
+        layered.config.agent.args = Some(vec!["--model".to_string(), "opus".to_string()]);
+        layered.calibrate_context_limit();
+        assert_eq!(layered.config.context_limit.max_tokens, 190_000);
+
+        // Limits that are set are left alone, as is a window-less model
+        let mut layered = load(&[("RALPH_CONTEXT_LIMIT", "100000")]);
+        layered.config.context_limit.model = Some("gpt-5".to_string());
+        assert_eq!(layered.calibrate_context_limit(), None);
+        let mut layered = load(&[("RALPH_CONTEXT_LIMIT__WARNING_THRESHOLD", "50%")]);
+        layered.config.context_limit.model = Some("my-model".to_string());
+        assert_eq!(layered.calibrate_context_limit(), None);
+        layered
+            .config
+            .context_limit
+            .windows
+            .insert("my-".to_string(), 100_000);
+        layered.calibrate_context_limit();
+        assert_eq!(layered.config.context_limit.max_tokens, 95_000);
+        assert_eq!(layered.config.context_limit.warning_tokens(), 47_500);
+    }
+
+    #[test]
+    fn get_returns_effective_values_of_known_keys() {
+        let env = [("RALPH_CONTEXT_LIMIT".to_string(), "1000".to_string())];
+        let layered = LayeredConfig::load(&ConfigSources::default(), env).unwrap();
+
+        assert_eq!(
+            layered.get("context_limit.max_tokens").unwrap(),
+            Some(toml::Value::Integer(1000))
+        );
+        assert!(layered.get("context_limit").unwrap().unwrap().is_table());
+        assert_eq!(layered.get("plan_file").unwrap(), None);
+        assert!(layered.get("max_iteration").is_err());
+    }
 }
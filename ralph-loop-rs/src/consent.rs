@@ -0,0 +1,166 @@
+//! First-run confirmation before looping an agent with permission checks disabled.
+//!
+//! The first time skip-permissions mode is used in a project, the user has to
+//! confirm interactively (or pass `--yes`). An interactive confirmation is
+//! persisted in the project's `.ralph.toml` so later runs don't ask again.
+
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+
+use crate::config::{self, Config, PROJECT_CONFIG_FILE};
+use crate::error::{RalphError, Result};
+
+/// Config key recording the consent
+pub const CONSENT_KEY: &str = "skip_permissions_consent";
+
+/// Make sure running with permission checks disabled has been agreed to
+pub fn ensure_consent(config: &Config, project_root: &Path, assume_yes: bool) -> Result<()> {
+    if !config.skips_permissions() || config.skip_permissions_consent || assume_yes {
+        return Ok(());
+    }
+
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        return Err(RalphError::ConsentRequired(format!(
+            "the agent would run with permission checks disabled in {}; \
+             confirm interactively once, pass --yes, set RALPH_YES=1, or set {CONSENT_KEY} = true in {PROJECT_CONFIG_FILE}",
+            project_root.display()
+        )));
+    }
+
+    let confirmed = confirm(
+        &summary(config, project_root),
+        &mut stdin.lock(),
+        &mut std::io::stderr(),
+    )
+    .map_err(|e| RalphError::ConsentRequired(e.to_string()))?;
+    if !confirmed {
+        return Err(RalphError::ConsentRequired("declined by user".to_string()));
+    }
+
+    persist_consent(project_root)
+}
+
+/// What the user is agreeing to
+pub fn summary(config: &Config, project_root: &Path) -> String {
+    let iterations = match config.max_iterations {
+        Some(max) => max.to_string(),
+        None => "unlimited".to_string(),
+    };
+    format!(
+        "ralph-loop is about to run {} in a loop with ALL permission checks disabled.\n\
+         The agent may execute any command and modify any file it can access.\n\n\
+         \x20 Project directory: {}\n\
+         \x20 Max iterations:    {}\n\
+         \x20 Context limit:     {} tokens per iteration\n",
+        config.agent_path(),
+        project_root.display(),
+        iterations,
+        config.context_limit.max_tokens,
+    )
+}
+
+/// Show `summary` and ask for a yes/no answer; anything but yes declines
+pub fn confirm(
+    summary: &str,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> std::io::Result<bool> {
+    write!(
+        output,
+        "{summary}\nContinue and remember this for the project? [y/N] "
+    )?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+/// Record the consent in the project's `.ralph.toml`, replacing an existing
+/// `skip_permissions_consent = false`
+pub fn persist_consent(project_root: &Path) -> Result<()> {
+    config::set_config_value(&project_root.join(PROJECT_CONFIG_FILE), CONSENT_KEY, "true")
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn only_yes_confirms() {
+        for (answer, expected) in [
+            ("y\n", true),
+            ("YES\n", true),
+            ("n\n", false),
+            ("\n", false),
+        ] {
+            let mut output = Vec::new();
+            let confirmed = confirm("summary", &mut answer.as_bytes(), &mut output).unwrap();
+            assert_eq!(confirmed, expected, "answer {answer:?}");
+        }
+    }
+
+    #[test]
+    fn summary_lists_scope_and_limits() {
+        let config = Config {
+            max_iterations: Some(7),
+            ..Config::default()
+        };
+        let summary = summary(&config, Path::new("/work/project"));
+
+        assert!(summary.contains("/work/project"));
+        assert!(summary.contains("Max iterations:    7"));
+        assert!(summary.contains("180000 tokens"));
+    }
+
+    #[test]
+    fn persisted_consent_is_read_back_with_existing_tables() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(
+            temp_dir.path().join(PROJECT_CONFIG_FILE),
+            "[agent]\nprovider = \"codex\"\n",
+        )
+        .unwrap();
+
+        persist_consent(temp_dir.path()).unwrap();
+
+        let config = Config::from_file(&temp_dir.path().join(PROJECT_CONFIG_FILE)).unwrap();
+        assert!(config.skip_permissions_consent);
+        assert_eq!(config.agent_provider(), crate::config::AgentProvider::Codex);
+    }
+
+    #[test]
+    fn persisted_consent_replaces_a_declined_one() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(PROJECT_CONFIG_FILE);
+        fs::write(
+            &path,
+            "max_iterations = 3\nskip_permissions_consent = false\n\n[agent]\nprobe = false\n",
+        )
+        .unwrap();
+
+        persist_consent(temp_dir.path()).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(content.matches(CONSENT_KEY).count(), 1, "{content}");
+        let config = Config::from_file(&path).unwrap();
+        assert!(config.skip_permissions_consent);
+        assert_eq!(config.max_iterations, Some(3));
+    }
+
+    #[test]
+    fn no_consent_needed_without_skip_mode() {
+        let config = Config {
+            permissions: crate::config::PermissionMode::Default,
+            ..Config::default()
+        };
+        assert!(ensure_consent(&config, Path::new("/nowhere"), false).is_ok());
+    }
+}
@@ -0,0 +1,99 @@
+//! Context window sizes of the models agents run, to derive the context
+//! limit from.
+//!
+//! Unless `context_limit.max_tokens` is set somewhere, the limit follows the
+//! model: the agent is restarted at 95% of its context window and warned at
+//! 80%. The model is `context_limit.model`, or else the one the provider is
+//! configured with (`--model` in the agent's arguments for the CLI agents).
+//! Models missing from the table below, or sized differently, can be added
+//! in `[context_limit.windows]`.
+
+use std::collections::BTreeMap;
+
+/// Share of the window, in percent, at which the agent is restarted
+pub const LIMIT_PERCENT: usize = 95;
+
+/// Share of the window, in percent, at which a warning is logged
+pub const WARNING_PERCENT: usize = 80;
+
+/// Context windows by model name prefix; the longest matching prefix wins
+const WINDOWS: &[(&str, usize)] = &[
+    ("claude-", 200_000),
+    ("sonnet", 200_000),
+    ("opus", 200_000),
+    ("haiku", 200_000),
+    ("gpt-4o", 128_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-5", 400_000),
+    ("o3", 200_000),
+    ("o4-mini", 200_000),
+    ("codex-mini", 200_000),
+    ("llama3.1", 131_072),
+    ("llama3.2", 131_072),
+    ("llama3.3", 131_072),
+    ("qwen2.5-coder", 32_768),
+    ("qwen3", 40_960),
+    ("mistral", 32_768),
+    ("deepseek-r1", 131_072),
+];
+
+/// Context window of `model`, looked up in `overrides` before the built-in
+/// table, by the longest name prefix
+pub fn lookup(model: &str, overrides: &BTreeMap<String, usize>) -> Option<usize> {
+    let longest_prefix = |windows: &mut dyn Iterator<Item = (&str, usize)>| {
+        windows
+            .filter(|(prefix, _)| model.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, window)| window)
+    };
+    longest_prefix(&mut overrides.iter().map(|(prefix, &w)| (prefix.as_str(), w)))
+        .or_else(|| longest_prefix(&mut WINDOWS.iter().copied()))
+}
+
+/// The `--model` or `-m` value in the arguments of a CLI agent
+pub fn model_in_args(args: &[String]) -> Option<String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if let Some(model) = arg.strip_prefix("--model=") {
+            return Some(model.to_string());
+        }
+        if arg == "--model" || arg == "-m" {
+            return args.next().cloned();
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_are_found_by_the_longest_prefix() {
+        let overrides = BTreeMap::from([
+            ("claude-sonnet-4-5".to_string(), 1_000_000),
+            ("my-finetune".to_string(), 64_000),
+        ]);
+        assert_eq!(lookup("claude-opus-4-1", &overrides), Some(200_000));
+        assert_eq!(lookup("claude-sonnet-4-5", &overrides), Some(1_000_000));
+        assert_eq!(lookup("my-finetune-v2", &overrides), Some(64_000));
+        assert_eq!(lookup("gpt-4o-mini", &BTreeMap::new()), Some(128_000));
+        assert_eq!(lookup("llama3.2:3b", &BTreeMap::new()), Some(131_072));
+        assert_eq!(lookup("unknown", &BTreeMap::new()), None);
+    }
+
+    #[test]
+    fn model_is_read_from_the_agent_arguments() {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            model_in_args(&args(&["--verbose", "--model", "opus"])),
+            Some("opus".to_string())
+        );
+        assert_eq!(
+            model_in_args(&args(&["--model=gpt-5"])),
+            Some("gpt-5".to_string())
+        );
+        assert_eq!(model_in_args(&args(&["-m", "o3"])), Some("o3".to_string()));
+        assert_eq!(model_in_args(&args(&["--verbose"])), None);
+    }
+}
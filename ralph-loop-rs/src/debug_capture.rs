@@ -0,0 +1,158 @@
+//! Trace-level log of a single run, for bug reports.
+//!
+//! With `--capture-debug`, everything ralph-loop logs down to trace level is
+//! written to `debug.log` in the run directory, redacted, while the terminal
+//! keeps its usual level. What is logged before the run directory exists is
+//! kept in memory and written once it does.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::error::{RalphError, Result};
+use crate::redact::Redactor;
+
+/// Name of the captured log inside the run directory
+pub const DEBUG_LOG_FILE: &str = "debug.log";
+
+/// Levels of the captured log: all of ralph-loop, and dependencies down to debug
+const FILTER: &str = "debug,ralph_loop=trace";
+
+/// Shared destination of the captured log
+#[derive(Debug, Clone, Default)]
+pub struct DebugCapture {
+    inner: Arc<Mutex<Capture>>,
+}
+
+#[derive(Debug, Default)]
+struct Capture {
+    /// Log written before the run directory was known
+    buffer: Vec<u8>,
+    file: Option<File>,
+    redactor: Option<Redactor>,
+}
+
+impl Capture {
+    fn write_redacted(&mut self, buf: &[u8]) -> io::Result<()> {
+        let Some(ref mut file) = self.file else {
+            self.buffer.extend_from_slice(buf);
+            return Ok(());
+        };
+        match self.redactor {
+            Some(ref redactor) => {
+                let text = String::from_utf8_lossy(buf);
+                file.write_all(redactor.redact(&text).as_bytes())
+            }
+            None => file.write_all(buf),
+        }
+    }
+}
+
+impl DebugCapture {
+    /// Create a capture keeping the log in memory until it is attached
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the log to `debug.log` in `run_dir` from now on, starting with
+    /// what was logged so far, with secrets redacted by `redactor`
+    pub fn attach(&self, run_dir: &Path, redactor: Option<Redactor>) -> Result<PathBuf> {
+        let path = run_dir.join(DEBUG_LOG_FILE);
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| RalphError::TranscriptWriteError(format!("{}: {e}", path.display())))?;
+        let mut capture = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        capture.file = Some(file);
+        capture.redactor = redactor;
+        let buffer = std::mem::take(&mut capture.buffer);
+        capture
+            .write_redacted(&buffer)
+            .map_err(|e| RalphError::TranscriptWriteError(format!("{}: {e}", path.display())))?;
+        Ok(path)
+    }
+
+    /// Layer writing the log down to trace level into this capture
+    pub fn layer<S>(&self) -> impl Layer<S>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        tracing_subscriber::fmt::layer()
+            .with_writer(self.clone())
+            .with_ansi(false)
+            .with_filter(EnvFilter::new(FILTER))
+    }
+}
+
+/// Writer of a single log line into the capture
+pub struct CaptureWriter {
+    inner: Arc<Mutex<Capture>>,
+}
+
+impl Write for CaptureWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .write_redacted(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.inner.lock().unwrap_or_else(|e| e.into_inner()).file {
+            Some(ref mut file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for DebugCapture {
+    type Writer = CaptureWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        CaptureWriter {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RedactionConfig;
+    use tempfile::TempDir;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn trace_log_is_buffered_until_attached_and_redacted() {
+        let temp_dir = TempDir::new().unwrap();
+        let capture = DebugCapture::new();
+        let subscriber = tracing_subscriber::registry().with(capture.layer());
+        let redactor = Redactor::from_config(&RedactionConfig {
+            enabled: true,
+            patterns: vec!["sk-[a-z0-9]+".to_string()],
+            ..RedactionConfig::default()
+        })
+        .unwrap();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::trace!(target: "ralph_loop::monitor", "before key=sk-abc123");
+            let path = capture.attach(temp_dir.path(), redactor).unwrap();
+            tracing::trace!(target: "ralph_loop::monitor", "after key=sk-def456");
+            tracing::trace!(target: "hyper", "dependency trace is left out");
+
+            let log = std::fs::read_to_string(path).unwrap();
+            assert!(log.contains("before key="), "{log}");
+            assert!(log.contains("after key="), "{log}");
+            assert!(!log.contains("sk-"), "{log}");
+            assert!(!log.contains("dependency"), "{log}");
+        });
+    }
+}
@@ -0,0 +1,104 @@
+//! Free disk space check before each iteration.
+//!
+//! An agent that runs out of disk space fails mid-iteration with I/O errors
+//! that rarely say why, and so does ralph-loop writing the run artifacts.
+//! Before each iteration, the free space of the output and project
+//! directories is compared with `[disk_space] min_free_mb`; below it, the
+//! loop either waits for space to be freed or aborts.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// Status recorded in the run metadata while the loop waits
+pub const PAUSED_REASON: &str = "low disk space";
+
+/// What the loop does when free space is below the threshold
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LowDiskSpaceAction {
+    /// Stop the run with an error
+    #[default]
+    Abort,
+    /// Wait until enough space is free again
+    Pause,
+}
+
+/// A directory with less free space than required, as recorded in the run metadata
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LowDiskSpaceRecord {
+    pub path: PathBuf,
+    /// Free space when the check ran, in MiB
+    pub free_mb: u64,
+    /// Iteration that was about to start
+    pub iteration: u32,
+    pub at: DateTime<Utc>,
+}
+
+/// Free space of the filesystem holding `path`, in MiB
+pub fn free_mb(path: &Path) -> std::io::Result<u64> {
+    let output = Command::new("df").arg("-Pk").arg(path).output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        return Err(std::io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    parse_df(&stdout)
+        .ok_or_else(|| std::io::Error::other(format!("unexpected df output: {stdout}")))
+}
+
+/// Available MiB in the output of `df -Pk`
+fn parse_df(output: &str) -> Option<u64> {
+    let available_kb: u64 = output
+        .lines()
+        .nth(1)?
+        .split_whitespace()
+        .nth(3)?
+        .parse()
+        .ok()?;
+    Some(available_kb / 1024)
+}
+
+/// The first of `paths` with less than `min_free_mb` free, with its free space.
+/// Paths whose free space can't be determined are skipped with a warning.
+pub fn find_low(paths: &[PathBuf], min_free_mb: u64) -> Option<(PathBuf, u64)> {
+    paths.iter().find_map(|path| match free_mb(path) {
+        Ok(free) => (free < min_free_mb).then(|| (path.clone(), free)),
+        Err(e) => {
+            warn!(
+                "Failed to check free disk space of {}: {}",
+                path.display(),
+                e
+            );
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn available_space_is_read_from_df() {
+        let output = "Filesystem     1024-blocks      Used Available Capacity Mounted on\n\
+                      /dev/nvme0n1p2   479596204 312034112 143106356      69% /\n";
+        assert_eq!(parse_df(output), Some(139_752));
+        assert_eq!(parse_df("df: /nope: No such file or directory\n"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn low_space_is_found_against_the_threshold() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let paths = vec![temp_dir.path().to_path_buf()];
+        assert!(free_mb(temp_dir.path()).is_ok());
+        assert_eq!(find_low(&paths, 0), None);
+        let (path, _) = find_low(&paths, u64::MAX).unwrap();
+        assert_eq!(path, temp_dir.path());
+    }
+}
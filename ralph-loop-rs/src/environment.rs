@@ -0,0 +1,159 @@
+//! Snapshot of the environment a run was made in.
+//!
+//! Captured once at run start and stored in the run metadata, so runs made
+//! days apart can be compared: which commit they started from, how many
+//! uncommitted changes there were, and which toolchain versions were used.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::config::EnvironmentConfig;
+use crate::git::GitSnapshot;
+
+/// Environment at the start of a run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvironmentSnapshot {
+    /// `git rev-parse HEAD` of the project
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_head: Option<String>,
+    /// Number of modified or untracked files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_dirty_files: Option<usize>,
+    /// Operating system, e.g. `linux`
+    pub os: String,
+    /// CPU architecture, e.g. `x86_64`
+    pub arch: String,
+    /// Kernel name and release from `uname -sr`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+    /// Name of the machine from `uname -n`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// First output line of each configured probe command that succeeded
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tools: BTreeMap<String, String>,
+}
+
+/// Capture the environment of the project at `project_path`, whose
+/// repository was in state `git`
+pub fn capture(
+    project_path: &Path,
+    config: &EnvironmentConfig,
+    git: Option<&GitSnapshot>,
+) -> EnvironmentSnapshot {
+    EnvironmentSnapshot {
+        git_head: git.map(|git| git.head.clone()),
+        git_dirty_files: git.map(|git| git.dirty.len()),
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        os_version: run(project_path, "uname", &["-sr"]).and_then(|out| first_line(&out)),
+        hostname: hostname(),
+        tools: config
+            .probes
+            .iter()
+            .filter_map(|(name, command)| {
+                let mut words = command.split_whitespace();
+                let program = words.next()?;
+                let args: Vec<&str> = words.collect();
+                let version = first_line(&run(project_path, program, &args)?)?;
+                Some((name.clone(), version))
+            })
+            .collect(),
+    }
+}
+
+/// Name of this machine, from `uname -n`
+pub fn hostname() -> Option<String> {
+    run(Path::new("/"), "uname", &["-n"]).and_then(|out| first_line(&out))
+}
+
+/// Run a probe and return its stdout, or `None` if it failed
+fn run(dir: &Path, program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| debug!("Environment probe {} failed: {}", program, e))
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn first_line(output: &str) -> Option<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_AUTHOR_NAME", "test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn captures_git_state_and_probes() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        git(dir, &["init", "-q"]);
+        std::fs::write(dir.join("a.txt"), "a").unwrap();
+        git(dir, &["add", "a.txt"]);
+        git(dir, &["commit", "-qm", "init"]);
+        std::fs::write(dir.join("a.txt"), "changed").unwrap();
+        std::fs::write(dir.join("b.txt"), "new").unwrap();
+
+        let config = EnvironmentConfig {
+            enabled: true,
+            probes: BTreeMap::from([
+                ("git".to_string(), "git --version".to_string()),
+                (
+                    "missing".to_string(),
+                    "ralph-no-such-tool --version".to_string(),
+                ),
+            ]),
+        };
+        let git_start = crate::git::snapshot(dir, &mut Vec::new());
+        let snapshot = capture(dir, &config, git_start.as_ref());
+
+        assert_eq!(snapshot.git_head.as_deref().map(str::len), Some(40));
+        assert_eq!(snapshot.git_dirty_files, Some(2));
+        assert_eq!(snapshot.os, std::env::consts::OS);
+        assert!(snapshot.tools["git"].starts_with("git version"));
+        assert!(!snapshot.tools.contains_key("missing"));
+    }
+
+    #[test]
+    fn outside_a_repository_git_fields_are_empty() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = EnvironmentConfig {
+            enabled: true,
+            probes: BTreeMap::new(),
+        };
+
+        let git_start = crate::git::snapshot(temp_dir.path(), &mut Vec::new());
+        let snapshot = capture(temp_dir.path(), &config, git_start.as_ref());
+
+        assert_eq!(snapshot.git_head, None);
+        assert_eq!(snapshot.git_dirty_files, None);
+    }
+}
@@ -46,6 +46,46 @@ pub enum RalphError {
     /// Self-upgrade failed
     #[error("upgrade failed: {0}")]
     UpgradeError(String),
+
+    /// Running with permission checks disabled was not confirmed
+    #[error("confirmation required: {0}")]
+    ConsentRequired(String),
+
+    /// Cancelling a running loop failed
+    #[error("cancel failed: {0}")]
+    CancelError(String),
+
+    /// Reporting a run to the central server failed
+    #[error("report failed: {0}")]
+    ReportError(String),
+
+    /// The run was stopped on purpose before completing
+    #[error("run aborted: {0}")]
+    Aborted(String),
+
+    /// Resuming a stopped run failed
+    #[error("resume failed: {0}")]
+    ResumeError(String),
+
+    /// Replaying a recorded run failed
+    #[error("replay failed: {0}")]
+    ReplayError(String),
+
+    /// The installed agent CLI lacks a feature ralph needs
+    #[error("incompatible agent CLI: {0}")]
+    IncompatibleAgent(String),
+
+    /// Summarizing the tool calls of a run failed
+    #[error("tool statistics failed: {0}")]
+    ToolStatsError(String),
+
+    /// Comparing runs, or managing their baselines, failed
+    #[error("stats failed: {0}")]
+    StatsError(String),
+
+    /// A git operation failed
+    #[error("git error: {0}")]
+    GitError(String),
 }
 
 /// Result type alias for Ralph operations
@@ -0,0 +1,161 @@
+//! Estimating how long iterations will take.
+//!
+//! The expected length of an iteration is the median length of the latest
+//! iterations of the current run and of past runs of the same prompt in
+//! the same project. From it the status line shows how long the current
+//! iteration has left, and how long until max iterations is reached.
+
+use std::time::Duration;
+
+use crate::banner::format_duration;
+use crate::transcript::RunMetadata;
+
+/// How many of the latest iteration lengths the estimate is based on
+const SAMPLE_SIZE: usize = 20;
+
+/// Expected timing of the iteration being run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IterationEta {
+    /// Expected length of an iteration
+    pub expected: Duration,
+    /// Iterations left after the current one, if max iterations is set
+    pub remaining_iterations: Option<u32>,
+}
+
+impl IterationEta {
+    /// Estimate from iteration lengths, latest first; `None` without any
+    pub fn estimate(durations: &[Duration], remaining_iterations: Option<u32>) -> Option<Self> {
+        let mut sample: Vec<Duration> = durations.iter().take(SAMPLE_SIZE).copied().collect();
+        if sample.is_empty() {
+            return None;
+        }
+        sample.sort();
+        Some(Self {
+            expected: sample[sample.len() / 2],
+            remaining_iterations,
+        })
+    }
+
+    /// Expected time until the current iteration ends, `elapsed` into it
+    pub fn iteration_left(&self, elapsed: Duration) -> Duration {
+        self.expected.saturating_sub(elapsed)
+    }
+
+    /// Expected time until max iterations is reached, if it is set
+    pub fn max_iterations_left(&self, elapsed: Duration) -> Option<Duration> {
+        self.remaining_iterations
+            .map(|remaining| self.iteration_left(elapsed) + self.expected * remaining)
+    }
+
+    /// Text for the status line, `elapsed` into the current iteration
+    pub fn render(&self, elapsed: Duration) -> String {
+        let mut text = if elapsed > self.expected {
+            format!(
+                "{} over ETA",
+                format_duration(elapsed.saturating_sub(self.expected))
+            )
+        } else {
+            format!("ETA {}", format_duration(self.iteration_left(elapsed)))
+        };
+        if let Some(left) = self.max_iterations_left(elapsed) {
+            text.push_str(&format!(" · max iterations in ~{}", format_duration(left)));
+        }
+        text
+    }
+}
+
+/// Lengths of the iterations of `metadata` that ended, latest first
+pub fn iteration_durations(metadata: &RunMetadata) -> Vec<Duration> {
+    metadata
+        .iterations
+        .iter()
+        .rev()
+        .filter_map(|iteration| {
+            let ended = iteration.ended_at?;
+            (ended - iteration.started_at).to_std().ok()
+        })
+        .collect()
+}
+
+/// Iteration lengths of the runs in `past` that ran the same prompt as
+/// `run`, latest run first
+pub fn history(run: &RunMetadata, past: &[RunMetadata]) -> Vec<Duration> {
+    past.iter()
+        .rev()
+        .filter(|other| other.run_id != run.run_id && run.same_prompt(other))
+        .flat_map(iteration_durations)
+        .take(SAMPLE_SIZE)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AgentProvider;
+    use crate::transcript::{IterationEndReason, TokenUsageRecord, TranscriptWriter};
+    use tempfile::TempDir;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn estimates_use_the_median_iteration() {
+        assert_eq!(IterationEta::estimate(&[], Some(3)), None);
+        let eta = IterationEta::estimate(&[secs(60), secs(600), secs(90)], Some(3)).unwrap();
+        assert_eq!(eta.expected, secs(90));
+
+        assert_eq!(
+            eta.render(secs(30)),
+            "ETA 1m 00s · max iterations in ~5m 30s"
+        );
+        assert_eq!(
+            eta.render(secs(100)),
+            "10s over ETA · max iterations in ~4m 30s"
+        );
+        let unbounded = IterationEta {
+            remaining_iterations: None,
+            ..eta
+        };
+        assert_eq!(unbounded.render(secs(30)), "ETA 1m 00s");
+    }
+
+    #[test]
+    fn history_comes_from_runs_of_the_same_prompt() {
+        let dir = TempDir::new().unwrap();
+        let run = |run_id: &str, prompt: &str, iterations: usize| {
+            let mut writer = TranscriptWriter::new(
+                dir.path(),
+                dir.path(),
+                prompt,
+                None,
+                AgentProvider::Claude,
+                "DONE".to_string(),
+                Some(run_id.to_string()),
+            )
+            .unwrap();
+            for _ in 0..iterations {
+                writer.start_iteration().unwrap();
+                writer
+                    .end_iteration(
+                        IterationEndReason::Normal,
+                        "agent ended without the promise".to_string(),
+                        TokenUsageRecord::default(),
+                    )
+                    .unwrap();
+            }
+            writer.metadata().clone()
+        };
+        let current = run("current", "fix the tests", 1);
+        let past = vec![
+            run("same", "fix the tests", 2),
+            run("other", "write docs", 3),
+            current.clone(),
+        ];
+
+        assert_eq!(iteration_durations(&current).len(), 1);
+        assert_eq!(history(&current, &past).len(), 2);
+        assert!(current.same_prompt(&past[0]));
+        assert!(!current.same_prompt(&past[1]));
+    }
+}
@@ -0,0 +1,250 @@
+//! Filtering of noisy tool events out of the stored agent output.
+//!
+//! Some loops call the same tool thousands of times, e.g. Claude's `Read`,
+//! and those events drown everything else in the output kept for the
+//! iteration. With `[event_filter]`, the calls of muted tools and their
+//! results are left out, and of sampled tools only every Nth call is kept.
+//! The monitor still processes the events, so usage and the promise are
+//! tracked as before.
+//!
+//! Tools are named by Claude's `tool_use` blocks, whose results are matched
+//! by `tool_use_id`, and by the item type of Codex items, matched by item id.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use serde::Deserialize;
+
+use crate::config::EventFilterConfig;
+
+/// Decides which output lines are left out, tracking the calls it has seen
+#[derive(Debug)]
+pub struct EventFilter {
+    mute: HashSet<String>,
+    sample: HashMap<String, u32>,
+    /// Calls seen per sampled tool
+    calls: HashMap<String, u64>,
+    /// Whether the call with this ID was left out, until its result is seen
+    decisions: HashMap<String, bool>,
+    /// Count of lines left out
+    hidden: u64,
+}
+
+/// The parts of a Claude or Codex line that name tools
+#[derive(Deserialize)]
+struct Line<'a> {
+    #[serde(rename = "type", default, borrow)]
+    event_type: Cow<'a, str>,
+    #[serde(default, borrow)]
+    message: Option<Message<'a>>,
+    #[serde(default, borrow)]
+    item: Option<Item<'a>>,
+}
+
+#[derive(Deserialize)]
+struct Message<'a> {
+    #[serde(default, borrow)]
+    content: Vec<Block<'a>>,
+}
+
+#[derive(Deserialize)]
+struct Block<'a> {
+    #[serde(rename = "type", default, borrow)]
+    kind: Cow<'a, str>,
+    #[serde(default, borrow)]
+    id: Cow<'a, str>,
+    #[serde(default, borrow)]
+    name: Cow<'a, str>,
+    #[serde(default, borrow)]
+    tool_use_id: Cow<'a, str>,
+}
+
+#[derive(Deserialize)]
+struct Item<'a> {
+    #[serde(default, borrow)]
+    id: Cow<'a, str>,
+    #[serde(rename = "type", default, borrow)]
+    kind: Cow<'a, str>,
+}
+
+impl EventFilter {
+    /// Create a filter from the config, or None if nothing is filtered
+    pub fn from_config(config: &EventFilterConfig) -> Option<Self> {
+        if config.mute_tools.is_empty() && config.sample_tools.is_empty() {
+            return None;
+        }
+        Some(Self {
+            mute: config.mute_tools.iter().cloned().collect(),
+            sample: config
+                .sample_tools
+                .iter()
+                .map(|(tool, every)| (tool.clone(), *every))
+                .collect(),
+            calls: HashMap::new(),
+            decisions: HashMap::new(),
+            hidden: 0,
+        })
+    }
+
+    /// Whether `line` should be left out of the stored output
+    pub fn hides(&mut self, line: &str) -> bool {
+        // Lines that aren't tool events, such as plain text messages, are kept
+        let Ok(line) = serde_json::from_str::<Line>(line) else {
+            return false;
+        };
+        let hidden = if let Some(item) = line.item {
+            self.hides_item(&line.event_type, &item)
+        } else if let Some(message) = line.message {
+            self.hides_message(&message)
+        } else {
+            false
+        };
+        if hidden {
+            self.hidden += 1;
+        }
+        hidden
+    }
+
+    /// Number of lines left out so far
+    pub fn hidden(&self) -> u64 {
+        self.hidden
+    }
+
+    /// A Codex item is decided when it starts and stays so until it completes
+    fn hides_item(&mut self, event_type: &str, item: &Item<'_>) -> bool {
+        if item.id.is_empty() {
+            return false;
+        }
+        let hidden = match self.decisions.get(item.id.as_ref()) {
+            Some(hidden) => *hidden,
+            None => {
+                let hidden = self.decide(&item.kind);
+                self.decisions.insert(item.id.to_string(), hidden);
+                hidden
+            }
+        };
+        if event_type == "item.completed" {
+            self.decisions.remove(item.id.as_ref());
+        }
+        hidden
+    }
+
+    /// A Claude message is left out if all its blocks are calls or results
+    /// being left out; text in it is always kept
+    fn hides_message(&mut self, message: &Message<'_>) -> bool {
+        let mut hidden = !message.content.is_empty();
+        for block in &message.content {
+            match block.kind.as_ref() {
+                "tool_use" => {
+                    let hide = self.decide(&block.name);
+                    self.decisions.insert(block.id.to_string(), hide);
+                    hidden &= hide;
+                }
+                "tool_result" => {
+                    hidden &= self
+                        .decisions
+                        .remove(block.tool_use_id.as_ref())
+                        .unwrap_or(false);
+                }
+                _ => hidden = false,
+            }
+        }
+        if !hidden {
+            // The calls are visible, so their results are kept too
+            for block in message.content.iter().filter(|b| b.kind == "tool_use") {
+                self.decisions.insert(block.id.to_string(), false);
+            }
+        }
+        hidden
+    }
+
+    /// Whether the next call of `tool` is left out
+    fn decide(&mut self, tool: &str) -> bool {
+        if self.mute.contains(tool) {
+            return true;
+        }
+        let Some(&every) = self.sample.get(tool) else {
+            return false;
+        };
+        let calls = self.calls.entry(tool.to_string()).or_insert(0);
+        *calls += 1;
+        // Keep the first call and every Nth after it
+        every > 1 && !(*calls - 1).is_multiple_of(u64::from(every))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(mute: &[&str], sample: &[(&str, u32)]) -> EventFilter {
+        EventFilter::from_config(&EventFilterConfig {
+            mute_tools: mute.iter().map(|t| t.to_string()).collect(),
+            sample_tools: sample.iter().map(|(t, n)| (t.to_string(), *n)).collect(),
+        })
+        .unwrap()
+    }
+
+    fn tool_use(id: &str, name: &str) -> String {
+        format!(
+            r#"{{"type":"assistant","message":{{"content":[{{"type":"tool_use","id":"{id}","name":"{name}","input":{{}}}}]}}}}"#
+        )
+    }
+
+    fn tool_result(id: &str) -> String {
+        format!(
+            r#"{{"type":"user","message":{{"content":[{{"type":"tool_result","tool_use_id":"{id}","content":"..."}}]}}}}"#
+        )
+    }
+
+    #[test]
+    fn nothing_configured_means_no_filter() {
+        assert!(EventFilter::from_config(&EventFilterConfig::default()).is_none());
+    }
+
+    #[test]
+    fn muted_claude_tools_are_left_out_with_their_results() {
+        let mut filter = filter(&["Read"], &[]);
+
+        assert!(filter.hides(&tool_use("t1", "Read")));
+        assert!(!filter.hides(&tool_use("t2", "Bash")));
+        assert!(filter.hides(&tool_result("t1")));
+        assert!(!filter.hides(&tool_result("t2")));
+        // Text in the same message is kept
+        let mixed = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Reading"},{"type":"tool_use","id":"t3","name":"Read","input":{}}]}}"#;
+        assert!(!filter.hides(mixed));
+        assert!(!filter.hides(&tool_result("t3")));
+        assert!(!filter.hides(r#"{"type":"user","message":{"content":"plain prompt"}}"#));
+        assert!(!filter.hides(r#"{"type":"result","usage":{}}"#));
+        assert_eq!(filter.hidden(), 2);
+    }
+
+    #[test]
+    fn sampled_tools_keep_every_nth_call() {
+        let mut filter = filter(&[], &[("Grep", 3)]);
+
+        let kept: Vec<usize> = (1..=7)
+            .filter(|i| {
+                let id = format!("t{i}");
+                let call_hidden = filter.hides(&tool_use(&id, "Grep"));
+                assert_eq!(filter.hides(&tool_result(&id)), call_hidden);
+                !call_hidden
+            })
+            .collect();
+
+        assert_eq!(kept, [1, 4, 7]);
+    }
+
+    #[test]
+    fn codex_items_are_filtered_by_type() {
+        let mut filter = filter(&["command_execution"], &[]);
+        let item = |event: &str, id: &str, kind: &str| {
+            format!(r#"{{"type":"{event}","item":{{"id":"{id}","type":"{kind}"}}}}"#)
+        };
+
+        assert!(filter.hides(&item("item.started", "i1", "command_execution")));
+        assert!(filter.hides(&item("item.completed", "i1", "command_execution")));
+        assert!(!filter.hides(&item("item.completed", "i2", "agent_message")));
+        assert!(filter.decisions.is_empty());
+    }
+}
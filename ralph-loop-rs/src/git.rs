@@ -0,0 +1,455 @@
+//! Git operations on the commits and files changed during a run.
+//!
+//! Agents often commit after every iteration. With `[git] squash_on_success`,
+//! the commits made since the run started are folded into a single commit
+//! whose message is the run summary. The original commits stay reachable on a
+//! side branch named `<branch_prefix><run-id>`.
+//!
+//! At the end of every run, the files changed since it started are listed in
+//! the run metadata, whether the agent committed them or not. Files that had
+//! uncommitted changes when it started are left out.
+//!
+//! Every git command is recorded in `audited`, for the run's audit log.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::Command;
+use std::time::Instant;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::audit::SubprocessRecord;
+use crate::error::{RalphError, Result};
+
+/// Run git in `repo`, record it in `audited` and return its trimmed stdout
+fn git(repo: &Path, args: &[&str], audited: &mut Vec<SubprocessRecord>) -> Result<String> {
+    git_output(repo, args, audited).map(|stdout| stdout.trim().to_string())
+}
+
+/// Run git in `repo`, record it in `audited` and return its stdout
+fn git_output(repo: &Path, args: &[&str], audited: &mut Vec<SubprocessRecord>) -> Result<String> {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let output = Command::new("git").args(args).current_dir(repo).output();
+    audited.push(SubprocessRecord {
+        kind: "git".to_string(),
+        program: "git".to_string(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        pid: None,
+        started_at,
+        exit_code: output.as_ref().ok().and_then(|output| output.status.code()),
+        duration_ms: started.elapsed().as_millis() as u64,
+    });
+    let output = output.map_err(|e| RalphError::GitError(format!("failed to run git: {e}")))?;
+    if !output.status.success() {
+        return Err(RalphError::GitError(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// State of a repository when a run starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitSnapshot {
+    /// Commit checked out
+    pub head: String,
+    /// Files with uncommitted changes, untracked ones included
+    pub dirty: Vec<String>,
+}
+
+/// The state of `repo`, if it is a git repository with a commit checked out
+pub fn snapshot(repo: &Path, audited: &mut Vec<SubprocessRecord>) -> Option<GitSnapshot> {
+    let head = git(repo, &["rev-parse", "--verify", "-q", "HEAD"], audited).ok()?;
+    // Not trimmed, the first entry may start with a space
+    let status = git_output(
+        repo,
+        &[
+            "status",
+            "--porcelain",
+            "-z",
+            "--no-renames",
+            "--untracked-files=all",
+        ],
+        audited,
+    )
+    .ok()?;
+    // Each entry is `XY path`, terminated by NUL
+    let dirty = status
+        .split('\0')
+        .filter_map(|entry| entry.get(3..))
+        .filter(|path| !path.is_empty())
+        .map(str::to_string)
+        .collect();
+    Some(GitSnapshot { head, dirty })
+}
+
+/// Outcome of [`squash_since`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SquashOutcome {
+    /// Nothing was committed since the run started
+    NoCommits,
+    /// `commits` commits were squashed into `head`; the originals are on `branch`
+    Squashed {
+        commits: usize,
+        head: String,
+        branch: String,
+    },
+}
+
+/// Squash every commit after `base` into one commit with `message`, keeping
+/// the original commits on `branch`. Uncommitted changes are left untouched.
+pub fn squash_since(
+    repo: &Path,
+    base: &str,
+    branch: &str,
+    message: &str,
+    audited: &mut Vec<SubprocessRecord>,
+) -> Result<SquashOutcome> {
+    let head = git(repo, &["rev-parse", "HEAD"], audited)?;
+    let commits: usize = git(
+        repo,
+        &["rev-list", "--count", &format!("{base}..HEAD")],
+        audited,
+    )?
+    .parse()
+    .map_err(|e| RalphError::GitError(format!("unexpected rev-list output: {e}")))?;
+    if commits == 0 {
+        return Ok(SquashOutcome::NoCommits);
+    }
+    if git(
+        repo,
+        &["merge-base", "--is-ancestor", base, "HEAD"],
+        audited,
+    )
+    .is_err()
+    {
+        return Err(RalphError::GitError(format!(
+            "{base} is no longer an ancestor of HEAD, not squashing"
+        )));
+    }
+
+    // Fails if the branch exists, before history is touched
+    git(repo, &["branch", branch, &head], audited)?;
+    // Commit the tree of HEAD rather than the index, which may hold changes
+    // staged but not committed, then move the branch without touching either
+    let squashed = git(
+        repo,
+        &["commit-tree", "HEAD^{tree}", "-p", base, "-m", message],
+        audited,
+    )?;
+    git(
+        repo,
+        &[
+            "update-ref",
+            "-m",
+            "ralph-loop: squash",
+            "HEAD",
+            &squashed,
+            &head,
+        ],
+        audited,
+    )?;
+
+    Ok(SquashOutcome::Squashed {
+        commits,
+        head: squashed,
+        branch: branch.to_string(),
+    })
+}
+
+/// How a file changed during a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileChange {
+    Added,
+    Modified,
+    Deleted,
+}
+
+/// A file changed during a run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangedFile {
+    pub path: String,
+    pub change: FileChange,
+}
+
+/// The files changed across a run, with counts per kind of change
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilesChanged {
+    pub added: usize,
+    pub modified: usize,
+    pub deleted: usize,
+    pub files: Vec<ChangedFile>,
+}
+
+impl FilesChanged {
+    fn push(&mut self, path: String, change: FileChange) {
+        match change {
+            FileChange::Added => self.added += 1,
+            FileChange::Modified => self.modified += 1,
+            FileChange::Deleted => self.deleted += 1,
+        }
+        self.files.push(ChangedFile { path, change });
+    }
+
+    /// Files changed section for the end of the run, empty if there were none
+    pub fn render(&self) -> String {
+        if self.files.is_empty() {
+            return String::new();
+        }
+        let mut section = format!(
+            "{} file(s) changed: {} added, {} modified, {} deleted\n",
+            self.files.len(),
+            self.added,
+            self.modified,
+            self.deleted
+        );
+        for file in &self.files {
+            let marker = match file.change {
+                FileChange::Added => 'A',
+                FileChange::Modified => 'M',
+                FileChange::Deleted => 'D',
+            };
+            section.push_str(&format!("  {marker} {}\n", file.path));
+        }
+        section
+    }
+}
+
+/// Files that differ between `base` and the working tree, committed or not,
+/// including untracked files that aren't ignored. Renames count as a delete
+/// and an add. Files under `exclude`, relative to `repo`, are left out, as
+/// are the `dirty` files that already had uncommitted changes at `base`.
+pub fn files_changed_since(
+    repo: &Path,
+    base: &str,
+    exclude: Option<&Path>,
+    dirty: &[String],
+    audited: &mut Vec<SubprocessRecord>,
+) -> Result<FilesChanged> {
+    let mut pathspec = vec![".".to_string()];
+    if let Some(exclude) = exclude {
+        pathspec.push(format!(":(exclude){}", exclude.display()));
+    }
+    let pathspec: Vec<&str> = pathspec.iter().map(String::as_str).collect();
+
+    let dirty: HashSet<&str> = dirty.iter().map(String::as_str).collect();
+    let mut changed = FilesChanged::default();
+    let diff_args = [
+        &["diff", "--name-status", "--no-renames", base, "--"],
+        &pathspec[..],
+    ]
+    .concat();
+    for line in git(repo, &diff_args, audited)?.lines() {
+        let Some((status, path)) = line.split_once('\t') else {
+            continue;
+        };
+        if dirty.contains(path) {
+            continue;
+        }
+        let change = match status {
+            "A" => FileChange::Added,
+            "D" => FileChange::Deleted,
+            _ => FileChange::Modified,
+        };
+        changed.push(path.to_string(), change);
+    }
+    let untracked_args = [
+        &["ls-files", "--others", "--exclude-standard", "--"],
+        &pathspec[..],
+    ]
+    .concat();
+    for path in git(repo, &untracked_args, audited)?
+        .lines()
+        .filter(|path| !path.is_empty() && !dirty.contains(path))
+    {
+        changed.push(path.to_string(), FileChange::Added);
+    }
+    changed.files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Run git without keeping the records
+    fn git(repo: &Path, args: &[&str]) -> Result<String> {
+        super::git(repo, args, &mut Vec::new())
+    }
+
+    fn repo() -> TempDir {
+        let temp_dir = TempDir::new().unwrap();
+        git(temp_dir.path(), &["init", "-q"]).unwrap();
+        git(temp_dir.path(), &["config", "user.name", "test"]).unwrap();
+        git(
+            temp_dir.path(),
+            &["config", "user.email", "test@example.com"],
+        )
+        .unwrap();
+        commit(temp_dir.path(), "base.txt", "initial");
+        temp_dir
+    }
+
+    fn commit(repo: &Path, file: &str, message: &str) {
+        std::fs::write(repo.join(file), message).unwrap();
+        git(repo, &["add", file]).unwrap();
+        git(repo, &["commit", "-qm", message]).unwrap();
+    }
+
+    #[test]
+    fn squashes_commits_and_keeps_originals_on_branch() {
+        let repo = repo();
+        let dir = repo.path();
+        let base = git(dir, &["rev-parse", "HEAD"]).unwrap();
+        commit(dir, "a.txt", "iteration 1");
+        commit(dir, "b.txt", "iteration 2");
+        std::fs::write(dir.join("wip.txt"), "uncommitted").unwrap();
+
+        let mut audited = Vec::new();
+        let outcome = squash_since(dir, &base, "ralph/run-1", "Add a and b", &mut audited).unwrap();
+
+        let SquashOutcome::Squashed { commits, head, .. } = outcome else {
+            panic!("expected a squash, got {outcome:?}");
+        };
+        assert_eq!(commits, 2);
+        assert_eq!(git(dir, &["rev-parse", "HEAD^"]).unwrap(), base);
+        assert_eq!(git(dir, &["rev-parse", "HEAD"]).unwrap(), head);
+        assert_eq!(
+            git(dir, &["log", "-1", "--format=%s"]).unwrap(),
+            "Add a and b"
+        );
+        assert_eq!(
+            git(
+                dir,
+                &["rev-list", "--count", &format!("{base}..ralph/run-1")]
+            )
+            .unwrap(),
+            "2"
+        );
+        assert_eq!(
+            git(dir, &["diff", "HEAD", "ralph/run-1", "--stat"]).unwrap(),
+            ""
+        );
+        assert!(dir.join("wip.txt").exists());
+        let commands: Vec<&str> = audited
+            .iter()
+            .map(|record| record.args[0].as_str())
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "rev-parse",
+                "rev-list",
+                "merge-base",
+                "branch",
+                "commit-tree",
+                "update-ref"
+            ]
+        );
+        assert!(audited.iter().all(|record| record.exit_code == Some(0)));
+    }
+
+    #[test]
+    fn staged_changes_stay_out_of_the_squash_commit() {
+        let repo = repo();
+        let dir = repo.path();
+        let base = git(dir, &["rev-parse", "HEAD"]).unwrap();
+        commit(dir, "a.txt", "iteration 1");
+        std::fs::write(dir.join("staged.txt"), "staged").unwrap();
+        git(dir, &["add", "staged.txt"]).unwrap();
+
+        squash_since(dir, &base, "ralph/run-1", "Add a", &mut Vec::new()).unwrap();
+
+        assert_eq!(
+            git(dir, &["show", "--name-only", "--format=", "HEAD"]).unwrap(),
+            "a.txt"
+        );
+        assert_eq!(
+            git(dir, &["diff", "--cached", "--name-only"]).unwrap(),
+            "staged.txt"
+        );
+    }
+
+    #[test]
+    fn snapshot_is_only_taken_in_a_repository_with_commits() {
+        let repo = repo();
+        let empty = TempDir::new().unwrap();
+        let mut audited = Vec::new();
+
+        assert_eq!(
+            snapshot(repo.path(), &mut audited),
+            Some(GitSnapshot {
+                head: git(repo.path(), &["rev-parse", "HEAD"]).unwrap(),
+                dirty: Vec::new(),
+            })
+        );
+        assert_eq!(snapshot(empty.path(), &mut audited), None);
+        assert_eq!(audited.len(), 3);
+    }
+
+    #[test]
+    fn files_dirty_before_the_run_are_not_counted() {
+        let repo = repo();
+        let dir = repo.path();
+        std::fs::write(dir.join("base.txt"), "edited before the run").unwrap();
+        std::fs::create_dir(dir.join("notes")).unwrap();
+        std::fs::write(dir.join("notes/todo.txt"), "untracked before the run").unwrap();
+        let before = snapshot(dir, &mut Vec::new()).unwrap();
+        assert_eq!(before.dirty, ["base.txt", "notes/todo.txt"]);
+
+        commit(dir, "a.txt", "iteration 1");
+        std::fs::write(dir.join("notes/new.txt"), "added by the run").unwrap();
+        let changed =
+            files_changed_since(dir, &before.head, None, &before.dirty, &mut Vec::new()).unwrap();
+
+        assert_eq!(
+            changed.render(),
+            "2 file(s) changed: 2 added, 0 modified, 0 deleted\n  A a.txt\n  A notes/new.txt\n"
+        );
+    }
+
+    #[test]
+    fn nothing_to_squash_without_new_commits() {
+        let repo = repo();
+        let base = git(repo.path(), &["rev-parse", "HEAD"]).unwrap();
+
+        assert_eq!(
+            squash_since(repo.path(), &base, "ralph/run-1", "msg", &mut Vec::new()).unwrap(),
+            SquashOutcome::NoCommits
+        );
+    }
+
+    #[test]
+    fn lists_committed_and_uncommitted_changes() {
+        let repo = repo();
+        let dir = repo.path();
+        commit(dir, "old.txt", "before the run");
+        let base = git(dir, &["rev-parse", "HEAD"]).unwrap();
+        commit(dir, "a.txt", "iteration 1");
+        std::fs::write(dir.join("base.txt"), "changed").unwrap();
+        git(dir, &["rm", "-q", "old.txt"]).unwrap();
+        std::fs::write(dir.join("new.txt"), "untracked").unwrap();
+        std::fs::create_dir(dir.join("output")).unwrap();
+        std::fs::write(dir.join("output/run.json"), "{}").unwrap();
+
+        let changed =
+            files_changed_since(dir, &base, Some(Path::new("output")), &[], &mut Vec::new())
+                .unwrap();
+
+        assert_eq!(
+            (changed.added, changed.modified, changed.deleted),
+            (2, 1, 1)
+        );
+        assert_eq!(
+            changed.render(),
+            "4 file(s) changed: 2 added, 1 modified, 1 deleted\n  A a.txt\n  M base.txt\n  \
+             A new.txt\n  D old.txt\n"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Heartbeat file for external supervisors.
+//!
+//! While a loop runs, `runs/<id>/heartbeat` is rewritten every few seconds
+//! from a task independent of the agent subprocess. A stale heartbeat means
+//! ralph itself is hung; a fresh one during a long iteration means the agent
+//! is simply still working.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::error::{RalphError, Result};
+use crate::state::SharedState;
+
+/// Name of the heartbeat file inside each run directory
+pub const HEARTBEAT_FILE: &str = "heartbeat";
+
+/// Contents of the heartbeat file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    /// When the heartbeat was written
+    pub timestamp: DateTime<Utc>,
+    /// Process ID of the ralph-loop process
+    pub pid: u32,
+    /// Iteration in progress
+    pub iteration: u32,
+}
+
+/// Write `heartbeat` to `run_dir`, replacing the previous one atomically
+pub fn write(run_dir: &Path, heartbeat: &Heartbeat) -> Result<()> {
+    let json = serde_json::to_string(heartbeat)
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+    let tmp = run_dir.join(format!("{HEARTBEAT_FILE}.tmp"));
+    std::fs::write(&tmp, json).map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+    std::fs::rename(&tmp, run_dir.join(HEARTBEAT_FILE))
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))
+}
+
+/// Read the heartbeat of the run in `run_dir`
+pub fn read(run_dir: &Path) -> Result<Heartbeat> {
+    let content = std::fs::read_to_string(run_dir.join(HEARTBEAT_FILE))
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+    serde_json::from_str(&content).map_err(|e| RalphError::JsonParseError(e.to_string()))
+}
+
+/// Background task writing heartbeats; stopped when dropped
+pub struct HeartbeatTask {
+    handle: JoinHandle<()>,
+}
+
+impl HeartbeatTask {
+    /// Start writing a heartbeat to `run_dir` every `interval`
+    pub fn spawn(run_dir: PathBuf, state: Arc<SharedState>, interval: Duration) -> Self {
+        let handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let heartbeat = Heartbeat {
+                    timestamp: Utc::now(),
+                    pid: std::process::id(),
+                    iteration: state.snapshot().await.iteration,
+                };
+                if let Err(e) = write(&run_dir, &heartbeat) {
+                    warn!("Failed to write heartbeat: {}", e);
+                }
+            }
+        });
+        Self { handle }
+    }
+}
+
+impl Drop for HeartbeatTask {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn writes_heartbeat_until_dropped() {
+        let temp_dir = TempDir::new().unwrap();
+        let state = SharedState::new_shared();
+        state.increment_iteration().await;
+
+        let task = HeartbeatTask::spawn(
+            temp_dir.path().to_path_buf(),
+            Arc::clone(&state),
+            Duration::from_millis(10),
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(task);
+
+        let heartbeat = read(temp_dir.path()).unwrap();
+        assert_eq!(heartbeat.pid, std::process::id());
+        assert_eq!(heartbeat.iteration, 1);
+        assert!(!temp_dir.path().join("heartbeat.tmp").exists());
+    }
+}
@@ -0,0 +1,168 @@
+//! Single-shot job mode for containers and CI.
+//!
+//! `ralph-loop job` runs one loop without prompting, writes a
+//! `job-result.json` summary into the run directory, publishes the run
+//! directory to the configured artifact destinations and exits with the
+//! code of the outcome, see [`crate::run_result`].
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::JobConfig;
+use crate::error::{RalphError, Result};
+use crate::loop_controller::LoopResult;
+use crate::run_result::RunOutcome;
+use crate::store;
+
+/// Name of the job summary written into the run directory
+pub const JOB_RESULT_FILE: &str = "job-result.json";
+
+/// Exit code when the run finished but publishing its artifacts failed
+pub const EXIT_PUBLISH_FAILED: i32 = 5;
+
+/// How a job ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobOutcome {
+    /// The promise was found or the plan completed
+    Completed,
+    /// Max iterations were exhausted
+    MaxIterationsExceeded,
+    /// The run was interrupted or cancelled
+    Interrupted,
+    /// An error aborted the run
+    Failed,
+}
+
+impl JobOutcome {
+    /// Classify the result of a loop
+    pub fn from_result(result: &Result<LoopResult>) -> Self {
+        match result {
+            Ok(LoopResult::PromiseFulfilled { .. }) | Ok(LoopResult::PlanCompleted { .. }) => {
+                Self::Completed
+            }
+            Ok(LoopResult::Shutdown { .. }) | Err(RalphError::ShutdownRequested) => {
+                Self::Interrupted
+            }
+            Err(RalphError::MaxIterationsExceeded(_)) => Self::MaxIterationsExceeded,
+            Ok(LoopResult::Failed { .. }) | Err(_) => Self::Failed,
+        }
+    }
+}
+
+/// Contents of `job-result.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobResult {
+    /// How the job ended
+    pub outcome: JobOutcome,
+    /// Exit code of the process
+    pub exit_code: i32,
+    /// Run ID, if the run got far enough to create its directory
+    pub run_id: Option<String>,
+    /// Iterations run
+    pub iterations: Option<u32>,
+    /// Total cost reported by the backend
+    pub cost_usd: Option<f64>,
+    /// Error message for failed jobs
+    pub error: Option<String>,
+}
+
+impl JobResult {
+    /// Summarize the result of a loop
+    pub fn new(result: &Result<LoopResult>, run_id: Option<String>) -> Self {
+        let outcome = JobOutcome::from_result(result);
+        let (iterations, cost_usd) = match result {
+            Ok(LoopResult::PromiseFulfilled {
+                iterations,
+                cost_usd,
+                ..
+            })
+            | Ok(LoopResult::PlanCompleted {
+                iterations,
+                cost_usd,
+                ..
+            }) => (Some(*iterations), *cost_usd),
+            Ok(LoopResult::Shutdown { iterations }) | Ok(LoopResult::Failed { iterations, .. }) => {
+                (Some(*iterations), None)
+            }
+            Err(RalphError::MaxIterationsExceeded(max)) => (Some(*max), None),
+            Err(_) => (None, None),
+        };
+        Self {
+            outcome,
+            exit_code: RunOutcome::from_result(result).exit_code(),
+            run_id,
+            iterations,
+            cost_usd,
+            error: result.as_ref().err().map(ToString::to_string),
+        }
+    }
+}
+
+/// Write `job-result.json` into `run_dir` and publish the run directory to
+/// every configured destination. Returns where it was published.
+pub fn publish(run_dir: &Path, job_result: &JobResult, config: &JobConfig) -> Result<Vec<String>> {
+    let json = serde_json::to_string_pretty(job_result)
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+    fs::write(run_dir.join(JOB_RESULT_FILE), json)
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+
+    let name = run_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "run".to_string());
+
+    let mut destinations = Vec::new();
+    for store in store::configured(config) {
+        let location = store.upload(run_dir, &name)?;
+        info!("Published run artifacts to {}", location);
+        destinations.push(location);
+    }
+
+    Ok(destinations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn publish_copies_run_dir_with_job_result() {
+        let temp_dir = TempDir::new().unwrap();
+        let run_dir = temp_dir.path().join("runs").join("run-1");
+        fs::create_dir_all(run_dir.join("nested")).unwrap();
+        fs::write(run_dir.join("nested").join("file.txt"), "data").unwrap();
+        let artifacts_dir = temp_dir.path().join("artifacts");
+
+        let result: Result<LoopResult> = Ok(LoopResult::PromiseFulfilled {
+            iterations: 2,
+            promise: "DONE".to_string(),
+            summary: None,
+            cost_usd: Some(0.5),
+        });
+        let job_result = JobResult::new(&result, Some("run-1".to_string()));
+        let config = JobConfig {
+            artifacts_dir: Some(artifacts_dir.clone()),
+            ..JobConfig::default()
+        };
+
+        let destinations = publish(&run_dir, &job_result, &config).unwrap();
+
+        assert_eq!(destinations.len(), 1);
+        let published = artifacts_dir.join("run-1");
+        assert_eq!(
+            fs::read_to_string(published.join("nested").join("file.txt")).unwrap(),
+            "data"
+        );
+        let summary: JobResult =
+            serde_json::from_str(&fs::read_to_string(published.join(JOB_RESULT_FILE)).unwrap())
+                .unwrap();
+        assert_eq!(summary.outcome, JobOutcome::Completed);
+        assert_eq!(summary.exit_code, 0);
+        assert_eq!(summary.iterations, Some(2));
+    }
+}
@@ -1,10 +1,14 @@
 //! JSON event parsing for supported coding agent CLIs.
 
-use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
-use crate::config::AgentProvider;
+use crate::config::{AgentProvider, TokenAccounting};
 use crate::error::{RalphError, Result};
+use crate::tools::{ToolCall, ToolOutput};
 
 /// Token usage statistics from an agent result event
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -26,61 +30,211 @@ impl TokenUsage {
     pub fn total(&self) -> usize {
         self.input_tokens + self.output_tokens
     }
+
+    /// Tokens occupying the context window: input including cache reads and
+    /// writes, plus output
+    pub fn context_tokens(&self) -> usize {
+        self.input_tokens
+            + self.cache_creation_input_tokens
+            + self.cache_read_input_tokens
+            + self.output_tokens
+    }
+
+    /// Tokens counted under `accounting`
+    pub fn counted(&self, accounting: TokenAccounting) -> usize {
+        match accounting {
+            TokenAccounting::Billed => self.total(),
+            TokenAccounting::Context => self.context_tokens(),
+        }
+    }
+
+    /// Add the usage of another request
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+        self.cached_input_tokens += other.cached_input_tokens;
+    }
+
+    /// Fold in a cumulative usage report, keeping the largest count seen for
+    /// each field (streamed reports may leave fields out)
+    pub fn merge(&mut self, other: &TokenUsage) {
+        self.input_tokens = self.input_tokens.max(other.input_tokens);
+        self.output_tokens = self.output_tokens.max(other.output_tokens);
+        self.cache_creation_input_tokens = self
+            .cache_creation_input_tokens
+            .max(other.cache_creation_input_tokens);
+        self.cache_read_input_tokens = self
+            .cache_read_input_tokens
+            .max(other.cache_read_input_tokens);
+        self.cached_input_tokens = self.cached_input_tokens.max(other.cached_input_tokens);
+    }
+}
+
+/// Part of a tool call requested by an API backend. Streamed calls arrive in
+/// pieces that share an `index`; the arguments are concatenated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCallDelta {
+    pub index: usize,
+    pub id: Option<String>,
+    pub name: Option<String>,
+    /// JSON-encoded arguments, or a fragment of them
+    pub arguments: String,
 }
 
 /// Content block within an assistant message
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type")]
-pub enum ContentBlock {
-    #[serde(rename = "text")]
-    Text { text: String },
-    #[serde(rename = "tool_use")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentBlock<'a> {
+    Text {
+        #[serde(borrow)]
+        text: Cow<'a, str>,
+    },
+    /// Extended reasoning shown before the answer
+    Thinking {
+        #[serde(default, borrow)]
+        thinking: Cow<'a, str>,
+    },
     ToolUse {
-        id: String,
-        name: String,
-        input: Value,
+        #[serde(default, borrow)]
+        id: Cow<'a, str>,
+        #[serde(default, borrow)]
+        name: Cow<'a, str>,
+        #[serde(default)]
+        input: Option<ToolInput>,
     },
     #[serde(other)]
     Other,
 }
 
+/// The arguments of a tool use that name the file it works on
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ToolInput {
+    #[serde(default, deserialize_with = "lenient_string")]
+    file_path: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    notebook_path: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    path: Option<String>,
+}
+
+/// A tool the agent asked to run, in an assistant message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolUse {
+    /// ID the tool's result refers to
+    pub id: String,
+    pub name: String,
+    /// File the tool works on, if its input names one
+    pub path: Option<String>,
+    /// ID of the Task tool use whose sub-agent asked for this one
+    pub parent: Option<String>,
+}
+
+/// The result of a tool call handed back to the model
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolResultRef {
+    /// ID of the tool use the result belongs to
+    pub tool_use_id: String,
+    /// Whether the tool reported a failure
+    pub is_error: bool,
+    /// Text of the result
+    pub text: String,
+}
+
 /// A normalized parsed JSON event from a supported agent backend
 #[derive(Debug, Clone)]
 pub enum AgentEvent {
     /// Session or thread start
     SessionStart { session_id: Option<String> },
-    /// Assistant message content
-    AssistantMessage { text: String },
+    /// Assistant message content, with the turn's usage if reported and the
+    /// tools it asked to run. `thinking` is the reasoning that came with it,
+    /// which isn't part of the answer.
+    AssistantMessage {
+        text: String,
+        thinking: String,
+        usage: Option<TokenUsage>,
+        tool_uses: Vec<ToolUse>,
+        /// ID of the message; the usage of each message is repeated with
+        /// every one of its content blocks
+        message_id: Option<String>,
+        /// ID of the Task tool use, if a sub-agent it started sent the message
+        parent_tool_use_id: Option<String>,
+    },
+    /// Part of an assistant message streamed in chunks; `done` marks its end
+    TextDelta { text: String, done: bool },
+    /// Tool calls requested by an API backend, possibly in pieces
+    ToolCalls { calls: Vec<ToolCallDelta> },
+    /// Cumulative usage reported while a response is streaming
+    Usage {
+        session_id: Option<String>,
+        usage: TokenUsage,
+    },
+    /// Tool calls finished and their results were handed back to the model;
+    /// `results` is empty for backends that don't say which calls
+    ToolResult { results: Vec<ToolResultRef> },
     /// Final result with token usage statistics
     Result {
         session_id: Option<String>,
         usage: TokenUsage,
+        /// Total cost reported by the backend, if any
+        cost_usd: Option<f64>,
+        /// Final answer of the session, if the backend repeats it here
+        text: Option<String>,
     },
     /// Unknown event type (for forward compatibility)
     Unknown { event_type: String, raw: Value },
 }
 
 impl AgentEvent {
-    /// Parse a JSON line into a normalized agent event
+    /// Parse a JSON line into a normalized agent event.
+    ///
+    /// Known events are deserialized into typed structs, borrowing from the
+    /// line where possible; payloads ralph-loop doesn't look at, such as tool
+    /// results, are skipped. Valid JSON of any other type or shape becomes
+    /// `Unknown` with the raw value.
     pub fn parse(provider: AgentProvider, line: &str) -> Result<Self> {
-        let line = line.trim();
+        let mut line = line.trim();
+        if matches!(provider, AgentProvider::Openai | AgentProvider::Anthropic) {
+            // Server-sent events: `data: {...}`, terminated by `data: [DONE]`
+            // for OpenAI. The `event:` lines repeat the type found in the data.
+            if line.starts_with("event:") {
+                return Err(RalphError::JsonParseError("SSE event name".to_string()));
+            }
+            line = line.strip_prefix("data:").map(str::trim).unwrap_or(line);
+            if line == "[DONE]" {
+                return Ok(AgentEvent::Unknown {
+                    event_type: "done".to_string(),
+                    raw: Value::Null,
+                });
+            }
+        }
         if line.is_empty() {
             return Err(RalphError::JsonParseError("Empty line".to_string()));
         }
+        if line.starts_with(LOCAL_TOOL_PREFIX) {
+            if let Ok(event) = serde_json::from_str::<LocalToolEvent>(line) {
+                return Ok(event.into_event());
+            }
+        }
 
-        let value: Value =
-            serde_json::from_str(line).map_err(|e| RalphError::JsonParseError(e.to_string()))?;
-
-        match provider {
-            AgentProvider::Claude => parse_claude_event(value),
-            AgentProvider::Codex => parse_codex_event(value),
+        let event = match provider {
+            AgentProvider::Claude => serde_json::from_str(line).map(ClaudeEvent::into_event),
+            AgentProvider::Codex => serde_json::from_str(line).map(CodexEvent::into_event),
+            AgentProvider::Openai => serde_json::from_str(line).map(OpenAiChunk::into_event),
+            AgentProvider::Anthropic => serde_json::from_str(line).map(AnthropicEvent::into_event),
+            AgentProvider::Ollama => serde_json::from_str(line).map(OllamaLine::into_event),
+        };
+        match event {
+            Ok(Some(event)) => Ok(event),
+            Ok(None) | Err(_) => unknown(line),
         }
     }
 
     /// Extract plain text content from an assistant event
     pub fn extract_text(&self) -> Option<&str> {
         match self {
-            AgentEvent::AssistantMessage { text } => Some(text),
+            AgentEvent::AssistantMessage { text, .. } => Some(text),
             _ => None,
         }
     }
@@ -88,7 +242,7 @@ impl AgentEvent {
     /// Check if this event contains token usage info
     pub fn get_usage(&self) -> Option<&TokenUsage> {
         match self {
-            AgentEvent::Result { usage, .. } => Some(usage),
+            AgentEvent::Result { usage, .. } | AgentEvent::Usage { usage, .. } => Some(usage),
             _ => None,
         }
     }
@@ -98,110 +252,696 @@ impl AgentEvent {
         match self {
             AgentEvent::SessionStart { .. } => "session_start",
             AgentEvent::AssistantMessage { .. } => "assistant_message",
+            AgentEvent::TextDelta { .. } => "text_delta",
+            AgentEvent::ToolCalls { .. } => "tool_calls",
+            AgentEvent::Usage { .. } => "usage",
+            AgentEvent::ToolResult { .. } => "tool_result",
             AgentEvent::Result { .. } => "result",
             AgentEvent::Unknown { event_type, .. } => event_type,
         }
     }
 }
 
-fn parse_claude_event(value: Value) -> Result<AgentEvent> {
-    let event_type = value
-        .get("type")
-        .and_then(|t| t.as_str())
-        .unwrap_or("unknown");
-
-    match event_type {
-        "init" | "system" => Ok(AgentEvent::SessionStart {
-            session_id: value
-                .get("session_id")
-                .and_then(|s| s.as_str())
-                .map(String::from),
-        }),
-        "assistant" => {
-            let content: Vec<ContentBlock> = if let Some(message) = value.get("message") {
-                message
-                    .get("content")
-                    .and_then(|c| serde_json::from_value(c.clone()).ok())
-                    .unwrap_or_default()
-            } else if let Some(content) = value.get("content") {
-                serde_json::from_value(content.clone()).unwrap_or_default()
-            } else {
-                Vec::new()
-            };
+/// Start of the lines ralph-loop writes into the output of an API backend
+/// for the tool calls it executes between requests
+const LOCAL_TOOL_PREFIX: &str = r#"{"type":"ralph_tool_"#;
 
-            let text = content
-                .iter()
-                .filter_map(|block| match block {
-                    ContentBlock::Text { text } => Some(text.as_str()),
-                    _ => None,
-                })
-                .collect::<Vec<_>>()
-                .join("\n");
+/// A tool call ralph-loop executed for an API backend, recorded in the output
+/// so it is monitored like the tool events of the CLI agents' streams
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LocalToolEvent {
+    /// The calls the model asked for, with the usage of the request it
+    /// asked in
+    RalphToolUse {
+        calls: Vec<ToolCall>,
+        #[serde(default, deserialize_with = "lenient_usage")]
+        usage: Option<TokenUsage>,
+    },
+    /// The result of one of the calls
+    RalphToolResult {
+        tool_call_id: String,
+        name: String,
+        #[serde(flatten)]
+        output: ToolOutput,
+    },
+}
+
+impl LocalToolEvent {
+    /// The event as a line of output
+    pub fn to_line(&self) -> String {
+        serde_json::to_string(self).expect("tool events serialize to JSON")
+    }
 
-            Ok(AgentEvent::AssistantMessage { text })
+    fn into_event(self) -> AgentEvent {
+        match self {
+            LocalToolEvent::RalphToolUse { calls, usage } => AgentEvent::AssistantMessage {
+                text: String::new(),
+                thinking: String::new(),
+                usage,
+                tool_uses: calls
+                    .into_iter()
+                    .map(|call| ToolUse {
+                        path: serde_json::from_str::<ToolInput>(&call.arguments)
+                            .ok()
+                            .and_then(|input| {
+                                input.file_path.or(input.notebook_path).or(input.path)
+                            }),
+                        id: call.id,
+                        name: call.name,
+                        parent: None,
+                    })
+                    .collect(),
+                message_id: None,
+                parent_tool_use_id: None,
+            },
+            LocalToolEvent::RalphToolResult {
+                tool_call_id,
+                output,
+                ..
+            } => AgentEvent::ToolResult {
+                results: vec![ToolResultRef {
+                    tool_use_id: tool_call_id,
+                    is_error: output.is_error,
+                    text: output.content,
+                }],
+            },
         }
-        "result" => {
-            let session_id = value
-                .get("session_id")
-                .and_then(|s| s.as_str())
-                .map(String::from);
-            let usage = value
-                .get("usage")
-                .and_then(|u| serde_json::from_value(u.clone()).ok())
-                .unwrap_or_default();
-            Ok(AgentEvent::Result { session_id, usage })
+    }
+}
+
+/// Keep `line` as an event of a type or shape ralph-loop doesn't handle.
+/// Backends without a `type` field report errors in an `error` field.
+fn unknown(line: &str) -> Result<AgentEvent> {
+    let raw: Value =
+        serde_json::from_str(line).map_err(|e| RalphError::JsonParseError(e.to_string()))?;
+    let event_type = match raw.get("type").and_then(|t| t.as_str()) {
+        Some(event_type) => event_type,
+        None if raw.get("error").is_some() => "error",
+        None => "unknown",
+    };
+    Ok(AgentEvent::Unknown {
+        event_type: event_type.to_string(),
+        raw,
+    })
+}
+
+/// Usage that doesn't parse counts as not reported, rather than failing the event
+fn lenient_usage<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<TokenUsage>, D::Error> {
+    Ok(Option::<Value>::deserialize(deserializer)?.and_then(|u| serde_json::from_value(u).ok()))
+}
+
+/// A field that isn't a string counts as not reported, rather than failing the event
+fn lenient_string<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Option<String>, D::Error> {
+    Ok(match Option::<Value>::deserialize(deserializer)? {
+        Some(Value::String(text)) => Some(text),
+        _ => None,
+    })
+}
+
+/// Text of the text blocks of a message, one per line, and of its
+/// thinking blocks, and its tool uses, made by the sub-agent of the Task
+/// tool use `parent` if set
+fn split_blocks(
+    blocks: Vec<ContentBlock<'_>>,
+    parent: Option<String>,
+) -> (String, String, Vec<ToolUse>) {
+    let mut text = String::new();
+    let mut thinking = String::new();
+    let mut tool_uses = Vec::new();
+    let push_line = |lines: &mut String, next: &str| {
+        if !lines.is_empty() {
+            lines.push('\n');
         }
-        _ => Ok(AgentEvent::Unknown {
-            event_type: event_type.to_string(),
-            raw: value,
-        }),
-    }
-}
-
-fn parse_codex_event(value: Value) -> Result<AgentEvent> {
-    let event_type = value
-        .get("type")
-        .and_then(|t| t.as_str())
-        .unwrap_or("unknown");
-
-    match event_type {
-        "thread.started" => Ok(AgentEvent::SessionStart {
-            session_id: value
-                .get("thread_id")
-                .and_then(|s| s.as_str())
-                .map(String::from),
-        }),
-        "item.completed" => {
-            let item = value.get("item").cloned().unwrap_or(Value::Null);
-            let item_type = item.get("type").and_then(|t| t.as_str()).unwrap_or("");
-            if item_type == "agent_message" {
-                let text = item
-                    .get("text")
-                    .and_then(|t| t.as_str())
-                    .unwrap_or("")
-                    .to_string();
-                Ok(AgentEvent::AssistantMessage { text })
-            } else {
-                Ok(AgentEvent::Unknown {
-                    event_type: event_type.to_string(),
-                    raw: value,
-                })
+        lines.push_str(next);
+    };
+    for block in blocks {
+        match block {
+            ContentBlock::Text { text: next } => push_line(&mut text, &next),
+            ContentBlock::Thinking { thinking: next } => push_line(&mut thinking, &next),
+            ContentBlock::ToolUse { id, name, input } => tool_uses.push(ToolUse {
+                id: id.into_owned(),
+                name: name.into_owned(),
+                path: input
+                    .and_then(|input| input.file_path.or(input.notebook_path).or(input.path)),
+                parent: parent.clone(),
+            }),
+            ContentBlock::Other => {}
+        }
+    }
+    (text, thinking, tool_uses)
+}
+
+/// An event of Claude's `stream-json` output
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeEvent<'a> {
+    #[serde(alias = "init")]
+    System {
+        #[serde(default)]
+        session_id: Option<String>,
+    },
+    Assistant {
+        #[serde(default, borrow)]
+        message: Option<ClaudeMessage<'a>>,
+        /// Older versions put the content on the event itself
+        #[serde(default, borrow)]
+        content: Vec<ContentBlock<'a>>,
+        /// Set on the messages of a sub-agent started by the Task tool
+        #[serde(default, deserialize_with = "lenient_string")]
+        parent_tool_use_id: Option<String>,
+    },
+    /// Tool results; only which calls they belong to is looked at
+    User {
+        #[serde(default)]
+        message: Option<ClaudeUserMessage>,
+    },
+    Result {
+        #[serde(default)]
+        session_id: Option<String>,
+        #[serde(default, deserialize_with = "lenient_usage")]
+        usage: Option<TokenUsage>,
+        #[serde(default)]
+        total_cost_usd: Option<f64>,
+        #[serde(default, deserialize_with = "lenient_string")]
+        result: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct ClaudeMessage<'a> {
+    #[serde(default, deserialize_with = "lenient_string")]
+    id: Option<String>,
+    #[serde(default, borrow)]
+    content: Vec<ContentBlock<'a>>,
+    #[serde(default, deserialize_with = "lenient_usage")]
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize)]
+struct ClaudeUserMessage {
+    #[serde(default)]
+    content: Option<ClaudeUserContent>,
+}
+
+/// Content of a user message: tool results, or anything else such as a
+/// plain prompt, which isn't looked into
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ClaudeUserContent {
+    Blocks(Vec<ClaudeUserBlock>),
+    #[allow(dead_code)]
+    Other(IgnoredAny),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeUserBlock {
+    ToolResult {
+        #[serde(default, deserialize_with = "lenient_string")]
+        tool_use_id: Option<String>,
+        #[serde(default)]
+        is_error: Option<bool>,
+        #[serde(default)]
+        content: Option<ClaudeToolOutput>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Output of a tool: text, or a list of parts such as text and images
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ClaudeToolOutput {
+    Text(String),
+    Parts(Vec<ClaudeToolOutputPart>),
+    #[allow(dead_code)]
+    Other(IgnoredAny),
+}
+
+#[derive(Deserialize)]
+struct ClaudeToolOutputPart {
+    #[serde(default, deserialize_with = "lenient_string")]
+    text: Option<String>,
+}
+
+impl ClaudeToolOutput {
+    /// Text of the output
+    fn into_text(self) -> String {
+        match self {
+            ClaudeToolOutput::Text(text) => text,
+            ClaudeToolOutput::Parts(parts) => {
+                parts.into_iter().filter_map(|part| part.text).collect()
             }
+            ClaudeToolOutput::Other(_) => String::new(),
         }
-        "turn.completed" => {
-            let usage = value
-                .get("usage")
-                .and_then(|u| serde_json::from_value(u.clone()).ok())
-                .unwrap_or_default();
-            Ok(AgentEvent::Result {
-                session_id: None,
+    }
+}
+
+impl ClaudeEvent<'_> {
+    fn into_event(self) -> Option<AgentEvent> {
+        Some(match self {
+            ClaudeEvent::System { session_id } => AgentEvent::SessionStart { session_id },
+            ClaudeEvent::Assistant {
+                message,
+                content,
+                parent_tool_use_id,
+            } => {
+                let (content, usage, message_id) = match message {
+                    Some(message) => (message.content, message.usage, message.id),
+                    None => (content, None, None),
+                };
+                let (text, thinking, tool_uses) = split_blocks(content, parent_tool_use_id.clone());
+                AgentEvent::AssistantMessage {
+                    text,
+                    thinking,
+                    usage,
+                    tool_uses,
+                    message_id,
+                    parent_tool_use_id,
+                }
+            }
+            ClaudeEvent::User { message } => {
+                let blocks = match message.and_then(|message| message.content) {
+                    Some(ClaudeUserContent::Blocks(blocks)) => blocks,
+                    _ => Vec::new(),
+                };
+                AgentEvent::ToolResult {
+                    results: blocks
+                        .into_iter()
+                        .filter_map(|block| match block {
+                            ClaudeUserBlock::ToolResult {
+                                tool_use_id: Some(tool_use_id),
+                                is_error,
+                                content,
+                            } => Some(ToolResultRef {
+                                tool_use_id,
+                                is_error: is_error.unwrap_or(false),
+                                text: content.map(ClaudeToolOutput::into_text).unwrap_or_default(),
+                            }),
+                            _ => None,
+                        })
+                        .collect(),
+                }
+            }
+            ClaudeEvent::Result {
+                session_id,
                 usage,
-            })
+                total_cost_usd,
+                result,
+            } => AgentEvent::Result {
+                session_id,
+                usage: usage.unwrap_or_default(),
+                cost_usd: total_cost_usd,
+                text: result,
+            },
+            ClaudeEvent::Other => return None,
+        })
+    }
+}
+
+/// An event of `codex exec --json`
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum CodexEvent<'a> {
+    #[serde(rename = "thread.started")]
+    ThreadStarted {
+        #[serde(default)]
+        thread_id: Option<String>,
+    },
+    #[serde(rename = "item.completed")]
+    ItemCompleted {
+        #[serde(borrow)]
+        item: CodexItem<'a>,
+    },
+    #[serde(rename = "turn.completed")]
+    TurnCompleted {
+        #[serde(default, deserialize_with = "lenient_usage")]
+        usage: Option<TokenUsage>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum CodexItem<'a> {
+    AgentMessage {
+        #[serde(default, borrow)]
+        text: Cow<'a, str>,
+    },
+    CommandExecution,
+    #[serde(other)]
+    Other,
+}
+
+impl CodexEvent<'_> {
+    fn into_event(self) -> Option<AgentEvent> {
+        Some(match self {
+            CodexEvent::ThreadStarted { thread_id } => AgentEvent::SessionStart {
+                session_id: thread_id,
+            },
+            CodexEvent::ItemCompleted { item } => match item {
+                CodexItem::AgentMessage { text } => AgentEvent::AssistantMessage {
+                    text: text.into_owned(),
+                    thinking: String::new(),
+                    usage: None,
+                    tool_uses: Vec::new(),
+                    message_id: None,
+                    parent_tool_use_id: None,
+                },
+                CodexItem::CommandExecution => AgentEvent::ToolResult {
+                    results: Vec::new(),
+                },
+                CodexItem::Other => return None,
+            },
+            CodexEvent::TurnCompleted { usage } => AgentEvent::Result {
+                session_id: None,
+                usage: usage.unwrap_or_default(),
+                cost_usd: None,
+                text: None,
+            },
+            CodexEvent::Other => return None,
+        })
+    }
+}
+
+/// A chunk of an OpenAI-compatible streaming chat completion
+#[derive(Deserialize)]
+struct OpenAiChunk<'a> {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    error: Option<IgnoredAny>,
+    /// With `stream_options.include_usage` the last chunk carries the usage
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+    #[serde(default, borrow)]
+    choices: Vec<OpenAiChoice<'a>>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiUsage {
+    #[serde(default)]
+    prompt_tokens: usize,
+    #[serde(default)]
+    completion_tokens: usize,
+}
+
+#[derive(Deserialize)]
+struct OpenAiChoice<'a> {
+    #[serde(default, borrow)]
+    delta: OpenAiDelta<'a>,
+    #[serde(default)]
+    finish_reason: Option<IgnoredAny>,
+}
+
+#[derive(Default, Deserialize)]
+struct OpenAiDelta<'a> {
+    #[serde(default)]
+    role: Option<IgnoredAny>,
+    #[serde(default, borrow)]
+    content: Option<Cow<'a, str>>,
+    #[serde(default)]
+    tool_calls: Option<Vec<OpenAiToolCall>>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiToolCall {
+    #[serde(default)]
+    index: Option<usize>,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: OpenAiFunction,
+}
+
+#[derive(Default, Deserialize)]
+struct OpenAiFunction {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+}
+
+impl OpenAiChunk<'_> {
+    fn into_event(self) -> Option<AgentEvent> {
+        if self.error.is_some() {
+            return None;
+        }
+        if let Some(usage) = self.usage {
+            return Some(AgentEvent::Result {
+                session_id: self.id,
+                usage: TokenUsage {
+                    input_tokens: usage.prompt_tokens,
+                    output_tokens: usage.completion_tokens,
+                    ..TokenUsage::default()
+                },
+                cost_usd: None,
+                text: None,
+            });
+        }
+        let choice = self.choices.into_iter().next()?;
+        let delta = choice.delta;
+        if let Some(calls) = delta.tool_calls {
+            let calls = calls
+                .into_iter()
+                .enumerate()
+                .map(|(position, call)| ToolCallDelta {
+                    index: call.index.unwrap_or(position),
+                    id: call.id,
+                    name: call.function.name,
+                    arguments: call.function.arguments.unwrap_or_default(),
+                })
+                .collect();
+            return Some(AgentEvent::ToolCalls { calls });
         }
-        _ => Ok(AgentEvent::Unknown {
-            event_type: event_type.to_string(),
-            raw: value,
-        }),
+        let text = delta.content.unwrap_or_default();
+        let done = choice.finish_reason.is_some();
+        if text.is_empty() && !done && delta.role.is_some() {
+            return Some(AgentEvent::SessionStart {
+                session_id: self.id,
+            });
+        }
+        Some(AgentEvent::TextDelta {
+            text: text.into_owned(),
+            done,
+        })
+    }
+}
+
+/// An event of an Anthropic Messages API stream
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicEvent<'a> {
+    /// Carries the input tokens before any output is generated
+    MessageStart {
+        message: AnthropicMessage,
+    },
+    ContentBlockStart {
+        #[serde(default)]
+        index: usize,
+        content_block: AnthropicBlock,
+    },
+    ContentBlockDelta {
+        #[serde(default)]
+        index: usize,
+        #[serde(default, borrow)]
+        delta: Option<AnthropicDelta<'a>>,
+    },
+    ContentBlockStop,
+    /// Output tokens so far
+    MessageDelta {
+        #[serde(default, deserialize_with = "lenient_usage")]
+        usage: Option<TokenUsage>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct AnthropicMessage {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_usage")]
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicBlock {
+    ToolUse {
+        #[serde(default)]
+        id: Option<String>,
+        #[serde(default)]
+        name: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicDelta<'a> {
+    InputJsonDelta {
+        #[serde(default, borrow)]
+        partial_json: Cow<'a, str>,
+    },
+    TextDelta {
+        #[serde(default, borrow)]
+        text: Cow<'a, str>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl AnthropicEvent<'_> {
+    fn into_event(self) -> Option<AgentEvent> {
+        Some(match self {
+            AnthropicEvent::MessageStart { message } => AgentEvent::Usage {
+                session_id: message.id,
+                usage: message.usage.unwrap_or_default(),
+            },
+            AnthropicEvent::ContentBlockStart {
+                index,
+                content_block: AnthropicBlock::ToolUse { id, name },
+            } => AgentEvent::ToolCalls {
+                calls: vec![ToolCallDelta {
+                    index,
+                    id,
+                    name,
+                    arguments: String::new(),
+                }],
+            },
+            AnthropicEvent::ContentBlockStart { .. } => return None,
+            AnthropicEvent::ContentBlockDelta { index, delta } => match delta {
+                Some(AnthropicDelta::InputJsonDelta { partial_json }) => AgentEvent::ToolCalls {
+                    calls: vec![ToolCallDelta {
+                        index,
+                        id: None,
+                        name: None,
+                        arguments: partial_json.into_owned(),
+                    }],
+                },
+                Some(AnthropicDelta::TextDelta { text }) => AgentEvent::TextDelta {
+                    text: text.into_owned(),
+                    done: false,
+                },
+                Some(AnthropicDelta::Other) | None => AgentEvent::TextDelta {
+                    text: String::new(),
+                    done: false,
+                },
+            },
+            AnthropicEvent::ContentBlockStop => AgentEvent::TextDelta {
+                text: String::new(),
+                done: true,
+            },
+            AnthropicEvent::MessageDelta { usage } => AgentEvent::Usage {
+                session_id: None,
+                usage: usage.unwrap_or_default(),
+            },
+            AnthropicEvent::Other => return None,
+        })
+    }
+}
+
+/// A line of an Ollama `/api/chat` stream
+#[derive(Deserialize)]
+struct OllamaLine<'a> {
+    #[serde(default)]
+    error: Option<IgnoredAny>,
+    #[serde(default, borrow)]
+    message: Option<OllamaMessage<'a>>,
+    #[serde(default)]
+    done: bool,
+    /// The final line carries the counts for the whole request
+    #[serde(default)]
+    prompt_eval_count: usize,
+    #[serde(default)]
+    eval_count: usize,
+}
+
+#[derive(Deserialize)]
+struct OllamaMessage<'a> {
+    #[serde(default, borrow)]
+    content: Option<Cow<'a, str>>,
+    /// Ollama sends complete tool calls in a single line
+    #[serde(default)]
+    tool_calls: Vec<OllamaToolCall>,
+}
+
+#[derive(Deserialize)]
+struct OllamaToolCall {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: Option<OllamaFunction>,
+}
+
+#[derive(Deserialize)]
+struct OllamaFunction {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<Value>,
+}
+
+impl OllamaLine<'_> {
+    fn into_event(self) -> Option<AgentEvent> {
+        if self.error.is_some() {
+            return None;
+        }
+        let (text, tool_calls) = match self.message {
+            Some(message) => (message.content.unwrap_or_default(), message.tool_calls),
+            None => (Cow::Borrowed(""), Vec::new()),
+        };
+        if !tool_calls.is_empty() {
+            let calls = tool_calls
+                .into_iter()
+                .enumerate()
+                .map(|(index, call)| {
+                    let (name, arguments) = match call.function {
+                        Some(function) => (
+                            function.name,
+                            function
+                                .arguments
+                                .map(|args| args.to_string())
+                                .unwrap_or_default(),
+                        ),
+                        None => (None, String::new()),
+                    };
+                    ToolCallDelta {
+                        index,
+                        id: call.id,
+                        name,
+                        arguments,
+                    }
+                })
+                .collect();
+            return Some(AgentEvent::ToolCalls { calls });
+        }
+        if !self.done {
+            return Some(AgentEvent::TextDelta {
+                text: text.into_owned(),
+                done: false,
+            });
+        }
+        Some(AgentEvent::Result {
+            session_id: None,
+            usage: TokenUsage {
+                input_tokens: self.prompt_eval_count,
+                output_tokens: self.eval_count,
+                ..TokenUsage::default()
+            },
+            cost_usd: None,
+            text: None,
+        })
     }
 }
 
@@ -217,13 +957,112 @@ mod tests {
         assert_eq!(event.extract_text(), Some("Hello, world!"));
     }
 
+    #[test]
+    fn thinking_blocks_are_kept_apart_from_the_text() {
+        let json = r#"{"type":"assistant","message":{"content":[{"type":"thinking","thinking":"The parser","signature":"abc"},{"type":"redacted_thinking","data":"xyz"},{"type":"thinking","thinking":"needs a test"},{"type":"text","text":"Adding a test"}]}}"#;
+        let AgentEvent::AssistantMessage { text, thinking, .. } =
+            AgentEvent::parse(AgentProvider::Claude, json).unwrap()
+        else {
+            panic!("expected assistant event");
+        };
+        assert_eq!(text, "Adding a test");
+        assert_eq!(thinking, "The parser\nneeds a test");
+    }
+
+    #[test]
+    fn test_parse_claude_assistant_usage_and_tool_result() {
+        let json = r#"{"type":"assistant","message":{"content":[],"usage":{"input_tokens":5,"cache_creation_input_tokens":100,"cache_read_input_tokens":2000,"output_tokens":50}}}"#;
+        let event = AgentEvent::parse(AgentProvider::Claude, json).unwrap();
+
+        if let AgentEvent::AssistantMessage { usage, .. } = event {
+            assert_eq!(usage.unwrap().context_tokens(), 2155);
+        } else {
+            panic!("Expected assistant event");
+        }
+
+        let json =
+            r#"{"type":"user","message":{"content":[{"type":"tool_result","content":"ok"}]}}"#;
+        let event = AgentEvent::parse(AgentProvider::Claude, json).unwrap();
+        assert!(matches!(event, AgentEvent::ToolResult { .. }));
+    }
+
+    #[test]
+    fn tool_uses_and_results_carry_their_ids() {
+        let json = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Checking"},{"type":"tool_use","id":"toolu_1","name":"Bash","input":{"command":"ls"}}]}}"#;
+        let AgentEvent::AssistantMessage {
+            text, tool_uses, ..
+        } = AgentEvent::parse(AgentProvider::Claude, json).unwrap()
+        else {
+            panic!("expected assistant event");
+        };
+        assert_eq!(text, "Checking");
+        assert_eq!(
+            tool_uses,
+            [ToolUse {
+                id: "toolu_1".to_string(),
+                name: "Bash".to_string(),
+                path: None,
+                parent: None,
+            }]
+        );
+
+        let json = r#"{"type":"user","message":{"content":[{"type":"tool_result","tool_use_id":"toolu_1","is_error":true,"content":[{"type":"text","text":"boom"}]}]}}"#;
+        let AgentEvent::ToolResult { results } =
+            AgentEvent::parse(AgentProvider::Claude, json).unwrap()
+        else {
+            panic!("expected tool result event");
+        };
+        assert_eq!(
+            results,
+            [ToolResultRef {
+                tool_use_id: "toolu_1".to_string(),
+                is_error: true,
+                text: "boom".to_string()
+            }]
+        );
+
+        let json = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","id":"toolu_2","name":"Edit","input":{"file_path":"src/lib.rs","old_string":"a"}}]}}"#;
+        let AgentEvent::AssistantMessage { tool_uses, .. } =
+            AgentEvent::parse(AgentProvider::Claude, json).unwrap()
+        else {
+            panic!("expected assistant event");
+        };
+        assert_eq!(tool_uses[0].path.as_deref(), Some("src/lib.rs"));
+
+        // Sub-agents of the Task tool name the call that started them
+        let json = r#"{"type":"assistant","parent_tool_use_id":"toolu_task","message":{"content":[{"type":"tool_use","id":"toolu_3","name":"Grep","input":{}}]}}"#;
+        let AgentEvent::AssistantMessage { tool_uses, .. } =
+            AgentEvent::parse(AgentProvider::Claude, json).unwrap()
+        else {
+            panic!("expected assistant event");
+        };
+        assert_eq!(tool_uses[0].parent.as_deref(), Some("toolu_task"));
+
+        // A plain prompt is still a user event, without results
+        let json = r#"{"type":"user","message":{"content":"continue"}}"#;
+        let AgentEvent::ToolResult { results } =
+            AgentEvent::parse(AgentProvider::Claude, json).unwrap()
+        else {
+            panic!("expected tool result event");
+        };
+        assert!(results.is_empty());
+    }
+
     #[test]
     fn test_parse_claude_result_event() {
-        let json = r#"{"type":"result","session_id":"sess_123","usage":{"input_tokens":1000,"output_tokens":500},"total_cost_usd":0.05}"#;
+        let json = r#"{"type":"result","session_id":"sess_123","usage":{"input_tokens":1000,"output_tokens":500},"total_cost_usd":0.05,"result":"All done"}"#;
         let event = AgentEvent::parse(AgentProvider::Claude, json).unwrap();
 
-        if let AgentEvent::Result { session_id, usage } = event {
+        if let AgentEvent::Result {
+            session_id,
+            usage,
+            cost_usd,
+            text,
+        } = event
+        {
             assert_eq!(session_id, Some("sess_123".to_string()));
+            assert_eq!(cost_usd, Some(0.05));
+            assert_eq!(text.as_deref(), Some("All done"));
             assert_eq!(usage.input_tokens, 1000);
             assert_eq!(usage.output_tokens, 500);
             assert_eq!(usage.total(), 1500);
@@ -232,6 +1071,62 @@ mod tests {
         }
     }
 
+    #[test]
+    fn unexpected_types_and_shapes_are_kept_as_unknown() {
+        for (line, expected) in [
+            (
+                r#"{"type":"rate_limit_event","retry_after":3}"#,
+                "rate_limit_event",
+            ),
+            (
+                r#"{"type":"assistant","message":{"content":"not a list"}}"#,
+                "assistant",
+            ),
+            (r#"{"session_id":"s"}"#, "unknown"),
+        ] {
+            let event = AgentEvent::parse(AgentProvider::Claude, line).unwrap();
+            let AgentEvent::Unknown { event_type, raw } = event else {
+                panic!("expected unknown event for {line}");
+            };
+            assert_eq!(event_type, expected);
+            assert_eq!(raw, serde_json::from_str::<Value>(line).unwrap());
+        }
+        assert!(AgentEvent::parse(AgentProvider::Claude, "{not json").is_err());
+    }
+
+    #[test]
+    fn skipped_payloads_and_bad_usage_do_not_fail_events() {
+        // Tool results aren't looked into, whatever their shape
+        let json = r#"{"type":"user","message":{"content":[{"type":"tool_result","content":[{"type":"image","source":{}}]}]}}"#;
+        assert!(matches!(
+            AgentEvent::parse(AgentProvider::Claude, json).unwrap(),
+            AgentEvent::ToolResult { .. }
+        ));
+
+        let json = r#"{"type":"result","session_id":"s","usage":{"input_tokens":null},"total_cost_usd":0.5,"result":{"unexpected":true}}"#;
+        let AgentEvent::Result {
+            session_id,
+            usage,
+            cost_usd,
+            text,
+        } = AgentEvent::parse(AgentProvider::Claude, json).unwrap()
+        else {
+            panic!("expected result event");
+        };
+        assert_eq!(session_id.as_deref(), Some("s"));
+        assert_eq!(usage.total(), 0);
+        assert_eq!(cost_usd, Some(0.5));
+        assert_eq!(text, None);
+    }
+
+    #[test]
+    fn text_blocks_are_joined_with_escapes_resolved() {
+        let json = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"plain"},{"type":"tool_use","id":"t","name":"Bash","input":{}},{"type":"text","text":"esc\"aped\n"}]}}"#;
+        let event = AgentEvent::parse(AgentProvider::Claude, json).unwrap();
+
+        assert_eq!(event.extract_text(), Some("plain\nesc\"aped\n"));
+    }
+
     #[test]
     fn test_parse_codex_thread_started_event() {
         let json = r#"{"type":"thread.started","thread_id":"thread_123"}"#;
@@ -257,7 +1152,10 @@ mod tests {
         let json = r#"{"type":"turn.completed","usage":{"input_tokens":17725,"cached_input_tokens":3456,"output_tokens":45}}"#;
         let event = AgentEvent::parse(AgentProvider::Codex, json).unwrap();
 
-        if let AgentEvent::Result { session_id, usage } = event {
+        if let AgentEvent::Result {
+            session_id, usage, ..
+        } = event
+        {
             assert_eq!(session_id, None);
             assert_eq!(usage.input_tokens, 17725);
             assert_eq!(usage.cached_input_tokens, 3456);
@@ -267,4 +1165,167 @@ mod tests {
             panic!("Expected result event");
         }
     }
+
+    #[test]
+    fn parses_openai_stream_chunks() {
+        let start = r#"data: {"id":"chatcmpl-1","choices":[{"index":0,"delta":{"role":"assistant","content":""},"finish_reason":null}]}"#;
+        let delta = r#"data: {"id":"chatcmpl-1","choices":[{"index":0,"delta":{"content":"Hel"},"finish_reason":null}]}"#;
+        let end = r#"data: {"id":"chatcmpl-1","choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}"#;
+        let usage = r#"data: {"id":"chatcmpl-1","choices":[],"usage":{"prompt_tokens":12,"completion_tokens":3,"total_tokens":15}}"#;
+
+        assert!(matches!(
+            AgentEvent::parse(AgentProvider::Openai, start).unwrap(),
+            AgentEvent::SessionStart { session_id: Some(ref id) } if id == "chatcmpl-1"
+        ));
+        assert!(matches!(
+            AgentEvent::parse(AgentProvider::Openai, delta).unwrap(),
+            AgentEvent::TextDelta { ref text, done: false } if text == "Hel"
+        ));
+        assert!(matches!(
+            AgentEvent::parse(AgentProvider::Openai, end).unwrap(),
+            AgentEvent::TextDelta { done: true, .. }
+        ));
+        let event = AgentEvent::parse(AgentProvider::Openai, usage).unwrap();
+        assert_eq!(event.get_usage().unwrap().total(), 15);
+        assert!(matches!(
+            AgentEvent::parse(AgentProvider::Openai, "data: [DONE]").unwrap(),
+            AgentEvent::Unknown { ref event_type, .. } if event_type == "done"
+        ));
+    }
+
+    #[test]
+    fn parses_anthropic_stream_events() {
+        let start = r#"data: {"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":25,"output_tokens":1}}}"#;
+        let delta = r#"data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi"}}"#;
+        let stop = r#"data: {"type":"content_block_stop","index":0}"#;
+        let usage = r#"data: {"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":15}}"#;
+
+        assert!(AgentEvent::parse(AgentProvider::Anthropic, "event: message_start").is_err());
+        let event = AgentEvent::parse(AgentProvider::Anthropic, start).unwrap();
+        assert!(matches!(
+            event,
+            AgentEvent::Usage { session_id: Some(ref id), .. } if id == "msg_1"
+        ));
+        let mut total = event.get_usage().unwrap().clone();
+        assert!(matches!(
+            AgentEvent::parse(AgentProvider::Anthropic, delta).unwrap(),
+            AgentEvent::TextDelta { ref text, done: false } if text == "Hi"
+        ));
+        assert!(matches!(
+            AgentEvent::parse(AgentProvider::Anthropic, stop).unwrap(),
+            AgentEvent::TextDelta { done: true, .. }
+        ));
+        total.merge(
+            AgentEvent::parse(AgentProvider::Anthropic, usage)
+                .unwrap()
+                .get_usage()
+                .unwrap(),
+        );
+        assert_eq!(total.total(), 40);
+        assert!(matches!(
+            AgentEvent::parse(AgentProvider::Anthropic, r#"{"type":"error","error":{}}"#).unwrap(),
+            AgentEvent::Unknown { ref event_type, .. } if event_type == "error"
+        ));
+    }
+
+    #[test]
+    fn parses_ollama_stream_lines() {
+        let delta =
+            r#"{"model":"llama3.2","message":{"role":"assistant","content":"Hi"},"done":false}"#;
+        let end = r#"{"model":"llama3.2","message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":26,"eval_count":9}"#;
+
+        assert!(matches!(
+            AgentEvent::parse(AgentProvider::Ollama, delta).unwrap(),
+            AgentEvent::TextDelta { ref text, done: false } if text == "Hi"
+        ));
+        let event = AgentEvent::parse(AgentProvider::Ollama, end).unwrap();
+        assert_eq!(event.get_usage().unwrap().total(), 35);
+        assert!(matches!(
+            AgentEvent::parse(AgentProvider::Ollama, r#"{"error":"model not found"}"#).unwrap(),
+            AgentEvent::Unknown { ref event_type, .. } if event_type == "error"
+        ));
+    }
+
+    #[test]
+    fn parses_streamed_tool_calls() {
+        let openai = r#"data: {"id":"c1","choices":[{"index":0,"delta":{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"read_file","arguments":"{\"pa"}}]},"finish_reason":null}]}"#;
+        let anthropic = r#"data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"path\""}}"#;
+        let ollama = r#"{"message":{"role":"assistant","content":"","tool_calls":[{"function":{"name":"bash","arguments":{"command":"ls"}}}]},"done":false}"#;
+
+        let AgentEvent::ToolCalls { calls } =
+            AgentEvent::parse(AgentProvider::Openai, openai).unwrap()
+        else {
+            panic!("expected tool calls");
+        };
+        assert_eq!(calls[0].id.as_deref(), Some("call_1"));
+        assert_eq!(calls[0].name.as_deref(), Some("read_file"));
+        assert_eq!(calls[0].arguments, "{\"pa");
+
+        let AgentEvent::ToolCalls { calls } =
+            AgentEvent::parse(AgentProvider::Anthropic, anthropic).unwrap()
+        else {
+            panic!("expected tool calls");
+        };
+        assert_eq!(calls[0].index, 1);
+        assert_eq!(calls[0].arguments, "{\"path\"");
+
+        let AgentEvent::ToolCalls { calls } =
+            AgentEvent::parse(AgentProvider::Ollama, ollama).unwrap()
+        else {
+            panic!("expected tool calls");
+        };
+        assert_eq!(calls[0].name.as_deref(), Some("bash"));
+        assert_eq!(calls[0].arguments, r#"{"command":"ls"}"#);
+    }
+
+    #[test]
+    fn executed_tool_calls_parse_for_every_provider() {
+        let requested = LocalToolEvent::RalphToolUse {
+            calls: vec![ToolCall {
+                id: "call_1".to_string(),
+                name: "write_file".to_string(),
+                arguments: r#"{"path":"notes.txt","content":"hi"}"#.to_string(),
+            }],
+            usage: Some(TokenUsage {
+                input_tokens: 100,
+                output_tokens: 5,
+                ..Default::default()
+            }),
+        }
+        .to_line();
+        let finished = LocalToolEvent::RalphToolResult {
+            tool_call_id: "call_1".to_string(),
+            name: "write_file".to_string(),
+            output: ToolOutput {
+                content: "wrote 2 bytes".to_string(),
+                is_error: false,
+            },
+        }
+        .to_line();
+
+        for provider in [
+            AgentProvider::Openai,
+            AgentProvider::Anthropic,
+            AgentProvider::Ollama,
+        ] {
+            let AgentEvent::AssistantMessage {
+                usage, tool_uses, ..
+            } = AgentEvent::parse(provider, &requested).unwrap()
+            else {
+                panic!("expected the tool use for {provider:?}");
+            };
+            assert_eq!(usage.unwrap().total(), 105);
+            assert_eq!(tool_uses[0].id, "call_1");
+            assert_eq!(tool_uses[0].path.as_deref(), Some("notes.txt"));
+
+            let AgentEvent::ToolResult { results } =
+                AgentEvent::parse(provider, &finished).unwrap()
+            else {
+                panic!("expected the tool result for {provider:?}");
+            };
+            assert_eq!(results[0].tool_use_id, "call_1");
+            assert!(!results[0].is_error);
+            assert_eq!(results[0].text.len(), 13);
+        }
+    }
 }
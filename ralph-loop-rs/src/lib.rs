@@ -8,16 +8,69 @@
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub mod agent;
+#[cfg(feature = "anthropic-api")]
+pub mod anthropic_agent;
+pub mod api_agent;
+pub mod audit;
+pub mod banner;
+pub mod build_info;
+pub mod cancel;
+pub mod capabilities;
+pub mod checkpoint;
+pub mod classify;
+pub mod commands;
 pub mod config;
+pub mod consent;
+pub mod context_window;
+pub mod debug_capture;
+pub mod disk_space;
+pub mod environment;
 pub mod error;
+pub mod eta;
+pub mod event_filter;
+pub mod git;
+pub mod heartbeat;
+pub mod job;
 pub mod json_events;
 pub mod loop_controller;
 pub mod monitor;
+pub mod network;
+pub mod notify;
+pub mod ollama_agent;
+pub mod pager;
+pub mod plan;
+pub mod probe;
 pub mod process;
+pub mod promise;
+pub mod quiet_hours;
+pub mod reason;
+pub mod redact;
+pub mod registry;
+pub mod replay;
+pub mod reporter;
+pub mod resources;
+pub mod run_result;
+pub mod scratch;
+#[cfg(feature = "self-update")]
 pub mod self_update;
 pub mod state;
+pub mod stats;
+pub mod status_line;
+pub mod store;
+pub mod summary;
+pub mod systemd;
+pub mod table;
+pub mod text;
+pub mod time_display;
+pub mod timeline;
 pub mod token_counter;
+pub mod tool_spans;
+pub mod tools;
 pub mod transcript;
+pub mod transcript_sources;
+pub mod umbrella;
+pub mod usage;
+pub mod warnings;
 
 pub use agent::{Agent, AgentResult, CliAgent, ExitReason};
 pub use config::{AgentProvider, Config};
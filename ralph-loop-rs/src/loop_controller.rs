@@ -1,13 +1,43 @@
-use std::path::Path;
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{Local, Utc};
 use tokio::sync::Mutex;
 use tracing::{debug, info, trace, warn};
+use uuid::Uuid;
 
-use crate::agent::{Agent, AgentResult, ExitReason};
+use crate::agent::{Agent, AgentResult, ExitReason, IterationSettings};
+use crate::checkpoint::{self, Checkpoint};
+use crate::classify::{self, IterationOutcome, Verdict};
+use crate::commands::AgentCommand;
 use crate::config::Config;
+use crate::disk_space::{self, LowDiskSpaceAction, LowDiskSpaceRecord};
+use crate::environment;
 use crate::error::{RalphError, Result};
+use crate::eta::{self, IterationEta};
+use crate::git::{self, SquashOutcome};
+use crate::heartbeat::HeartbeatTask;
+use crate::network;
+use crate::plan::{self, PlanProgress};
+use crate::probe;
+use crate::promise;
+use crate::quiet_hours;
+use crate::reason;
+use crate::redact::{redact_opt, Redactor};
+use crate::registry;
+use crate::scratch;
 use crate::state::SharedState;
-use crate::transcript::{ExitReason as TranscriptExitReason, IterationEndReason, TranscriptWriter};
+use crate::status_line;
+use crate::summary;
+use crate::text;
+use crate::timeline::TimelineEvent;
+use crate::token_counter::TokenCounter;
+use crate::transcript::{
+    self, ExitReason as TranscriptExitReason, IterationEndReason, IterationLimits, SubAgentTokens,
+    TokenUsageRecord, TranscriptWriter,
+};
 
 /// Result of the loop execution
 #[derive(Debug, Clone)]
@@ -18,62 +48,147 @@ pub enum LoopResult {
         iterations: u32,
         /// The promise text that was found
         promise: String,
+        /// Summary the agent gave with the promise, if any
+        summary: Option<String>,
+        /// Total cost across iterations, if the backend reported one
+        cost_usd: Option<f64>,
+    },
+    /// Every item in the plan file was checked
+    PlanCompleted {
+        /// Number of iterations it took
+        iterations: u32,
+        /// Final plan progress
+        plan: PlanProgress,
+        /// Total cost across iterations, if the backend reported one
+        cost_usd: Option<f64>,
     },
     /// Shutdown was requested
     Shutdown {
         /// Number of iterations completed before shutdown
         iterations: u32,
     },
+    /// Too many iterations in a row ended in an error
+    Failed {
+        /// Number of iterations run
+        iterations: u32,
+        /// Length of the streak of failed iterations
+        consecutive_errors: u32,
+    },
 }
 
+/// Output lines the run must have produced before `max_unparsed_ratio` applies
+const MIN_LINES_FOR_UNPARSED_RATIO: u64 = 20;
+
+/// How often a cancel request is checked for during quiet hours
+const QUIET_HOURS_POLL: Duration = Duration::from_secs(30);
+
+/// How often free disk space is checked again while waiting for it
+const DISK_SPACE_POLL: Duration = Duration::from_secs(30);
+
 /// Main loop controller that orchestrates agent invocations
 pub struct LoopController<A: Agent> {
     config: Arc<Config>,
     agent: A,
     state: Arc<SharedState>,
     transcript_writer: Option<Arc<Mutex<TranscriptWriter>>>,
+    /// Estimates usage for every iteration, so counts are cached across them
+    token_counter: Arc<TokenCounter>,
 }
 
 impl<A: Agent> LoopController<A> {
-    /// Create a new LoopController
-    pub fn new(config: Config, agent: A) -> Self {
+    fn from_parts(
+        config: Config,
+        agent: A,
+        state: Arc<SharedState>,
+        transcript_writer: Option<Arc<Mutex<TranscriptWriter>>>,
+    ) -> Self {
         Self {
+            token_counter: Arc::new(TokenCounter::new(config.context_limit.estimation_method)),
             config: Arc::new(config),
             agent,
-            state: SharedState::new_shared(),
-            transcript_writer: None,
+            state,
+            transcript_writer,
         }
     }
 
+    /// Create a new LoopController
+    pub fn new(config: Config, agent: A) -> Self {
+        Self::from_parts(config, agent, SharedState::new_shared(), None)
+    }
+
     /// Create a new LoopController with a transcript writer
     pub fn with_transcript_writer(config: Config, agent: A, project_path: &Path) -> Result<Self> {
         let output_dir = &config.output_dir;
-        let writer = TranscriptWriter::new(
+        let redactor = Redactor::from_config(&config.redaction)?;
+        let mut writer = TranscriptWriter::new(
             output_dir,
             project_path,
-            &config.prompt,
+            &redact_opt(redactor.as_ref(), &config.prompt),
             None, // prompt_file not tracked at this level
             config.agent_provider(),
             config.completion_promise.clone(),
             None, // auto-generate run_id
         )?;
+        if config.prompt_preview_chars != text::DEFAULT_PROMPT_PREVIEW_CHARS {
+            let prompt = redact_opt(redactor.as_ref(), &config.prompt);
+            writer
+                .set_prompt_preview(text::prompt_preview(&prompt, config.prompt_preview_chars))?;
+        }
+        if !config.tags.is_empty() {
+            writer.set_tags(config.tags.clone())?;
+        }
+        // Squashing and the files changed start from this state
+        let mut audited = Vec::new();
+        let git_start = git::snapshot(project_path, &mut audited);
+        for subprocess in audited {
+            writer.audit(None, subprocess)?;
+        }
+        if config.environment.enabled {
+            writer.set_environment(environment::capture(
+                project_path,
+                &config.environment,
+                git_start.as_ref(),
+            ))?;
+        }
+        if let Some(git_start) = git_start {
+            writer.set_git_start(git_start)?;
+        }
 
-        Ok(Self {
-            config: Arc::new(config),
+        Ok(Self::from_parts(
+            config,
             agent,
-            state: SharedState::new_shared(),
-            transcript_writer: Some(Arc::new(Mutex::new(writer))),
-        })
+            SharedState::new_shared(),
+            Some(Arc::new(Mutex::new(writer))),
+        ))
     }
 
-    /// Create a new LoopController with an existing shared state
-    pub fn with_state(config: Config, agent: A, state: Arc<SharedState>) -> Self {
-        Self {
-            config: Arc::new(config),
+    /// Create a LoopController continuing the stopped run `run_id` after its
+    /// last iteration. The iteration budget of its checkpoint is not applied
+    /// here, so the caller can choose between it and a new one.
+    pub async fn resume(config: Config, agent: A, run_id: &str) -> Result<Self> {
+        let writer = TranscriptWriter::open(&config.output_dir, run_id)?;
+        // An iteration that was cut short still counts
+        let state = SharedState::new_shared();
+        state
+            .set_iteration(writer.metadata().current_iteration())
+            .await;
+        info!(
+            "Resuming run {} after iteration {}",
+            run_id,
+            writer.metadata().current_iteration()
+        );
+
+        Ok(Self::from_parts(
+            config,
             agent,
             state,
-            transcript_writer: None,
-        }
+            Some(Arc::new(Mutex::new(writer))),
+        ))
+    }
+
+    /// Create a new LoopController with an existing shared state
+    pub fn with_state(config: Config, agent: A, state: Arc<SharedState>) -> Self {
+        Self::from_parts(config, agent, state, None)
     }
 
     /// Get a reference to the shared state
@@ -86,22 +201,230 @@ impl<A: Agent> LoopController<A> {
         &self.config
     }
 
-    /// Run the loop until the promise is found or max iterations is reached
+    /// Get a reference to the agent
+    pub fn agent(&self) -> &A {
+        &self.agent
+    }
+
+    /// Directory of this run's artifacts, if a transcript is written
+    pub async fn run_dir(&self) -> Option<PathBuf> {
+        match self.transcript_writer {
+            Some(ref writer) => Some(writer.lock().await.run_dir().to_path_buf()),
+            None => None,
+        }
+    }
+
+    /// Log the prompt's estimated size and warn if it alone fills most of the context
+    fn check_prompt_size(&self) {
+        let limits = &self.config.context_limit;
+        let prompt_tokens = self.token_counter.count(&self.config.prompt);
+        info!("Prompt size: ~{} tokens", prompt_tokens);
+        if prompt_tokens >= limits.warning_tokens() {
+            warn!(
+                "Prompt alone is ~{} tokens, close to the context limit of {}",
+                prompt_tokens, limits.max_tokens
+            );
+        }
+    }
+
+    /// Start the heartbeat for the run directory, if there is one
+    async fn start_heartbeat(&self) -> Option<HeartbeatTask> {
+        let writer = self.transcript_writer.as_ref()?;
+        if self.config.heartbeat_interval_secs == 0 {
+            return None;
+        }
+        let run_dir = writer.lock().await.run_dir().to_path_buf();
+        Some(HeartbeatTask::spawn(
+            run_dir,
+            Arc::clone(&self.state),
+            Duration::from_secs(self.config.heartbeat_interval_secs),
+        ))
+    }
+
+    /// Generate the summary of a successful run and store it in the metadata
+    async fn write_summary(&self, promise: &str) {
+        if !self.config.summary.enabled {
+            return;
+        }
+        let Some(ref writer) = self.transcript_writer else {
+            return;
+        };
+        let metadata = writer.lock().await.metadata().clone();
+        let (summary, subprocess) = summary::generate(&self.config, &metadata, promise).await;
+        info!("Run summary:\n{}", summary);
+
+        let mut writer = writer.lock().await;
+        if let Some(subprocess) = subprocess {
+            if let Err(e) = writer.audit(None, subprocess) {
+                warn!("Failed to write audit log: {}", e);
+            }
+        }
+        if let Err(e) = writer.set_summary(summary) {
+            warn!("Failed to write run summary: {}", e);
+        }
+    }
+
+    /// Squash the commits made during the run into one, if enabled
+    async fn squash_commits(&self) {
+        if !self.config.git.squash_on_success {
+            return;
+        }
+        let Some(ref writer) = self.transcript_writer else {
+            return;
+        };
+        let metadata = writer.lock().await.metadata().clone();
+        let Some(base) = metadata.base_commit() else {
+            warn!("Not squashing commits: no commit was checked out when the run started");
+            return;
+        };
+        let message = metadata.summary.clone().unwrap_or_else(|| {
+            summary::render_template(
+                summary::DEFAULT_TEMPLATE,
+                &metadata,
+                &metadata.completion_promise,
+            )
+        });
+        let branch = format!("{}{}", self.config.git.branch_prefix, metadata.run_id);
+
+        let mut audited = Vec::new();
+        let squashed = git::squash_since(
+            Path::new(&metadata.project_path),
+            base,
+            &branch,
+            &message,
+            &mut audited,
+        );
+        for subprocess in audited {
+            if let Err(e) = writer.lock().await.audit(None, subprocess) {
+                warn!("Failed to write audit log: {}", e);
+            }
+        }
+        match squashed {
+            Ok(SquashOutcome::NoCommits) => debug!("No commits to squash"),
+            Ok(SquashOutcome::Squashed {
+                commits,
+                head,
+                branch,
+            }) => info!(
+                "Squashed {} commit(s) into {}; originals kept on {}",
+                commits, head, branch
+            ),
+            Err(e) => warn!("Failed to squash commits: {}", e),
+        }
+    }
+
+    /// Create the scratch directory for `iteration` and prune old ones
+    async fn create_scratch_dir(&self, iteration: u32) -> Option<PathBuf> {
+        if !self.config.scratch.enabled {
+            return None;
+        }
+        let run_dir = self
+            .transcript_writer
+            .as_ref()?
+            .lock()
+            .await
+            .run_dir()
+            .to_path_buf();
+        let dir = match scratch::create(&run_dir, iteration) {
+            Ok(dir) => dir,
+            Err(e) => {
+                warn!("Failed to create scratch directory: {}", e);
+                return None;
+            }
+        };
+        if let Err(e) = scratch::prune(&run_dir, self.config.scratch.keep) {
+            warn!("Failed to prune scratch directories: {}", e);
+        }
+        Some(dir)
+    }
+
+    /// Lengths of the iterations of past runs of the same prompt, latest
+    /// first, for estimating how long iterations take
+    async fn past_iteration_durations(&self) -> Vec<Duration> {
+        let Some(ref writer) = self.transcript_writer else {
+            return Vec::new();
+        };
+        let metadata = writer.lock().await.metadata().clone();
+        match registry::list_runs(&self.config.output_dir, Some(&metadata.project_path)) {
+            Ok(runs) => eta::history(&metadata, &runs),
+            Err(e) => {
+                debug!("Failed to list past runs: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Estimate for `iteration`, from the run's earlier iterations and `past`
+    async fn iteration_eta(
+        &self,
+        iteration: u32,
+        max_iterations: Option<u32>,
+        past: &[Duration],
+    ) -> Option<IterationEta> {
+        let mut durations = match self.transcript_writer {
+            Some(ref writer) => eta::iteration_durations(writer.lock().await.metadata()),
+            None => Vec::new(),
+        };
+        durations.extend_from_slice(past);
+        IterationEta::estimate(
+            &durations,
+            max_iterations.map(|max| max.saturating_sub(iteration)),
+        )
+    }
+
+    /// Read the configured plan file; read errors are logged and ignored
+    fn read_plan(&self) -> Option<PlanProgress> {
+        let path = self.config.plan_file.as_ref()?;
+        match plan::read(path) {
+            Ok(progress) => progress,
+            Err(e) => {
+                warn!("{}", e);
+                None
+            }
+        }
+    }
+
+    /// Run the loop until the promise is found, the plan is completed, or max iterations is reached
     pub async fn run(&self) -> Result<LoopResult> {
         let prompt = &self.config.prompt;
+        self.check_prompt_size();
+        let _heartbeat = self.start_heartbeat().await;
+        // The agent may extend the budget with `extend-iterations`
+        let mut max_iterations = self.config.max_iterations;
+        let mut consecutive_errors = 0;
+        let mut retries = 0;
+        // Output lines of the run, for `max_unparsed_ratio`
+        let mut events_parsed = 0;
+        let mut lines_unparsed = 0;
+        // Milestones carry over to later iterations, and to a resumed run
+        let mut milestones_reached = self.milestones_reached().await;
+        let past_durations = self.past_iteration_durations().await;
+        if let Some(ref writer) = self.transcript_writer {
+            let after_iteration = self.state.snapshot().await.iteration;
+            let event = if after_iteration > 0 {
+                TimelineEvent::RunResumed { after_iteration }
+            } else {
+                TimelineEvent::RunStarted
+            };
+            if let Err(e) = writer.lock().await.record(event) {
+                warn!("Failed to write timeline: {}", e);
+            }
+        }
 
         loop {
             // Increment iteration
             let iteration = self.state.increment_iteration().await;
 
             // Check max iterations
-            if let Some(max) = self.config.max_iterations {
+            if let Some(max) = max_iterations {
                 if iteration > max {
                     // Complete transcript with max iterations exceeded
                     if let Some(ref writer) = self.transcript_writer {
                         let mut writer = writer.lock().await;
-                        if let Err(e) = writer.complete(TranscriptExitReason::MaxIterationsExceeded)
-                        {
+                        if let Err(e) = writer.complete(
+                            TranscriptExitReason::MaxIterationsExceeded,
+                            format!("no promise in max iterations ({max})"),
+                        ) {
                             warn!("Failed to complete transcript: {}", e);
                         }
                     }
@@ -109,31 +432,152 @@ impl<A: Agent> LoopController<A> {
                 }
             }
 
+            if !self.wait_for_quiet_hours().await {
+                info!("Cancel requested during quiet hours, stopping");
+                if let Some(ref writer) = self.transcript_writer {
+                    let mut writer = writer.lock().await;
+                    if let Err(e) = writer.complete(
+                        TranscriptExitReason::UserInterrupt,
+                        "cancelled during quiet hours".to_string(),
+                    ) {
+                        warn!("Failed to complete transcript: {}", e);
+                    }
+                }
+                return Ok(LoopResult::Shutdown {
+                    iterations: iteration - 1,
+                });
+            }
+
+            match self.ensure_disk_space(iteration).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    info!("Cancel requested while waiting for disk space, stopping");
+                    if let Some(ref writer) = self.transcript_writer {
+                        let mut writer = writer.lock().await;
+                        if let Err(e) = writer.complete(
+                            TranscriptExitReason::UserInterrupt,
+                            "cancelled while waiting for disk space".to_string(),
+                        ) {
+                            warn!("Failed to complete transcript: {}", e);
+                        }
+                    }
+                    return Ok(LoopResult::Shutdown {
+                        iterations: iteration - 1,
+                    });
+                }
+                Err(e) => {
+                    if let Some(ref writer) = self.transcript_writer {
+                        let mut writer = writer.lock().await;
+                        let detail = format!("disk space check failed: {e}");
+                        if let Err(e) = writer.complete(TranscriptExitReason::Error, detail) {
+                            warn!("Failed to complete transcript: {}", e);
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+
             info!("Starting iteration {}", iteration);
             debug!("Prompt length: {} chars", prompt.len());
             trace!("Prompt: {}", prompt);
 
+            // The first iteration of a run may get more headroom
+            let limits = self.config.context_limit.for_iteration(iteration == 1);
+            if limits.max_tokens != self.config.context_limit.max_tokens {
+                info!(
+                    "Context limit for this iteration: {} tokens",
+                    limits.max_tokens
+                );
+            }
+
             // Start iteration in transcript
             if let Some(ref writer) = self.transcript_writer {
                 let mut writer = writer.lock().await;
                 if let Err(e) = writer.start_iteration() {
                     warn!("Failed to start transcript iteration: {}", e);
                 }
+                if let Err(e) = writer.set_limits(IterationLimits {
+                    max_tokens: limits.max_tokens,
+                    warning_threshold: limits.warning_tokens(),
+                }) {
+                    warn!("Failed to write iteration limits: {}", e);
+                }
+            }
+
+            let eta = self
+                .iteration_eta(iteration, max_iterations, &past_durations)
+                .await;
+            if let Some(ref eta) = eta {
+                debug!("Iteration expected to take {:?}", eta.expected);
             }
+            status_line::set_eta(eta);
 
             // Reset state for new iteration
             debug!("Resetting state for new iteration");
             self.state.reset().await;
 
             // Run the agent
+            let scratch_dir = self.create_scratch_dir(iteration).await;
+            let (prompt, env) = match scratch_dir {
+                Some(ref dir) => (
+                    scratch::render_prompt(prompt, dir),
+                    vec![(
+                        scratch::SCRATCH_ENV_VAR.to_string(),
+                        dir.to_string_lossy().to_string(),
+                    )],
+                ),
+                None => (Cow::Borrowed(prompt.as_str()), Vec::new()),
+            };
+            let prompt = if self.config.inject_promise_instructions {
+                Cow::Owned(
+                    prompt.into_owned()
+                        + &promise::instructions(
+                            self.config.promise_instructions.as_deref(),
+                            &self.config.completion_promise,
+                            iteration,
+                            max_iterations,
+                        ),
+                )
+            } else {
+                prompt
+            };
+            let max_tokens = limits.max_tokens;
+            let settings = IterationSettings {
+                limits,
+                milestones: self.config.milestones[milestones_reached..].to_vec(),
+                token_counter: Arc::clone(&self.token_counter),
+            };
             debug!("Calling agent.run()...");
-            let result: AgentResult = self.agent.run(prompt).await?;
+            let mut result: AgentResult =
+                self.agent.run_iteration(&prompt, &env, &settings).await?;
             debug!(
                 "Agent returned - exit_reason: {:?}, promise_found: {:?}",
                 result.exit_reason,
                 result.promise_found.is_some()
             );
 
+            // Record the milestones reached; the promise only counts after the last
+            milestones_reached += result.milestones.len();
+            if let Some(ref writer) = self.transcript_writer {
+                let mut writer = writer.lock().await;
+                for milestone in &result.milestones {
+                    if let Err(e) = writer.record_milestone(milestone.clone()) {
+                        warn!("Failed to record milestone: {}", e);
+                    }
+                }
+                if let Err(e) = writer.write_timeline(result.timeline.clone()) {
+                    warn!("Failed to write timeline: {}", e);
+                }
+            }
+            if let Some(milestone) = self.config.milestones.get(milestones_reached) {
+                if result.promise_found.take().is_some() {
+                    info!(
+                        "Promise given before milestone \"{}\", continuing",
+                        milestone
+                    );
+                }
+            }
+
             // Record session ID if available
             if let Some(ref session_id) = result.session_id {
                 if let Some(ref writer) = self.transcript_writer {
@@ -142,6 +586,134 @@ impl<A: Agent> LoopController<A> {
                         warn!("Failed to set session ID: {}", e);
                     }
                 }
+            } else if !self.config.agent_provider().is_api()
+                && result.exit_reason != ExitReason::Shutdown
+            {
+                // The CLI reports its session in the init event; without it
+                // the iteration can't be resumed or looked up
+                warn!("Agent reported no session ID");
+            }
+
+            // Record cost if the backend reported one
+            if let Some(cost_usd) = result.cost_usd {
+                if let Some(ref writer) = self.transcript_writer {
+                    let mut writer = writer.lock().await;
+                    if let Err(e) = writer.set_cost_usd(cost_usd) {
+                        warn!("Failed to set iteration cost: {}", e);
+                    }
+                }
+            }
+
+            // Append the agent subprocess to the audit log
+            if let Some(ref subprocess) = result.subprocess {
+                if let Some(ref writer) = self.transcript_writer {
+                    let writer = writer.lock().await;
+                    if let Err(e) = writer.audit(Some(iteration), subprocess.clone()) {
+                        warn!("Failed to write audit log: {}", e);
+                    }
+                }
+            }
+
+            // Keep stderr, where the reason a failed iteration failed often is
+            if !result.stderr.is_empty() {
+                if let Some(ref writer) = self.transcript_writer {
+                    let mut writer = writer.lock().await;
+                    if let Err(e) = writer.write_stderr(&result.stderr) {
+                        warn!("Failed to write iteration stderr: {}", e);
+                    }
+                }
+            }
+
+            // Keep output that couldn't be parsed, it shows agent format changes
+            events_parsed += result.event_count;
+            lines_unparsed += result.unparsed.len() as u64 + result.unparsed_dropped;
+            if !result.unparsed.is_empty() {
+                if let Some(ref writer) = self.transcript_writer {
+                    let mut writer = writer.lock().await;
+                    if let Err(e) = writer.write_unparsed(&result.unparsed, result.unparsed_dropped)
+                    {
+                        warn!("Failed to write unparsed output: {}", e);
+                    }
+                }
+            }
+            if !result.unknown.is_empty() {
+                if let Some(ref writer) = self.transcript_writer {
+                    let writer = writer.lock().await;
+                    if let Err(e) = writer.write_unknown_events(&result.unknown) {
+                        warn!("Failed to write unknown events: {}", e);
+                    }
+                }
+            }
+            if !result.tool_spans.is_empty() {
+                if let Some(ref writer) = self.transcript_writer {
+                    let writer = writer.lock().await;
+                    if let Err(e) = writer.write_tool_spans(&result.tool_spans) {
+                        warn!("Failed to write tool calls: {}", e);
+                    }
+                }
+            }
+
+            // Keep the output for `ralph-loop replay`; classify_command writes it anyway
+            if self.config.keep_output && self.config.classify_command.is_none() {
+                if let Some(ref writer) = self.transcript_writer {
+                    let run_dir = writer.lock().await.run_dir().to_path_buf();
+                    if let Err(e) = classify::write_output(&run_dir, iteration, &result.output) {
+                        warn!("Failed to write iteration output: {}", e);
+                    }
+                }
+            }
+
+            // Record phase timings
+            if let Some(ref timings) = result.timings {
+                if let Some(ref writer) = self.transcript_writer {
+                    let mut writer = writer.lock().await;
+                    if let Err(e) = writer.set_timings(timings.clone()) {
+                        warn!("Failed to set iteration timings: {}", e);
+                    }
+                }
+            }
+
+            // Handle commands the agent sent
+            for command in &result.commands {
+                match command {
+                    AgentCommand::ExtendIterations(extra) => {
+                        if let Some(max) = max_iterations.as_mut() {
+                            *max = max.saturating_add(*extra);
+                            info!("Agent extended max iterations to {}", max);
+                        }
+                    }
+                    AgentCommand::Note(note) => {
+                        if let Some(ref writer) = self.transcript_writer {
+                            let mut writer = writer.lock().await;
+                            if let Err(e) = writer.add_note(note.clone()) {
+                                warn!("Failed to record agent note: {}", e);
+                            }
+                        }
+                    }
+                    AgentCommand::Restart => {}
+                }
+            }
+
+            // Record progress reported by the agent
+            if let Some(percent) = result.progress {
+                if let Some(ref writer) = self.transcript_writer {
+                    let mut writer = writer.lock().await;
+                    if let Err(e) = writer.set_progress(percent) {
+                        warn!("Failed to set iteration progress: {}", e);
+                    }
+                }
+            }
+
+            // Record plan file progress
+            let plan_progress = self.read_plan();
+            if let Some(progress) = plan_progress {
+                info!("Plan progress: {}/{} items", progress.done, progress.total);
+                if let Some(ref writer) = self.transcript_writer {
+                    let mut writer = writer.lock().await;
+                    if let Err(e) = writer.set_plan_progress(progress) {
+                        warn!("Failed to set plan progress: {}", e);
+                    }
+                }
             }
 
             // Determine end reason and record it
@@ -153,8 +725,17 @@ impl<A: Agent> LoopController<A> {
                         (IterationEndReason::Normal, 0, 0)
                     }
                 }
+                ExitReason::PromiseFound => (IterationEndReason::PromiseFound, 0, 0),
                 ExitReason::ContextLimit => (IterationEndReason::ContextLimit, 0, 0),
+                ExitReason::RestartRequested => (IterationEndReason::RestartRequested, 0, 0),
                 ExitReason::Shutdown => (IterationEndReason::Interrupted, 0, 0),
+                ExitReason::Error => {
+                    if result.is_fulfilled() {
+                        (IterationEndReason::PromiseFound, 0, 0)
+                    } else {
+                        (IterationEndReason::Error, 0, 0)
+                    }
+                }
             };
 
             // Get token usage from result if available
@@ -163,62 +744,595 @@ impl<A: Agent> LoopController<A> {
             } else {
                 (input_tokens, output_tokens)
             };
+            let (cache_read, cache_creation) =
+                result.token_usage.as_ref().map_or((0, 0), |usage| {
+                    (
+                        usage.cache_read_input_tokens,
+                        usage.cache_creation_input_tokens,
+                    )
+                });
+            // Sub-agents report their usage apart from the agent's; it counts
+            // toward the iteration all the same
+            let subagents = result.subagent_usage.as_ref().map(|subagents| {
+                info!(
+                    "{} sub-agent(s) used {} tokens",
+                    subagents.count,
+                    subagents.usage.total()
+                );
+                SubAgentTokens {
+                    count: subagents.count,
+                    input: subagents.usage.input_tokens,
+                    output: subagents.usage.output_tokens,
+                }
+            });
+            let tokens = TokenUsageRecord {
+                input: input_tokens + subagents.as_ref().map_or(0, |s| s.input),
+                output: output_tokens + subagents.as_ref().map_or(0, |s| s.output),
+                cache_read,
+                cache_creation,
+                subagents,
+            };
+
+            // Let the configured command overrule the built-in checks
+            let verdict = self.classify(iteration, &result).await;
+            if verdict == Some(Verdict::Retry) {
+                if retries < self.config.max_retries {
+                    retries += 1;
+                    if let Some(max) = max_iterations.as_mut() {
+                        *max = max.saturating_add(1);
+                    }
+                } else {
+                    warn!(
+                        "classify_command asked for a retry after {} retries (max_retries), \
+                         the iteration counts",
+                        retries
+                    );
+                }
+            }
+
+            // A second opinion on completion, for tuning prompts
+            self.probe(iteration, &result).await;
+
+            // An iteration that failed for lack of network doesn't count
+            let network_error = self.network_lost(&result).await;
+            if network_error.is_some() {
+                if let Some(max) = max_iterations.as_mut() {
+                    *max = max.saturating_add(1);
+                }
+            }
 
             // End iteration in transcript
+            let end_detail = reason::iteration_end(
+                &end_reason,
+                &result,
+                max_tokens,
+                &self.config.completion_promise,
+            );
+            debug!("Iteration {} ended: {}", iteration, end_detail);
             if let Some(ref writer) = self.transcript_writer {
                 let mut writer = writer.lock().await;
-                if let Err(e) = writer.end_iteration(end_reason, input_tokens, output_tokens) {
+                if let Err(e) = writer.end_iteration(end_reason, end_detail.clone(), tokens) {
                     warn!("Failed to end transcript iteration: {}", e);
                 }
             }
+            self.save_checkpoint(iteration, max_iterations).await;
+
+            if verdict == Some(Verdict::Abort) {
+                let message =
+                    format!("classify_command aborted the run after iteration {iteration}");
+                if let Some(ref writer) = self.transcript_writer {
+                    let mut writer = writer.lock().await;
+                    if let Err(e) = writer.complete(TranscriptExitReason::Error, message.clone()) {
+                        warn!("Failed to complete transcript: {}", e);
+                    }
+                }
+                return Err(RalphError::Aborted(message));
+            }
+            if let Some(ratio) = self.unparsed_ratio_exceeded(events_parsed, lines_unparsed) {
+                let message = format!(
+                    "{lines_unparsed} of {} output lines couldn't be parsed ({:.0}%), \
+                     the agent's output format may have changed; see {}",
+                    events_parsed + lines_unparsed,
+                    ratio * 100.0,
+                    transcript::UNPARSED_FILE
+                );
+                if let Some(ref writer) = self.transcript_writer {
+                    let mut writer = writer.lock().await;
+                    if let Err(e) = writer.complete(TranscriptExitReason::Error, message.clone()) {
+                        warn!("Failed to complete transcript: {}", e);
+                    }
+                }
+                return Err(RalphError::Aborted(message));
+            }
+            let overruled = matches!(verdict, Some(Verdict::Continue | Verdict::Retry));
 
             // Check if promise was found
-            if result.is_fulfilled() {
-                let promise = result.promise_found.unwrap_or_default();
+            let fulfilled = result.is_fulfilled() && !overruled;
+            if verdict == Some(Verdict::Done) || fulfilled {
+                let promise = result
+                    .promise_found
+                    .unwrap_or_else(|| self.config.completion_promise.clone());
                 info!(
                     "Promise fulfilled after {} iterations: {}",
                     iteration, promise
                 );
+                let summary = result.promise_summary.clone();
+                if let Some(ref summary) = summary {
+                    info!("Agent's summary:\n{}", summary);
+                    if let Some(ref writer) = self.transcript_writer {
+                        let mut writer = writer.lock().await;
+                        if let Err(e) = writer.set_promise_summary(summary.clone()) {
+                            warn!("Failed to write promise summary: {}", e);
+                        }
+                    }
+                }
+
+                self.write_summary(&promise).await;
+                self.squash_commits().await;
 
                 // Complete transcript
+                let mut cost_usd = result.cost_usd;
                 if let Some(ref writer) = self.transcript_writer {
                     let mut writer = writer.lock().await;
-                    if let Err(e) = writer.complete(TranscriptExitReason::PromiseFulfilled) {
+                    let detail = if fulfilled {
+                        format!("promise \"{promise}\" found in iteration {iteration}")
+                    } else {
+                        format!("classify_command judged the task done in iteration {iteration}")
+                    };
+                    if let Err(e) = writer.complete(TranscriptExitReason::PromiseFulfilled, detail)
+                    {
                         warn!("Failed to complete transcript: {}", e);
                     }
+                    cost_usd = writer.metadata().total_cost_usd();
                 }
 
                 return Ok(LoopResult::PromiseFulfilled {
                     iterations: iteration,
                     promise,
+                    summary,
+                    cost_usd,
                 });
             }
 
-            info!(
-                "Iteration {} complete, no promise found. Continuing...",
-                iteration
-            );
-        }
-    }
-}
+            // A fully checked plan is a completion signal of its own
+            if let Some(progress) = plan_progress
+                .filter(PlanProgress::is_complete)
+                .filter(|_| !overruled)
+            {
+                info!("Plan completed after {} iterations", iteration);
+                self.write_summary(&format!("all {} plan items checked", progress.total))
+                    .await;
+                self.squash_commits().await;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use async_trait::async_trait;
-    use std::sync::atomic::{AtomicU32, Ordering};
+                let mut cost_usd = result.cost_usd;
+                if let Some(ref writer) = self.transcript_writer {
+                    let mut writer = writer.lock().await;
+                    let detail = format!(
+                        "all {} plan items checked in iteration {iteration}",
+                        progress.total
+                    );
+                    if let Err(e) = writer.complete(TranscriptExitReason::PlanCompleted, detail) {
+                        warn!("Failed to complete transcript: {}", e);
+                    }
+                    cost_usd = writer.metadata().total_cost_usd();
+                }
 
-    /// Mock agent for testing
-    struct MockAgent {
-        /// Number of calls before returning a promise
-        calls_until_promise: AtomicU32,
-        /// The promise to return
-        promise: String,
-    }
+                return Ok(LoopResult::PlanCompleted {
+                    iterations: iteration,
+                    plan: progress,
+                    cost_usd,
+                });
+            }
 
-    impl MockAgent {
-        fn new(calls_until_promise: u32, promise: &str) -> Self {
-            Self {
+            if let Some(error) = network_error {
+                if !self.wait_for_network(error).await {
+                    info!("Cancel requested while waiting for the network, stopping");
+                    if let Some(ref writer) = self.transcript_writer {
+                        let mut writer = writer.lock().await;
+                        if let Err(e) = writer.complete(
+                            TranscriptExitReason::UserInterrupt,
+                            "cancelled while waiting for the network".to_string(),
+                        ) {
+                            warn!("Failed to complete transcript: {}", e);
+                        }
+                    }
+                    return Ok(LoopResult::Shutdown {
+                        iterations: iteration,
+                    });
+                }
+                continue;
+            }
+
+            // A broken environment fails every iteration the same way
+            if result.exit_reason == ExitReason::Error {
+                consecutive_errors += 1;
+            } else {
+                consecutive_errors = 0;
+            }
+            if self
+                .config
+                .max_consecutive_errors
+                .is_some_and(|max| consecutive_errors >= max)
+            {
+                warn!(
+                    "{} iterations in a row ended in an error, aborting",
+                    consecutive_errors
+                );
+                if let Some(ref writer) = self.transcript_writer {
+                    let mut writer = writer.lock().await;
+                    let detail = format!(
+                        "{consecutive_errors} iterations in a row ended in an error, the last: {end_detail}"
+                    );
+                    if let Err(e) = writer.complete(TranscriptExitReason::Error, detail) {
+                        warn!("Failed to complete transcript: {}", e);
+                    }
+                }
+                return Ok(LoopResult::Failed {
+                    iterations: iteration,
+                    consecutive_errors,
+                });
+            }
+
+            // Stop gracefully if `ralph-loop cancel` was issued for this run
+            if let Some(ref writer) = self.transcript_writer {
+                let mut writer = writer.lock().await;
+                if writer.cancel_requested() {
+                    info!("Cancel requested, stopping after iteration {}", iteration);
+                    let detail =
+                        format!("cancelled with ralph-loop cancel after iteration {iteration}");
+                    if let Err(e) = writer.complete(TranscriptExitReason::UserInterrupt, detail) {
+                        warn!("Failed to complete transcript: {}", e);
+                    }
+                    return Ok(LoopResult::Shutdown {
+                        iterations: iteration,
+                    });
+                }
+            }
+
+            info!(
+                "Iteration {} complete, no promise found. Continuing...",
+                iteration
+            );
+            self.cool_down().await;
+        }
+    }
+
+    /// The share of unparsed output lines, if it exceeds `max_unparsed_ratio`
+    fn unparsed_ratio_exceeded(&self, events_parsed: u64, lines_unparsed: u64) -> Option<f64> {
+        let max = self.config.max_unparsed_ratio?;
+        let lines = events_parsed + lines_unparsed;
+        if lines < MIN_LINES_FOR_UNPARSED_RATIO {
+            return None;
+        }
+        let ratio = lines_unparsed as f64 / lines as f64;
+        (ratio > max).then_some(ratio)
+    }
+
+    /// Ask `classify_command` what to do after `iteration`
+    async fn classify(&self, iteration: u32, result: &AgentResult) -> Option<Verdict> {
+        let command = self.config.classify_command.as_ref()?;
+        let writer = self.transcript_writer.as_ref()?;
+        let run_dir = writer.lock().await.run_dir().to_path_buf();
+        let output = match classify::write_output(&run_dir, iteration, &result.output) {
+            Ok(output) => output,
+            Err(e) => {
+                warn!(
+                    "Failed to write iteration output for classify_command: {}",
+                    e
+                );
+                return None;
+            }
+        };
+        let outcome = IterationOutcome {
+            iteration,
+            exit_reason: result.exit_reason,
+            exit_code: result
+                .subprocess
+                .as_ref()
+                .and_then(|subprocess| subprocess.exit_code),
+            promise_found: result.is_fulfilled(),
+        };
+
+        let (verdict, subprocess) = classify::classify(command, &run_dir, &output, &outcome).await;
+        if let Err(e) = writer.lock().await.audit(Some(iteration), subprocess) {
+            warn!("Failed to write audit log: {}", e);
+        }
+        if let Some(verdict) = verdict {
+            info!(
+                "classify_command verdict for iteration {}: {}",
+                iteration, verdict
+            );
+        }
+        verdict
+    }
+
+    /// Ask the completion probe about `iteration` and warn if it disagrees
+    /// with the promise
+    async fn probe(&self, iteration: u32, result: &AgentResult) {
+        if !self.config.probe.enabled || result.text.trim().is_empty() {
+            return;
+        }
+        let (answer, subprocess) =
+            probe::ask(&self.config, &self.config.prompt, &result.text).await;
+        if let Some(ref writer) = self.transcript_writer {
+            let mut writer = writer.lock().await;
+            if let Some(subprocess) = subprocess {
+                if let Err(e) = writer.audit(Some(iteration), subprocess) {
+                    warn!("Failed to write audit log: {}", e);
+                }
+            }
+            if let Some(complete) = answer {
+                if let Err(e) = writer.set_probe_complete(complete) {
+                    warn!("Failed to write completion probe answer: {}", e);
+                }
+            }
+        }
+        let Some(complete) = answer else {
+            return;
+        };
+        let fulfilled = result.is_fulfilled();
+        if complete == fulfilled {
+            debug!(
+                "Completion probe agrees with the promise for iteration {}",
+                iteration
+            );
+        } else if complete {
+            warn!(
+                "Iteration {}: the completion probe judged the task complete, but the promise wasn't found",
+                iteration
+            );
+        } else {
+            warn!(
+                "Iteration {}: the promise was found, but the completion probe judged the task incomplete",
+                iteration
+            );
+        }
+    }
+
+    /// Count of the configured milestones a resumed run already reached
+    async fn milestones_reached(&self) -> usize {
+        let Some(ref writer) = self.transcript_writer else {
+            return 0;
+        };
+        let writer = writer.lock().await;
+        writer
+            .metadata()
+            .milestones
+            .iter()
+            .zip(&self.config.milestones)
+            .take_while(|(reached, milestone)| reached.promise == **milestone)
+            .count()
+    }
+
+    /// Persist the loop state so the run can be resumed after this iteration
+    async fn save_checkpoint(&self, iteration: u32, max_iterations: Option<u32>) {
+        let Some(ref writer) = self.transcript_writer else {
+            return;
+        };
+        // Config was validated before the loop started
+        let redactor = Redactor::from_config(&self.config.redaction).unwrap_or(None);
+        let checkpoint = Checkpoint::new(
+            iteration,
+            max_iterations,
+            &self.config.prompt,
+            redactor.as_ref(),
+        );
+        let writer = writer.lock().await;
+        if let Err(e) = checkpoint::save(writer.run_dir(), &checkpoint) {
+            warn!("Failed to write checkpoint: {}", e);
+        }
+    }
+
+    /// Wait until the configured quiet hours are over, recording the pause
+    /// in the run metadata. Returns false if the run was cancelled meanwhile.
+    async fn wait_for_quiet_hours(&self) -> bool {
+        let Some(quiet_hours) = self.config.quiet_hours else {
+            return true;
+        };
+        let Some(remaining) = quiet_hours.remaining(Local::now().naive_local()) else {
+            return true;
+        };
+        info!(
+            "Quiet hours ({}), pausing for {} minute(s)",
+            quiet_hours,
+            remaining.as_secs().div_ceil(60)
+        );
+        self.set_paused(Some(quiet_hours::PAUSED_REASON)).await;
+
+        while let Some(remaining) = quiet_hours.remaining(Local::now().naive_local()) {
+            if let Some(ref writer) = self.transcript_writer {
+                if writer.lock().await.cancel_requested() {
+                    self.set_paused(None).await;
+                    return false;
+                }
+            }
+            tokio::time::sleep(remaining.min(QUIET_HOURS_POLL)).await;
+        }
+
+        self.set_paused(None).await;
+        info!("Quiet hours over, resuming");
+        true
+    }
+
+    /// Check that the output and project directories have `[disk_space]
+    /// min_free_mb` free before iteration `iteration`, recording a shortage in
+    /// the run metadata. Depending on `action`, fails or waits until space is
+    /// freed. Returns false if the run was cancelled while waiting.
+    async fn ensure_disk_space(&self, iteration: u32) -> Result<bool> {
+        let min_free_mb = self.config.disk_space.min_free_mb;
+        if min_free_mb == 0 {
+            return Ok(true);
+        }
+        let mut paths = vec![self.config.output_dir.clone()];
+        if let Some(ref writer) = self.transcript_writer {
+            paths.push(PathBuf::from(&writer.lock().await.metadata().project_path));
+        }
+        let Some((path, free_mb)) = disk_space::find_low(&paths, min_free_mb) else {
+            return Ok(true);
+        };
+        warn!(
+            "Only {} MiB free in {}, below disk_space.min_free_mb ({})",
+            free_mb,
+            path.display(),
+            min_free_mb
+        );
+        if let Some(ref writer) = self.transcript_writer {
+            let mut writer = writer.lock().await;
+            if let Err(e) = writer.record_low_disk_space(LowDiskSpaceRecord {
+                path: path.clone(),
+                free_mb,
+                iteration,
+                at: Utc::now(),
+            }) {
+                warn!("Failed to record low disk space: {}", e);
+            }
+        }
+        if self.config.disk_space.action == LowDiskSpaceAction::Abort {
+            return Err(RalphError::Aborted(format!(
+                "only {free_mb} MiB free in {}, below disk_space.min_free_mb ({min_free_mb})",
+                path.display()
+            )));
+        }
+
+        info!("Pausing until {} MiB are free", min_free_mb);
+        self.set_paused(Some(disk_space::PAUSED_REASON)).await;
+        while disk_space::find_low(&paths, min_free_mb).is_some() {
+            if let Some(ref writer) = self.transcript_writer {
+                if writer.lock().await.cancel_requested() {
+                    self.set_paused(None).await;
+                    return Ok(false);
+                }
+            }
+            tokio::time::sleep(DISK_SPACE_POLL).await;
+        }
+
+        self.set_paused(None).await;
+        info!("Enough disk space free again, resuming");
+        Ok(true)
+    }
+
+    /// The error a failed iteration reported, if it was a lost connection
+    /// and the agent's API is unreachable now
+    async fn network_lost(&self, result: &AgentResult) -> Option<String> {
+        if !self.config.network.wait_when_offline
+            || result.exit_reason != ExitReason::Error
+            || result.is_fulfilled()
+        {
+            return None;
+        }
+        let error = network::network_error(&result.stderr)
+            .or_else(|| network::network_error(&result.output))?;
+        let url = network::probe_url(&self.config);
+        if network::probe(&url).await {
+            debug!("{} is reachable, counting the failed iteration", url);
+            return None;
+        }
+        // Config was validated before the loop started
+        let redactor = Redactor::from_config(&self.config.redaction).unwrap_or(None);
+        Some(redact_opt(redactor.as_ref(), error).into_owned())
+    }
+
+    /// Wait until the agent's API is reachable again, recording the outage in
+    /// the timeline and the pause in the run metadata. Returns false if the
+    /// run was cancelled meanwhile.
+    async fn wait_for_network(&self, error: String) -> bool {
+        let url = network::probe_url(&self.config);
+        warn!("Network unreachable ({}), waiting for {}", error, url);
+        if let Some(ref writer) = self.transcript_writer {
+            if let Err(e) = writer
+                .lock()
+                .await
+                .record(TimelineEvent::NetworkLost { error })
+            {
+                warn!("Failed to write timeline: {}", e);
+            }
+        }
+        self.set_paused(Some(network::PAUSED_REASON)).await;
+
+        let lost_at = Instant::now();
+        let interval = Duration::from_secs(self.config.network.probe_interval_secs);
+        loop {
+            if let Some(ref writer) = self.transcript_writer {
+                if writer.lock().await.cancel_requested() {
+                    self.set_paused(None).await;
+                    return false;
+                }
+            }
+            tokio::time::sleep(interval).await;
+            if network::probe(&url).await {
+                break;
+            }
+        }
+
+        let offline_secs = lost_at.elapsed().as_secs();
+        self.set_paused(None).await;
+        info!("Network back after {}s, resuming", offline_secs);
+        if let Some(ref writer) = self.transcript_writer {
+            if let Err(e) = writer
+                .lock()
+                .await
+                .record(TimelineEvent::NetworkRestored { offline_secs })
+            {
+                warn!("Failed to write timeline: {}", e);
+            }
+        }
+        true
+    }
+
+    async fn set_paused(&self, reason: Option<&str>) {
+        if let Some(ref writer) = self.transcript_writer {
+            let mut writer = writer.lock().await;
+            if let Err(e) = writer.set_paused(reason.map(String::from)) {
+                warn!("Failed to record pause: {}", e);
+            }
+        }
+    }
+
+    /// Wait the configured delay before the next iteration
+    async fn cool_down(&self) {
+        let delay = iteration_delay(
+            self.config.iteration_delay_secs,
+            self.config.iteration_delay_jitter_secs,
+            Uuid::new_v4().as_u128() as u64,
+        );
+        if !delay.is_zero() {
+            info!(
+                "Waiting {:.1}s before the next iteration",
+                delay.as_secs_f64()
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// `secs` plus up to `jitter_secs` chosen by `random`, in milliseconds steps
+fn iteration_delay(secs: u64, jitter_secs: u64, random: u64) -> Duration {
+    let jitter_ms = jitter_secs.saturating_mul(1000);
+    let jitter = if jitter_ms == 0 {
+        0
+    } else {
+        random % (jitter_ms + 1)
+    };
+    Duration::from_secs(secs) + Duration::from_millis(jitter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Mock agent for testing
+    struct MockAgent {
+        /// Number of calls before returning a promise
+        calls_until_promise: AtomicU32,
+        /// The promise to return
+        promise: String,
+    }
+
+    impl MockAgent {
+        fn new(calls_until_promise: u32, promise: &str) -> Self {
+            Self {
                 calls_until_promise: AtomicU32::new(calls_until_promise),
                 promise: promise.to_string(),
             }
@@ -261,6 +1375,24 @@ mod tests {
                 exit_reason: ExitReason::ContextLimit,
                 session_id: None,
                 token_usage: None,
+                cost_usd: None,
+                timings: None,
+                subprocess: None,
+                progress: None,
+                commands: Vec::new(),
+                text: String::new(),
+                tool_calls: Vec::new(),
+                stderr: String::new(),
+                event_count: 0,
+                unparsed: Vec::new(),
+                unparsed_dropped: 0,
+                unknown: Vec::new(),
+                promise_mentions: 0,
+                promise_summary: None,
+                milestones: Vec::new(),
+                timeline: Vec::new(),
+                tool_spans: Vec::new(),
+                subagent_usage: None,
             })
         }
     }
@@ -282,6 +1414,7 @@ mod tests {
             LoopResult::PromiseFulfilled {
                 iterations,
                 promise,
+                ..
             } => {
                 assert_eq!(iterations, 3);
                 assert_eq!(promise, "TASK COMPLETE");
@@ -331,6 +1464,688 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_loop_stops_after_iteration_when_cancel_requested() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            prompt: "test prompt".to_string(),
+            max_iterations: Some(10),
+            output_dir: temp_dir.path().to_path_buf(),
+            ..Config::default()
+        };
+
+        let controller =
+            LoopController::with_transcript_writer(config, NeverFindsMockAgent, temp_dir.path())
+                .unwrap();
+        let run_dir = temp_dir.path().join("latest");
+        std::fs::write(run_dir.join(crate::transcript::CANCEL_FILE), "graceful").unwrap();
+
+        let result = controller.run().await.unwrap();
+
+        assert!(matches!(result, LoopResult::Shutdown { iterations: 1 }));
+        let metadata = crate::transcript::RunMetadata::load(&run_dir).unwrap();
+        assert_eq!(metadata.status, crate::transcript::RunStatus::Interrupted);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_during_quiet_hours_stops_without_iterating() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let now = Local::now().time();
+        let config = Config {
+            prompt: "test prompt".to_string(),
+            output_dir: temp_dir.path().to_path_buf(),
+            quiet_hours: Some(crate::quiet_hours::QuietHours {
+                start: now - chrono::Duration::hours(1),
+                end: now + chrono::Duration::hours(1),
+            }),
+            ..Config::default()
+        };
+
+        let controller =
+            LoopController::with_transcript_writer(config, NeverFindsMockAgent, temp_dir.path())
+                .unwrap();
+        let run_dir = temp_dir.path().join("latest");
+        std::fs::write(run_dir.join(crate::transcript::CANCEL_FILE), "graceful").unwrap();
+
+        let result = controller.run().await.unwrap();
+
+        assert!(matches!(result, LoopResult::Shutdown { iterations: 0 }));
+        let metadata = crate::transcript::RunMetadata::load(&run_dir).unwrap();
+        assert!(metadata.iterations.is_empty());
+        assert_eq!(metadata.paused, None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_low_disk_space_aborts_or_pauses_before_iterating() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config {
+            prompt: "test prompt".to_string(),
+            output_dir: temp_dir.path().join("out"),
+            ..Config::default()
+        };
+        config.disk_space.min_free_mb = u64::MAX;
+
+        let controller = LoopController::with_transcript_writer(
+            config.clone(),
+            NeverFindsMockAgent,
+            temp_dir.path(),
+        )
+        .unwrap();
+        let error = controller.run().await.unwrap_err().to_string();
+        assert!(error.contains("below disk_space.min_free_mb"), "{error}");
+        let run_dir = temp_dir.path().join("out").join("latest");
+        let metadata = crate::transcript::RunMetadata::load(&run_dir).unwrap();
+        assert!(metadata.iterations.is_empty());
+        assert_eq!(metadata.low_disk_space.len(), 1);
+        assert_eq!(metadata.low_disk_space[0].iteration, 1);
+        assert_eq!(metadata.exit_reason, Some(TranscriptExitReason::Error));
+
+        config.disk_space.action = LowDiskSpaceAction::Pause;
+        let controller =
+            LoopController::with_transcript_writer(config, NeverFindsMockAgent, temp_dir.path())
+                .unwrap();
+        std::fs::write(run_dir.join(crate::transcript::CANCEL_FILE), "graceful").unwrap();
+        let result = controller.run().await.unwrap();
+        assert!(matches!(result, LoopResult::Shutdown { iterations: 0 }));
+        let metadata = crate::transcript::RunMetadata::load(&run_dir).unwrap();
+        assert_eq!(metadata.low_disk_space.len(), 1);
+        assert_eq!(metadata.paused, None);
+    }
+
+    /// Mock agent that loses the network in its first call
+    struct OfflineMockAgent {
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl Agent for OfflineMockAgent {
+        async fn run(&self, _prompt: &str) -> Result<AgentResult> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                return Ok(AgentResult {
+                    exit_reason: ExitReason::Error,
+                    stderr: "Error: getaddrinfo ENOTFOUND api.anthropic.com\n".to_string(),
+                    ..AgentResult::without_promise()
+                });
+            }
+            Ok(AgentResult::with_promise("TASK COMPLETE"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_iteration_without_network_waits_and_does_not_count() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        // curl reads file:// URLs, so the probe succeeds once the file exists
+        let online = temp_dir.path().join("online");
+        let mut config = Config {
+            prompt: "test prompt".to_string(),
+            output_dir: temp_dir.path().join("out"),
+            max_iterations: Some(1),
+            ..Config::default()
+        };
+        config.network.probe_url = Some(format!("file://{}", online.display()));
+        config.network.probe_interval_secs = 1;
+
+        let controller = LoopController::with_transcript_writer(
+            config,
+            OfflineMockAgent {
+                calls: AtomicU32::new(0),
+            },
+            temp_dir.path(),
+        )
+        .unwrap();
+        let reconnect = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            std::fs::write(online, "").unwrap();
+        });
+        let result = controller.run().await.unwrap();
+        reconnect.await.unwrap();
+
+        assert!(matches!(
+            result,
+            LoopResult::PromiseFulfilled { iterations: 2, .. }
+        ));
+        let run_dir = temp_dir.path().join("out").join("latest");
+        let events: Vec<_> = crate::timeline::load(&run_dir)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.event)
+            .filter(|event| {
+                matches!(
+                    event,
+                    TimelineEvent::NetworkLost { .. } | TimelineEvent::NetworkRestored { .. }
+                )
+            })
+            .collect();
+        assert_eq!(events.len(), 2, "{events:?}");
+        assert_eq!(
+            events[0],
+            TimelineEvent::NetworkLost {
+                error: "Error: getaddrinfo ENOTFOUND api.anthropic.com".to_string()
+            }
+        );
+        // Probed again after the 1s interval
+        assert!(
+            matches!(events[1], TimelineEvent::NetworkRestored { offline_secs } if offline_secs >= 1),
+            "{events:?}"
+        );
+        let metadata = crate::transcript::RunMetadata::load(&run_dir).unwrap();
+        assert_eq!(metadata.paused, None);
+    }
+
+    /// Mock agent whose process dies during the given call
+    struct CrashingMockAgent {
+        calls: AtomicU32,
+        crash_on: u32,
+    }
+
+    #[async_trait]
+    impl Agent for CrashingMockAgent {
+        async fn run(&self, _prompt: &str) -> Result<AgentResult> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) + 1 == self.crash_on {
+                return Err(RalphError::ShutdownRequested);
+            }
+            Ok(AgentResult::without_promise())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume_continues_after_last_checkpoint() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            prompt: "test prompt".to_string(),
+            max_iterations: Some(10),
+            output_dir: temp_dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let agent = CrashingMockAgent {
+            calls: AtomicU32::new(0),
+            crash_on: 3,
+        };
+        let controller =
+            LoopController::with_transcript_writer(config.clone(), agent, temp_dir.path()).unwrap();
+        assert!(controller.run().await.is_err());
+
+        // The process is gone; its run is left behind as running
+        let run_dir = temp_dir.path().join("latest").canonicalize().unwrap();
+        let run_id = run_dir.file_name().unwrap().to_string_lossy().to_string();
+        let mut metadata = crate::transcript::RunMetadata::load(&run_dir).unwrap();
+        metadata.pid = None;
+        std::fs::write(
+            run_dir.join(crate::transcript::META_FILE),
+            serde_json::to_string(&metadata).unwrap(),
+        )
+        .unwrap();
+        let saved = checkpoint::load(&run_dir).unwrap().unwrap();
+        assert_eq!(saved.iteration, 2);
+        assert_eq!(saved.max_iterations, Some(10));
+
+        let controller = LoopController::resume(config, MockAgent::new(1, "DONE"), &run_id)
+            .await
+            .unwrap();
+        let result = controller.run().await.unwrap();
+
+        assert!(matches!(
+            result,
+            LoopResult::PromiseFulfilled { iterations: 4, .. }
+        ));
+        let metadata = crate::transcript::RunMetadata::load(&run_dir).unwrap();
+        assert_eq!(metadata.status, crate::transcript::RunStatus::Completed);
+        assert_eq!(metadata.iterations.len(), 4);
+        assert_eq!(
+            metadata.iterations[2].end_reason,
+            Some(IterationEndReason::Interrupted)
+        );
+    }
+
+    /// Mock agent whose process always fails
+    struct FailingMockAgent;
+
+    #[async_trait]
+    impl Agent for FailingMockAgent {
+        async fn run(&self, _prompt: &str) -> Result<AgentResult> {
+            Ok(AgentResult {
+                exit_reason: ExitReason::Error,
+                ..AgentResult::without_promise()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_consecutive_errors_abort_the_run() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            prompt: "test prompt".to_string(),
+            max_iterations: Some(10),
+            max_consecutive_errors: Some(3),
+            output_dir: temp_dir.path().to_path_buf(),
+            ..Config::default()
+        };
+
+        let controller =
+            LoopController::with_transcript_writer(config, FailingMockAgent, temp_dir.path())
+                .unwrap();
+        let result = controller.run().await.unwrap();
+
+        assert!(matches!(
+            result,
+            LoopResult::Failed {
+                iterations: 3,
+                consecutive_errors: 3
+            }
+        ));
+        let metadata =
+            crate::transcript::RunMetadata::load(&temp_dir.path().join("latest")).unwrap();
+        assert_eq!(metadata.status, crate::transcript::RunStatus::Failed);
+        assert_eq!(
+            metadata.iterations[2].end_reason,
+            Some(IterationEndReason::Error)
+        );
+    }
+
+    /// Mock agent of which some output lines can't be parsed
+    struct GarbledMockAgent;
+
+    #[async_trait]
+    impl Agent for GarbledMockAgent {
+        async fn run(&self, _prompt: &str) -> Result<AgentResult> {
+            let unparsed = crate::monitor::UnparsedLine {
+                line: "Error: unexpected token".to_string(),
+                error: "expected value".to_string(),
+            };
+            Ok(AgentResult {
+                event_count: 10,
+                unparsed: vec![unparsed; 4],
+                ..AgentResult::without_promise()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unparsed_output_is_kept_and_aborts_above_ratio() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            prompt: "test prompt".to_string(),
+            max_iterations: Some(5),
+            max_unparsed_ratio: Some(0.25),
+            output_dir: temp_dir.path().to_path_buf(),
+            ..Config::default()
+        };
+
+        let controller =
+            LoopController::with_transcript_writer(config, GarbledMockAgent, temp_dir.path())
+                .unwrap();
+
+        // The first iteration has too few lines for the ratio to apply
+        assert!(matches!(
+            controller.run().await,
+            Err(RalphError::Aborted(_))
+        ));
+        let run_dir = temp_dir.path().join("latest");
+        let metadata = crate::transcript::RunMetadata::load(&run_dir).unwrap();
+        assert_eq!(metadata.iterations.len(), 2);
+        assert_eq!(metadata.iterations[1].unparsed_lines, Some(4));
+        assert_eq!(metadata.status, crate::transcript::RunStatus::Failed);
+        let unparsed =
+            std::fs::read_to_string(run_dir.join(crate::transcript::UNPARSED_FILE)).unwrap();
+        assert_eq!(unparsed.lines().count(), 8);
+    }
+
+    fn classified_config(temp_dir: &tempfile::TempDir, command: &str) -> Config {
+        Config {
+            prompt: "test prompt".to_string(),
+            max_iterations: Some(2),
+            output_dir: temp_dir.path().to_path_buf(),
+            classify_command: Some(command.to_string()),
+            ..Config::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_classify_command_decides_completion() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = classified_config(&temp_dir, "echo done");
+        let controller =
+            LoopController::with_transcript_writer(config, NeverFindsMockAgent, temp_dir.path())
+                .unwrap();
+
+        match controller.run().await.unwrap() {
+            LoopResult::PromiseFulfilled {
+                iterations,
+                promise,
+                ..
+            } => {
+                assert_eq!(iterations, 1);
+                assert_eq!(promise, "TASK COMPLETE");
+            }
+            other => panic!("Expected PromiseFulfilled, got {other:?}"),
+        }
+        assert!(temp_dir
+            .path()
+            .join("latest/output/iteration_001.jsonl")
+            .exists());
+
+        // `continue` overrules a found promise
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = classified_config(&temp_dir, "echo continue");
+        let controller = LoopController::with_transcript_writer(
+            config,
+            MockAgent::new(1, "DONE"),
+            temp_dir.path(),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            controller.run().await,
+            Err(RalphError::MaxIterationsExceeded(2))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_classify_command_retries_and_aborts() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = classified_config(
+            &temp_dir,
+            r#"case $RALPH_ITERATION in 1) echo retry ;; 2) echo continue ;; *) echo abort ;; esac"#,
+        );
+        let controller =
+            LoopController::with_transcript_writer(config, NeverFindsMockAgent, temp_dir.path())
+                .unwrap();
+
+        // The retry grants a third iteration, which is aborted
+        assert!(matches!(
+            controller.run().await,
+            Err(RalphError::Aborted(_))
+        ));
+        let metadata =
+            crate::transcript::RunMetadata::load(&temp_dir.path().join("latest")).unwrap();
+        assert_eq!(metadata.iterations.len(), 3);
+        assert_eq!(metadata.status, crate::transcript::RunStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_extending_the_budget_after_max_retries() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            max_retries: 2,
+            ..classified_config(&temp_dir, "echo retry")
+        };
+        let controller =
+            LoopController::with_transcript_writer(config, NeverFindsMockAgent, temp_dir.path())
+                .unwrap();
+
+        // Two retries on top of the two iterations, then retries count
+        assert!(matches!(
+            controller.run().await,
+            Err(RalphError::MaxIterationsExceeded(4))
+        ));
+    }
+
+    /// Mock agent that checks off one plan item per call
+    struct PlanMockAgent {
+        plan_file: std::path::PathBuf,
+    }
+
+    #[async_trait]
+    impl Agent for PlanMockAgent {
+        async fn run(&self, _prompt: &str) -> Result<AgentResult> {
+            let plan = std::fs::read_to_string(&self.plan_file).unwrap();
+            std::fs::write(&self.plan_file, plan.replacen("- [ ]", "- [x]", 1)).unwrap();
+            Ok(AgentResult::without_promise())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_loop_stops_when_plan_is_completed() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let plan_file = temp_dir.path().join("PLAN.md");
+        std::fs::write(&plan_file, "- [ ] one\n- [ ] two\n- [x] three\n").unwrap();
+        let config = Config {
+            prompt: "test prompt".to_string(),
+            max_iterations: Some(10),
+            output_dir: temp_dir.path().to_path_buf(),
+            plan_file: Some(plan_file.clone()),
+            ..Config::default()
+        };
+
+        let controller = LoopController::with_transcript_writer(
+            config,
+            PlanMockAgent { plan_file },
+            temp_dir.path(),
+        )
+        .unwrap();
+        let result = controller.run().await.unwrap();
+
+        match result {
+            LoopResult::PlanCompleted {
+                iterations, plan, ..
+            } => {
+                assert_eq!(iterations, 2);
+                assert_eq!(plan, PlanProgress { done: 3, total: 3 });
+            }
+            _ => panic!("Expected PlanCompleted"),
+        }
+        let metadata =
+            crate::transcript::RunMetadata::load(&temp_dir.path().join("latest")).unwrap();
+        assert_eq!(metadata.status, crate::transcript::RunStatus::Completed);
+        assert_eq!(
+            metadata.iterations[0].plan,
+            Some(PlanProgress { done: 2, total: 3 })
+        );
+    }
+
+    /// Mock agent that asks for one more iteration and leaves a note on its first call
+    struct ExtendingMockAgent {
+        calls: AtomicU32,
+    }
+
+    #[async_trait]
+    impl Agent for ExtendingMockAgent {
+        async fn run(&self, _prompt: &str) -> Result<AgentResult> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) > 0 {
+                return Ok(AgentResult::without_promise());
+            }
+            Ok(AgentResult {
+                commands: vec![
+                    AgentCommand::ExtendIterations(1),
+                    AgentCommand::Note("need more time".to_string()),
+                ],
+                ..AgentResult::without_promise()
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_agent_commands_extend_budget_and_record_notes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            prompt: "test prompt".to_string(),
+            max_iterations: Some(1),
+            output_dir: temp_dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        let agent = ExtendingMockAgent {
+            calls: AtomicU32::new(0),
+        };
+
+        let controller =
+            LoopController::with_transcript_writer(config, agent, temp_dir.path()).unwrap();
+        let result = controller.run().await;
+
+        assert!(matches!(result, Err(RalphError::MaxIterationsExceeded(2))));
+        let metadata =
+            crate::transcript::RunMetadata::load(&temp_dir.path().join("latest")).unwrap();
+        assert_eq!(metadata.iterations.len(), 2);
+        assert_eq!(metadata.iterations[0].notes, vec!["need more time"]);
+        assert!(metadata.iterations[1].notes.is_empty());
+    }
+
+    /// Mock agent recording the prompt and context limit of each call
+    #[derive(Default)]
+    struct LimitsMockAgent {
+        prompts: std::sync::Mutex<Vec<String>>,
+        max_tokens: std::sync::Mutex<Vec<usize>>,
+    }
+
+    #[async_trait]
+    impl Agent for LimitsMockAgent {
+        async fn run(&self, _prompt: &str) -> Result<AgentResult> {
+            unreachable!("the controller passes the iteration's settings")
+        }
+
+        async fn run_iteration(
+            &self,
+            prompt: &str,
+            _env: &[(String, String)],
+            settings: &IterationSettings,
+        ) -> Result<AgentResult> {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            self.max_tokens
+                .lock()
+                .unwrap()
+                .push(settings.limits.max_tokens);
+            Ok(AgentResult::without_promise())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_first_iteration_gets_its_own_context_limit() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut config = Config {
+            prompt: "test prompt".to_string(),
+            max_iterations: Some(3),
+            output_dir: temp_dir.path().to_path_buf(),
+            ..Config::default()
+        };
+        config.context_limit.first_iteration_max_tokens = Some(250_000);
+        let agent = LimitsMockAgent::default();
+
+        let controller =
+            LoopController::with_transcript_writer(config, agent, temp_dir.path()).unwrap();
+        let _ = controller.run().await;
+
+        assert_eq!(
+            *controller.agent.max_tokens.lock().unwrap(),
+            [250_000, 180_000, 180_000]
+        );
+        let metadata =
+            crate::transcript::RunMetadata::load(&temp_dir.path().join("latest")).unwrap();
+        let limits: Vec<_> = metadata
+            .iterations
+            .iter()
+            .map(|iteration| iteration.limits.unwrap())
+            .collect();
+        assert_eq!(
+            limits,
+            [
+                IterationLimits {
+                    max_tokens: 250_000,
+                    warning_threshold: 150_000,
+                },
+                IterationLimits {
+                    max_tokens: 180_000,
+                    warning_threshold: 150_000,
+                },
+                IterationLimits {
+                    max_tokens: 180_000,
+                    warning_threshold: 150_000,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_promise_instructions_are_appended_to_every_prompt() {
+        let config = Config {
+            prompt: "test prompt".to_string(),
+            max_iterations: Some(2),
+            completion_promise: "ALL DONE".to_string(),
+            inject_promise_instructions: true,
+            ..Config::default()
+        };
+
+        let controller = LoopController::new(config, LimitsMockAgent::default());
+        let _ = controller.run().await;
+
+        let prompts = controller.agent.prompts.lock().unwrap();
+        assert_eq!(prompts.len(), 2);
+        for (prompt, reminder) in prompts.iter().zip([
+            "session 1 of at most 2 (sessions left after this one: 1)",
+            "session 2 of at most 2 (sessions left after this one: 0)",
+        ]) {
+            assert!(prompt.starts_with("test prompt\n\n## Completing the task"));
+            assert!(prompt.contains("<promise>ALL DONE</promise>"));
+            assert!(prompt.contains(reminder), "{prompt}");
+        }
+    }
+
+    /// Mock agent reaching one remaining milestone per call, always giving
+    /// the promise too
+    #[derive(Default)]
+    struct MilestonesMockAgent {
+        remaining: std::sync::Mutex<Vec<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Agent for MilestonesMockAgent {
+        async fn run(&self, _prompt: &str) -> Result<AgentResult> {
+            unreachable!("the controller passes the iteration's settings")
+        }
+
+        async fn run_iteration(
+            &self,
+            _prompt: &str,
+            _env: &[(String, String)],
+            settings: &IterationSettings,
+        ) -> Result<AgentResult> {
+            self.remaining
+                .lock()
+                .unwrap()
+                .push(settings.milestones.clone());
+            let mut result = AgentResult::with_promise("DONE");
+            result.milestones = settings.milestones.iter().take(1).cloned().collect();
+            Ok(result)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_promise_counts_once_the_milestones_were_reached() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let config = Config {
+            prompt: "test prompt".to_string(),
+            max_iterations: Some(5),
+            completion_promise: "DONE".to_string(),
+            milestones: vec!["PLAN READY".to_string(), "TESTS PASS".to_string()],
+            output_dir: temp_dir.path().to_path_buf(),
+            ..Config::default()
+        };
+
+        let controller = LoopController::with_transcript_writer(
+            config,
+            MilestonesMockAgent::default(),
+            temp_dir.path(),
+        )
+        .unwrap();
+        let result = controller.run().await.unwrap();
+
+        assert!(matches!(
+            result,
+            LoopResult::PromiseFulfilled { iterations: 2, .. }
+        ));
+        assert_eq!(
+            *controller.agent.remaining.lock().unwrap(),
+            [vec!["PLAN READY", "TESTS PASS"], vec!["TESTS PASS"]]
+        );
+        let metadata =
+            crate::transcript::RunMetadata::load(&temp_dir.path().join("latest")).unwrap();
+        let reached: Vec<_> = metadata
+            .milestones
+            .iter()
+            .map(|milestone| (milestone.promise.as_str(), milestone.iteration))
+            .collect();
+        assert_eq!(reached, [("PLAN READY", 1), ("TESTS PASS", 2)]);
+    }
+
     #[tokio::test]
     async fn test_returns_max_iterations_exceeded_error() {
         let agent = NeverFindsMockAgent;
@@ -345,4 +2160,15 @@ mod tests {
 
         assert!(matches!(result, Err(RalphError::MaxIterationsExceeded(3))));
     }
+
+    #[test]
+    fn iteration_delay_adds_bounded_jitter() {
+        assert_eq!(iteration_delay(0, 0, 12345), Duration::ZERO);
+        assert_eq!(iteration_delay(2, 0, 12345), Duration::from_secs(2));
+        assert_eq!(iteration_delay(1, 3, 1500), Duration::from_millis(2500));
+        for random in [0, 2999, 3000, u64::MAX] {
+            let delay = iteration_delay(1, 3, random);
+            assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(4));
+        }
+    }
 }
@@ -1,17 +1,50 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use clap::{Args, Parser, Subcommand};
 use colored::Colorize;
 use tokio::sync::broadcast;
 use tracing::{error, info, warn};
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
 
-use ralph_loop::agent::CliAgent;
-use ralph_loop::config::{AgentProvider, CliOverrides, Config};
+use ralph_loop::agent;
+use ralph_loop::audit::{self, AuditEntry};
+use ralph_loop::banner::{self, BannerContext};
+use ralph_loop::build_info::BuildInfo;
+use ralph_loop::cancel::cancel_run;
+use ralph_loop::capabilities;
+use ralph_loop::checkpoint;
+use ralph_loop::config::{
+    project_root, set_config_value, AgentProvider, CliOverrides, Config, ConfigSources, JobConfig,
+    LayeredConfig, PermissionMode, RedactionConfig, ReporterConfig, PROJECT_CONFIG_FILE,
+};
+use ralph_loop::consent::ensure_consent;
+use ralph_loop::debug_capture::DebugCapture;
+use ralph_loop::environment;
 use ralph_loop::error::RalphError;
+use ralph_loop::git::{self, FilesChanged};
+use ralph_loop::job::{self, JobResult};
 use ralph_loop::loop_controller::{LoopController, LoopResult};
-use ralph_loop::self_update::upgrade_current_binary;
+use ralph_loop::notify::{notify_run_end, RunEvent};
+use ralph_loop::pager;
+use ralph_loop::promise;
+use ralph_loop::redact::{redact_opt, Redactor};
+use ralph_loop::registry;
+use ralph_loop::replay;
+use ralph_loop::reporter::Reporter;
+use ralph_loop::run_result::{RunOutcome, RunResult};
+use ralph_loop::stats::{self, GroupBy, Metric, Thresholds};
+use ralph_loop::status_line::LogWriter;
+use ralph_loop::systemd::{self, UnitSpec};
+use ralph_loop::table::{self, Column, Elide};
+use ralph_loop::time_display::{TimeDisplay, TimeZoneChoice};
+use ralph_loop::tool_spans;
+use ralph_loop::transcript::{RunMetadata, RunStatus};
+use ralph_loop::usage::{SummaryFormat, UsageSummary};
+use ralph_loop::warnings::{self, RunWarning, WarningCollector};
 use ralph_loop::VERSION;
 
 /// Ralph Loop: Run a coding agent in a loop until a promise is fulfilled
@@ -28,19 +61,324 @@ struct Cli {
     /// Enable verbose logging (debug level). Use RUST_LOG=ralph_loop=trace for trace level
     #[arg(short = 'v', long = "verbose")]
     verbose: bool,
+
+    /// Log only errors and print the outcome as a single plain line
+    #[arg(short = 'q', long = "quiet", conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print nothing; the exit code tells the outcome
+    #[arg(long = "silent", conflicts_with_all = ["verbose", "quiet"])]
+    silent: bool,
+
+    /// Leave out the colored outcome banner at the end of a run
+    #[arg(long = "no-banner")]
+    no_banner: bool,
+
+    /// Print the usage summary at the end of a run as text, Markdown or JSON
+    #[arg(long = "summary-format", value_enum, default_value_t)]
+    summary_format: SummaryFormat,
+
+    /// Show timestamps in UTC
+    #[arg(long = "utc", global = true, conflicts_with = "local")]
+    utc: bool,
+
+    /// Show timestamps in local time (default)
+    #[arg(long = "local", global = true)]
+    local: bool,
+
+    /// Show timestamps as RFC 3339 and durations as ISO 8601
+    #[arg(long = "iso-8601", global = true)]
+    iso_8601: bool,
+
+    /// Print long output directly instead of through the pager
+    #[arg(long = "no-pager", global = true)]
+    no_pager: bool,
+
+    /// Redact secrets in what subcommands print, even if [redaction] is off
+    #[arg(long = "redact", global = true)]
+    redact: bool,
+
+    /// Print version, commit, build date, features and target as JSON
+    #[arg(long = "build-info")]
+    build_info: bool,
+}
+
+impl Cli {
+    fn verbosity(&self) -> Verbosity {
+        if self.silent {
+            Verbosity::Silent
+        } else if self.quiet {
+            Verbosity::Quiet
+        } else if self.verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        }
+    }
+
+    fn time_display(&self) -> TimeDisplay {
+        TimeDisplay {
+            zone: if self.utc {
+                TimeZoneChoice::Utc
+            } else {
+                TimeZoneChoice::Local
+            },
+            iso_8601: self.iso_8601,
+        }
+    }
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    /// Upgrade ralph-loop to the latest GitHub release
-    #[command(alias = "update")]
-    Upgrade,
+    /// Upgrade ralph-loop to the latest GitHub release, after verifying its checksum
+    #[command(name = "self-update", visible_alias = "upgrade", alias = "update")]
+    Upgrade {
+        /// Only report whether a newer release is available
+        #[arg(long = "check")]
+        check: bool,
+    },
+    /// Stop a running loop after its current iteration
+    Cancel {
+        /// ID of the run to cancel (directory name under <output-dir>/runs)
+        run_id: String,
+
+        /// Also kill the agent subprocess instead of waiting for the iteration to end
+        #[arg(long = "now")]
+        now: bool,
+
+        #[command(flatten)]
+        store: StoreArgs,
+    },
+    /// List recorded runs
+    Runs {
+        /// List runs of all projects, not just the current one
+        #[arg(long = "all-projects")]
+        all_projects: bool,
+
+        #[command(flatten)]
+        store: StoreArgs,
+    },
+    /// Continue a stopped run from its last checkpoint
+    Resume {
+        /// ID of the run to resume (directory name under <output-dir>/runs)
+        run_id: String,
+
+        #[command(flatten)]
+        run_args: RunArgs,
+    },
+    /// Re-run the loop's decisions on the agent output a past run kept
+    Replay {
+        /// ID of the run to replay (directory name under <output-dir>/runs)
+        run_id: String,
+
+        #[command(flatten)]
+        run_args: RunArgs,
+    },
+    /// Show calls, time, failures and output per tool for a run
+    Tools {
+        /// ID of the run (directory name under <output-dir>/runs)
+        run_id: String,
+
+        /// Print the absolute paths of the files the tool calls named instead
+        #[arg(long = "print-paths", conflicts_with = "edit")]
+        print_paths: bool,
+
+        /// Open the files the tool calls named in $VISUAL or $EDITOR instead
+        #[arg(long = "edit", conflicts_with = "calls")]
+        edit: bool,
+
+        /// List every call instead, with the calls of sub-agents indented
+        /// under the Task call that started them
+        #[arg(long = "calls", conflicts_with = "print_paths")]
+        calls: bool,
+
+        #[command(flatten)]
+        store: StoreArgs,
+    },
+    /// Show iterations, tokens, cost and duration per run, compared with baselines
+    Stats {
+        /// Show runs of all projects, not just the current one
+        #[arg(long = "all-projects")]
+        all_projects: bool,
+
+        /// Growth over a baseline flagged as a regression, in percent, for
+        /// every metric (`10`) or one (`cost=5`); may be repeated (default: 20)
+        #[arg(long = "threshold", value_name = "[METRIC=]PERCENT")]
+        thresholds: Vec<String>,
+
+        /// Sum the runs up per month and tag, or per month and task, instead
+        #[arg(long = "by", value_enum)]
+        by: Option<GroupBy>,
+
+        /// Print the table as CSV, without the baseline comparisons
+        #[arg(long = "csv")]
+        csv: bool,
+
+        #[command(flatten)]
+        store: StoreArgs,
+    },
+    /// Delete the run directories of finished runs started before a cutoff
+    Clean {
+        /// Delete runs started more than this many days ago
+        #[arg(long = "older-than", value_name = "DAYS", default_value_t = 30)]
+        older_than_days: u32,
+
+        /// Delete runs of all projects, not just the current one
+        #[arg(long = "all-projects")]
+        all_projects: bool,
+
+        /// List the runs that would be deleted without deleting them
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+
+        #[command(flatten)]
+        store: StoreArgs,
+    },
+    /// Mark runs as baselines that later runs of the same prompt are compared with
+    Baseline {
+        #[command(subcommand)]
+        command: BaselineCommand,
+    },
+    /// Run one loop unattended and publish its artifacts (for containers and CI)
+    Job(JobArgs),
+    /// Manage systemd units for unattended loops
+    Systemd {
+        #[command(subcommand)]
+        command: SystemdCommand,
+    },
+    /// Inspect the effective configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Print the configuration a run with these arguments would use
+    Show {
+        /// Print each value with the layer it came from
+        #[arg(long = "origin")]
+        origin: bool,
+
+        #[command(flatten)]
+        run_args: RunArgs,
+    },
+    /// Print the effective value of a key such as `context_limit.max_tokens`
+    Get {
+        key: String,
+
+        #[command(flatten)]
+        run_args: RunArgs,
+    },
+    /// Set a key in the project config file, e.g. `context_limit.max_tokens 150000`
+    Set {
+        key: String,
+
+        /// Value as TOML, e.g. `false` or `["--json"]`; anything else is a string
+        value: String,
+
+        /// Config file to change instead of the project's `.ralph.toml`
+        #[arg(long = "config")]
+        config: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BaselineCommand {
+    /// Mark a run as the baseline NAME, replacing an earlier one of that name
+    Set {
+        /// Name of the baseline, e.g. `main`
+        name: String,
+
+        /// ID of the run (directory name under <output-dir>/runs)
+        run_id: String,
+
+        #[command(flatten)]
+        store: StoreArgs,
+    },
+    /// Remove the baseline NAME
+    Remove {
+        name: String,
+
+        #[command(flatten)]
+        store: StoreArgs,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SystemdCommand {
+    /// Write a user unit running the loop configured for the current project
+    Install {
+        /// Task name; the unit is called ralph-<name>.service
+        #[arg(long = "name")]
+        name: String,
+
+        /// Arguments for the loop, given after `--`
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+}
+
+/// Artifact destinations for job mode, overriding `[job]` in the config
+#[derive(Args, Debug, Default)]
+struct JobArgs {
+    /// Directory (e.g. a mounted volume) to copy the run directory to
+    #[arg(long = "artifacts-dir", env = "RALPH_ARTIFACTS_DIR")]
+    artifacts_dir: Option<PathBuf>,
+
+    /// S3 URL (s3://bucket/prefix) to upload the run directory to
+    #[arg(long = "s3-url", env = "RALPH_S3_URL")]
+    s3_url: Option<String>,
+
+    /// Endpoint of an S3-compatible service
+    #[arg(long = "s3-endpoint-url", env = "RALPH_S3_ENDPOINT_URL")]
+    s3_endpoint_url: Option<String>,
+}
+
+impl JobArgs {
+    fn apply(self, config: &mut JobConfig) {
+        if self.artifacts_dir.is_some() {
+            config.artifacts_dir = self.artifacts_dir;
+        }
+        if self.s3_url.is_some() {
+            config.s3_url = self.s3_url;
+        }
+        if self.s3_endpoint_url.is_some() {
+            config.s3_endpoint_url = self.s3_endpoint_url;
+        }
+    }
+}
+
+/// Where to find recorded runs
+#[derive(Args, Debug, Default)]
+struct StoreArgs {
+    /// Output directory the runs live in (default: .ralph-loop-output)
+    #[arg(short = 'o', long = "output-dir")]
+    output_dir: Option<PathBuf>,
+
+    /// Use the global run store (RALPH_HOME or ~/.local/share/ralph)
+    #[arg(long = "global")]
+    global: bool,
+}
+
+impl StoreArgs {
+    fn resolve(self) -> Result<PathBuf, RalphError> {
+        Config {
+            output_dir: self
+                .output_dir
+                .unwrap_or_else(|| Config::default().output_dir),
+            global_store: self.global,
+            ..Config::default()
+        }
+        .runs_output_dir()
+    }
 }
 
 #[derive(Args, Debug, Default)]
 struct RunArgs {
     /// Prompt file path
-    #[arg(short = 'f', long = "prompt-file")]
+    #[arg(short = 'f', long = "prompt-file", env = "RALPH_PROMPT_FILE")]
     prompt_file: Option<PathBuf>,
 
     /// Prompt text (alternative to prompt file)
@@ -64,7 +402,7 @@ struct RunArgs {
     context_limit: Option<usize>,
 
     /// Config file (TOML format)
-    #[arg(long = "config")]
+    #[arg(long = "config", env = "RALPH_CONFIG")]
     config: Option<PathBuf>,
 
     /// Coding agent backend to use
@@ -78,32 +416,90 @@ struct RunArgs {
     /// Extra CLI args passed to the coding agent
     #[arg(long = "agent-arg")]
     agent_args: Vec<String>,
+
+    /// Tag recorded with the run, e.g. the task category; may be repeated
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+
+    /// Store runs in the global run store (RALPH_HOME or ~/.local/share/ralph)
+    #[arg(long = "global")]
+    global: bool,
+
+    /// Skip the first-run confirmation for running without permission checks
+    #[arg(
+        short = 'y',
+        long = "yes",
+        env = "RALPH_YES",
+        value_parser = clap::builder::BoolishValueParser::new()
+    )]
+    yes: bool,
+
+    /// Permission profile for the agent (default: skip)
+    #[arg(long = "permissions", value_enum)]
+    permissions: Option<PermissionMode>,
+
+    /// Markdown checklist; the run completes once every item is checked
+    #[arg(long = "plan-file")]
+    plan_file: Option<PathBuf>,
+
+    /// Append instructions for giving the completion promise to every prompt
+    #[arg(long = "inject-promise-instructions")]
+    inject_promise_instructions: bool,
+
+    /// Log the agent's thinking blocks, dimmed, along with what it does
+    #[arg(long = "show-thinking")]
+    show_thinking: bool,
+
+    /// Write a trace-level log of this run to debug.log in its run directory
+    #[arg(long = "capture-debug")]
+    capture_debug: bool,
+}
+
+/// How much a run prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verbosity {
+    /// Nothing at all (`--silent`)
+    Silent,
+    /// Errors and a one-line outcome (`--quiet`)
+    Quiet,
+    Normal,
+    /// Debug logging (`--verbose`)
+    Verbose,
 }
 
-fn setup_logging(verbose: bool) {
-    // Allow RUST_LOG to override, otherwise use verbose flag
+fn setup_logging(
+    verbosity: Verbosity,
+    warnings: &WarningCollector,
+    capture: Option<&DebugCapture>,
+) {
+    // Allow RUST_LOG to override, otherwise use the verbosity flags
     // Levels: info (default), debug (-v), trace (RUST_LOG=ralph_loop=trace)
-    let filter = if std::env::var("RUST_LOG").is_ok() {
-        EnvFilter::from_default_env()
-    } else if verbose {
-        EnvFilter::new("ralph_loop=debug,info")
-    } else {
-        EnvFilter::new("ralph_loop=info,warn")
+    let filter = match verbosity {
+        Verbosity::Silent => EnvFilter::new("off"),
+        _ if std::env::var("RUST_LOG").is_ok() => EnvFilter::from_default_env(),
+        Verbosity::Quiet => EnvFilter::new("error"),
+        Verbosity::Normal => EnvFilter::new("ralph_loop=info,warn"),
+        Verbosity::Verbose => EnvFilter::new("ralph_loop=debug,info"),
     };
 
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_target(false)
+    // Warnings are collected regardless of the log level
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_writer(LogWriter)
+                .with_filter(filter),
+        )
+        .with(warnings.layer())
+        .with(capture.map(|capture| capture.layer()))
         .init();
 }
 
-fn load_config(cli: &RunArgs) -> Result<Config, RalphError> {
-    // Start with default config or load from file
-    let mut config = if let Some(ref config_path) = cli.config {
-        Config::from_file(config_path)?
-    } else {
-        Config::default()
-    };
+/// The config layers merged with the CLI arguments, before a resumed run's settings
+fn layered_config(cli: &RunArgs, sources: &ConfigSources) -> Result<LayeredConfig, RalphError> {
+    let env = std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)));
+    let mut layered = LayeredConfig::load(sources, env)?;
 
     // Load prompt from file if specified
     let prompt = if let Some(ref prompt_file) = cli.prompt_file {
@@ -113,7 +509,7 @@ fn load_config(cli: &RunArgs) -> Result<Config, RalphError> {
     };
 
     // Merge CLI arguments
-    config.merge_cli_args(CliOverrides {
+    layered.merge_cli_args(CliOverrides {
         prompt,
         max_iterations: cli.max_iterations,
         completion_promise: cli.completion_promise.clone(),
@@ -126,20 +522,124 @@ fn load_config(cli: &RunArgs) -> Result<Config, RalphError> {
         } else {
             Some(cli.agent_args.clone())
         },
+        global_store: cli.global.then_some(true),
+        permissions: cli.permissions,
+        plan_file: cli.plan_file.clone(),
+        inject_promise_instructions: cli.inject_promise_instructions.then_some(true),
+        log_thinking: cli.show_thinking.then_some(true),
+        tags: (!cli.tags.is_empty()).then(|| cli.tags.clone()),
     });
+    if let Some((model, window)) = layered.calibrate_context_limit() {
+        info!(
+            "Context limit derived from the {} token context window of {}",
+            window, model
+        );
+    }
+    Ok(layered)
+}
+
+fn load_config(
+    cli: &RunArgs,
+    project_root: &Path,
+    resume: Option<&str>,
+) -> Result<Config, RalphError> {
+    // System and user config, then the explicit config file or the discovered .ralph.toml
+    let sources = ConfigSources::discover(project_root, cli.config.as_deref());
+    if let (Some(path), Some(_)) = (&sources.project, &sources.project_root) {
+        info!("Using project config {}", path.display());
+    }
+    let mut config = layered_config(cli, &sources)?.config;
+    config.output_dir = config.runs_output_dir()?;
+    if let Some(run_id) = resume {
+        restore_run_config(&mut config, cli, run_id)?;
+    }
+
+    // Fail early on contradicting settings and invalid redaction patterns
+    config.validate()?;
+    Redactor::from_config(&config.redaction)?;
 
     // Validate that we have a prompt
     if config.prompt.is_empty() {
         return Err(RalphError::NoPromptProvided);
     }
+    // Without the promise the agent can't end the loop, unless a plan file does
+    if !config.inject_promise_instructions && config.plan_file.is_none() {
+        if let Some(problem) = promise::lint_prompt(&config.prompt, &config.completion_promise) {
+            warn!("{problem}, so the loop may never finish (see --inject-promise-instructions)");
+        }
+    }
 
     Ok(config)
 }
 
+/// Take the prompt, completion promise and iteration budget of a resumed run
+/// from its checkpoint and metadata, unless they were given again
+fn restore_run_config(config: &mut Config, cli: &RunArgs, run_id: &str) -> Result<(), RalphError> {
+    let run_dir = config.output_dir.join("runs").join(run_id);
+    if !run_dir.is_dir() {
+        return Err(RalphError::ResumeError(format!(
+            "run '{run_id}' not found in {}",
+            config.output_dir.join("runs").display()
+        )));
+    }
+    let metadata = RunMetadata::load(&run_dir)?;
+    if cli.completion_promise.is_none() {
+        config.completion_promise = metadata.completion_promise;
+    }
+    let checkpoint = checkpoint::load(&run_dir)?;
+    if let Some(ref checkpoint) = checkpoint {
+        if cli.max_iterations.is_none() {
+            config.max_iterations = checkpoint.max_iterations;
+        }
+    }
+    if cli.prompt.is_none() && cli.prompt_file.is_none() {
+        match checkpoint.and_then(|checkpoint| checkpoint.prompt) {
+            Some(prompt) => config.prompt = prompt,
+            None if config.prompt.is_empty() => {
+                return Err(RalphError::ResumeError(format!(
+                    "run '{run_id}' has no stored prompt: pass it again with -p or -f"
+                )));
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+/// Run the loop; the run directory, if the run got that far, and how it ended
 async fn run(
     config: Config,
+    project_path: &Path,
+    resume: Option<&str>,
+    capture: Option<&DebugCapture>,
     mut shutdown_rx: broadcast::Receiver<()>,
-) -> Result<LoopResult, RalphError> {
+) -> (Option<PathBuf>, Result<LoopResult, RalphError>) {
+    let controller = match start(config, project_path, resume, capture).await {
+        Ok(controller) => controller,
+        Err(e) => return (None, Err(e)),
+    };
+    let run_dir = controller.run_dir().await;
+
+    // Run the loop with shutdown handling
+    let result = tokio::select! {
+        result = controller.run() => {
+            result
+        }
+        _ = shutdown_rx.recv() => {
+            warn!("Shutdown signal received");
+            Err(RalphError::ShutdownRequested)
+        }
+    };
+    (run_dir, result)
+}
+
+/// Create the agent and the controller of the loop
+async fn start(
+    config: Config,
+    project_path: &Path,
+    resume: Option<&str>,
+    capture: Option<&DebugCapture>,
+) -> Result<LoopController<Box<dyn agent::Agent>>, RalphError> {
     // Create output directory
     std::fs::create_dir_all(&config.output_dir).map_err(RalphError::OutputDirError)?;
 
@@ -158,45 +658,918 @@ async fn run(
         info!("Running in infinite loop mode (until promise found or Ctrl+C)");
     }
     info!("Context limit: {} tokens", config.context_limit.max_tokens);
-
-    // Get current working directory as project path
-    let project_path = std::env::current_dir().map_err(RalphError::OutputDirError)?;
+    if config.skips_permissions() {
+        warn!(
+            "{} the agent runs with ALL permission checks disabled and may execute any command. \
+             Use --permissions accept-edits or --permissions default to restrict it.",
+            "WARNING:".red().bold()
+        );
+    }
 
     // Create the agent and controller with transcript writer
-    let agent = CliAgent::new(Arc::new(config.clone()));
-    let controller = LoopController::with_transcript_writer(config, agent, &project_path)?;
-    info!("Run metadata will be written to .ralph-loop-output/runs");
-
-    // Run the loop with shutdown handling
-    tokio::select! {
-        result = controller.run() => {
-            result
-        }
-        _ = shutdown_rx.recv() => {
-            warn!("Shutdown signal received");
-            Err(RalphError::ShutdownRequested)
+    let agent = agent::from_config(Arc::new(config.clone()))?;
+    let runs_dir = config.output_dir.join("runs");
+    let controller = match resume {
+        Some(run_id) => LoopController::resume(config, agent, run_id).await?,
+        None => LoopController::with_transcript_writer(config, agent, project_path)?,
+    };
+    info!("Run metadata will be written to {}", runs_dir.display());
+    if let (Some(capture), Some(run_dir)) = (capture, controller.run_dir().await) {
+        // Config was validated in load_config
+        let redactor = Redactor::from_config(&controller.config().redaction).unwrap_or(None);
+        match capture.attach(&run_dir, redactor) {
+            Ok(path) => info!("Debug log will be written to {}", path.display()),
+            Err(e) => warn!("Failed to capture the debug log: {}", e),
         }
     }
+    Ok(controller)
 }
 
-#[tokio::main]
-async fn main() {
-    let cli = Cli::parse();
+/// How subcommands print their outcome
+struct Printer {
+    paged: bool,
+    redactor: Option<Redactor>,
+}
 
-    setup_logging(cli.verbose);
+impl Printer {
+    /// Output goes through the pager unless `no_pager`. Secrets are redacted
+    /// with the project's redaction patterns if `redact` or `[redaction]` is
+    /// enabled, so output can be shown on a shared screen.
+    fn new(no_pager: bool, redact: bool) -> Self {
+        let run_args = RunArgs::default();
+        let mut redaction = current_sources(&run_args)
+            .and_then(|sources| layered_config(&run_args, &sources))
+            .map(|layered| layered.config.redaction)
+            .unwrap_or_default();
+        redaction.enabled |= redact;
+        let redactor = Redactor::from_config(&redaction).unwrap_or_else(|e| {
+            warn!("Ignoring the redaction patterns: {}", e);
+            Redactor::from_config(&RedactionConfig {
+                enabled: redaction.enabled,
+                ..RedactionConfig::default()
+            })
+            .unwrap_or(None)
+        });
+        Self {
+            paged: !no_pager,
+            redactor,
+        }
+    }
 
-    if let Some(Commands::Upgrade) = cli.command {
-        match upgrade_current_binary() {
+    /// Print the outcome of a subcommand, redacted and through the pager if
+    /// configured, and exit
+    fn exit_with(&self, outcome: Result<String, RalphError>) -> ! {
+        match outcome {
             Ok(message) => {
-                println!("{message}");
+                pager::print(&redact_opt(self.redactor.as_ref(), &message), self.paged);
                 std::process::exit(0);
             }
             Err(error) => {
-                eprintln!("{error}");
+                eprintln!("{}", redact_opt(self.redactor.as_ref(), &error.to_string()));
                 std::process::exit(1);
             }
         }
     }
+}
+
+/// Run a maintenance subcommand, print its outcome and exit
+fn run_subcommand(command: Commands, time: TimeDisplay, printer: Printer) -> ! {
+    let outcome = match command {
+        Commands::Upgrade { check } => self_update(check),
+        Commands::Cancel { run_id, now, store } => store
+            .resolve()
+            .and_then(|output_dir| cancel_run(&output_dir, &run_id, now)),
+        Commands::Runs {
+            all_projects,
+            store,
+        } => list_runs(store, all_projects, time),
+        Commands::Tools {
+            run_id,
+            print_paths,
+            edit,
+            calls,
+            store,
+        } => tool_stats(store, &run_id, time, print_paths, edit, calls),
+        Commands::Stats {
+            all_projects,
+            thresholds,
+            by,
+            csv,
+            store,
+        } => run_stats(store, all_projects, &thresholds, by, csv, time),
+        Commands::Clean {
+            older_than_days,
+            all_projects,
+            dry_run,
+            store,
+        } => clean_runs(store, all_projects, older_than_days, dry_run),
+        Commands::Baseline {
+            command:
+                BaselineCommand::Set {
+                    name,
+                    run_id,
+                    store,
+                },
+        } => store.resolve().and_then(|output_dir| {
+            stats::set_baseline(&output_dir, &name, &run_id)
+                .map(|baseline| format!("baseline '{}' is run {}", baseline.name, baseline.run_id))
+        }),
+        Commands::Baseline {
+            command: BaselineCommand::Remove { name, store },
+        } => store.resolve().and_then(|output_dir| {
+            stats::remove_baseline(&output_dir, &name)
+                .map(|()| format!("removed baseline '{name}'"))
+        }),
+        Commands::Systemd {
+            command: SystemdCommand::Install { name, args },
+        } => install_systemd_unit(name, args),
+        Commands::Config {
+            command: ConfigCommand::Show { origin, run_args },
+        } => show_config(&run_args, origin),
+        Commands::Config {
+            command: ConfigCommand::Get { key, run_args },
+        } => get_config_value(&run_args, &key),
+        Commands::Config {
+            command: ConfigCommand::Set { key, value, config },
+        } => set_project_config_value(config, &key, &value),
+        Commands::Job(_) | Commands::Resume { .. } | Commands::Replay { .. } => {
+            unreachable!("runs the loop")
+        }
+    };
+    printer.exit_with(outcome)
+}
+
+/// Replay the agent output the run `run_id` kept through the loop controller
+async fn replay_run(run_args: &RunArgs, run_id: &str) -> Result<String, RalphError> {
+    let mut config = layered_config(run_args, &current_sources(run_args)?)?.config;
+    config.output_dir = config.runs_output_dir()?;
+    let run_dir = config.output_dir.join("runs").join(run_id);
+    if !run_dir.is_dir() {
+        return Err(RalphError::ReplayError(format!(
+            "run '{run_id}' not found in {}",
+            config.output_dir.join("runs").display()
+        )));
+    }
+    // The run's promise and agent, unless given again
+    let metadata = RunMetadata::load(&run_dir)?;
+    let notice = origin_notice(&metadata);
+    if run_args.completion_promise.is_none() {
+        config.completion_promise = metadata.completion_promise;
+    }
+    if run_args.agent_provider.is_none() {
+        config.agent.provider = metadata.agent_provider;
+    }
+    config.validate()?;
+
+    let (iterations, outcome) = replay::replay_run(config, &run_dir).await?;
+    Ok(with_notice(notice, replay::render(&iterations, &outcome)))
+}
+
+/// The config files for a run with `run_args` from the current directory
+fn current_sources(run_args: &RunArgs) -> Result<ConfigSources, RalphError> {
+    let cwd = std::env::current_dir().map_err(RalphError::OutputDirError)?;
+    Ok(ConfigSources::discover(
+        &project_root(&cwd),
+        run_args.config.as_deref(),
+    ))
+}
+
+/// The effective configuration as TOML, or with `origin` as one value per
+/// line followed by the layer it came from
+fn show_config(run_args: &RunArgs, origin: bool) -> Result<String, RalphError> {
+    let layered = layered_config(run_args, &current_sources(run_args)?)?;
+    if !origin {
+        return toml::to_string(&layered.config)
+            .map(|text| text.trim_end().to_string())
+            .map_err(|e| RalphError::ConfigError(e.to_string()));
+    }
+    let lines: Vec<(String, String)> = layered
+        .values()?
+        .into_iter()
+        .map(|(key, value, origin)| (format!("{key} = {value}"), origin.to_string()))
+        .collect();
+    // Align the origins, unless a long value like the prompt would push them far out
+    let width = lines
+        .iter()
+        .map(|(line, _)| line.len())
+        .filter(|len| *len <= 60)
+        .max()
+        .unwrap_or(0);
+    Ok(lines
+        .into_iter()
+        .map(|(line, origin)| format!("{line:width$}  # {origin}"))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// The effective value of `key`; strings are printed without quotes
+fn get_config_value(run_args: &RunArgs, key: &str) -> Result<String, RalphError> {
+    let layered = layered_config(run_args, &current_sources(run_args)?)?;
+    match layered.get(key)? {
+        Some(toml::Value::String(text)) => Ok(text),
+        Some(toml::Value::Table(table)) => toml::to_string(&table)
+            .map(|text| text.trim_end().to_string())
+            .map_err(|e| RalphError::ConfigError(e.to_string())),
+        Some(value) => Ok(value.to_string()),
+        None => Err(RalphError::ConfigError(format!("`{key}` is not set"))),
+    }
+}
+
+/// Set `key` in `config`, or in the project's `.ralph.toml`
+fn set_project_config_value(
+    config: Option<PathBuf>,
+    key: &str,
+    value: &str,
+) -> Result<String, RalphError> {
+    let path = match config {
+        Some(path) => path,
+        None => {
+            let cwd = std::env::current_dir().map_err(RalphError::OutputDirError)?;
+            project_root(&cwd).join(PROJECT_CONFIG_FILE)
+        }
+    };
+    let value = set_config_value(&path, key, value)?;
+    Ok(format!("Set {key} = {value} in {}", path.display()))
+}
+
+/// Warnings logged so far, with secrets redacted
+fn redacted_warnings(collector: &WarningCollector, redactor: Option<&Redactor>) -> Vec<RunWarning> {
+    collector
+        .warnings()
+        .into_iter()
+        .map(|warning| RunWarning {
+            message: redact_opt(redactor, &warning.message).into_owned(),
+            ..warning
+        })
+        .collect()
+}
+
+/// Store the warnings of the run in its metadata, so they are reported too
+fn record_warnings(run_dir: Option<&Path>, warnings: &[RunWarning]) {
+    if warnings.is_empty() {
+        return;
+    }
+    let Some(run_dir) = run_dir else {
+        return;
+    };
+    if let Err(e) = RunMetadata::record_warnings(run_dir, warnings.to_vec()) {
+        // Logged after collection ended, so this one only shows in the log
+        warn!("Failed to record warnings: {}", e);
+    }
+}
+
+/// Write the machine-readable outcome of the run into its directory
+fn write_run_result(
+    run_dir: Option<&Path>,
+    result: &Result<LoopResult, RalphError>,
+    exit_code: i32,
+) {
+    let Some(run_dir) = run_dir else {
+        return;
+    };
+    let written = RunMetadata::load(run_dir)
+        .and_then(|metadata| RunResult::new(result, &metadata, exit_code).write(run_dir));
+    if let Err(e) = written {
+        warn!("Failed to write the run result: {}", e);
+    }
+}
+
+/// Usage of the run that just ended, for the summary printed at its end
+fn usage_summary(run_dir: Option<&Path>, wall_time: Duration) -> Option<UsageSummary> {
+    let metadata = RunMetadata::load(run_dir?).ok()?;
+    Some(UsageSummary::from_metadata(&metadata, wall_time))
+}
+
+/// Store the files changed since the run started in its metadata
+fn record_files_changed(run_dir: Option<&Path>, output_dir: &Path) -> Option<FilesChanged> {
+    let run_dir = run_dir?;
+    let metadata = RunMetadata::load(run_dir).ok()?;
+    let base = metadata.base_commit()?;
+    let project_path = Path::new(&metadata.project_path);
+    // The run's own output doesn't count, unless it's kept outside the project
+    let output_dir = output_dir.canonicalize().ok();
+    let exclude = output_dir
+        .as_deref()
+        .and_then(|dir| dir.strip_prefix(project_path).ok());
+    let mut audited = Vec::new();
+    let files_changed = git::files_changed_since(
+        project_path,
+        base,
+        exclude,
+        &metadata.git_dirty,
+        &mut audited,
+    );
+    for record in audited {
+        let entry = AuditEntry {
+            iteration: None,
+            record,
+        };
+        if let Err(e) = audit::append(run_dir, &entry) {
+            warn!("Failed to write audit log: {}", e);
+        }
+    }
+    let files_changed = match files_changed {
+        Ok(files_changed) => files_changed,
+        Err(e) => {
+            warn!("Failed to list the files changed during the run: {}", e);
+            return None;
+        }
+    };
+    if let Err(e) = RunMetadata::record_files_changed(run_dir, files_changed.clone()) {
+        warn!("Failed to record the files changed during the run: {}", e);
+    }
+    Some(files_changed)
+}
+
+/// Send the run to the central reporting server, if one is configured
+async fn report_run(config: &ReporterConfig, output_dir: &Path, run_dir: Option<&Path>) {
+    let Some(reporter) = Reporter::from_config(config, output_dir) else {
+        return;
+    };
+    let Some(run_dir) = run_dir.map(Path::to_path_buf) else {
+        return;
+    };
+    // Retries back off with blocking sleeps
+    match tokio::task::spawn_blocking(move || reporter.report(&run_dir)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!("Failed to report run: {}", e),
+        Err(e) => warn!("Failed to report run: {}", e),
+    }
+}
+
+/// Log an error that kept the run from starting and exit with its code
+fn exit_on_error(error: RalphError) -> ! {
+    error!("{}", error);
+    std::process::exit(RunOutcome::from_error(&error).exit_code());
+}
+
+/// Publish the artifacts of a job and return its exit code, printing the
+/// outcome and where the artifacts went if `report`
+fn finish_job(
+    config: &JobConfig,
+    run_dir: Option<&Path>,
+    result: &Result<LoopResult, RalphError>,
+    report: bool,
+) -> i32 {
+    let run_id = run_dir
+        .and_then(|dir| dir.file_name())
+        .map(|name| name.to_string_lossy().to_string());
+    let job_result = JobResult::new(result, run_id);
+    if report {
+        println!(
+            "job outcome: {:?} (exit code {})",
+            job_result.outcome, job_result.exit_code
+        );
+    }
+
+    let Some(run_dir) = run_dir else {
+        warn!("No run directory was created, nothing to publish");
+        return job_result.exit_code;
+    };
+    match job::publish(run_dir, &job_result, config) {
+        Ok(destinations) => {
+            for destination in destinations.iter().filter(|_| report) {
+                println!("artifacts: {destination}");
+            }
+            job_result.exit_code
+        }
+        Err(e) => {
+            error!("Failed to publish artifacts: {}", e);
+            if job_result.exit_code == 0 {
+                job::EXIT_PUBLISH_FAILED
+            } else {
+                job_result.exit_code
+            }
+        }
+    }
+}
+
+fn install_systemd_unit(name: String, args: Vec<String>) -> Result<String, RalphError> {
+    let cwd = std::env::current_dir().map_err(RalphError::OutputDirError)?;
+    let executable = std::env::current_exe().map_err(RalphError::OutputDirError)?;
+    let spec = UnitSpec {
+        name,
+        executable,
+        working_dir: project_root(&cwd),
+        args,
+    };
+    systemd::install(&spec, None)
+}
+
+fn list_runs(
+    store: StoreArgs,
+    all_projects: bool,
+    time: TimeDisplay,
+) -> Result<String, RalphError> {
+    let output_dir = store.resolve()?;
+    let project = if all_projects {
+        None
+    } else {
+        Some(current_project()?)
+    };
+
+    let runs = registry::list_runs(&output_dir, project.as_deref())?;
+    if runs.is_empty() {
+        return Ok(format!("no runs found in {}", output_dir.display()));
+    }
+    let mut columns = vec![
+        Column::new("run", Elide::Never, 0),
+        Column::new("started", Elide::Never, 0),
+        Column::new("status", Elide::End, 8),
+        Column::new("iterations", Elide::Never, 0),
+    ];
+    if all_projects {
+        columns.push(Column::new("project", Elide::Start, 16));
+    }
+    let rows: Vec<Vec<String>> = runs
+        .iter()
+        .map(|run| {
+            let mut row = vec![
+                run.run_id.clone(),
+                time.timestamp(run.started_at),
+                match (&run.status, &run.paused) {
+                    (RunStatus::Running, Some(reason)) => format!("paused: {reason}"),
+                    (status, _) => {
+                        let status = format!("{status:?}").to_lowercase();
+                        match run.reason_detail {
+                            Some(ref detail) => format!("{status}: {detail}"),
+                            None => status,
+                        }
+                    }
+                },
+                format!("{} iteration(s)", run.iterations.len()),
+            ];
+            if all_projects {
+                row.push(run.project_path.clone());
+            }
+            row
+        })
+        .collect();
+    Ok(table::render(&columns, &rows, table::terminal_width()))
+}
+
+/// Path of the project the current directory belongs to, as runs record it
+fn current_project() -> Result<String, RalphError> {
+    let root = project_root(&std::env::current_dir().map_err(RalphError::OutputDirError)?);
+    Ok(root
+        .canonicalize()
+        .unwrap_or(root)
+        .to_string_lossy()
+        .to_string())
+}
+
+/// Check for a newer release, or install it
+#[cfg(feature = "self-update")]
+fn self_update(check: bool) -> Result<String, RalphError> {
+    if check {
+        ralph_loop::self_update::check_for_update()
+    } else {
+        ralph_loop::self_update::upgrade_current_binary()
+    }
+}
+
+#[cfg(not(feature = "self-update"))]
+fn self_update(_check: bool) -> Result<String, RalphError> {
+    Err(RalphError::UpgradeError(
+        "self-update is not included in this build; update ralph-loop through the package manager it came from".to_string(),
+    ))
+}
+
+/// Delete the runs started more than `older_than_days` ago, except runs
+/// that may still be running, baselines and the latest run
+fn clean_runs(
+    store: StoreArgs,
+    all_projects: bool,
+    older_than_days: u32,
+    dry_run: bool,
+) -> Result<String, RalphError> {
+    let output_dir = store.resolve()?;
+    let project = if all_projects {
+        None
+    } else {
+        Some(current_project()?)
+    };
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(older_than_days.into());
+    let baselines = stats::load_baselines(&output_dir)?;
+    let latest = std::fs::read_link(output_dir.join("latest"))
+        .ok()
+        .and_then(|target| {
+            target
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        });
+
+    let mut lines = Vec::new();
+    for run in registry::list_runs(&output_dir, project.as_deref())? {
+        if run.started_at >= cutoff
+            || run.status == RunStatus::Running
+            || baselines.iter().any(|b| b.run_id == run.run_id)
+            || latest.as_deref() == Some(run.run_id.as_str())
+        {
+            continue;
+        }
+        if dry_run {
+            lines.push(format!("would delete {}", run.run_id));
+        } else {
+            registry::remove_run(&output_dir, &run.run_id)?;
+            lines.push(format!("deleted {}", run.run_id));
+        }
+    }
+    if lines.is_empty() {
+        return Ok(format!(
+            "no finished runs older than {older_than_days} day(s) in {}",
+            output_dir.display()
+        ));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Totals of each run as a table, followed by a table per baseline
+/// comparing the later runs of its prompt with it, or the totals per month
+/// and group with `by`; as CSV with `csv`
+fn run_stats(
+    store: StoreArgs,
+    all_projects: bool,
+    threshold_specs: &[String],
+    by: Option<GroupBy>,
+    csv: bool,
+    time: TimeDisplay,
+) -> Result<String, RalphError> {
+    let mut thresholds = Thresholds::default();
+    for spec in threshold_specs {
+        thresholds.apply(spec)?;
+    }
+    let output_dir = store.resolve()?;
+    let project = if all_projects {
+        None
+    } else {
+        Some(current_project()?)
+    };
+    let runs = registry::list_runs(&output_dir, project.as_deref())?;
+    if runs.is_empty() {
+        return Ok(format!("no runs found in {}", output_dir.display()));
+    }
+    if let Some(group_by) = by {
+        let totals = stats::by_group(&runs, group_by, time);
+        let group = match group_by {
+            GroupBy::Tag => "tag",
+            GroupBy::Task => "task",
+        };
+        let header = ["month", group, "runs", "iterations", "tokens", "cost"];
+        let rows: Vec<Vec<String>> = totals
+            .iter()
+            .map(|t| {
+                vec![
+                    t.month.clone(),
+                    t.group.clone(),
+                    t.runs.to_string(),
+                    t.iterations.to_string(),
+                    t.tokens.to_string(),
+                    if csv {
+                        format!("{:.4}", t.cost_usd)
+                    } else {
+                        format!("${:.2}", t.cost_usd)
+                    },
+                ]
+            })
+            .collect();
+        if csv {
+            return Ok(stats::csv(&header, &rows));
+        }
+        let columns = [
+            Column::new("month", Elide::Never, 0),
+            Column::new(group, Elide::End, 12),
+            Column::new("runs", Elide::Never, 0),
+            Column::new("iterations", Elide::Never, 0),
+            Column::new("tokens", Elide::Never, 0),
+            Column::new("cost", Elide::Never, 0),
+        ];
+        return Ok(table::render_with_header(
+            &columns,
+            &rows,
+            table::terminal_width(),
+        ));
+    }
+    if csv {
+        let header = [
+            "run",
+            "started",
+            "iterations",
+            "tokens",
+            "cost_usd",
+            "duration_secs",
+        ];
+        let rows: Vec<Vec<String>> = runs
+            .iter()
+            .map(|run| {
+                let mut row = vec![
+                    run.run_id.clone(),
+                    run.started_at
+                        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                ];
+                row.extend(Metric::ALL.map(|metric| {
+                    metric
+                        .value(run)
+                        .map_or_else(String::new, |v| v.to_string())
+                }));
+                row
+            })
+            .collect();
+        return Ok(stats::csv(&header, &rows));
+    }
+
+    let width = table::terminal_width();
+    let metric_columns = Metric::ALL.map(|metric| match metric {
+        Metric::Iterations => Column::new("iterations", Elide::Never, 0),
+        Metric::Tokens => Column::new("tokens", Elide::Never, 0),
+        Metric::Cost => Column::new("cost", Elide::Never, 0),
+        Metric::Duration => Column::new("duration", Elide::Never, 0),
+    });
+
+    let mut columns = vec![
+        Column::new("run", Elide::Never, 0),
+        Column::new("started", Elide::Never, 0),
+    ];
+    columns.extend(metric_columns);
+    let rows: Vec<Vec<String>> = runs
+        .iter()
+        .map(|run| {
+            let mut row = vec![run.run_id.clone(), time.timestamp(run.started_at)];
+            row.extend(Metric::ALL.map(|metric| {
+                metric.value(run).map_or_else(
+                    || "-".to_string(),
+                    |value| metric.format(value, false, time),
+                )
+            }));
+            row
+        })
+        .collect();
+    let mut output = table::render_with_header(&columns, &rows, width);
+
+    let baselines = stats::load_baselines(&output_dir)?;
+    let mut columns = vec![Column::new("run", Elide::Never, 0)];
+    columns.extend(metric_columns);
+    columns.push(Column::new("status", Elide::End, 10));
+    for comparison in stats::comparisons(&baselines, &runs, &thresholds) {
+        output.push_str(&format!(
+            "\n\nbaseline '{}': run {}, started {}",
+            comparison.baseline.name,
+            comparison.baseline_run.run_id,
+            time.timestamp(comparison.baseline_run.started_at)
+        ));
+        if comparison.runs.is_empty() {
+            output.push_str("\nno later runs of the same prompt");
+            continue;
+        }
+        let rows: Vec<Vec<String>> = comparison
+            .runs
+            .iter()
+            .map(|(run, deltas)| {
+                let mut row = vec![run.run_id.clone()];
+                row.extend(Metric::ALL.map(|metric| {
+                    deltas
+                        .iter()
+                        .find(|delta| delta.metric == metric)
+                        .map_or_else(|| "-".to_string(), |delta| delta.render(time))
+                }));
+                let regressions: Vec<String> = deltas
+                    .iter()
+                    .filter(|delta| delta.regression)
+                    .map(|delta| delta.metric.to_string())
+                    .collect();
+                row.push(if regressions.is_empty() {
+                    "ok".to_string()
+                } else {
+                    format!("regression: {}", regressions.join(", "))
+                });
+                row
+            })
+            .collect();
+        output.push('\n');
+        output.push_str(&table::render_with_header(&columns, &rows, width));
+    }
+    Ok(output)
+}
+
+/// Usage per tool of the run `run_id`, as a table, or the files its tool
+/// calls named, printed or opened in the editor
+fn tool_stats(
+    store: StoreArgs,
+    run_id: &str,
+    time: TimeDisplay,
+    print_paths: bool,
+    edit: bool,
+    calls: bool,
+) -> Result<String, RalphError> {
+    let output_dir = store.resolve()?;
+    let run_dir = output_dir.join("runs").join(run_id);
+    if !run_dir.is_dir() {
+        return Err(RalphError::ToolStatsError(format!(
+            "run '{run_id}' not found in {}",
+            output_dir.join("runs").display()
+        )));
+    }
+    let spans = tool_spans::load(&run_dir)?;
+    let metadata = RunMetadata::load(&run_dir);
+    let notice = metadata.as_ref().ok().and_then(origin_notice);
+    if print_paths || edit {
+        // Keep stdout to the paths, for piping them on
+        if let Some(ref notice) = notice {
+            eprintln!("{notice}");
+        }
+        let project_path = metadata?.project_path;
+        let paths = tool_spans::referenced_paths(&spans, Path::new(&project_path));
+        if paths.is_empty() {
+            return Ok(format!(
+                "no files named by the tool calls of run '{run_id}'"
+            ));
+        }
+        if edit {
+            open_in_editor(&paths)?;
+            return Ok(format!("opened {} file(s)", paths.len()));
+        }
+        return Ok(paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n"));
+    }
+    if spans.is_empty() {
+        return Ok(format!("no tool calls recorded for run '{run_id}'"));
+    }
+    if calls {
+        return Ok(with_notice(notice, tool_spans::render_calls(&spans)));
+    }
+    let stats = tool_spans::stats(&spans);
+
+    let columns = [
+        Column::new("tool", Elide::End, 8),
+        Column::new("calls", Elide::Never, 0),
+        Column::new("total", Elide::Never, 0),
+        Column::new("average", Elide::Never, 0),
+        Column::new("failed", Elide::Never, 0),
+        Column::new("output", Elide::Never, 0),
+    ];
+    let rows: Vec<Vec<String>> = stats
+        .iter()
+        .map(|tool| {
+            vec![
+                tool.name.clone(),
+                tool.calls.to_string(),
+                time.duration(Duration::from_millis(tool.total_ms)),
+                format!("{:.1}s", tool.average_ms() as f64 / 1000.0),
+                tool.failures.to_string(),
+                format_bytes(tool.output_bytes),
+            ]
+        })
+        .collect();
+    Ok(with_notice(
+        notice,
+        table::render_with_header(&columns, &rows, table::terminal_width()),
+    ))
+}
+
+/// The note for a run made on another machine or in a project that isn't
+/// here, shown once above what is printed about it
+fn origin_notice(metadata: &RunMetadata) -> Option<String> {
+    metadata.origin_notice(environment::hostname().as_deref())
+}
+
+/// `output` with `notice`, if any, on the line above
+fn with_notice(notice: Option<String>, output: String) -> String {
+    match notice {
+        Some(notice) => format!("{notice}\n{output}"),
+        None => output,
+    }
+}
+
+/// Open `paths` in `$VISUAL` or `$EDITOR`, which may carry arguments, and
+/// wait until it exits
+fn open_in_editor(paths: &[PathBuf]) -> Result<(), RalphError> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .ok_or_else(|| {
+            RalphError::ToolStatsError("set $VISUAL or $EDITOR, or use --print-paths".to_string())
+        })?;
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg("editor")
+        .args(paths)
+        .status()
+        .map_err(|e| RalphError::ToolStatsError(format!("failed to run '{editor}': {e}")))?;
+    if !status.success() {
+        return Err(RalphError::ToolStatsError(format!(
+            "'{editor}' exited with {status}"
+        )));
+    }
+    Ok(())
+}
+
+/// `bytes` as e.g. `512 B`, `4.2 KiB` or `1.5 MiB`
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{bytes} B"),
+        1024..=1_048_575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/// Map the outcome of a run to the event notifications are sent for
+fn run_event(
+    result: &Result<LoopResult, RalphError>,
+    redactor: Option<&Redactor>,
+) -> Option<RunEvent> {
+    match result {
+        Ok(LoopResult::PromiseFulfilled {
+            iterations,
+            summary,
+            ..
+        }) => Some(RunEvent::PromiseFulfilled {
+            iterations: *iterations,
+            summary: summary.clone(),
+        }),
+        Ok(LoopResult::PlanCompleted { iterations, .. }) => Some(RunEvent::PlanCompleted {
+            iterations: *iterations,
+        }),
+        Ok(LoopResult::Shutdown { .. }) | Err(RalphError::ShutdownRequested) => None,
+        Ok(LoopResult::Failed {
+            consecutive_errors, ..
+        }) => Some(RunEvent::Error {
+            message: format!("{consecutive_errors} iterations in a row ended in an error"),
+        }),
+        Err(RalphError::MaxIterationsExceeded(max)) => {
+            Some(RunEvent::MaxIterationsExceeded { max: *max })
+        }
+        Err(e) => Some(RunEvent::Error {
+            message: redact_opt(redactor, &e.to_string()).into_owned(),
+        }),
+    }
+}
+
+/// Short description of the outcome for terminal notifications
+fn alert_message(result: &Result<LoopResult, RalphError>) -> String {
+    match result {
+        Ok(LoopResult::PromiseFulfilled { iterations, .. }) => {
+            format!("ralph-loop: promise fulfilled after {iterations} iteration(s)")
+        }
+        Ok(LoopResult::PlanCompleted { iterations, .. }) => {
+            format!("ralph-loop: plan completed after {iterations} iteration(s)")
+        }
+        Ok(LoopResult::Shutdown { .. }) | Err(RalphError::ShutdownRequested) => {
+            "ralph-loop: interrupted".to_string()
+        }
+        Ok(LoopResult::Failed {
+            consecutive_errors, ..
+        }) => format!("ralph-loop: {consecutive_errors} iterations in a row failed"),
+        Err(e) => format!("ralph-loop: {e}"),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    if cli.build_info {
+        let info = serde_json::to_string_pretty(&BuildInfo::current())
+            .expect("build info serializes to JSON");
+        println!("{info}");
+        return;
+    }
+    let time = cli.time_display();
+
+    let warnings = WarningCollector::new();
+    // Only a run has a run directory to capture the debug log into
+    let capture_debug = match cli.command {
+        Some(Commands::Resume { ref run_args, .. }) => run_args.capture_debug,
+        Some(Commands::Job(_)) | None => cli.run_args.capture_debug,
+        Some(_) => false,
+    };
+    let capture = capture_debug.then(DebugCapture::new);
+    let verbosity = cli.verbosity();
+    let no_banner = cli.no_banner;
+    let summary_format = cli.summary_format;
+    setup_logging(verbosity, &warnings, capture.as_ref());
+
+    let mut run_args = cli.run_args;
+    let mut resume = None;
+    let job = match cli.command {
+        Some(Commands::Job(job)) => Some(job),
+        Some(Commands::Resume {
+            run_id,
+            run_args: resume_args,
+        }) => {
+            run_args = resume_args;
+            resume = Some(run_id);
+            None
+        }
+        Some(Commands::Replay { run_id, run_args }) => {
+            Printer::new(cli.no_pager, cli.redact).exit_with(replay_run(&run_args, &run_id).await)
+        }
+        Some(command) => run_subcommand(command, time, Printer::new(cli.no_pager, cli.redact)),
+        None => None,
+    };
 
     // Setup shutdown signal handling
     let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
@@ -211,28 +1584,159 @@ async fn main() {
         let _ = shutdown_tx_clone.send(());
     });
 
-    // Load configuration
-    let config = match load_config(&cli.run_args) {
-        Ok(c) => c,
+    // Locate the project root so running from a subdirectory behaves the same
+    let cwd = match std::env::current_dir() {
+        Ok(cwd) => cwd,
         Err(e) => {
-            error!("{}", e);
+            error!("{}", RalphError::OutputDirError(e));
             std::process::exit(1);
         }
     };
+    let project_path = project_root(&cwd);
+
+    // Load configuration
+    let mut config = match load_config(&run_args, &project_path, resume.as_deref()) {
+        Ok(c) => c,
+        Err(e) => exit_on_error(e),
+    };
+
+    // The status line is as much noise in scripts as the log
+    if !matches!(verbosity, Verbosity::Normal | Verbosity::Verbose) {
+        config.progress = false;
+    }
+
+    // Fail fast if the installed agent CLI can't do what ralph needs
+    if let Err(e) = capabilities::adapt_config(&mut config) {
+        exit_on_error(e);
+    }
+
+    // First run in a project with permission checks disabled needs confirmation
+    if let Err(e) = ensure_consent(&config, &project_path, run_args.yes) {
+        exit_on_error(e);
+    }
+
+    // Report readiness when running as a systemd Type=notify service
+    systemd::start();
 
     // Run the main loop
-    match run(config, shutdown_rx).await {
+    let notifications = config.notifications.clone();
+    // Config was validated in load_config
+    let redactor = Redactor::from_config(&config.redaction).unwrap_or(None);
+    let banner_config = config.banner.clone();
+    let job = job.map(|job| {
+        job.apply(&mut config.job);
+        config.job.clone()
+    });
+    let reporter_config = config.reporter.clone();
+    let output_dir = config.output_dir.clone();
+    let started = Instant::now();
+    let (run_dir, result) = run(
+        config,
+        &project_path,
+        resume.as_deref(),
+        capture.as_ref(),
+        shutdown_rx,
+    )
+    .await;
+    let run_dir = run_dir.as_deref();
+    let files_changed = record_files_changed(run_dir, &output_dir);
+    let warnings = redacted_warnings(&warnings, redactor.as_ref());
+    record_warnings(run_dir, &warnings);
+    report_run(&reporter_config, &output_dir, run_dir).await;
+    if let Some(event) = run_event(&result, redactor.as_ref()) {
+        notify_run_end(&notifications, &event).await;
+    }
+    banner::alert(&banner_config, &alert_message(&result));
+
+    // Quiet and silent runs leave out everything but the outcome
+    let sections = matches!(verbosity, Verbosity::Normal | Verbosity::Verbose);
+    if let Some(section) = files_changed
+        .map(|files_changed| files_changed.render())
+        .filter(|section| sections && !section.is_empty())
+    {
+        println!(
+            "\n{} {}",
+            "FILES CHANGED:".cyan().bold(),
+            section.trim_end()
+        );
+    }
+
+    let section = warnings::render(&warnings);
+    if sections && !section.is_empty() {
+        println!("\n{} {}", "WARNINGS:".yellow().bold(), section.trim_end());
+    }
+    if let Some(usage) = usage_summary(run_dir, started.elapsed()).filter(|_| sections) {
+        let block = usage.render(summary_format, time);
+        match summary_format {
+            SummaryFormat::Text => println!("\n{}\n{}", "USAGE:".cyan().bold(), block),
+            SummaryFormat::Md | SummaryFormat::Json => println!("\n{block}"),
+        }
+    }
+
+    let exit_code = RunOutcome::from_result(&result).exit_code();
+    write_run_result(run_dir, &result, exit_code);
+
+    if let Some(job_config) = job {
+        std::process::exit(finish_job(
+            &job_config,
+            run_dir,
+            &result,
+            verbosity != Verbosity::Silent,
+        ));
+    }
+    match verbosity {
+        Verbosity::Silent => std::process::exit(exit_code),
+        Verbosity::Quiet => {
+            println!("{}", alert_message(&result));
+            std::process::exit(exit_code);
+        }
+        Verbosity::Normal | Verbosity::Verbose => {}
+    }
+    if no_banner {
+        if let Err(e) = result {
+            error!("{}", e);
+        }
+        std::process::exit(exit_code);
+    }
+
+    match result {
         Ok(LoopResult::PromiseFulfilled {
             iterations,
             promise,
+            summary,
+            cost_usd,
         }) => {
+            let message = banner::render(
+                &banner_config,
+                &BannerContext {
+                    promise: &promise,
+                    iterations,
+                    duration: started.elapsed(),
+                    cost_usd,
+                    time,
+                },
+            );
+            println!("\n{} {}", "SUCCESS:".green().bold(), message);
+            if let Some(summary) = summary {
+                println!("{summary}");
+            }
+        }
+        Ok(LoopResult::PlanCompleted {
+            iterations,
+            plan,
+            cost_usd,
+        }) => {
+            let cost = cost_usd
+                .map(|cost| format!(", cost ${cost:.2}"))
+                .unwrap_or_default();
             println!(
-                "\n{} Promise '{}' fulfilled after {} iteration(s)",
+                "\n{} All {} plan item(s) checked after {} iteration(s) in {}{}",
                 "SUCCESS:".green().bold(),
-                promise.cyan(),
-                iterations
+                plan.total,
+                iterations,
+                time.duration(started.elapsed()),
+                cost
             );
-            std::process::exit(0);
         }
         Ok(LoopResult::Shutdown { iterations }) => {
             println!(
@@ -240,7 +1744,17 @@ async fn main() {
                 "INTERRUPTED:".yellow().bold(),
                 iterations
             );
-            std::process::exit(130); // Standard exit code for Ctrl+C
+        }
+        Ok(LoopResult::Failed {
+            iterations,
+            consecutive_errors,
+        }) => {
+            println!(
+                "\n{} Aborted after {} iteration(s): the last {} ended in an error",
+                "FAILED:".red().bold(),
+                iterations,
+                consecutive_errors
+            );
         }
         Err(RalphError::MaxIterationsExceeded(max)) => {
             println!(
@@ -248,15 +1762,11 @@ async fn main() {
                 "FAILED:".red().bold(),
                 max
             );
-            std::process::exit(1);
         }
         Err(RalphError::ShutdownRequested) => {
             println!("\n{} Shutdown requested", "INTERRUPTED:".yellow().bold());
-            std::process::exit(130);
-        }
-        Err(e) => {
-            error!("{}", e);
-            std::process::exit(1);
         }
+        Err(e) => error!("{}", e),
     }
+    std::process::exit(exit_code);
 }
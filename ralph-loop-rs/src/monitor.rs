@@ -3,20 +3,85 @@
 //! In supported headless modes, stdout produces JSON events while stderr is plain text.
 
 use regex::Regex;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::IsTerminal;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::sync::mpsc;
 use tracing::{debug, info, trace, warn};
 
+use crate::commands::{AgentCommand, CommandDispatcher};
 use crate::config::{AgentProvider, Config};
-use crate::json_events::{AgentEvent, TokenUsage};
+use crate::event_filter::EventFilter;
+use crate::json_events::{AgentEvent, TokenUsage, ToolUse};
+use crate::promise::PromiseDetector;
+use crate::redact::{redact_opt, Redactor};
 use crate::state::SharedState;
+use crate::text::{prefix_within_bytes, shorten};
+use crate::timeline::{self, KillReason, TimelineEntry, TimelineEvent};
+use crate::token_counter::TokenCounter;
+use crate::tool_spans::{ToolSpan, ToolTracker};
+use crate::tools::ToolCall;
+
+/// Tracing target of logged assistant messages, for filtering with `RUST_LOG`
+pub const ASSISTANT_TARGET: &str = "ralph_loop::assistant";
+
+/// Tracing target of logged thinking blocks, for filtering with `RUST_LOG`
+pub const THINKING_TARGET: &str = "ralph_loop::thinking";
+
+/// Fill levels of the context, in percent of the limit, marked in the timeline
+const CONTEXT_FILL_MARKS: [u8; 4] = [25, 50, 75, 90];
+
+/// Unparsed lines kept per monitor; the ones after that are only counted
+const MAX_UNPARSED_LINES: usize = 100;
 
 /// Commands that can be sent from the monitor to the controller
 #[derive(Debug, Clone)]
 pub enum ProcessCommand {
     /// Kill the process due to context limit
     Kill,
+    /// Stop the process because the completion promise was found
+    StopOnPromise,
+    /// Stop the process because the agent asked for a fresh context
+    Restart,
+}
+
+/// An output line that couldn't be parsed as an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnparsedLine {
+    /// The line, with secrets redacted if configured
+    pub line: String,
+    /// Why parsing failed
+    pub error: String,
+}
+
+/// An event of a type ralph-loop doesn't handle, kept so new event types
+/// of the agent show up instead of being dropped silently
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownEvent {
+    /// The event's type
+    pub event_type: String,
+    /// The event, with secrets redacted if configured
+    pub raw: Value,
+}
+
+/// Tokens used by the sub-agents the agent started with the Task tool. They
+/// work in contexts of their own, so their usage isn't in the agent's.
+#[derive(Debug, Clone, Default)]
+pub struct SubAgentUsage {
+    /// Number of sub-agents that reported usage
+    pub count: u32,
+    pub usage: TokenUsage,
+}
+
+impl SubAgentUsage {
+    /// Add the sub-agents of another request
+    pub fn add(&mut self, other: &SubAgentUsage) {
+        self.count += other.count;
+        self.usage.add(&other.usage);
+    }
 }
 
 /// Result from monitoring an agent session
@@ -26,6 +91,40 @@ pub struct MonitorResult {
     pub session_id: Option<String>,
     /// Token usage from the result event
     pub token_usage: Option<TokenUsage>,
+    /// Cost reported by the result event
+    pub cost_usd: Option<f64>,
+    /// When the first non-empty line was read
+    pub first_event_at: Option<Instant>,
+    /// When the last non-empty line was read
+    pub last_event_at: Option<Instant>,
+    /// Task progress last reported by the agent, in percent
+    pub progress: Option<u8>,
+    /// Commands the agent sent that the loop controller handles
+    pub commands: Vec<AgentCommand>,
+    /// Assistant text of the session
+    pub text: String,
+    /// Tool calls an API backend asked ralph to execute
+    pub tool_calls: Vec<ToolCall>,
+    /// Count of events parsed successfully
+    pub event_count: u64,
+    /// Lines that weren't valid events, up to `MAX_UNPARSED_LINES`
+    pub unparsed: Vec<UnparsedLine>,
+    /// Lines that weren't valid events but didn't fit in `unparsed`
+    pub unparsed_dropped: u64,
+    /// Events of types ralph-loop doesn't handle
+    pub unknown: Vec<UnknownEvent>,
+    /// Assistant messages that contained the promise
+    pub promise_mentions: u32,
+    /// Summary the agent gave with the promise
+    pub promise_summary: Option<String>,
+    /// Milestones reached in the session, in order
+    pub milestones: Vec<String>,
+    /// Decisions made while monitoring, for the run's timeline
+    pub timeline: Vec<TimelineEntry>,
+    /// The agent's tool calls, each paired with its result
+    pub tool_spans: Vec<ToolSpan>,
+    /// Usage of the agent's sub-agents, if any reported usage
+    pub subagent_usage: Option<SubAgentUsage>,
 }
 
 /// JSON event monitor for stdout (in headless mode)
@@ -33,17 +132,75 @@ pub struct JsonEventMonitor {
     config: Arc<Config>,
     provider: AgentProvider,
     state: Arc<SharedState>,
-    promise_regex: Regex,
+    promise: PromiseDetector,
+    /// Milestones still to be reached with their detectors, in order
+    milestones: Vec<(String, PromiseDetector)>,
+    /// Count of `milestones` reached so far
+    milestones_reached: usize,
+    progress_regex: Regex,
+    dispatcher: CommandDispatcher,
+    redactor: Option<Redactor>,
+    /// Tool events left out of the stored output, if configured
+    event_filter: Option<EventFilter>,
     cmd_tx: mpsc::Sender<ProcessCommand>,
     warning_emitted: bool,
+    /// Whether a stop command has already been issued
+    stop_sent: bool,
+    /// Assistant messages that contained the promise
+    promise_mentions: u32,
+    /// Whether the promise was confirmed often enough to count
+    promise_accepted: bool,
+    /// Summary the agent gave with the promise
+    promise_summary: Option<String>,
     /// Captured session ID
     session_id: Option<String>,
     /// Captured token usage
     token_usage: Option<TokenUsage>,
+    /// Captured cost
+    cost_usd: Option<f64>,
+    /// When the first non-empty line was read
+    first_event_at: Option<Instant>,
+    /// When the last non-empty line was read
+    last_event_at: Option<Instant>,
+    /// Progress last reported via `<progress>NN%</progress>`
+    progress: Option<u8>,
+    /// Dispatched commands the loop controller handles
+    commands: Vec<AgentCommand>,
+    /// Context size reported with the latest assistant turn
+    context_tokens: Option<usize>,
+    /// Largest context growth between two assistant turns
+    max_turn_growth: usize,
+    /// Count of `CONTEXT_FILL_MARKS` the context has filled past
+    context_fill_marks: usize,
+    /// Whether to restart as soon as the running tool call finishes
+    restart_pending: bool,
+    /// Assistant text streamed in chunks, until the message is complete
+    pending_text: String,
+    /// Complete assistant messages, separated by newlines
+    text: String,
+    /// Tool calls streamed by an API backend, by index
+    tool_calls: BTreeMap<usize, ToolCall>,
+    /// Counter for estimating usage when the backend reports none
+    token_counter: Arc<TokenCounter>,
     /// Count of lines read
     line_count: u64,
     /// Count of events parsed successfully
     event_count: u64,
+    /// Lines that weren't valid events, up to `MAX_UNPARSED_LINES`
+    unparsed: Vec<UnparsedLine>,
+    /// Lines that weren't valid events but didn't fit in `unparsed`
+    unparsed_dropped: u64,
+    /// Events of types ralph-loop doesn't handle
+    unknown: Vec<UnknownEvent>,
+    /// Decisions made so far, for the run's timeline
+    timeline: Vec<TimelineEntry>,
+    /// How many of the decisions were marked in the output
+    markers_written: usize,
+    /// Tool requests of the agent paired with their results
+    tools: ToolTracker,
+    /// Latest usage of each message of a sub-agent, by message ID, and the
+    /// Task tool use that started the sub-agent
+    subagent_messages: HashMap<String, (String, TokenUsage)>,
 }
 
 impl JsonEventMonitor {
@@ -52,34 +209,145 @@ impl JsonEventMonitor {
         config: Arc<Config>,
         state: Arc<SharedState>,
         cmd_tx: mpsc::Sender<ProcessCommand>,
-    ) -> Self {
-        // Match <promise>TEXT</promise> pattern
-        let promise_regex = Regex::new(&format!(
-            r"<promise>{}</promise>",
-            regex::escape(&config.completion_promise)
-        ))
-        .expect("Invalid promise regex");
+    ) -> crate::error::Result<Self> {
+        let promise =
+            PromiseDetector::new(&config.completion_promise, &config.promise_ignore_contexts);
+        let milestones = config
+            .milestones
+            .iter()
+            .map(|milestone| {
+                let detector = PromiseDetector::new(milestone, &config.promise_ignore_contexts);
+                (milestone.clone(), detector)
+            })
+            .collect();
+        let progress_regex = Regex::new(r"<progress>\s*(\d{1,3})\s*%\s*</progress>")
+            .expect("Invalid progress regex");
 
-        Self {
+        let redactor = Redactor::from_config(&config.redaction)?;
+        let token_counter = Arc::new(TokenCounter::new(config.context_limit.estimation_method));
+
+        Ok(Self {
             provider: config.agent_provider(),
+            dispatcher: CommandDispatcher::new(config.agent_commands.clone()),
+            redactor,
+            event_filter: EventFilter::from_config(&config.event_filter),
             config,
             state,
-            promise_regex,
+            promise,
+            milestones,
+            milestones_reached: 0,
+            progress_regex,
             cmd_tx,
             warning_emitted: false,
+            stop_sent: false,
+            promise_mentions: 0,
+            promise_accepted: false,
+            promise_summary: None,
             session_id: None,
             token_usage: None,
+            cost_usd: None,
+            first_event_at: None,
+            last_event_at: None,
+            progress: None,
+            commands: Vec::new(),
+            context_tokens: None,
+            max_turn_growth: 0,
+            context_fill_marks: 0,
+            restart_pending: false,
+            pending_text: String::new(),
+            text: String::new(),
+            tool_calls: BTreeMap::new(),
+            token_counter,
             line_count: 0,
             event_count: 0,
+            unparsed: Vec::new(),
+            unparsed_dropped: 0,
+            unknown: Vec::new(),
+            timeline: Vec::new(),
+            markers_written: 0,
+            tools: ToolTracker::default(),
+            subagent_messages: HashMap::new(),
+        })
+    }
+
+    /// Estimate usage with `token_counter` instead of a counter of its own,
+    /// so its cache is shared with other monitors
+    pub fn with_token_counter(self, token_counter: Arc<TokenCounter>) -> Self {
+        Self {
+            token_counter,
+            ..self
         }
     }
 
+    /// Create a monitor for the tool calls ralph-loop executes for an API
+    /// backend. The requests' own monitors already warn about and mark how
+    /// full the context is, so this one only tracks the calls and its growth.
+    pub(crate) fn for_tool_calls(
+        config: Arc<Config>,
+        state: Arc<SharedState>,
+        cmd_tx: mpsc::Sender<ProcessCommand>,
+    ) -> crate::error::Result<Self> {
+        Ok(Self {
+            warning_emitted: true,
+            context_fill_marks: CONTEXT_FILL_MARKS.len(),
+            ..Self::new(config, state, cmd_tx)?
+        })
+    }
+
     /// Get the monitor result with captured session ID and token usage
     pub fn result(&self) -> MonitorResult {
         MonitorResult {
             session_id: self.session_id.clone(),
             token_usage: self.token_usage.clone(),
+            cost_usd: self.cost_usd,
+            first_event_at: self.first_event_at,
+            last_event_at: self.last_event_at,
+            progress: self.progress,
+            commands: self.commands.clone(),
+            text: self.text.clone(),
+            tool_calls: self
+                .tool_calls
+                .iter()
+                .map(|(index, call)| ToolCall {
+                    id: if call.id.is_empty() {
+                        format!("call_{index}")
+                    } else {
+                        call.id.clone()
+                    },
+                    ..call.clone()
+                })
+                .collect(),
+            event_count: self.event_count,
+            unparsed: self.unparsed.clone(),
+            unparsed_dropped: self.unparsed_dropped,
+            unknown: self.unknown.clone(),
+            promise_mentions: self.promise_mentions,
+            promise_summary: self.promise_summary.clone(),
+            milestones: self.milestones[..self.milestones_reached]
+                .iter()
+                .map(|(milestone, _)| milestone.clone())
+                .collect(),
+            timeline: self.timeline.clone(),
+            tool_spans: self.tools.spans().to_vec(),
+            subagent_usage: self.subagent_usage(),
+        }
+    }
+
+    /// Usage summed over the messages of all sub-agents
+    fn subagent_usage(&self) -> Option<SubAgentUsage> {
+        if self.subagent_messages.is_empty() {
+            return None;
         }
+        let mut usage = TokenUsage::default();
+        let mut parents = HashSet::new();
+        for (parent, message_usage) in self.subagent_messages.values() {
+            usage.add(message_usage);
+            parents.insert(parent);
+        }
+        Some(SubAgentUsage {
+            count: parents.len() as u32,
+            usage,
+        })
     }
 
     /// Monitor stdout for JSON events
@@ -101,16 +369,28 @@ impl JsonEventMonitor {
                         "stdout monitor: stream closed - read {} lines, parsed {} events",
                         self.line_count, self.event_count
                     );
+                    let hidden = self.event_filter.as_ref().map_or(0, EventFilter::hidden);
+                    if hidden > 0 {
+                        info!("Left {} filtered tool event(s) out of the output", hidden);
+                    }
+                    if !self.unparsed.is_empty() {
+                        warn!(
+                            "{} line(s) of agent output could not be parsed",
+                            self.unparsed.len() as u64 + self.unparsed_dropped
+                        );
+                    }
                     break;
                 }
+                Ok(_) if timeline::is_marker(&line) => {
+                    trace!("stdout monitor: skipping marker of an earlier run");
+                }
                 Ok(bytes) => {
-                    self.line_count += 1;
                     trace!(
                         "stdout monitor: read line {} ({} bytes)",
-                        self.line_count,
+                        self.line_count + 1,
                         bytes
                     );
-                    self.process_json_line(&line).await?;
+                    self.process_line(&line).await?;
                 }
                 Err(e) => {
                     warn!(
@@ -124,6 +404,22 @@ impl JsonEventMonitor {
         Ok(())
     }
 
+    /// Process the next line of output and mark the decisions made at it
+    pub(crate) async fn process_line(&mut self, line: &str) -> crate::error::Result<()> {
+        self.line_count += 1;
+        self.process_json_line(line).await?;
+        self.write_markers().await;
+        Ok(())
+    }
+
+    /// Mark the decisions made at the current line in the output, after it
+    async fn write_markers(&mut self) {
+        for entry in &self.timeline[self.markers_written..] {
+            self.state.append_line(&entry.marker()).await;
+        }
+        self.markers_written = self.timeline.len();
+    }
+
     /// Process a JSON event line
     async fn process_json_line(&mut self, line: &str) -> crate::error::Result<()> {
         let line = line.trim();
@@ -132,30 +428,61 @@ impl JsonEventMonitor {
             return Ok(());
         }
 
-        // Store raw JSON for output
-        self.state.append_output(line).await;
-        self.state.append_output("\n").await;
+        let now = Instant::now();
+        self.first_event_at.get_or_insert(now);
+        self.last_event_at = Some(now);
+        self.state.record_event().await;
+
+        // Store JSON for output, with secrets redacted if configured and
+        // muted tool events left out
+        let redacted = redact_opt(self.redactor.as_ref(), line);
+        if !self
+            .event_filter
+            .as_mut()
+            .is_some_and(|filter| filter.hides(line))
+        {
+            self.state.append_line(&redacted).await;
+        }
 
         // Parse the JSON event
         let event = match AgentEvent::parse(self.provider, line) {
             Ok(e) => e,
             Err(e) => {
+                if self.unparsed.len() < MAX_UNPARSED_LINES {
+                    self.unparsed.push(UnparsedLine {
+                        line: redacted.into_owned(),
+                        error: e.to_string(),
+                    });
+                } else {
+                    self.unparsed_dropped += 1;
+                }
                 debug!(
                     "stdout monitor: failed to parse JSON event: {} - line: {}",
                     e,
-                    if line.len() > 100 { &line[..100] } else { line }
+                    prefix_within_bytes(line, 100)
                 );
                 return Ok(());
             }
         };
 
         self.event_count += 1;
+        self.state.count_event().await;
         debug!(
             "stdout monitor: parsed event #{} - type: {}",
             self.event_count,
             event.event_type()
         );
 
+        // Synthetic events such as the end of an SSE stream carry no JSON
+        if let AgentEvent::Unknown { event_type, raw } = &event {
+            if !raw.is_null() {
+                self.unknown.push(UnknownEvent {
+                    event_type: event_type.clone(),
+                    raw: self.redact_value(raw),
+                });
+            }
+        }
+
         // Process based on event type
         match &event {
             AgentEvent::SessionStart { session_id } => {
@@ -164,46 +491,147 @@ impl JsonEventMonitor {
                     self.session_id = Some(sid.clone());
                 }
             }
-            AgentEvent::AssistantMessage { .. } => {
-                if let Some(text) = event.extract_text() {
-                    if self.promise_regex.is_match(text) {
-                        info!(
-                            "Promise found in output: {}",
-                            self.config.completion_promise
-                        );
-                        self.state
-                            .set_promise_found(self.config.completion_promise.clone())
-                            .await;
+            AgentEvent::AssistantMessage {
+                text,
+                thinking,
+                usage,
+                tool_uses,
+                message_id,
+                parent_tool_use_id,
+            } => {
+                // Reasoning isn't the answer, so promises and commands in it don't count
+                if self.config.log_thinking && !thinking.trim().is_empty() {
+                    self.log_thinking(thinking);
+                }
+                for tool_use in tool_uses {
+                    let tool_use = ToolUse {
+                        path: tool_use
+                            .path
+                            .as_deref()
+                            .map(|path| redact_opt(self.redactor.as_ref(), path).into_owned()),
+                        ..tool_use.clone()
+                    };
+                    self.tools.requested(&tool_use, self.line_count, now);
+                }
+                if !tool_uses.is_empty() {
+                    self.state
+                        .set_current_tool(self.tools.running().map(str::to_string))
+                        .await;
+                }
+                match (parent_tool_use_id, usage) {
+                    // A sub-agent's usage says nothing about the agent's context
+                    (Some(parent), Some(usage)) => {
+                        let key = message_id
+                            .clone()
+                            .unwrap_or_else(|| format!("line {}", self.line_count));
+                        let (_, recorded) = self
+                            .subagent_messages
+                            .entry(key)
+                            .or_insert_with(|| (parent.clone(), TokenUsage::default()));
+                        recorded.merge(usage);
+                    }
+                    (None, Some(usage)) => self.track_context_growth(usage).await,
+                    _ => {}
+                }
+                let has_usage = usage.is_some() || parent_tool_use_id.is_some();
+                self.handle_assistant_text(text, has_usage).await;
+            }
+            AgentEvent::TextDelta { text, done } => {
+                self.pending_text.push_str(text);
+                if *done {
+                    let text = std::mem::take(&mut self.pending_text);
+                    self.handle_assistant_text(&text, false).await;
+                }
+            }
+            AgentEvent::ToolCalls { calls } => {
+                for delta in calls {
+                    let call = self.tool_calls.entry(delta.index).or_insert(ToolCall {
+                        id: String::new(),
+                        name: String::new(),
+                        arguments: String::new(),
+                    });
+                    if let Some(ref id) = delta.id {
+                        call.id = id.clone();
                     }
+                    if let Some(ref name) = delta.name {
+                        call.name = name.clone();
+                    }
+                    call.arguments.push_str(&delta.arguments);
                 }
             }
-            AgentEvent::Result { session_id, usage } => {
+            AgentEvent::Usage { session_id, usage } => {
+                if let Some(sid) = session_id {
+                    debug!("Captured session ID: {}", sid);
+                    self.session_id = Some(sid.clone());
+                }
+                let merged = self.token_usage.get_or_insert_with(TokenUsage::default);
+                merged.merge(usage);
+                let total = merged.counted(self.config.context_limit.accounting);
+                self.state.set_tokens(total).await;
+                self.record_context_fill(total);
+                self.check_context_limit(total);
+            }
+            AgentEvent::Result {
+                session_id,
+                usage,
+                cost_usd,
+                text,
+            } => {
                 if let Some(sid) = session_id {
                     debug!("Captured session ID from result: {}", sid);
                     self.session_id = Some(sid.clone());
                 }
+                if cost_usd.is_some() {
+                    self.cost_usd = *cost_usd;
+                }
+                // Streams that end without marking the text as complete
+                if !self.pending_text.is_empty() {
+                    let text = std::mem::take(&mut self.pending_text);
+                    self.handle_assistant_text(&text, true).await;
+                }
+                if let Some(text) = text.as_deref() {
+                    self.record_milestones(text);
+                }
+                // The final answer isn't a plan being described, so it counts at once
+                if let Some(found) = text.as_deref().and_then(|text| self.promise.find(text)) {
+                    self.record_promise_summary(found.summary);
+                    self.accept_promise().await;
+                }
 
                 self.token_usage = Some(usage.clone());
 
-                let total = usage.total();
+                let total = usage.counted(self.config.context_limit.accounting);
                 debug!("Result event: {} total tokens", total);
 
                 self.state.set_tokens(total).await;
-
-                if !self.warning_emitted && total >= self.config.context_limit.warning_threshold {
-                    warn!(
-                        "Context limit warning: {} tokens (threshold: {})",
-                        total, self.config.context_limit.warning_threshold
-                    );
-                    self.warning_emitted = true;
+                self.check_context_limit(total);
+            }
+            AgentEvent::Unknown { event_type, raw } if event_type == "error" => {
+                warn!("Agent backend reported an error: {}", raw);
+            }
+            AgentEvent::ToolResult { results } => {
+                for result in results {
+                    let line = self.line_count;
+                    if let Some(span) = self.tools.finished(result, line, now) {
+                        debug!("Tool call finished: {}", span);
+                    }
                 }
-
-                if total >= self.config.context_limit.max_tokens {
-                    info!(
-                        "Context limit reached: {} tokens (limit: {})",
-                        total, self.config.context_limit.max_tokens
+                self.state
+                    .set_current_tool(self.tools.running().map(str::to_string))
+                    .await;
+                // Results are handed back to the model, so they fill its context too
+                if self.token_usage.is_none() && self.context_tokens.is_none() {
+                    for result in results {
+                        self.estimate_tokens(&result.text).await;
+                    }
+                }
+                if self.restart_pending {
+                    info!("Tool call finished, restarting before the context limit");
+                    self.send_command(
+                        ProcessCommand::Kill,
+                        KillReason::ProjectedContextLimit,
+                        self.context_tokens,
                     );
-                    let _ = self.cmd_tx.send(ProcessCommand::Kill).await;
                 }
             }
             _ => {
@@ -213,11 +641,302 @@ impl JsonEventMonitor {
 
         Ok(())
     }
+
+    /// `raw` with secrets redacted, as a string if redaction broke the JSON
+    fn redact_value(&self, raw: &Value) -> Value {
+        let Some(ref redactor) = self.redactor else {
+            return raw.clone();
+        };
+        let json = raw.to_string();
+        let redacted = redactor.redact(&json);
+        if redacted == json {
+            return raw.clone();
+        }
+        serde_json::from_str(&redacted).unwrap_or_else(|_| Value::String(redacted.into_owned()))
+    }
+
+    /// Look for usage estimates, progress, commands and the promise in a
+    /// complete assistant message
+    async fn handle_assistant_text(&mut self, text: &str, has_usage: bool) {
+        if !text.is_empty() {
+            if !self.text.is_empty() {
+                self.text.push('\n');
+            }
+            self.text.push_str(text);
+            if self.config.log_assistant_text {
+                self.log_text(text);
+            }
+        }
+        if !has_usage && self.token_usage.is_none() && self.context_tokens.is_none() {
+            self.estimate_tokens(text).await;
+        }
+        self.record_progress(text).await;
+        self.dispatch_commands(text);
+        self.record_milestones(text);
+        if let Some(found) = self.promise.find(text) {
+            self.record_promise_summary(found.summary);
+            self.promise_mentions += 1;
+            let required = self.config.promise_confirmations.max(1);
+            if self.promise_mentions >= required {
+                self.accept_promise().await;
+            } else {
+                info!(
+                    "Promise found in output ({} of {} confirmations), not accepted yet",
+                    self.promise_mentions, required
+                );
+            }
+        }
+    }
+
+    /// Keep the latest summary given with the promise, redacted
+    fn record_promise_summary(&mut self, summary: Option<String>) {
+        if let Some(summary) = summary {
+            self.promise_summary = Some(redact_opt(self.redactor.as_ref(), &summary).into_owned());
+        }
+    }
+
+    /// Record the milestones given in `text`. Each only counts once the
+    /// ones before it were reached.
+    fn record_milestones(&mut self, text: &str) {
+        while let Some((milestone, detector)) = self.milestones.get(self.milestones_reached) {
+            if !detector.is_match(text) {
+                break;
+            }
+            info!("Milestone reached: {}", milestone);
+            self.timeline
+                .push(TimelineEntry::now(TimelineEvent::MilestoneReached {
+                    promise: milestone.clone(),
+                    line: self.line_count,
+                }));
+            self.milestones_reached += 1;
+        }
+    }
+
+    /// Record the promise as found and stop the agent if configured, once
+    /// every milestone was reached
+    async fn accept_promise(&mut self) {
+        if self.promise_accepted {
+            return;
+        }
+        if let Some((milestone, _)) = self.milestones.get(self.milestones_reached) {
+            info!(
+                "Promise found in output, but milestone \"{}\" wasn't reached yet",
+                milestone
+            );
+            let reason = format!("milestone \"{milestone}\" not reached");
+            self.timeline
+                .push(TimelineEntry::now(TimelineEvent::PromiseIgnored {
+                    line: self.line_count,
+                    reason,
+                }));
+            return;
+        }
+        self.promise_accepted = true;
+        self.timeline
+            .push(TimelineEntry::now(TimelineEvent::PromiseDetected {
+                line: self.line_count,
+            }));
+        info!(
+            "Promise found in output: {}",
+            self.config.completion_promise
+        );
+        self.state
+            .set_promise_found(self.config.completion_promise.clone())
+            .await;
+        if self.config.stop_on_promise {
+            self.send_command(ProcessCommand::StopOnPromise, KillReason::Promise, None);
+        }
+    }
+
+    /// Log an assistant message, redacted and shortened to the configured length
+    fn log_text(&self, text: &str) {
+        let text = redact_opt(self.redactor.as_ref(), text.trim());
+        info!(
+            target: ASSISTANT_TARGET,
+            "{}",
+            shorten(&text, self.config.log_assistant_text_max_chars)
+        );
+    }
+
+    /// Log a thinking block like an assistant message, dimmed on a terminal
+    /// so it stands apart from what the agent says and does
+    fn log_thinking(&self, thinking: &str) {
+        let thinking = redact_opt(self.redactor.as_ref(), thinking.trim());
+        let thinking = shorten(&thinking, self.config.log_assistant_text_max_chars);
+        if std::io::stderr().is_terminal() {
+            info!(target: THINKING_TARGET, "\x1b[2mthinking: {}\x1b[0m", thinking);
+        } else {
+            info!(target: THINKING_TARGET, "thinking: {}", thinking);
+        }
+    }
+
+    /// Warn near the context limit and request a kill once it is reached
+    fn check_context_limit(&mut self, total: usize) {
+        let warning_tokens = self.config.context_limit.warning_tokens();
+        if !self.warning_emitted && total >= warning_tokens {
+            warn!(
+                "Context limit warning: {} tokens (threshold: {})",
+                total, warning_tokens
+            );
+            self.warning_emitted = true;
+            self.timeline
+                .push(TimelineEntry::now(TimelineEvent::ContextWarning {
+                    tokens: total,
+                    threshold: warning_tokens,
+                    line: self.line_count,
+                }));
+        }
+
+        if total >= self.config.context_limit.max_tokens {
+            info!(
+                "Context limit reached: {} tokens (limit: {})",
+                total, self.config.context_limit.max_tokens
+            );
+            self.send_command(ProcessCommand::Kill, KillReason::ContextLimit, Some(total));
+        }
+    }
+
+    /// Estimate usage from assistant text or tool results for backends that
+    /// haven't reported any
+    async fn estimate_tokens(&mut self, text: &str) {
+        let estimate = self.token_counter.count(text);
+        self.state.add_tokens(estimate).await;
+
+        let total = self.state.get_token_count().await;
+        debug!("Estimated {} tokens so far (no usage reported)", total);
+        self.record_context_fill(total);
+        self.check_context_limit(total);
+    }
+
+    /// Mark in the timeline where the context filled past the next of
+    /// `CONTEXT_FILL_MARKS`; a jump past several marks records the highest
+    fn record_context_fill(&mut self, tokens: usize) {
+        let limit = self.config.context_limit.max_tokens.max(1);
+        let percent = tokens.saturating_mul(100) / limit;
+        let passed = CONTEXT_FILL_MARKS
+            .iter()
+            .take_while(|&&mark| percent >= usize::from(mark))
+            .count();
+        if passed > self.context_fill_marks {
+            self.context_fill_marks = passed;
+            self.timeline
+                .push(TimelineEntry::now(TimelineEvent::ContextFilled {
+                    percent: CONTEXT_FILL_MARKS[passed - 1],
+                    tokens,
+                    line: self.line_count,
+                }));
+        }
+    }
+
+    /// Project the context size of the next turn from the growth seen so far
+    /// and schedule a restart if it would exceed the limit. The restart waits
+    /// for the running tool call so the agent isn't killed halfway through it;
+    /// a turn already past the limit stops the agent at once.
+    async fn track_context_growth(&mut self, usage: &TokenUsage) {
+        let context = usage.context_tokens();
+        if let Some(previous) = self.context_tokens {
+            self.max_turn_growth = self.max_turn_growth.max(context.saturating_sub(previous));
+        }
+        self.context_tokens = Some(context);
+        self.state.set_tokens(context).await;
+        self.record_context_fill(context);
+        self.check_context_limit(context);
+        if self.stop_sent {
+            return;
+        }
+
+        let projected = context + self.max_turn_growth;
+        let limit = self.config.context_limit.max_tokens;
+        if self.config.context_limit.adaptive_restart
+            && !self.restart_pending
+            && self.max_turn_growth > 0
+            && projected >= limit
+        {
+            info!(
+                "Next turn projected at {} tokens (limit: {}), restarting after the current tool call",
+                projected, limit
+            );
+            self.restart_pending = true;
+        }
+    }
+
+    /// Record the last `<progress>NN%</progress>` marker in `text`, capped at 100%
+    async fn record_progress(&mut self, text: &str) {
+        let Some(percent) = self
+            .progress_regex
+            .captures_iter(text)
+            .last()
+            .and_then(|caps| caps[1].parse::<u16>().ok())
+        else {
+            return;
+        };
+        let percent = percent.min(100) as u8;
+        if self.progress != Some(percent) {
+            info!("Agent reports progress: {}%", percent);
+        }
+        self.progress = Some(percent);
+        self.state.set_progress(percent).await;
+    }
+
+    /// Handle `<ralph:cmd>` sentinels: restarts stop the process, everything
+    /// else is passed on to the loop controller
+    fn dispatch_commands(&mut self, text: &str) {
+        for command in self.dispatcher.dispatch(text) {
+            match command {
+                AgentCommand::Restart => {
+                    self.send_command(ProcessCommand::Restart, KillReason::RestartRequested, None)
+                }
+                AgentCommand::Note(note) => self.commands.push(AgentCommand::Note(
+                    redact_opt(self.redactor.as_ref(), &note).into_owned(),
+                )),
+                command => self.commands.push(command),
+            }
+        }
+    }
+
+    /// Ask the controller to stop the process without ever blocking the monitor.
+    ///
+    /// Only the first request is sent; the controller may already have stopped
+    /// receiving (e.g. the process exited on its own), in which case the
+    /// request is dropped and the monitor keeps draining the stream.
+    fn send_command(&mut self, cmd: ProcessCommand, reason: KillReason, tokens: Option<usize>) {
+        if self.stop_sent {
+            trace!("stdout monitor: stop already requested, dropping {:?}", cmd);
+            return;
+        }
+        self.timeline
+            .push(TimelineEntry::now(TimelineEvent::KillSent {
+                reason,
+                line: self.line_count,
+                tokens,
+            }));
+        match self.cmd_tx.try_send(cmd) {
+            Ok(()) => self.stop_sent = true,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                // A command is already queued, which will kill the process
+                self.stop_sent = true;
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                debug!("stdout monitor: controller no longer receiving, kill dropped");
+                self.stop_sent = true;
+            }
+        }
+    }
+
+    /// Whether a stop (kill) has been requested
+    pub fn stop_requested(&self) -> bool {
+        self.stop_sent
+    }
 }
 
 /// Plain text monitor for stderr
 pub struct StderrMonitor {
     line_count: u64,
+    /// Bytes of stderr kept (0 keeps nothing)
+    max_bytes: usize,
+    redactor: Option<Redactor>,
+    captured: String,
+    dropped_bytes: usize,
 }
 
 impl Default for StderrMonitor {
@@ -227,9 +946,48 @@ impl Default for StderrMonitor {
 }
 
 impl StderrMonitor {
-    /// Create a new StderrMonitor
+    /// Create a new StderrMonitor that only logs
     pub fn new() -> Self {
-        Self { line_count: 0 }
+        Self::capturing(0, None)
+    }
+
+    /// Create a StderrMonitor that also keeps up to `max_bytes` of stderr
+    pub fn capturing(max_bytes: usize, redactor: Option<Redactor>) -> Self {
+        Self {
+            line_count: 0,
+            max_bytes,
+            redactor,
+            captured: String::new(),
+            dropped_bytes: 0,
+        }
+    }
+
+    /// Keep `line` if it still fits, counting what doesn't
+    fn capture(&mut self, line: &str) {
+        if self.max_bytes == 0 {
+            return;
+        }
+        let line = redact_opt(self.redactor.as_ref(), line);
+        if self.dropped_bytes == 0 && self.captured.len() + line.len() <= self.max_bytes {
+            self.captured.push_str(&line);
+        } else {
+            self.dropped_bytes += line.len();
+        }
+    }
+
+    /// The captured stderr, noting how much didn't fit
+    pub fn into_captured(self) -> String {
+        let mut captured = self.captured;
+        if self.dropped_bytes > 0 {
+            if !captured.is_empty() && !captured.ends_with('\n') {
+                captured.push('\n');
+            }
+            captured.push_str(&format!(
+                "[{} more bytes of stderr not kept]\n",
+                self.dropped_bytes
+            ));
+        }
+        captured
     }
 
     /// Monitor stderr for plain text output
@@ -251,7 +1009,7 @@ impl StderrMonitor {
                 }
                 Ok(_) => {
                     self.line_count += 1;
-                    // stderr is informational/error messages, just log them
+                    self.capture(&line);
                     let trimmed = line.trim();
                     if !trimmed.is_empty() {
                         debug!("stderr[{}]: {}", self.line_count, trimmed);
@@ -273,25 +1031,27 @@ impl StderrMonitor {
 /// Spawn monitor tasks for stdout (JSON) and stderr (plain text)
 ///
 /// Returns handles for both tasks. The stdout handle returns MonitorResult with
-/// captured session ID and token usage.
+/// captured session ID and token usage. Fails without spawning either task if
+/// the redaction patterns are invalid.
 pub fn spawn_monitors(
     config: Arc<Config>,
     state: Arc<SharedState>,
+    token_counter: Arc<TokenCounter>,
     stdout: BufReader<tokio::process::ChildStdout>,
     stderr: BufReader<tokio::process::ChildStderr>,
     cmd_tx: mpsc::Sender<ProcessCommand>,
-) -> (
+) -> crate::error::Result<(
     tokio::task::JoinHandle<MonitorResult>,
-    tokio::task::JoinHandle<()>,
-) {
+    tokio::task::JoinHandle<String>,
+)> {
     debug!("spawn_monitors: creating stdout and stderr monitor tasks");
-    let config_stdout = Arc::clone(&config);
-    let state_stdout = Arc::clone(&state);
+    let mut monitor = JsonEventMonitor::new(Arc::clone(&config), state, cmd_tx)?
+        .with_token_counter(token_counter);
+    let redactor = Redactor::from_config(&config.redaction)?;
 
     let stdout_handle = tokio::spawn(async move {
         debug!("stdout monitor task: started");
         let mut stdout = stdout;
-        let mut monitor = JsonEventMonitor::new(config_stdout, state_stdout, cmd_tx);
         if let Err(e) = monitor.monitor_stream(&mut stdout).await {
             warn!("stdout monitor error: {}", e);
         }
@@ -302,13 +1062,684 @@ pub fn spawn_monitors(
     let stderr_handle = tokio::spawn(async move {
         debug!("stderr monitor task: started");
         let mut stderr = stderr;
-        let mut monitor = StderrMonitor::new();
+        let mut monitor = StderrMonitor::capturing(config.agent.stderr_max_bytes, redactor);
         if let Err(e) = monitor.monitor_stream(&mut stderr).await {
             warn!("stderr monitor error: {}", e);
         }
         debug!("stderr monitor task: exiting");
+        monitor.into_captured()
     });
 
     debug!("spawn_monitors: tasks spawned successfully");
-    (stdout_handle, stderr_handle)
+    Ok((stdout_handle, stderr_handle))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TokenAccounting;
+    use crate::json_events::LocalToolEvent;
+    use crate::tools::ToolOutput;
+    use std::time::Duration;
+
+    const PROMISE: &str = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"<promise>TASK COMPLETE</promise>"}]}}"#;
+    const OVER_LIMIT: &str =
+        r#"{"type":"result","usage":{"input_tokens":200000,"output_tokens":10}}"#;
+
+    fn config() -> Arc<Config> {
+        Arc::new(Config {
+            prompt: "test".to_string(),
+            ..Config::default()
+        })
+    }
+
+    async fn drain(monitor: &mut JsonEventMonitor, input: String) {
+        let mut reader = BufReader::new(input.as_bytes());
+        tokio::time::timeout(Duration::from_secs(5), monitor.monitor_stream(&mut reader))
+            .await
+            .expect("monitor blocked")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn repeated_kill_conditions_do_not_block_when_channel_is_full() {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let mut monitor =
+            JsonEventMonitor::new(config(), SharedState::new_shared(), cmd_tx).unwrap();
+
+        drain(
+            &mut monitor,
+            format!("{OVER_LIMIT}\n{OVER_LIMIT}\n{OVER_LIMIT}\n"),
+        )
+        .await;
+
+        assert!(monitor.stop_requested());
+        assert!(matches!(cmd_rx.try_recv(), Ok(ProcessCommand::Kill)));
+        assert!(cmd_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn kill_after_receiver_dropped_does_not_block() {
+        let (cmd_tx, cmd_rx) = mpsc::channel(1);
+        drop(cmd_rx);
+        let mut monitor =
+            JsonEventMonitor::new(config(), SharedState::new_shared(), cmd_tx).unwrap();
+
+        drain(&mut monitor, format!("{OVER_LIMIT}\n{OVER_LIMIT}\n")).await;
+
+        assert!(monitor.stop_requested());
+        assert_eq!(monitor.result().token_usage.unwrap().total(), 200_010);
+    }
+
+    #[tokio::test]
+    async fn context_accounting_counts_cached_tokens() {
+        // 170k of the 180k limit read from the cache
+        let cached = r#"{"type":"result","usage":{"input_tokens":5000,"cache_read_input_tokens":170000,"output_tokens":5000}}"#;
+        for (accounting, killed) in [
+            (TokenAccounting::Billed, false),
+            (TokenAccounting::Context, true),
+        ] {
+            let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+            let mut config = (*config()).clone();
+            config.context_limit.accounting = accounting;
+            let mut monitor =
+                JsonEventMonitor::new(Arc::new(config), SharedState::new_shared(), cmd_tx).unwrap();
+
+            drain(&mut monitor, format!("{cached}\n")).await;
+
+            assert_eq!(cmd_rx.try_recv().is_ok(), killed, "{accounting:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn promise_requests_stop_when_configured() {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let state = SharedState::new_shared();
+        let mut monitor = JsonEventMonitor::new(config(), Arc::clone(&state), cmd_tx).unwrap();
+
+        drain(&mut monitor, format!("{PROMISE}\n{OVER_LIMIT}\n")).await;
+
+        assert!(state.is_promise_found().await);
+        assert!(matches!(
+            cmd_rx.try_recv(),
+            Ok(ProcessCommand::StopOnPromise)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn promise_does_not_stop_when_disabled() {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let config = Arc::new(Config {
+            stop_on_promise: false,
+            ..(*config()).clone()
+        });
+        let state = SharedState::new_shared();
+        let mut monitor = JsonEventMonitor::new(config, Arc::clone(&state), cmd_tx).unwrap();
+
+        drain(&mut monitor, format!("{PROMISE}\n")).await;
+
+        assert!(state.is_promise_found().await);
+        assert!(cmd_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn promise_in_thinking_does_not_count() {
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let config = Arc::new(Config {
+            log_thinking: true,
+            ..(*config()).clone()
+        });
+        let state = SharedState::new_shared();
+        let mut monitor = JsonEventMonitor::new(config, Arc::clone(&state), cmd_tx).unwrap();
+
+        let thinking = r#"{"type":"assistant","message":{"content":[{"type":"thinking","thinking":"Once done I will say <promise>TASK COMPLETE</promise>"},{"type":"text","text":"Still working"}]}}"#;
+        drain(&mut monitor, format!("{thinking}\n")).await;
+
+        assert!(!state.is_promise_found().await);
+        assert_eq!(monitor.result().text, "Still working");
+    }
+
+    #[tokio::test]
+    async fn promise_needs_the_configured_confirmations() {
+        let config = Arc::new(Config {
+            promise_confirmations: 2,
+            ..(*config()).clone()
+        });
+        let quoted = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"I'll print <promise>TASK COMPLETE</promise> once the tests pass"}]}}"#;
+
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let state = SharedState::new_shared();
+        let mut monitor =
+            JsonEventMonitor::new(Arc::clone(&config), Arc::clone(&state), cmd_tx).unwrap();
+        drain(&mut monitor, format!("{quoted}\n")).await;
+        assert!(!state.is_promise_found().await);
+        assert!(cmd_rx.try_recv().is_err());
+        drain(&mut monitor, format!("{PROMISE}\n")).await;
+        assert!(state.is_promise_found().await);
+        assert!(matches!(
+            cmd_rx.try_recv(),
+            Ok(ProcessCommand::StopOnPromise)
+        ));
+        assert_eq!(monitor.result().promise_mentions, 2);
+
+        // The final result text counts on its own
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let state = SharedState::new_shared();
+        let mut monitor = JsonEventMonitor::new(config, Arc::clone(&state), cmd_tx).unwrap();
+        let result = r#"{"type":"result","usage":{},"result":"<promise>TASK COMPLETE</promise>"}"#;
+        drain(&mut monitor, format!("{quoted}\n{result}\n")).await;
+        assert!(state.is_promise_found().await);
+    }
+
+    #[tokio::test]
+    async fn promise_only_counts_after_the_milestones_in_order() {
+        let config = Arc::new(Config {
+            milestones: vec!["PLAN READY".to_string(), "TESTS PASS".to_string()],
+            ..(*config()).clone()
+        });
+        let text = |text: &str| {
+            format!(
+                r#"{{"type":"assistant","message":{{"content":[{{"type":"text","text":"{text}"}}]}}}}"#
+            )
+        };
+
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let state = SharedState::new_shared();
+        let mut monitor = JsonEventMonitor::new(config, Arc::clone(&state), cmd_tx).unwrap();
+        // Out of order, the second milestone doesn't count
+        drain(&mut monitor, text("<promise>TESTS PASS</promise>") + "\n").await;
+        drain(&mut monitor, format!("{PROMISE}\n")).await;
+        assert!(!state.is_promise_found().await);
+        assert!(cmd_rx.try_recv().is_err());
+
+        drain(
+            &mut monitor,
+            text("<promise>PLAN READY</promise> <promise>TESTS PASS</promise>") + "\n",
+        )
+        .await;
+        drain(&mut monitor, format!("{PROMISE}\n")).await;
+        assert!(state.is_promise_found().await);
+        assert_eq!(monitor.result().milestones, ["PLAN READY", "TESTS PASS"]);
+        let events: Vec<_> = monitor
+            .result()
+            .timeline
+            .into_iter()
+            .map(|entry| entry.event)
+            .collect();
+        assert!(matches!(
+            &events[..],
+            [
+                TimelineEvent::PromiseIgnored { line: 2, .. },
+                TimelineEvent::MilestoneReached { line: 3, .. },
+                TimelineEvent::MilestoneReached { line: 3, .. },
+                TimelineEvent::PromiseDetected { line: 4 },
+                TimelineEvent::KillSent {
+                    reason: KillReason::Promise,
+                    line: 4,
+                    tokens: None
+                },
+            ]
+        ));
+    }
+
+    #[tokio::test]
+    async fn stored_output_is_redacted_but_promise_still_detected() {
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let mut config = (*config()).clone();
+        config.redaction.enabled = true;
+        let state = SharedState::new_shared();
+        let mut monitor =
+            JsonEventMonitor::new(Arc::new(config), Arc::clone(&state), cmd_tx).unwrap();
+
+        let line = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"token sk-ant-REDACTED <promise>TASK COMPLETE</promise>"}]}}"#;
+        drain(&mut monitor, format!("{line}\n")).await;
+
+        let output = state.get_output().await;
+        assert!(!output.contains("sk-ant-"));
+        assert!(output.contains("[REDACTED]"));
+        assert!(state.is_promise_found().await);
+    }
+
+    #[tokio::test]
+    async fn unknown_events_are_kept_redacted() {
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let mut config = (*config()).clone();
+        config.redaction.enabled = true;
+        let mut monitor =
+            JsonEventMonitor::new(Arc::new(config), SharedState::new_shared(), cmd_tx).unwrap();
+
+        let input = concat!(
+            r#"{"type":"system","subtype":"init","session_id":"s"}"#,
+            "\n",
+            r#"{"type":"rate_limit","detail":{"key":"sk-ant-REDACTED"}}"#,
+            "\n",
+        );
+        drain(&mut monitor, input.to_string()).await;
+
+        let unknown = monitor.result().unknown;
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(unknown[0].event_type, "rate_limit");
+        assert_eq!(unknown[0].raw["detail"]["key"], "[REDACTED]");
+    }
+
+    #[tokio::test]
+    async fn muted_tool_events_are_left_out_of_output_but_processed() {
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let mut config = (*config()).clone();
+        config.event_filter.mute_tools = vec!["Read".to_string()];
+        let state = SharedState::new_shared();
+        let mut monitor =
+            JsonEventMonitor::new(Arc::new(config), Arc::clone(&state), cmd_tx).unwrap();
+
+        let read = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","id":"t1","name":"Read","input":{}}],"usage":{"input_tokens":900,"output_tokens":5}}}"#;
+        let result = r#"{"type":"user","message":{"content":[{"type":"tool_result","tool_use_id":"t1","content":"fn main() {}"}]}}"#;
+        let text =
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Looks fine"}]}}"#;
+        drain(&mut monitor, format!("{read}\n{result}\n{text}\n")).await;
+
+        assert_eq!(state.get_output().await, format!("{text}\n"));
+        assert_eq!(monitor.context_tokens, Some(905));
+        assert_eq!(monitor.result().text, "Looks fine");
+    }
+
+    #[tokio::test]
+    async fn parallel_tool_calls_are_paired_with_their_results() {
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let mut monitor =
+            JsonEventMonitor::new(config(), SharedState::new_shared(), cmd_tx).unwrap();
+
+        let calls = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","id":"a","name":"Bash","input":{}},{"type":"tool_use","id":"b","name":"Read","input":{}}]}}"#;
+        let read = r#"{"type":"user","message":{"content":[{"type":"tool_result","tool_use_id":"b","content":"ok"}]}}"#;
+        let bash = r#"{"type":"user","message":{"content":[{"type":"tool_result","tool_use_id":"a","is_error":true,"content":"exit 1"}]}}"#;
+        let next = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","id":"c","name":"Edit","input":{}}]}}"#;
+        drain(&mut monitor, format!("{calls}\n{read}\n{bash}\n{next}\n")).await;
+
+        let spans = monitor.result().tool_spans;
+        let summary: Vec<_> = spans
+            .iter()
+            .map(|span| {
+                (
+                    span.name.as_str(),
+                    span.line,
+                    span.result_line,
+                    span.is_error,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("Bash", 1, Some(3), true),
+                ("Read", 1, Some(2), false),
+                ("Edit", 4, None, false)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn records_last_reported_progress() {
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let state = SharedState::new_shared();
+        let mut monitor = JsonEventMonitor::new(config(), Arc::clone(&state), cmd_tx).unwrap();
+
+        let first = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"<progress>10%</progress> then <progress>40%</progress>"}]}}"#;
+        let second = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"<progress> 250 % </progress>"}]}}"#;
+        drain(&mut monitor, format!("{first}\n")).await;
+        assert_eq!(state.get_progress().await, Some(40));
+
+        drain(&mut monitor, format!("{second}\n")).await;
+        assert_eq!(monitor.result().progress, Some(100));
+    }
+
+    #[tokio::test]
+    async fn restart_command_stops_process_and_notes_are_passed_on() {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let mut monitor =
+            JsonEventMonitor::new(config(), SharedState::new_shared(), cmd_tx).unwrap();
+
+        let line = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"<ralph:cmd>note parser done</ralph:cmd><ralph:cmd>restart</ralph:cmd>"}]}}"#;
+        drain(&mut monitor, format!("{line}\n")).await;
+
+        assert!(matches!(cmd_rx.try_recv(), Ok(ProcessCommand::Restart)));
+        assert_eq!(
+            monitor.result().commands,
+            vec![AgentCommand::Note("parser done".to_string())]
+        );
+    }
+
+    fn turn(context_tokens: usize) -> String {
+        format!(
+            r#"{{"type":"assistant","message":{{"content":[{{"type":"tool_use","id":"t","name":"Bash","input":{{}}}}],"usage":{{"input_tokens":{context_tokens},"output_tokens":0}}}}}}"#
+        )
+    }
+
+    const TOOL_RESULT: &str =
+        r#"{"type":"user","message":{"content":[{"type":"tool_result","content":"ok"}]}}"#;
+
+    #[tokio::test]
+    async fn restarts_after_tool_call_when_next_turn_would_exceed_limit() {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let mut monitor =
+            JsonEventMonitor::new(config(), SharedState::new_shared(), cmd_tx).unwrap();
+
+        // Growth of 40k per turn: 150k + 40k would exceed the 180k limit
+        drain(
+            &mut monitor,
+            format!("{}\n{TOOL_RESULT}\n{}\n", turn(110_000), turn(150_000)),
+        )
+        .await;
+        assert!(cmd_rx.try_recv().is_err(), "must wait for the tool call");
+
+        drain(&mut monitor, format!("{TOOL_RESULT}\n")).await;
+        assert!(matches!(cmd_rx.try_recv(), Ok(ProcessCommand::Kill)));
+    }
+
+    #[tokio::test]
+    async fn executed_tool_calls_restart_before_the_next_request_would_exceed_limit() {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let mut monitor =
+            JsonEventMonitor::for_tool_calls(config(), SharedState::new_shared(), cmd_tx).unwrap();
+        let call = |id: &str| ToolCall {
+            id: id.to_string(),
+            name: "bash".to_string(),
+            arguments: r#"{"command":"ls"}"#.to_string(),
+        };
+        let requested = |id: &str, context_tokens: usize| {
+            LocalToolEvent::RalphToolUse {
+                calls: vec![call(id)],
+                usage: Some(TokenUsage {
+                    input_tokens: context_tokens,
+                    ..Default::default()
+                }),
+            }
+            .to_line()
+        };
+        let finished = |id: &str| {
+            LocalToolEvent::RalphToolResult {
+                tool_call_id: id.to_string(),
+                name: "bash".to_string(),
+                output: ToolOutput {
+                    content: "ok".to_string(),
+                    is_error: false,
+                },
+            }
+            .to_line()
+        };
+
+        for line in [
+            requested("a", 110_000),
+            finished("a"),
+            requested("b", 150_000),
+        ] {
+            monitor.process_line(&line).await.unwrap();
+        }
+        assert!(cmd_rx.try_recv().is_err(), "must wait for the tool call");
+
+        monitor.process_line(&finished("b")).await.unwrap();
+        assert!(matches!(cmd_rx.try_recv(), Ok(ProcessCommand::Kill)));
+        let result = monitor.result();
+        assert_eq!(result.tool_spans.len(), 2);
+        assert!(result
+            .tool_spans
+            .iter()
+            .all(|span| span.result_line.is_some()));
+        // The requests' monitors already marked the fill and warned
+        assert!(matches!(
+            &result.timeline[..],
+            [TimelineEntry {
+                event: TimelineEvent::KillSent {
+                    reason: KillReason::ProjectedContextLimit,
+                    line: 4,
+                    tokens: Some(150_000),
+                },
+                ..
+            }]
+        ));
+    }
+
+    #[tokio::test]
+    async fn no_adaptive_restart_when_disabled() {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let mut config = (*config()).clone();
+        config.context_limit.adaptive_restart = false;
+        let mut monitor =
+            JsonEventMonitor::new(Arc::new(config), SharedState::new_shared(), cmd_tx).unwrap();
+
+        drain(
+            &mut monitor,
+            format!(
+                "{}\n{TOOL_RESULT}\n{}\n{TOOL_RESULT}\n",
+                turn(110_000),
+                turn(150_000)
+            ),
+        )
+        .await;
+
+        assert!(cmd_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn text_turn_past_the_limit_stops_the_agent() {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let mut config = (*config()).clone();
+        config.context_limit.adaptive_restart = false;
+        let mut monitor =
+            JsonEventMonitor::new(Arc::new(config), SharedState::new_shared(), cmd_tx).unwrap();
+
+        let text_turn = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"still thinking"}],"usage":{"input_tokens":185000,"output_tokens":0}}}"#;
+        drain(&mut monitor, format!("{text_turn}\n")).await;
+
+        assert!(matches!(cmd_rx.try_recv(), Ok(ProcessCommand::Kill)));
+        assert!(monitor.result().timeline.iter().any(|entry| entry.event
+            == TimelineEvent::KillSent {
+                reason: KillReason::ContextLimit,
+                line: 1,
+                tokens: Some(185_000),
+            }));
+    }
+
+    #[tokio::test]
+    async fn context_fill_is_marked_in_the_timeline() {
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let mut monitor =
+            JsonEventMonitor::new(config(), SharedState::new_shared(), cmd_tx).unwrap();
+
+        // 180k limit: 10%, then 55% (past 25% and 50%), 60%, 80%
+        drain(
+            &mut monitor,
+            [18_000, 99_000, 108_000, 144_000]
+                .map(|tokens| format!("{}\n{TOOL_RESULT}\n", turn(tokens)))
+                .concat(),
+        )
+        .await;
+
+        let marks: Vec<_> = monitor
+            .result()
+            .timeline
+            .into_iter()
+            .filter_map(|entry| match entry.event {
+                TimelineEvent::ContextFilled {
+                    percent,
+                    tokens,
+                    line,
+                } => Some((percent, tokens, line)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(marks, [(50, 99_000, 3), (75, 144_000, 7)]);
+    }
+
+    #[tokio::test]
+    async fn decisions_are_marked_inline_and_skipped_on_replay() {
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let state = SharedState::new_shared();
+        let mut monitor = JsonEventMonitor::new(config(), Arc::clone(&state), cmd_tx).unwrap();
+        let input = format!("{}\n{TOOL_RESULT}\n{}\n", turn(18_000), turn(99_000));
+        drain(&mut monitor, input).await;
+
+        let output = state.get_output().await;
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(timeline::is_marker(lines[3]), "{output}");
+        assert!(lines[3].contains("line 3: context 50% full"), "{output}");
+
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let replayed_state = SharedState::new_shared();
+        let mut replayed =
+            JsonEventMonitor::new(config(), Arc::clone(&replayed_state), cmd_tx).unwrap();
+        drain(&mut replayed, output.clone()).await;
+        let events = |monitor: &JsonEventMonitor| -> Vec<TimelineEvent> {
+            monitor
+                .result()
+                .timeline
+                .into_iter()
+                .map(|entry| entry.event)
+                .collect()
+        };
+        assert_eq!(events(&replayed), events(&monitor));
+        assert_eq!(replayed_state.get_output().await.lines().count(), 4);
+    }
+
+    #[tokio::test]
+    async fn sub_agent_usage_is_summed_apart_from_the_context() {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let mut monitor =
+            JsonEventMonitor::new(config(), SharedState::new_shared(), cmd_tx).unwrap();
+        let message = |parent: &str, id: &str, output_tokens: usize| {
+            format!(
+                r#"{{"type":"assistant","parent_tool_use_id":"{parent}","message":{{"id":"{id}","content":[{{"type":"text","text":"..."}}],"usage":{{"input_tokens":150000,"output_tokens":{output_tokens}}}}}}}"#
+            )
+        };
+
+        // Each content block of a message repeats its usage
+        let lines = [
+            turn(10_000),
+            message("task_1", "msg_1", 10),
+            message("task_1", "msg_1", 20),
+            message("task_1", "msg_2", 5),
+            message("task_2", "msg_3", 5),
+        ];
+        drain(&mut monitor, format!("{}\n", lines.join("\n"))).await;
+
+        // Even past the agent's context limit, sub-agents don't restart it
+        assert!(cmd_rx.try_recv().is_err());
+        let subagents = monitor.result().subagent_usage.unwrap();
+        assert_eq!(subagents.count, 2);
+        assert_eq!(subagents.usage.input_tokens, 450_000);
+        assert_eq!(subagents.usage.output_tokens, 30);
+    }
+
+    #[tokio::test]
+    async fn estimates_tokens_when_no_usage_is_reported() {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let mut config = (*config()).clone();
+        config.agent.provider = AgentProvider::Codex;
+        config.context_limit.estimation_method = crate::config::TokenEstimationMethod::ByteRatio;
+        config.context_limit.max_tokens = 10;
+        let state = SharedState::new_shared();
+        let mut monitor =
+            JsonEventMonitor::new(Arc::new(config), Arc::clone(&state), cmd_tx).unwrap();
+
+        let message = r#"{"type":"item.completed","item":{"type":"agent_message","text":"0123456789012345678901234567890123456789"}}"#;
+        drain(&mut monitor, format!("{message}\n")).await;
+
+        assert_eq!(state.get_token_count().await, 10);
+        assert!(matches!(cmd_rx.try_recv(), Ok(ProcessCommand::Kill)));
+    }
+
+    #[tokio::test]
+    async fn tool_results_count_towards_the_estimate() {
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let mut config = (*config()).clone();
+        config.context_limit.estimation_method = crate::config::TokenEstimationMethod::ByteRatio;
+        let state = SharedState::new_shared();
+        let counter = Arc::new(TokenCounter::new(
+            crate::config::TokenEstimationMethod::ByteRatio,
+        ));
+        let mut monitor = JsonEventMonitor::new(Arc::new(config), Arc::clone(&state), cmd_tx)
+            .unwrap()
+            .with_token_counter(Arc::clone(&counter));
+
+        let finished = LocalToolEvent::RalphToolResult {
+            tool_call_id: "a".to_string(),
+            name: "bash".to_string(),
+            output: ToolOutput {
+                content: "x".repeat(400),
+                is_error: false,
+            },
+        };
+        drain(&mut monitor, format!("{}\n", finished.to_line())).await;
+
+        assert_eq!(state.get_token_count().await, 100);
+    }
+
+    #[tokio::test]
+    async fn unparsed_lines_past_the_cap_are_only_counted() {
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let mut monitor =
+            JsonEventMonitor::new(config(), SharedState::new_shared(), cmd_tx).unwrap();
+
+        drain(&mut monitor, "not json\n".repeat(MAX_UNPARSED_LINES + 5)).await;
+
+        let result = monitor.result();
+        assert_eq!(result.unparsed.len(), MAX_UNPARSED_LINES);
+        assert_eq!(result.unparsed_dropped, 5);
+    }
+
+    #[tokio::test]
+    async fn no_kill_below_context_limit() {
+        let (cmd_tx, mut cmd_rx) = mpsc::channel(1);
+        let mut monitor =
+            JsonEventMonitor::new(config(), SharedState::new_shared(), cmd_tx).unwrap();
+
+        drain(
+            &mut monitor,
+            r#"{"type":"result","usage":{"input_tokens":10,"output_tokens":10}}"#.to_string(),
+        )
+        .await;
+
+        assert!(!monitor.stop_requested());
+        assert!(cmd_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn invalid_redaction_pattern_is_a_config_error() {
+        let (cmd_tx, _cmd_rx) = mpsc::channel(1);
+        let config = Arc::new(Config {
+            prompt: "test".to_string(),
+            redaction: crate::config::RedactionConfig {
+                enabled: true,
+                patterns: vec!["(unclosed".to_string()],
+                ..Default::default()
+            },
+            ..Config::default()
+        });
+
+        let result = JsonEventMonitor::new(config, SharedState::new_shared(), cmd_tx);
+
+        assert!(matches!(
+            result,
+            Err(crate::error::RalphError::ConfigError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stderr_is_captured_up_to_the_cap_and_redacted() {
+        let redactor = Redactor::from_config(&crate::config::RedactionConfig {
+            enabled: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let mut monitor = StderrMonitor::capturing(64, redactor);
+        let input = "error: token sk-ant-REDACTED rejected\nsecond line that no longer fits\n";
+        let mut reader = BufReader::new(input.as_bytes());
+
+        monitor.monitor_stream(&mut reader).await.unwrap();
+
+        assert_eq!(
+            monitor.into_captured(),
+            "error: token [REDACTED] rejected\n[32 more bytes of stderr not kept]\n"
+        );
+    }
 }
@@ -0,0 +1,120 @@
+//! Waiting out network outages instead of burning iterations.
+//!
+//! When an iteration fails with an error that looks like a lost connection
+//! (DNS failures, refused or reset connections in the agent's stderr or
+//! output), the agent's API is probed. If it is unreachable, the iteration
+//! doesn't count toward `max_iterations` and the loop waits, probing every
+//! `probe_interval_secs`, until the network is back.
+
+use std::process::Stdio;
+
+use tokio::process::Command;
+use tracing::warn;
+
+use crate::config::{AgentProvider, Config};
+
+/// Status recorded in the run metadata while the loop waits
+pub const PAUSED_REASON: &str = "waiting for network";
+
+/// Seconds a single probe may take
+const PROBE_TIMEOUT_SECS: &str = "10";
+
+/// Lowercase fragments of error messages caused by a lost connection
+const NETWORK_ERRORS: &[&str] = &[
+    "could not resolve host",
+    "temporary failure in name resolution",
+    "name or service not known",
+    "failed to connect to",
+    "network is unreachable",
+    "connection timed out",
+    "connection error",
+    "fetch failed",
+    "enotfound",
+    "eai_again",
+    "econnrefused",
+    "econnreset",
+    "etimedout",
+    "enetunreach",
+];
+
+/// The first line of `text` that reports a lost connection, if any
+pub fn network_error(text: &str) -> Option<&str> {
+    text.lines().map(str::trim).find(|line| {
+        let line = line.to_lowercase();
+        NETWORK_ERRORS.iter().any(|error| line.contains(error))
+    })
+}
+
+/// URL probed to find out whether the agent's API is reachable
+pub fn probe_url(config: &Config) -> String {
+    if let Some(ref url) = config.network.probe_url {
+        return url.clone();
+    }
+    match config.agent_provider() {
+        AgentProvider::Claude => "https://api.anthropic.com".to_string(),
+        AgentProvider::Codex => "https://api.openai.com".to_string(),
+        AgentProvider::Openai => config.api.base_url.clone(),
+        AgentProvider::Anthropic => config.anthropic.base_url.clone(),
+        AgentProvider::Ollama => config.ollama.base_url.clone(),
+    }
+}
+
+/// Whether `url` answers at all; any HTTP response counts. If curl can't be
+/// run, the network is assumed to be up.
+pub async fn probe(url: &str) -> bool {
+    let status = Command::new("curl")
+        .args([
+            "-sS",
+            "-o",
+            "/dev/null",
+            "--max-time",
+            PROBE_TIMEOUT_SECS,
+            url,
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await;
+    match status {
+        Ok(status) => status.success(),
+        Err(e) => {
+            warn!("Failed to run curl to probe the network: {}", e);
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_failures_are_recognized() {
+        assert_eq!(
+            network_error("curl: (6) Could not resolve host: api.openai.com\n"),
+            Some("curl: (6) Could not resolve host: api.openai.com")
+        );
+        assert_eq!(
+            network_error(
+                r#"{"type":"result","is_error":true,"result":"API Error: Connection error."}"#
+            ),
+            Some(r#"{"type":"result","is_error":true,"result":"API Error: Connection error."}"#)
+        );
+        assert!(network_error("Error: getaddrinfo ENOTFOUND api.anthropic.com").is_some());
+        assert_eq!(
+            network_error("error: tests failed\npanicked at src/lib.rs"),
+            None
+        );
+    }
+
+    #[test]
+    fn probe_url_follows_the_provider() {
+        let mut config = Config::default();
+        assert_eq!(probe_url(&config), "https://api.anthropic.com");
+        config.agent.provider = AgentProvider::Ollama;
+        assert_eq!(probe_url(&config), "http://localhost:11434");
+        config.network.probe_url = Some("http://proxy.internal".to_string());
+        assert_eq!(probe_url(&config), "http://proxy.internal");
+    }
+}
@@ -0,0 +1,176 @@
+//! Notifications sent when a run ends.
+
+use tracing::{debug, warn};
+
+use crate::config::NotificationsConfig;
+
+/// Run outcome a notification can be sent for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunEvent {
+    /// The completion promise was found, with the summary the agent gave
+    PromiseFulfilled {
+        iterations: u32,
+        summary: Option<String>,
+    },
+    /// Every item in the plan file was checked
+    PlanCompleted { iterations: u32 },
+    /// Max iterations were exhausted without finding the promise
+    MaxIterationsExceeded { max: u32 },
+    /// An error aborted the run
+    Error { message: String },
+}
+
+impl RunEvent {
+    fn summary(&self) -> &'static str {
+        match self {
+            RunEvent::PromiseFulfilled { .. } => "ralph-loop: promise fulfilled",
+            RunEvent::PlanCompleted { .. } => "ralph-loop: plan completed",
+            RunEvent::MaxIterationsExceeded { .. } => "ralph-loop: max iterations exceeded",
+            RunEvent::Error { .. } => "ralph-loop: run failed",
+        }
+    }
+
+    fn body(&self) -> String {
+        match self {
+            RunEvent::PromiseFulfilled {
+                iterations,
+                summary: Some(summary),
+            } => format!("Completed after {iterations} iteration(s)\n{summary}"),
+            RunEvent::PromiseFulfilled { iterations, .. }
+            | RunEvent::PlanCompleted { iterations } => {
+                format!("Completed after {iterations} iteration(s)")
+            }
+            RunEvent::MaxIterationsExceeded { max } => {
+                format!("Stopped after {max} iteration(s) without finding the promise")
+            }
+            RunEvent::Error { message } => message.clone(),
+        }
+    }
+}
+
+/// Whether the configuration asks for a desktop notification for `event`
+pub fn desktop_enabled_for(config: &NotificationsConfig, event: &RunEvent) -> bool {
+    let desktop = &config.desktop;
+    desktop.enabled
+        && match event {
+            RunEvent::PromiseFulfilled { .. } | RunEvent::PlanCompleted { .. } => {
+                desktop.on_promise
+            }
+            RunEvent::MaxIterationsExceeded { .. } => desktop.on_max_iterations,
+            RunEvent::Error { .. } => desktop.on_error,
+        }
+}
+
+/// Send the notifications configured for `event`. Failures are logged, never fatal.
+pub async fn notify_run_end(config: &NotificationsConfig, event: &RunEvent) {
+    if !desktop_enabled_for(config, event) {
+        return;
+    }
+
+    debug!("Sending desktop notification for {:?}", event);
+    show_desktop_notification(event.summary(), event.body()).await;
+}
+
+/// Show a desktop notification. Showing one waits for the notification
+/// server, so it happens off the runtime's worker threads.
+#[cfg(feature = "desktop-notifications")]
+async fn show_desktop_notification(summary: &'static str, body: String) {
+    let shown = tokio::task::spawn_blocking(move || {
+        notify_rust::Notification::new()
+            .appname("ralph-loop")
+            .summary(summary)
+            .body(&body)
+            .show()
+            .map(drop)
+    })
+    .await;
+    match shown {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!("Failed to send desktop notification: {}", e),
+        Err(e) => warn!("Failed to send desktop notification: {}", e),
+    }
+}
+
+#[cfg(not(feature = "desktop-notifications"))]
+async fn show_desktop_notification(_summary: &'static str, _body: String) {
+    warn!("Desktop notifications aren't included in this build of ralph-loop");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DesktopNotificationConfig;
+
+    fn enabled() -> NotificationsConfig {
+        NotificationsConfig {
+            desktop: DesktopNotificationConfig {
+                enabled: true,
+                ..DesktopNotificationConfig::default()
+            },
+        }
+    }
+
+    #[test]
+    fn desktop_notifications_are_off_by_default() {
+        let config = NotificationsConfig::default();
+        assert!(!desktop_enabled_for(
+            &config,
+            &RunEvent::PromiseFulfilled {
+                iterations: 1,
+                summary: None
+            }
+        ));
+    }
+
+    #[test]
+    fn per_event_toggles_are_respected() {
+        let mut config = enabled();
+        config.desktop.on_max_iterations = false;
+
+        assert!(desktop_enabled_for(
+            &config,
+            &RunEvent::PromiseFulfilled {
+                iterations: 2,
+                summary: None
+            }
+        ));
+        assert!(!desktop_enabled_for(
+            &config,
+            &RunEvent::MaxIterationsExceeded { max: 5 }
+        ));
+        assert!(desktop_enabled_for(
+            &config,
+            &RunEvent::Error {
+                message: "boom".to_string()
+            }
+        ));
+    }
+
+    #[test]
+    fn promise_summary_is_included_in_the_body() {
+        let event = RunEvent::PromiseFulfilled {
+            iterations: 3,
+            summary: Some("Fixed the parser".to_string()),
+        };
+        assert_eq!(
+            event.body(),
+            "Completed after 3 iteration(s)\nFixed the parser"
+        );
+    }
+
+    #[test]
+    fn parses_notifications_table_from_toml() {
+        let config: crate::config::Config = toml::from_str(
+            r#"
+            [notifications.desktop]
+            enabled = true
+            on_error = false
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.notifications.desktop.enabled);
+        assert!(config.notifications.desktop.on_promise);
+        assert!(!config.notifications.desktop.on_error);
+    }
+}
@@ -0,0 +1,138 @@
+//! Agent backed by a local Ollama server.
+//!
+//! With `[agent] provider = "ollama"`, each iteration streams a response from
+//! `<base_url>/api/chat`, which makes cheap experimentation loops possible
+//! with local models. The server's newline-delimited JSON is parsed into the
+//! usual agent events, and the final line's prompt and eval counts are used
+//! for token accounting.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::agent::{Agent, AgentResult, IterationSettings};
+use crate::api_agent::{function_tools, run_conversation, ChatApi};
+use crate::config::Config;
+use crate::error::Result;
+use crate::tools::{ToolCall, ToolDefinition, ToolOutput};
+
+/// Agent that streams chat responses from an Ollama server
+pub struct OllamaAgent {
+    config: Arc<Config>,
+}
+
+impl OllamaAgent {
+    /// Create a new OllamaAgent with the given configuration
+    pub fn new(config: Arc<Config>) -> Self {
+        Self { config }
+    }
+}
+
+impl ChatApi for OllamaAgent {
+    fn config(&self) -> &Arc<Config> {
+        &self.config
+    }
+
+    fn endpoint(&self) -> String {
+        format!(
+            "{}/api/chat",
+            self.config.ollama.base_url.trim_end_matches('/')
+        )
+    }
+
+    fn headers(&self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    fn request_body(&self, messages: &[Value], tools: &[ToolDefinition]) -> Result<Value> {
+        let ollama = &self.config.ollama;
+        let mut all_messages = Vec::with_capacity(messages.len() + 1);
+        if let Some(ref system_prompt) = ollama.system_prompt {
+            all_messages.push(json!({ "role": "system", "content": system_prompt }));
+        }
+        all_messages.extend_from_slice(messages);
+
+        let mut body = json!({
+            "model": ollama.model,
+            "messages": all_messages,
+            "stream": true,
+        });
+        if let Some(num_ctx) = ollama.num_ctx {
+            body["options"] = json!({ "num_ctx": num_ctx });
+        }
+        if !tools.is_empty() {
+            body["tools"] = function_tools(tools);
+        }
+        Ok(body)
+    }
+
+    fn assistant_message(&self, text: &str, calls: &[ToolCall]) -> Value {
+        let tool_calls: Vec<Value> = calls
+            .iter()
+            .map(|call| json!({ "function": { "name": call.name, "arguments": call.input() } }))
+            .collect();
+        json!({ "role": "assistant", "content": text, "tool_calls": tool_calls })
+    }
+
+    fn tool_results(&self, results: &[(ToolCall, ToolOutput)]) -> Vec<Value> {
+        results
+            .iter()
+            .map(|(call, output)| {
+                json!({ "role": "tool", "tool_name": call.name, "content": output.content })
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Agent for OllamaAgent {
+    async fn run(&self, prompt: &str) -> Result<AgentResult> {
+        self.run_with_env(prompt, &[]).await
+    }
+
+    async fn run_with_env(&self, prompt: &str, env: &[(String, String)]) -> Result<AgentResult> {
+        run_conversation(
+            self,
+            prompt,
+            env,
+            &IterationSettings::from_config(&self.config),
+        )
+        .await
+    }
+
+    async fn run_iteration(
+        &self,
+        prompt: &str,
+        env: &[(String, String)],
+        settings: &IterationSettings,
+    ) -> Result<AgentResult> {
+        run_conversation(self, prompt, env, settings).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OllamaConfig;
+
+    #[test]
+    fn builds_streaming_request() {
+        let agent = OllamaAgent::new(Arc::new(Config {
+            ollama: OllamaConfig {
+                num_ctx: Some(32768),
+                ..OllamaConfig::default()
+            },
+            ..Config::default()
+        }));
+
+        let body = agent
+            .request_body(&[json!({ "role": "user", "content": "Fix the bug" })], &[])
+            .unwrap();
+
+        assert_eq!(agent.endpoint(), "http://localhost:11434/api/chat");
+        assert_eq!(body["model"], "llama3.2");
+        assert_eq!(body["options"]["num_ctx"], 32768);
+        assert_eq!(body["messages"][0]["content"], "Fix the bug");
+    }
+}
@@ -0,0 +1,84 @@
+//! Showing long subcommand output, like a run listing or a replay, in a pager.
+//!
+//! When stdout is a terminal, output goes through `RALPH_PAGER`, `PAGER` or
+//! `less`, so it can be scrolled and searched instead of flooding the
+//! scrollback. `less` gets `LESS=FRX` unless `LESS` is set, so output that
+//! fits on one screen is printed as usual. An empty pager or `cat` turns
+//! paging off, as does `--no-pager`.
+
+use std::io::{ErrorKind, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+use tracing::warn;
+
+/// Pager used when neither `RALPH_PAGER` nor `PAGER` is set
+const DEFAULT_PAGER: &str = "less";
+
+/// Options passed to `less` unless `LESS` is set: quit if the output fits on
+/// one screen, keep colors, and don't clear the screen on exit
+const DEFAULT_LESS: &str = "FRX";
+
+/// The pager command configured in the environment, `None` if paging is off
+fn pager_command() -> Option<String> {
+    let pager = std::env::var("RALPH_PAGER")
+        .or_else(|_| std::env::var("PAGER"))
+        .unwrap_or_else(|_| DEFAULT_PAGER.to_string());
+    let pager = pager.trim();
+    (!pager.is_empty() && pager != "cat").then(|| pager.to_string())
+}
+
+/// Print `text` through the pager if `enabled` and stdout is a terminal,
+/// otherwise, or if the pager can't be started, directly
+pub fn print(text: &str, enabled: bool) {
+    let pager = enabled && std::io::stdout().is_terminal();
+    if let Some(command) = pager.then(pager_command).flatten() {
+        match page(&command, text) {
+            Ok(()) => return,
+            Err(e) => warn!("Failed to run pager '{}': {}", command, e),
+        }
+    }
+    println!("{text}");
+}
+
+/// Feed `text` to `command`, run by the shell so it may carry arguments,
+/// and wait until the pager is closed
+fn page(command: &str, text: &str) -> std::io::Result<()> {
+    let mut pager = Command::new("sh");
+    pager.args(["-c", command]).stdin(Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        pager.env("LESS", DEFAULT_LESS);
+    }
+    let mut child = pager.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // Quitting the pager before reading everything closes the pipe
+        match writeln!(stdin, "{text}") {
+            Err(e) if e.kind() != ErrorKind::BrokenPipe => return Err(e),
+            _ => {}
+        }
+    }
+    // The shell reports a pager it can't find with 127
+    if child.wait()?.code() == Some(127) {
+        return Err(std::io::Error::new(
+            ErrorKind::NotFound,
+            "command not found",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn text_is_fed_to_the_pager() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("paged");
+        page(&format!("cat > '{}'", file.display()), "line 1\nline 2").unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "line 1\nline 2\n");
+
+        let missing = page("ralph-loop-missing-pager 2>/dev/null", "text").unwrap_err();
+        assert_eq!(missing.kind(), ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,109 @@
+//! Plan file tracking.
+//!
+//! A plan file (e.g. `PLAN.md`) holds markdown checkbox items the agent ticks
+//! off as it works. Ralph reads it between iterations, records progress, and
+//! treats a fully checked plan as a second completion signal.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{RalphError, Result};
+
+/// Checkbox completion counts of a plan file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanProgress {
+    /// Checked items
+    pub done: usize,
+    /// All checkbox items
+    pub total: usize,
+}
+
+impl PlanProgress {
+    /// Whether the plan has items and all of them are checked
+    pub fn is_complete(&self) -> bool {
+        self.total > 0 && self.done == self.total
+    }
+}
+
+/// Count the checkbox items (`- [ ]`, `* [x]`, `1. [X]`, ...) in markdown
+pub fn parse(content: &str) -> PlanProgress {
+    let mut progress = PlanProgress::default();
+    for line in content.lines() {
+        let Some(rest) = list_item_body(line.trim_start()) else {
+            continue;
+        };
+        match rest.get(..3) {
+            Some("[ ]") => progress.total += 1,
+            Some("[x]") | Some("[X]") => {
+                progress.total += 1;
+                progress.done += 1;
+            }
+            _ => {}
+        }
+    }
+    progress
+}
+
+/// Strip a bullet or ordered-list marker, returning the item text
+fn list_item_body(line: &str) -> Option<&str> {
+    if let Some(rest) = line
+        .strip_prefix("- ")
+        .or_else(|| line.strip_prefix("* "))
+        .or_else(|| line.strip_prefix("+ "))
+    {
+        return Some(rest.trim_start());
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits == 0 {
+        return None;
+    }
+    line[digits..]
+        .strip_prefix(". ")
+        .or_else(|| line[digits..].strip_prefix(") "))
+        .map(str::trim_start)
+}
+
+/// Read plan progress from `path`; `None` if the file doesn't exist yet
+pub fn read(path: &Path) -> Result<Option<PlanProgress>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(parse(&content))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(RalphError::ConfigError(format!(
+            "failed to read plan file {}: {}",
+            path.display(),
+            e
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_checked_and_unchecked_items() {
+        let plan = "# Plan\n\
+                    - [x] parse config\n\
+                    - [ ] write tests\n\
+                    \x20 * [X] nested item\n\
+                    1. [ ] ordered item\n\
+                    - not a task\n\
+                    - [y] not a checkbox\n";
+
+        assert_eq!(parse(plan), PlanProgress { done: 2, total: 4 });
+    }
+
+    #[test]
+    fn complete_only_when_all_items_checked() {
+        assert!(parse("- [x] a\n- [X] b\n").is_complete());
+        assert!(!parse("- [x] a\n- [ ] b\n").is_complete());
+        assert!(!parse("no items here\n").is_complete());
+    }
+
+    #[test]
+    fn missing_plan_file_is_not_an_error() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        assert_eq!(read(&temp_dir.path().join("PLAN.md")).unwrap(), None);
+    }
+}
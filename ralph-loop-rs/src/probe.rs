@@ -0,0 +1,167 @@
+//! Completion probe: a second opinion on whether the task is complete.
+//!
+//! With `[probe] enabled = true`, the end of the agent's text in each
+//! iteration is shown to a small model through a short, non-interactive
+//! call to the agent CLI, which answers whether the task looks complete.
+//! The answer doesn't change what the loop does; it is stored with the
+//! iteration, and iterations where it disagrees with the promise are
+//! logged as warnings, which points at prompts that need tuning.
+
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use tokio::process::Command;
+use tracing::warn;
+
+use crate::audit::SubprocessRecord;
+use crate::config::{AgentProvider, Config};
+
+/// How long the probe may take to answer
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Model asked by default when the agent is Claude
+const DEFAULT_CLAUDE_MODEL: &str = "haiku";
+
+/// How much of the end of the agent's text the probe is shown
+const MAX_MESSAGE_CHARS: usize = 4000;
+
+/// How much of the start of the prompt the probe is shown
+const MAX_TASK_CHARS: usize = 2000;
+
+/// Prompt asking whether `task` is complete, judging by the agent's
+/// final `message`
+pub fn prompt(task: &str, message: &str) -> String {
+    let skip = message.chars().count().saturating_sub(MAX_MESSAGE_CHARS);
+    let message: String = message.chars().skip(skip).collect();
+    let task: String = task.chars().take(MAX_TASK_CHARS).collect();
+    format!(
+        "An automated coding agent was given a task and has just ended its turn. \
+         Judging only by its final message, is the task complete? \
+         Answer with YES or NO on the first line.\n\nTask:\n{task}\n\n\
+         Final message of the agent:\n{message}"
+    )
+}
+
+/// The answer on the first non-empty line of `reply`: `Some(true)` for
+/// yes, `Some(false)` for no, `None` for anything else
+pub fn parse(reply: &str) -> Option<bool> {
+    let line = reply.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let word: String = line
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    match word.to_ascii_lowercase().as_str() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Ask the probe whether `task` is complete. Returns the answer, if one
+/// was given, and the agent subprocess, if one was spawned.
+pub async fn ask(
+    config: &Config,
+    task: &str,
+    message: &str,
+) -> (Option<bool>, Option<SubprocessRecord>) {
+    let model = config.probe.model.clone();
+    let mut args = match config.agent_provider() {
+        AgentProvider::Claude => vec![
+            "--print".to_string(),
+            "--model".to_string(),
+            model.unwrap_or_else(|| DEFAULT_CLAUDE_MODEL.to_string()),
+        ],
+        AgentProvider::Codex => {
+            let mut args = vec!["exec".to_string()];
+            if let Some(model) = model {
+                args.extend(["--model".to_string(), model]);
+            }
+            args
+        }
+        // The API backends have no one-shot CLI to ask
+        AgentProvider::Openai | AgentProvider::Anthropic | AgentProvider::Ollama => {
+            return (None, None);
+        }
+    };
+    let program = config.agent_path();
+    args.push(prompt(task, message));
+
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let output = tokio::time::timeout(
+        TIMEOUT,
+        Command::new(&program)
+            .args(&args)
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await;
+    let exit_code = match output {
+        Ok(Ok(ref output)) => output.status.code(),
+        _ => None,
+    };
+    let subprocess = SubprocessRecord {
+        kind: "probe".to_string(),
+        program,
+        args,
+        pid: None,
+        started_at,
+        exit_code,
+        duration_ms: started.elapsed().as_millis() as u64,
+    };
+
+    let answer = match output {
+        Ok(Ok(output)) if output.status.success() => {
+            let reply = String::from_utf8_lossy(&output.stdout);
+            let answer = parse(&reply);
+            if answer.is_none() {
+                warn!(
+                    "Completion probe gave no YES or NO: {}",
+                    reply.lines().next().unwrap_or_default().trim()
+                );
+            }
+            answer
+        }
+        Ok(Ok(output)) => {
+            warn!(
+                "Completion probe exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            None
+        }
+        Ok(Err(e)) => {
+            warn!("Failed to run completion probe: {}", e);
+            None
+        }
+        Err(_) => {
+            warn!("Completion probe timed out after {:?}", TIMEOUT);
+            None
+        }
+    };
+    (answer, Some(subprocess))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_come_from_the_first_line() {
+        assert_eq!(parse("YES\nAll tests pass."), Some(true));
+        assert_eq!(parse("\n  No, the build still fails"), Some(false));
+        assert_eq!(parse("yes."), Some(true));
+        assert_eq!(parse("Probably"), None);
+        assert_eq!(parse("Nope"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn prompts_keep_the_end_of_long_messages() {
+        let message = format!("{}the end", "x".repeat(MAX_MESSAGE_CHARS));
+        let prompt = prompt("Fix the parser", &message);
+        assert!(prompt.contains("Task:\nFix the parser"));
+        assert!(prompt.ends_with("the end"));
+        assert!(prompt.len() < MAX_MESSAGE_CHARS + 400);
+    }
+}
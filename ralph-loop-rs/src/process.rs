@@ -1,7 +1,10 @@
 use std::process::Stdio;
+use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, ChildStderr, ChildStdout, Command};
+use tracing::{debug, info};
 
+use crate::config::StopConfig;
 use crate::error::{RalphError, Result};
 
 /// Wrapper around a coding agent subprocess
@@ -34,10 +37,16 @@ impl AgentProcess {
         })
     }
 
-    /// Spawn a new agent process with prompt via stdin
-    pub async fn spawn_with_stdin(path: &str, args: &[String], prompt: &str) -> Result<Self> {
+    /// Spawn a new agent process with prompt via stdin and extra environment variables
+    pub async fn spawn_with_stdin(
+        path: &str,
+        args: &[String],
+        prompt: &str,
+        env: &[(String, String)],
+    ) -> Result<Self> {
         let mut cmd = Command::new(path);
         cmd.args(args)
+            .envs(env.iter().map(|(key, value)| (key, value)))
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
@@ -74,6 +83,48 @@ impl AgentProcess {
         self.child.kill().await.map_err(RalphError::ProcessIoError)
     }
 
+    /// Stop the process: send SIGINT so it can flush its result event, then
+    /// SIGTERM, then kill it, each after the grace period in `config`
+    pub async fn stop(&mut self, config: &StopConfig) -> Result<()> {
+        for (signal, grace_secs) in [
+            ("INT", config.interrupt_grace_secs),
+            ("TERM", config.terminate_grace_secs),
+        ] {
+            if grace_secs == 0 {
+                continue;
+            }
+            if !self.signal(signal).await {
+                break;
+            }
+            let grace = Duration::from_secs(grace_secs);
+            if let Ok(status) = tokio::time::timeout(grace, self.child.wait()).await {
+                let status = status.map_err(RalphError::ProcessIoError)?;
+                info!("Agent process exited after SIG{}: {:?}", signal, status);
+                return Ok(());
+            }
+            debug!(
+                "Agent process still running {}s after SIG{}",
+                grace_secs, signal
+            );
+        }
+        info!("Killing agent process");
+        self.kill().await
+    }
+
+    /// Send `signal` to the process with `kill`, whether that succeeded
+    async fn signal(&self, signal: &str) -> bool {
+        let Some(pid) = self.id() else {
+            return false;
+        };
+        Command::new("kill")
+            .args(["-s", signal, &pid.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .is_ok_and(|status| status.success())
+    }
+
     /// Check if the process has exited
     pub fn try_wait(&mut self) -> Result<Option<std::process::ExitStatus>> {
         self.child.try_wait().map_err(RalphError::ProcessIoError)
@@ -96,3 +147,44 @@ pub async fn read_lines(
         Err(e) => Err(RalphError::ProcessIoError(e)),
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    async fn spawn_sh(script: &str) -> AgentProcess {
+        AgentProcess::spawn_with_stdin("sh", &["-c".to_string(), script.to_string()], "", &[])
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn interrupted_process_can_write_before_exiting() {
+        let mut process =
+            spawn_sh("trap 'echo flushed; exit 130' INT; echo ready; sleep 30 & wait $!").await;
+        let mut stdout = process.stdout.take().unwrap();
+        assert_eq!(read_lines(&mut stdout).await.unwrap().unwrap(), "ready\n");
+
+        process.stop(&StopConfig::default()).await.unwrap();
+        assert_eq!(process.try_wait().unwrap().unwrap().code(), Some(130));
+        assert_eq!(read_lines(&mut stdout).await.unwrap().unwrap(), "flushed\n");
+    }
+
+    #[tokio::test]
+    async fn process_ignoring_signals_is_killed_after_the_grace_periods() {
+        let mut process = spawn_sh("trap '' INT TERM; echo ready; sleep 30 & wait $!").await;
+        let mut stdout = process.stdout.take().unwrap();
+        read_lines(&mut stdout).await.unwrap();
+
+        let started = Instant::now();
+        let config = StopConfig {
+            interrupt_grace_secs: 1,
+            terminate_grace_secs: 0,
+        };
+        process.stop(&config).await.unwrap();
+        let status = process.wait().await.unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(status.code(), None, "killed by a signal");
+    }
+}
@@ -0,0 +1,303 @@
+//! Detection of the completion promise in assistant text.
+//!
+//! Prompts that explain the `<promise>...</promise>` convention often get
+//! echoed back, e.g. in a code block showing what the agent will print once
+//! it's done. With `promise_ignore_contexts`, matches inside such markdown
+//! contexts don't count. Tool call inputs are never searched for the promise.
+//!
+//! Besides `<promise>TEXT</promise>`, the promise can be given as
+//! `<promise name="TEXT">summary</promise>`, carrying the model's summary of
+//! what it did.
+
+use std::ops::Range;
+
+use regex::Regex;
+
+use crate::config::PromiseContext;
+
+/// A promise found in assistant text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromiseMatch {
+    /// Payload of `<promise name="...">summary</promise>`, if not empty
+    pub summary: Option<String>,
+}
+
+/// Finds the promise outside the ignored contexts
+#[derive(Debug, Clone)]
+pub struct PromiseDetector {
+    regex: Regex,
+    ignore: Vec<PromiseContext>,
+}
+
+impl PromiseDetector {
+    /// Create a detector for `promise`, skipping matches in `ignore`
+    pub fn new(promise: &str, ignore: &[PromiseContext]) -> Self {
+        let promise = regex::escape(promise);
+        let regex = Regex::new(&format!(
+            r#"<promise>{promise}</promise>|<promise\s+name\s*=\s*"{promise}"\s*>(?s:(.*?))</promise>"#
+        ))
+        .expect("Invalid promise regex");
+        Self {
+            regex,
+            ignore: ignore.to_vec(),
+        }
+    }
+
+    /// Whether `text` contains the promise outside the ignored contexts
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// The last promise in `text` outside the ignored contexts
+    pub fn find(&self, text: &str) -> Option<PromiseMatch> {
+        let ignored = if self.ignore.is_empty() {
+            Vec::new()
+        } else {
+            ignored_ranges(text, &self.ignore)
+        };
+        self.regex
+            .captures_iter(text)
+            .filter(|captures| {
+                let found = captures.get(0).expect("whole match");
+                !ignored
+                    .iter()
+                    .any(|range| range.start <= found.start() && found.end() <= range.end)
+            })
+            .last()
+            .map(|captures| PromiseMatch {
+                summary: captures
+                    .get(1)
+                    .map(|summary| summary.as_str().trim())
+                    .filter(|summary| !summary.is_empty())
+                    .map(str::to_string),
+            })
+    }
+}
+
+/// What `prompt` lacks to tell the agent how to end the loop with `promise`
+pub fn lint_prompt(prompt: &str, promise: &str) -> Option<String> {
+    let tag = prompt.contains("<promise");
+    match (prompt.contains(promise), tag) {
+        (true, true) => None,
+        (true, false) => Some("The prompt never mentions the <promise> tag".to_string()),
+        (false, true) => Some(format!(
+            "The prompt never mentions the completion promise \"{promise}\""
+        )),
+        (false, false) => Some(format!(
+            "The prompt mentions neither the completion promise \"{promise}\" nor the <promise> tag"
+        )),
+    }
+}
+
+/// Instructions appended to every prompt with `inject_promise_instructions`,
+/// unless `promise_instructions` replaces them. Placeholders: `{promise}`,
+/// `{iteration}` and `{iteration_reminder}`.
+pub const DEFAULT_INSTRUCTIONS: &str = "\
+## Completing the task
+
+When the task is completely done, print exactly:
+<promise>{promise}</promise>
+
+Done means every requirement of the task is met and verified, e.g. the tests \
+pass. Don't print the promise to stop early, for a partial result or to ask \
+a question: the work continues in a new session either way, and a false \
+promise ends it for good. To also summarize what you did, print \
+<promise name=\"{promise}\">summary</promise> instead.
+
+{iteration_reminder}
+";
+
+/// The promise instructions for `iteration` of a run limited to
+/// `max_iterations`, from `template` or the default
+pub fn instructions(
+    template: Option<&str>,
+    promise: &str,
+    iteration: u32,
+    max_iterations: Option<u32>,
+) -> String {
+    let reminder = match max_iterations {
+        Some(max) => format!(
+            "This is session {iteration} of at most {max} (sessions left after this one: {}).",
+            max.saturating_sub(iteration)
+        ),
+        None => format!("This is session {iteration}; there is no session limit."),
+    };
+    let text = template
+        .unwrap_or(DEFAULT_INSTRUCTIONS)
+        .replace("{promise}", promise)
+        .replace("{iteration_reminder}", &reminder)
+        .replace("{iteration}", &iteration.to_string());
+    format!("\n\n{}\n", text.trim())
+}
+
+/// Byte ranges of `text` inside any of the `contexts`
+fn ignored_ranges(text: &str, contexts: &[PromiseContext]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    // Opening fence and where it started, while inside a fenced block
+    let mut fence: Option<(&str, usize)> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let trimmed = line.trim_start();
+
+        if let Some((marker, fence_start)) = fence {
+            if trimmed.trim_end() == marker {
+                fence = None;
+                if contexts.contains(&PromiseContext::CodeFence) {
+                    ranges.push(fence_start..offset);
+                }
+            }
+            continue;
+        }
+        if let Some(marker) = ["```", "~~~"]
+            .into_iter()
+            .find(|marker| trimmed.starts_with(marker))
+        {
+            fence = Some((marker, start));
+            continue;
+        }
+        if contexts.contains(&PromiseContext::BlockQuote) && trimmed.starts_with('>') {
+            ranges.push(start..offset);
+            continue;
+        }
+        if contexts.contains(&PromiseContext::InlineCode) {
+            ranges.extend(inline_code(line).map(|span| start + span.start..start + span.end));
+        }
+    }
+    // An unclosed fence runs to the end of the text
+    if let Some((_, fence_start)) = fence {
+        if contexts.contains(&PromiseContext::CodeFence) {
+            ranges.push(fence_start..text.len());
+        }
+    }
+    ranges
+}
+
+/// Byte ranges of the backtick code spans in `line`, backticks included
+fn inline_code(line: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut rest = 0;
+    std::iter::from_fn(move || {
+        let open = rest + line[rest..].find('`')?;
+        let close = open + 1 + line[open + 1..].find('`')?;
+        rest = close + 1;
+        Some(open..close + 1)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [PromiseContext; 3] = [
+        PromiseContext::CodeFence,
+        PromiseContext::InlineCode,
+        PromiseContext::BlockQuote,
+    ];
+
+    #[test]
+    fn matches_everywhere_without_ignored_contexts() {
+        let detector = PromiseDetector::new("DONE", &[]);
+
+        assert!(detector.is_match("```\n<promise>DONE</promise>\n```"));
+        assert!(!detector.is_match("<promise>NOT DONE</promise>"));
+    }
+
+    #[test]
+    fn skips_matches_in_ignored_contexts() {
+        let detector = PromiseDetector::new("DONE", &ALL);
+
+        for text in [
+            "When finished I'll print:\n```text\n<promise>DONE</promise>\n```\nStarting now.",
+            "  ~~~\n<promise>DONE</promise>",
+            "I'll print `<promise>DONE</promise>` at the end",
+            "The prompt says:\n> Print <promise>DONE</promise> when done\n",
+        ] {
+            assert!(!detector.is_match(text), "{text}");
+        }
+        for text in [
+            "All tests pass.\n<promise>DONE</promise>",
+            "```\ncargo test\n```\n<promise>DONE</promise>",
+            "`cargo test` passes <promise>DONE</promise>",
+            "> quoted\n\n<promise>DONE</promise>",
+        ] {
+            assert!(detector.is_match(text), "{text}");
+        }
+    }
+
+    #[test]
+    fn captures_the_summary_of_a_named_promise() {
+        let detector = PromiseDetector::new("TASK COMPLETE", &[PromiseContext::CodeFence]);
+
+        assert_eq!(
+            detector.find("<promise>TASK COMPLETE</promise>"),
+            Some(PromiseMatch { summary: None })
+        );
+        assert_eq!(
+            detector.find(
+                "Done.\n<promise name=\"TASK COMPLETE\">\n  Fixed the parser.\n  Added tests.\n</promise>"
+            ),
+            Some(PromiseMatch {
+                summary: Some("Fixed the parser.\n  Added tests.".to_string())
+            })
+        );
+        assert_eq!(
+            detector.find(r#"<promise name="TASK COMPLETE"></promise>"#),
+            Some(PromiseMatch { summary: None })
+        );
+        assert_eq!(detector.find(r#"<promise name="OTHER">x</promise>"#), None);
+        // An echoed example doesn't supply the summary
+        assert_eq!(
+            detector.find(
+                "<promise name=\"TASK COMPLETE\">real</promise>\n```\n<promise name=\"TASK COMPLETE\">example</promise>\n```"
+            ),
+            Some(PromiseMatch {
+                summary: Some("real".to_string())
+            })
+        );
+    }
+
+    #[test]
+    fn only_configured_contexts_are_skipped() {
+        let detector = PromiseDetector::new("DONE", &[PromiseContext::CodeFence]);
+
+        assert!(!detector.is_match("```\n<promise>DONE</promise>\n```"));
+        assert!(detector.is_match("`<promise>DONE</promise>`"));
+        assert!(detector.is_match("> <promise>DONE</promise>"));
+    }
+
+    #[test]
+    fn lint_names_what_the_prompt_lacks() {
+        let lint = |prompt: &str| lint_prompt(prompt, "DONE");
+
+        assert_eq!(lint("Print <promise>DONE</promise> when finished"), None);
+        assert!(lint("Fix the bug").unwrap().contains("neither"));
+        assert!(lint("Say DONE when finished")
+            .unwrap()
+            .contains("<promise> tag"));
+        assert!(lint("Print <promise>FINISHED</promise>")
+            .unwrap()
+            .contains("\"DONE\""));
+    }
+
+    #[test]
+    fn injected_instructions_satisfy_the_lint_and_the_detector() {
+        let prompt = format!("Fix the bug{}", instructions(None, "ALL DONE", 3, Some(10)));
+
+        assert_eq!(lint_prompt(&prompt, "ALL DONE"), None);
+        assert!(prompt.contains("session 3 of at most 10 (sessions left after this one: 7)"));
+        // The example in the instructions is on a line of its own
+        let example = prompt.lines().find(|line| line.starts_with("<promise>"));
+        assert!(PromiseDetector::new("ALL DONE", &[]).is_match(example.unwrap()));
+    }
+
+    #[test]
+    fn custom_instructions_fill_in_the_placeholders() {
+        let template = "Say <promise>{promise}</promise> (try {iteration}). {iteration_reminder}";
+
+        assert_eq!(
+            instructions(Some(template), "OK", 2, None),
+            "\n\nSay <promise>OK</promise> (try 2). This is session 2; there is no session limit.\n"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Quiet hours during which no iteration is started.
+//!
+//! With `quiet_hours = "23:00-07:00"`, the loop finishes the running
+//! iteration and then waits until the window ends before starting the next
+//! one. Times are local; a window whose end is before its start spans
+//! midnight.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+/// Status recorded in the run metadata while the loop waits
+pub const PAUSED_REASON: &str = "quiet hours";
+
+/// A daily time window, `HH:MM-HH:MM`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietHours {
+    /// Whether `time` falls inside the window
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// How long until the window ends, if `now` is inside it
+    pub fn remaining(&self, now: NaiveDateTime) -> Option<Duration> {
+        if !self.contains(now.time()) {
+            return None;
+        }
+        let mut end = now.date().and_time(self.end);
+        if end <= now {
+            end += chrono::Duration::days(1);
+        }
+        (end - now).to_std().ok()
+    }
+}
+
+impl FromStr for QuietHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid quiet_hours \"{s}\", expected HH:MM-HH:MM");
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let parse = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").map_err(|_| invalid());
+        let quiet_hours = Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+        if quiet_hours.start == quiet_hours.end {
+            return Err(format!("quiet_hours \"{s}\" is an empty window"));
+        }
+        Ok(quiet_hours)
+    }
+}
+
+impl TryFrom<String> for QuietHours {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<QuietHours> for String {
+    fn from(quiet_hours: QuietHours) -> Self {
+        quiet_hours.to_string()
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:02}:{:02}-{:02}:{:02}",
+            self.start.hour(),
+            self.start.minute(),
+            self.end.hour(),
+            self.end.minute()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 3, 1)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn window_spanning_midnight() {
+        let quiet: QuietHours = "23:00-07:00".parse().unwrap();
+
+        assert!(quiet.contains(at(23, 30).time()));
+        assert!(quiet.contains(at(3, 0).time()));
+        assert!(!quiet.contains(at(7, 0).time()));
+        assert!(!quiet.contains(at(12, 0).time()));
+        assert_eq!(
+            quiet.remaining(at(23, 30)),
+            Some(Duration::from_secs(7 * 3600 + 30 * 60))
+        );
+        assert_eq!(
+            quiet.remaining(at(6, 45)),
+            Some(Duration::from_secs(15 * 60))
+        );
+        assert_eq!(quiet.remaining(at(12, 0)), None);
+        assert_eq!(quiet.to_string(), "23:00-07:00");
+    }
+
+    #[test]
+    fn window_within_a_day() {
+        let quiet: QuietHours = "12:00-13:30".parse().unwrap();
+
+        assert!(quiet.contains(at(12, 0).time()));
+        assert!(!quiet.contains(at(13, 30).time()));
+        assert_eq!(
+            quiet.remaining(at(13, 0)),
+            Some(Duration::from_secs(30 * 60))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_windows() {
+        assert!("23:00".parse::<QuietHours>().is_err());
+        assert!("25:00-07:00".parse::<QuietHours>().is_err());
+        assert!("07:00-07:00".parse::<QuietHours>().is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Explanations of why iterations and runs ended.
+//!
+//! The end reasons in the metadata are terse; next to each, `reason_detail`
+//! says in words what the decision was based on, e.g. `killed: 182,340
+//! tokens ≥ limit 180,000 at line 412`. Line numbers are those of the
+//! timeline. `ralph-loop runs` shows the detail of each finished run.
+
+use crate::agent::AgentResult;
+use crate::text;
+use crate::timeline::{KillReason, TimelineEvent};
+use crate::transcript::IterationEndReason;
+
+/// How much of the agent's stderr a failed iteration is explained with
+const MAX_STDERR_CHARS: usize = 200;
+
+/// `count` with its thousands separated by commas, e.g. `182,340`
+pub fn count(count: usize) -> String {
+    let digits = count.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// Why an iteration ended with `reason`, from what the monitor recorded in
+/// `result`; `max_tokens` is the context limit of the iteration and
+/// `promise` the completion promise
+pub fn iteration_end(
+    reason: &IterationEndReason,
+    result: &AgentResult,
+    max_tokens: usize,
+    promise: &str,
+) -> String {
+    let kill = result
+        .timeline
+        .iter()
+        .rev()
+        .find_map(|entry| match entry.event {
+            TimelineEvent::KillSent {
+                reason,
+                line,
+                tokens,
+            } => Some((reason, line, tokens)),
+            _ => None,
+        });
+    let promise_line = result.timeline.iter().find_map(|entry| match entry.event {
+        TimelineEvent::PromiseDetected { line } => Some(line),
+        _ => None,
+    });
+    let limit = count(max_tokens);
+    match reason {
+        IterationEndReason::ContextLimit => match kill {
+            Some((KillReason::ProjectedContextLimit, line, tokens)) => format!(
+                "killed: next turn projected past limit {limit}{} at line {line}",
+                tokens
+                    .map(|tokens| format!(" from {} tokens", count(tokens)))
+                    .unwrap_or_default()
+            ),
+            Some((_, line, Some(tokens))) => {
+                format!(
+                    "killed: {} tokens ≥ limit {limit} at line {line}",
+                    count(tokens)
+                )
+            }
+            _ => format!("killed: context limit {limit} reached"),
+        },
+        IterationEndReason::PromiseFound => {
+            let promise = result.promise_found.as_deref().unwrap_or(promise);
+            let stopped = matches!(kill, Some((KillReason::Promise, _, _)));
+            match (promise_line, stopped) {
+                (Some(line), true) => {
+                    format!("stopped: promise \"{promise}\" found at line {line}")
+                }
+                (Some(line), false) => format!("promise \"{promise}\" found at line {line}"),
+                (None, _) => format!("promise \"{promise}\" found"),
+            }
+        }
+        IterationEndReason::RestartRequested => match kill {
+            Some((KillReason::RestartRequested, line, _)) => {
+                format!("stopped: the agent asked for a fresh context at line {line}")
+            }
+            _ => "stopped: the agent asked for a fresh context".to_string(),
+        },
+        IterationEndReason::Interrupted => {
+            "interrupted: ralph-loop was shut down during the iteration".to_string()
+        }
+        IterationEndReason::Normal => {
+            let mut detail = match result.subprocess.as_ref().and_then(|s| s.exit_code) {
+                Some(code) => format!("agent exited with code {code} without the promise"),
+                None => "agent ended without the promise".to_string(),
+            };
+            if result.promise_mentions > 0 {
+                detail.push_str(&format!(
+                    " (mentioned {} time(s) without counting)",
+                    result.promise_mentions
+                ));
+            }
+            detail
+        }
+        IterationEndReason::Error => {
+            let mut detail = match result.subprocess.as_ref() {
+                Some(subprocess) => match subprocess.exit_code {
+                    Some(code) => format!("agent failed: exited with code {code}"),
+                    None => "agent failed: terminated by a signal".to_string(),
+                },
+                None => "agent failed".to_string(),
+            };
+            if let Some(line) = result.stderr.lines().map(str::trim).find(|l| !l.is_empty()) {
+                detail.push_str(&format!(", {}", text::shorten(line, MAX_STDERR_CHARS)));
+            }
+            detail
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::SubprocessRecord;
+    use crate::timeline::TimelineEntry;
+    use chrono::Utc;
+
+    fn result(events: Vec<TimelineEvent>) -> AgentResult {
+        AgentResult {
+            timeline: events.into_iter().map(TimelineEntry::now).collect(),
+            ..AgentResult::without_promise()
+        }
+    }
+
+    #[test]
+    fn counts_are_grouped_by_thousands() {
+        assert_eq!(count(0), "0");
+        assert_eq!(count(999), "999");
+        assert_eq!(count(1_000), "1,000");
+        assert_eq!(count(182_340), "182,340");
+        assert_eq!(count(12_345_678), "12,345,678");
+    }
+
+    #[test]
+    fn kills_name_the_tokens_limit_and_line() {
+        let killed = result(vec![TimelineEvent::KillSent {
+            reason: KillReason::ContextLimit,
+            line: 412,
+            tokens: Some(182_340),
+        }]);
+        assert_eq!(
+            iteration_end(&IterationEndReason::ContextLimit, &killed, 180_000, "DONE"),
+            "killed: 182,340 tokens ≥ limit 180,000 at line 412"
+        );
+
+        let projected = result(vec![TimelineEvent::KillSent {
+            reason: KillReason::ProjectedContextLimit,
+            line: 90,
+            tokens: Some(150_000),
+        }]);
+        assert_eq!(
+            iteration_end(
+                &IterationEndReason::ContextLimit,
+                &projected,
+                180_000,
+                "DONE"
+            ),
+            "killed: next turn projected past limit 180,000 from 150,000 tokens at line 90"
+        );
+    }
+
+    #[test]
+    fn promises_and_failures_are_explained() {
+        let found = result(vec![
+            TimelineEvent::PromiseDetected { line: 7 },
+            TimelineEvent::KillSent {
+                reason: KillReason::Promise,
+                line: 7,
+                tokens: None,
+            },
+        ]);
+        assert_eq!(
+            iteration_end(&IterationEndReason::PromiseFound, &found, 180_000, "DONE"),
+            "stopped: promise \"DONE\" found at line 7"
+        );
+
+        let failed = AgentResult {
+            subprocess: Some(SubprocessRecord {
+                kind: "agent".to_string(),
+                program: "claude".to_string(),
+                args: Vec::new(),
+                pid: None,
+                started_at: Utc::now(),
+                exit_code: Some(1),
+                duration_ms: 10,
+            }),
+            stderr: "\nError: invalid API key\nmore\n".to_string(),
+            ..AgentResult::without_promise()
+        };
+        assert_eq!(
+            iteration_end(&IterationEndReason::Error, &failed, 180_000, "DONE"),
+            "agent failed: exited with code 1, Error: invalid API key"
+        );
+
+        let mentioned = AgentResult {
+            promise_mentions: 2,
+            ..AgentResult::without_promise()
+        };
+        assert_eq!(
+            iteration_end(&IterationEndReason::Normal, &mentioned, 180_000, "DONE"),
+            "agent ended without the promise (mentioned 2 time(s) without counting)"
+        );
+    }
+}
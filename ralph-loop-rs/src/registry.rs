@@ -0,0 +1,168 @@
+//! Global run store and the per-project run registry.
+//!
+//! By default every project keeps its runs in its own `.ralph-loop-output`.
+//! With the global store enabled, runs from all projects go to `RALPH_HOME`
+//! (or `~/.local/share/ralph`), and `registry.jsonl` maps each run to the
+//! project it belongs to.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{RalphError, Result};
+use crate::transcript::RunMetadata;
+
+/// Name of the registry file inside an output directory
+pub const REGISTRY_FILE: &str = "registry.jsonl";
+
+/// Location of the global run store: `$RALPH_HOME`, else `<data dir>/ralph`
+pub fn ralph_home() -> Option<PathBuf> {
+    if let Some(home) = std::env::var_os("RALPH_HOME").filter(|h| !h.is_empty()) {
+        return Some(PathBuf::from(home));
+    }
+    dirs::data_dir().map(|dir| dir.join("ralph"))
+}
+
+/// One line of the registry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    pub run_id: String,
+    pub project_path: String,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Record a run in the registry of `output_dir`
+pub fn register_run(output_dir: &Path, metadata: &RunMetadata) -> Result<()> {
+    let entry = RegistryEntry {
+        run_id: metadata.run_id.clone(),
+        project_path: metadata.project_path.clone(),
+        started_at: metadata.started_at,
+    };
+    let line = serde_json::to_string(&entry)
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(output_dir.join(REGISTRY_FILE))
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+    writeln!(file, "{line}").map_err(|e| RalphError::TranscriptWriteError(e.to_string()))
+}
+
+/// List runs in `output_dir`, oldest first, optionally only those of `project_path`.
+///
+/// Uses the registry when present and falls back to scanning `runs/` for
+/// output directories written before the registry existed.
+pub fn list_runs(output_dir: &Path, project_path: Option<&str>) -> Result<Vec<RunMetadata>> {
+    let runs_dir = output_dir.join("runs");
+    let run_ids = match fs::read_to_string(output_dir.join(REGISTRY_FILE)) {
+        Ok(content) => content
+            .lines()
+            .filter_map(|line| serde_json::from_str::<RegistryEntry>(line).ok())
+            .filter(|entry| project_path.is_none_or(|p| entry.project_path == p))
+            .map(|entry| entry.run_id)
+            .collect::<Vec<_>>(),
+        Err(_) => match fs::read_dir(&runs_dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .collect(),
+            Err(_) => Vec::new(),
+        },
+    };
+
+    let mut runs: Vec<RunMetadata> = run_ids
+        .iter()
+        .filter_map(|run_id| RunMetadata::load(&runs_dir.join(run_id)).ok())
+        .filter(|metadata| project_path.is_none_or(|p| metadata.project_path == p))
+        .collect();
+    runs.sort_by_key(|metadata| metadata.started_at);
+    runs.dedup_by(|a, b| a.run_id == b.run_id);
+    Ok(runs)
+}
+
+/// Delete the directory of the run `run_id` in `output_dir` and drop it
+/// from the registry
+pub fn remove_run(output_dir: &Path, run_id: &str) -> Result<()> {
+    fs::remove_dir_all(output_dir.join("runs").join(run_id)).map_err(RalphError::OutputDirError)?;
+    let registry = output_dir.join(REGISTRY_FILE);
+    let Ok(content) = fs::read_to_string(&registry) else {
+        return Ok(());
+    };
+    let kept: String = content
+        .lines()
+        .filter(|line| {
+            serde_json::from_str::<RegistryEntry>(line).map_or(true, |entry| entry.run_id != run_id)
+        })
+        .map(|line| format!("{line}\n"))
+        .collect();
+    fs::write(&registry, kept).map_err(RalphError::OutputDirError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AgentProvider;
+    use crate::transcript::TranscriptWriter;
+    use tempfile::TempDir;
+
+    fn start_run(output_dir: &Path, project: &Path, run_id: &str) {
+        TranscriptWriter::new(
+            output_dir,
+            project,
+            "prompt",
+            None,
+            AgentProvider::Claude,
+            "DONE".to_string(),
+            Some(run_id.to_string()),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn lists_runs_per_project_and_across_projects() {
+        let store = TempDir::new().unwrap();
+        let project_a = TempDir::new().unwrap();
+        let project_b = TempDir::new().unwrap();
+        start_run(store.path(), project_a.path(), "run-a1");
+        start_run(store.path(), project_b.path(), "run-b1");
+        start_run(store.path(), project_a.path(), "run-a2");
+
+        let project_a_path = project_a.path().canonicalize().unwrap();
+        let project_a_runs =
+            list_runs(store.path(), Some(&project_a_path.to_string_lossy())).unwrap();
+        let ids: Vec<_> = project_a_runs.iter().map(|m| m.run_id.as_str()).collect();
+        assert_eq!(ids, vec!["run-a1", "run-a2"]);
+
+        assert_eq!(list_runs(store.path(), None).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn removed_runs_leave_the_registry() {
+        let store = TempDir::new().unwrap();
+        start_run(store.path(), store.path(), "run-1");
+        start_run(store.path(), store.path(), "run-2");
+
+        remove_run(store.path(), "run-1").unwrap();
+
+        assert!(!store.path().join("runs/run-1").exists());
+        let registry = fs::read_to_string(store.path().join(REGISTRY_FILE)).unwrap();
+        assert!(!registry.contains("run-1") && registry.contains("run-2"));
+        assert!(remove_run(store.path(), "run-1").is_err());
+    }
+
+    #[test]
+    fn falls_back_to_scanning_runs_without_registry() {
+        let store = TempDir::new().unwrap();
+        start_run(store.path(), store.path(), "run-1");
+        fs::remove_file(store.path().join(REGISTRY_FILE)).unwrap();
+
+        let runs = list_runs(store.path(), None).unwrap();
+
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].run_id, "run-1");
+    }
+}
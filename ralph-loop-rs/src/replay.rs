@@ -0,0 +1,260 @@
+//! Replay of recorded agent output streams.
+//!
+//! `ReplayAgent` feeds recorded `stream-json` captures through the same
+//! `JsonEventMonitor` the real agent uses, so parser and promise-detection
+//! behaviour can be exercised without spawning a coding agent.
+//!
+//! `ralph-loop replay <run-id>` does the same for the output a past run
+//! kept with `keep_output`, running it through the loop controller to show
+//! why the loop decided what it did.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use tokio::io::BufReader;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::agent::{for_iteration, Agent, AgentResult, ExitReason, IterationSettings};
+use crate::config::Config;
+use crate::error::{RalphError, Result};
+use crate::loop_controller::{LoopController, LoopResult};
+use crate::monitor::{JsonEventMonitor, ProcessCommand};
+use crate::state::SharedState;
+use crate::transcript::RunMetadata;
+use crate::transcript_sources::{self, TranscriptSource};
+
+/// A replayed invocation
+#[derive(Debug, Clone)]
+pub struct IterationReplay {
+    /// The capture that was replayed
+    pub capture: PathBuf,
+    /// Where the capture was found, for the output of a past run
+    pub source: Option<TranscriptSource>,
+    /// What the monitor made of it
+    pub result: AgentResult,
+}
+
+/// Agent that replays one recorded capture per invocation.
+///
+/// Captures are used in order; once exhausted, the last one is repeated.
+pub struct ReplayAgent {
+    config: Arc<Config>,
+    captures: Vec<PathBuf>,
+    next: AtomicUsize,
+    replayed: Mutex<Vec<IterationReplay>>,
+}
+
+impl ReplayAgent {
+    /// Create a ReplayAgent over the given capture files
+    pub fn new(config: Arc<Config>, captures: Vec<PathBuf>) -> Self {
+        Self {
+            config,
+            captures,
+            next: AtomicUsize::new(0),
+            replayed: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The invocations replayed so far, in order
+    pub fn replayed(&self) -> Vec<IterationReplay> {
+        self.replayed.lock().expect("replay lock poisoned").clone()
+    }
+
+    /// Replay the next capture through a monitor with `config`
+    async fn replay_next(&self, config: Arc<Config>) -> Result<AgentResult> {
+        let index = self.next.fetch_add(1, Ordering::SeqCst);
+        let path = self
+            .captures
+            .get(index)
+            .or_else(|| self.captures.last())
+            .ok_or_else(|| RalphError::ConfigError("no replay captures given".to_string()))?;
+
+        debug!("Replaying capture {}", path.display());
+        let capture = std::fs::read_to_string(path).map_err(RalphError::ProcessIoError)?;
+        let result = Self::replay(config, &capture).await?;
+        self.replayed
+            .lock()
+            .expect("replay lock poisoned")
+            .push(IterationReplay {
+                capture: path.clone(),
+                source: None,
+                result: result.clone(),
+            });
+        Ok(result)
+    }
+
+    /// Replay a single capture's contents through a fresh monitor
+    pub async fn replay(config: Arc<Config>, capture: &str) -> Result<AgentResult> {
+        let state = SharedState::new_shared();
+        let (cmd_tx, mut cmd_rx) = mpsc::channel::<ProcessCommand>(1);
+
+        let mut monitor = JsonEventMonitor::new(config, Arc::clone(&state), cmd_tx)?;
+        let mut reader = BufReader::new(capture.as_bytes());
+        monitor.monitor_stream(&mut reader).await?;
+
+        // A recorded stream has no process to kill; a requested stop is
+        // reported the same way the real agent would report it.
+        let exit_reason = match cmd_rx.try_recv() {
+            Ok(ProcessCommand::Kill) => ExitReason::ContextLimit,
+            Ok(ProcessCommand::StopOnPromise) => ExitReason::PromiseFound,
+            Ok(ProcessCommand::Restart) => ExitReason::RestartRequested,
+            Err(_) => ExitReason::Natural,
+        };
+
+        let snapshot = state.snapshot().await;
+        let result = AgentResult {
+            output: state.take_output().await,
+            promise_found: snapshot.promise,
+            token_count: snapshot.token_count,
+            exit_reason,
+            session_id: None,
+            token_usage: None,
+            cost_usd: None,
+            timings: None,
+            subprocess: None,
+            progress: None,
+            commands: Vec::new(),
+            text: String::new(),
+            tool_calls: Vec::new(),
+            stderr: String::new(),
+            event_count: 0,
+            unparsed: Vec::new(),
+            unparsed_dropped: 0,
+            unknown: Vec::new(),
+            promise_mentions: 0,
+            promise_summary: None,
+            milestones: Vec::new(),
+            timeline: Vec::new(),
+            tool_spans: Vec::new(),
+            subagent_usage: None,
+        };
+        Ok(result.with_monitor_result(monitor.result()))
+    }
+}
+
+#[async_trait]
+impl Agent for ReplayAgent {
+    async fn run(&self, _prompt: &str) -> Result<AgentResult> {
+        self.replay_next(Arc::clone(&self.config)).await
+    }
+
+    async fn run_iteration(
+        &self,
+        _prompt: &str,
+        _env: &[(String, String)],
+        settings: &IterationSettings,
+    ) -> Result<AgentResult> {
+        self.replay_next(for_iteration(&self.config, settings))
+            .await
+    }
+}
+
+/// Replay the output of the run in `run_dir` through the loop controller,
+/// returning each replayed iteration and the loop's outcome. The output of
+/// each iteration is looked for in the run directory, Claude's project
+/// directory and `transcript_search_path`, in that order. Settings that
+/// act on the project or only delay the loop, such as `classify_command`
+/// and the plan file, are turned off.
+pub async fn replay_run(
+    mut config: Config,
+    run_dir: &Path,
+) -> Result<(Vec<IterationReplay>, Result<LoopResult>)> {
+    let metadata = RunMetadata::load(run_dir)?;
+    if metadata.iterations.is_empty() {
+        return Err(RalphError::ReplayError(format!(
+            "run '{}' has no iterations to replay",
+            metadata.run_id
+        )));
+    }
+    let sources = TranscriptSource::ordered(&config.transcript_search_path);
+    let transcripts = transcript_sources::resolve(&sources, run_dir, &metadata).map_err(|e| {
+        RalphError::ReplayError(format!(
+            "{e}\nset keep_output = true to keep the agent output in the run directory"
+        ))
+    })?;
+    let captures: Vec<PathBuf> = transcripts.iter().map(|t| t.path.clone()).collect();
+    config.max_iterations = Some(captures.len() as u32);
+    config.classify_command = None;
+    config.plan_file = None;
+    config.quiet_hours = None;
+    config.disk_space.min_free_mb = 0;
+    config.network.wait_when_offline = false;
+    config.iteration_delay_secs = 0;
+    config.iteration_delay_jitter_secs = 0;
+
+    let agent = ReplayAgent::new(Arc::new(config.clone()), captures);
+    let controller = LoopController::new(config, agent);
+    let outcome = controller.run().await;
+    let mut replayed = controller.agent().replayed();
+    for (iteration, transcript) in replayed.iter_mut().zip(transcripts) {
+        iteration.source = Some(transcript.source);
+    }
+    Ok((replayed, outcome))
+}
+
+/// Describe what the monitor decided in each replayed iteration and how
+/// the loop ended
+pub fn render(iterations: &[IterationReplay], outcome: &Result<LoopResult>) -> String {
+    let mut text = String::new();
+    for (index, iteration) in iterations.iter().enumerate() {
+        let result = &iteration.result;
+        let ended = match result.exit_reason {
+            ExitReason::Natural => "output ended",
+            ExitReason::ContextLimit => "stopped at the context limit",
+            ExitReason::PromiseFound => "stopped on the promise",
+            ExitReason::RestartRequested => "stopped for a restart",
+            ExitReason::Shutdown => "shut down",
+            ExitReason::Error => "failed",
+        };
+        text.push_str(&format!(
+            "Iteration {} ({}{}): {} events, {} unparsed lines, {} tokens, {}\n",
+            index + 1,
+            iteration
+                .source
+                .as_ref()
+                .map(|source| format!("{source}: "))
+                .unwrap_or_default(),
+            iteration.capture.display(),
+            result.event_count,
+            result.unparsed.len(),
+            result.token_count,
+            ended
+        ));
+        for entry in &result.timeline {
+            text.push_str(&format!("  {}\n", entry.event));
+        }
+        if result.promise_mentions > 0 && result.promise_found.is_none() {
+            text.push_str(&format!(
+                "  promise mentioned {} time(s) without counting\n",
+                result.promise_mentions
+            ));
+        }
+    }
+    text.push_str(&match outcome {
+        Ok(LoopResult::PromiseFulfilled {
+            iterations,
+            promise,
+            ..
+        }) => format!("Promise \"{promise}\" fulfilled after {iterations} iteration(s)"),
+        Ok(LoopResult::PlanCompleted { iterations, .. }) => {
+            format!("Plan completed after {iterations} iteration(s)")
+        }
+        Ok(LoopResult::Shutdown { iterations }) => {
+            format!("Shut down after {iterations} iteration(s)")
+        }
+        Ok(LoopResult::Failed {
+            iterations,
+            consecutive_errors,
+        }) => format!(
+            "Failed after {iterations} iteration(s), {consecutive_errors} in a row ended in an error"
+        ),
+        Err(RalphError::MaxIterationsExceeded(iterations)) => {
+            format!("No promise in {iterations} iteration(s)")
+        }
+        Err(e) => format!("Stopped: {e}"),
+    });
+    text
+}
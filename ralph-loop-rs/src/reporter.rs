@@ -0,0 +1,350 @@
+//! Reporting finished runs to a central server.
+//!
+//! When `[reporter] url` is set, the metadata of each finished run is POSTed
+//! as JSON so a team dashboard can aggregate ralph activity across machines.
+//! Reports that can't be delivered after the configured retries are appended
+//! to a queue file and resent, oldest first, after the next run.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use tracing::{info, warn};
+
+use crate::config::ReporterConfig;
+use crate::error::{RalphError, Result};
+use crate::transcript::RunMetadata;
+
+/// Default name of the queue file inside the output directory
+pub const QUEUE_FILE: &str = "reporter-queue.jsonl";
+
+/// Body of a report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunReport {
+    /// Host the run was made on
+    pub host: Option<String>,
+    /// Version of ralph-loop that made the run
+    pub ralph_version: String,
+    /// Tokens used across all iterations
+    pub total_tokens: usize,
+    /// Cost across all iterations, if the backend reported one
+    pub total_cost_usd: Option<f64>,
+    /// Wall-clock duration of the run, if it finished
+    pub duration_secs: Option<i64>,
+    /// Full run metadata
+    pub metadata: RunMetadata,
+}
+
+impl RunReport {
+    /// Build the report for finished run metadata
+    pub fn new(metadata: RunMetadata) -> Self {
+        Self {
+            host: hostname(),
+            ralph_version: crate::VERSION.to_string(),
+            total_tokens: metadata.total_tokens(),
+            total_cost_usd: metadata.total_cost_usd(),
+            duration_secs: metadata
+                .completed_at
+                .map(|completed| (completed - metadata.started_at).num_seconds()),
+            metadata,
+        }
+    }
+}
+
+fn hostname() -> Option<String> {
+    let output = Command::new("hostname").output().ok()?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !name.is_empty()).then_some(name)
+}
+
+/// Sends run reports, queueing the ones that can't be delivered
+#[derive(Debug, Clone)]
+pub struct Reporter {
+    url: String,
+    token: Option<String>,
+    retries: u32,
+    queue_file: PathBuf,
+    retry_delay: Duration,
+}
+
+impl Reporter {
+    /// Create a reporter if `[reporter] url` is set
+    pub fn from_config(config: &ReporterConfig, output_dir: &Path) -> Option<Self> {
+        let url = config.url.clone()?;
+        Some(Self {
+            url,
+            token: std::env::var(&config.token_env)
+                .ok()
+                .filter(|token| !token.is_empty()),
+            retries: config.retries,
+            queue_file: config
+                .queue_file
+                .clone()
+                .unwrap_or_else(|| output_dir.join(QUEUE_FILE)),
+            retry_delay: Duration::from_secs(1),
+        })
+    }
+
+    /// Deliver queued reports, then report the run in `run_dir`.
+    /// A report that can't be delivered is queued rather than returned as an error.
+    /// Retries sleep, so call it off the async runtime.
+    pub fn report(&self, run_dir: &Path) -> Result<()> {
+        let report = RunReport::new(RunMetadata::load(run_dir)?);
+        let payload =
+            serde_json::to_string(&report).map_err(|e| RalphError::ReportError(e.to_string()))?;
+
+        if let Err(e) = self.flush_queue() {
+            warn!("Failed to deliver queued reports: {}", e);
+        }
+
+        match self.send_with_retries(&payload) {
+            Ok(()) => {
+                info!("Reported run {} to {}", report.metadata.run_id, self.url);
+                Ok(())
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to report run {}, queued in {}: {}",
+                    report.metadata.run_id,
+                    self.queue_file.display(),
+                    e
+                );
+                self.enqueue(&payload)
+            }
+        }
+    }
+
+    /// Resend queued reports in order, stopping at the first failure.
+    /// Returns how many were delivered.
+    pub fn flush_queue(&self) -> Result<usize> {
+        let content = match fs::read_to_string(&self.queue_file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(RalphError::ReportError(e.to_string())),
+        };
+        let queued: Vec<&str> = content.lines().filter(|line| !line.is_empty()).collect();
+
+        let mut delivered = 0;
+        let mut error = None;
+        for payload in &queued {
+            match self.post(payload) {
+                Ok(()) => delivered += 1,
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        if delivered > 0 {
+            let remaining: String = queued[delivered..]
+                .iter()
+                .map(|payload| format!("{payload}\n"))
+                .collect();
+            fs::write(&self.queue_file, remaining)
+                .map_err(|e| RalphError::ReportError(e.to_string()))?;
+            info!("Delivered {} queued report(s)", delivered);
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(delivered),
+        }
+    }
+
+    fn send_with_retries(&self, payload: &str) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            match self.post(payload) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= self.retries => return Err(e),
+                Err(e) => {
+                    warn!("Report attempt {} failed: {}", attempt + 1, e);
+                    std::thread::sleep(self.retry_delay * 2u32.pow(attempt.min(5)));
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    fn enqueue(&self, payload: &str) -> Result<()> {
+        if let Some(parent) = self.queue_file.parent() {
+            fs::create_dir_all(parent).map_err(RalphError::OutputDirError)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.queue_file)
+            .map_err(|e| RalphError::ReportError(e.to_string()))?;
+        writeln!(file, "{payload}").map_err(|e| RalphError::ReportError(e.to_string()))
+    }
+
+    /// POST one payload with curl. The payload goes through a private
+    /// temporary file, the token on stdin so it never shows up in the process
+    /// list.
+    fn post(&self, payload: &str) -> Result<()> {
+        let mut payload_file = NamedTempFile::with_prefix("ralph-report-")
+            .map_err(|e| RalphError::ReportError(e.to_string()))?;
+        payload_file
+            .write_all(payload.as_bytes())
+            .map_err(|e| RalphError::ReportError(e.to_string()))?;
+        self.run_curl(payload_file.path())
+    }
+
+    fn run_curl(&self, payload_file: &Path) -> Result<()> {
+        let mut child = Command::new("curl")
+            .args(["-fsS", "-X", "POST", "--max-time", "30"])
+            .args(["-H", "Content-Type: application/json", "-H", "@-"])
+            .arg("--data-binary")
+            .arg(format!("@{}", payload_file.display()))
+            .arg(&self.url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| RalphError::ReportError(format!("failed to run curl: {e}")))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Some(ref token) = self.token {
+                writeln!(stdin, "Authorization: Bearer {token}")
+                    .map_err(|e| RalphError::ReportError(e.to_string()))?;
+            }
+        }
+
+        let output = child
+            .wait_with_output()
+            .map_err(|e| RalphError::ReportError(e.to_string()))?;
+        if !output.status.success() {
+            return Err(RalphError::ReportError(format!(
+                "POST {} failed: {}",
+                self.url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AgentProvider;
+    use crate::transcript::{ExitReason, TranscriptWriter};
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use tempfile::TempDir;
+
+    fn finished_run(output_dir: &Path) -> PathBuf {
+        let mut writer = TranscriptWriter::new(
+            output_dir,
+            Path::new("/project"),
+            "prompt",
+            None,
+            AgentProvider::Claude,
+            "DONE".to_string(),
+            None,
+        )
+        .unwrap();
+        writer.start_iteration().unwrap();
+        writer
+            .complete(ExitReason::PromiseFulfilled, "promise found".to_string())
+            .unwrap();
+        writer.run_dir().to_path_buf()
+    }
+
+    fn reporter(url: String, queue_file: PathBuf) -> Reporter {
+        Reporter {
+            url,
+            token: Some("secret".to_string()),
+            retries: 1,
+            queue_file,
+            retry_delay: Duration::from_millis(1),
+        }
+    }
+
+    /// Accept `count` requests, answer 200 and return their header blocks and bodies
+    fn serve(
+        listener: TcpListener,
+        count: usize,
+    ) -> std::thread::JoinHandle<Vec<(String, String)>> {
+        std::thread::spawn(move || {
+            (0..count)
+                .map(|_| {
+                    let (stream, _) = listener.accept().unwrap();
+                    let mut reader = BufReader::new(stream);
+                    let mut headers = String::new();
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        if line == "\r\n" {
+                            break;
+                        }
+                        headers.push_str(&line);
+                    }
+                    let length: usize = headers
+                        .lines()
+                        .find_map(|line| line.strip_prefix("Content-Length: "))
+                        .unwrap()
+                        .trim()
+                        .parse()
+                        .unwrap();
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+                    reader
+                        .get_mut()
+                        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                        .unwrap();
+                    (headers, String::from_utf8(body).unwrap())
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn unreachable_server_queues_report() {
+        let temp_dir = TempDir::new().unwrap();
+        let run_dir = finished_run(temp_dir.path());
+        let queue_file = temp_dir.path().join(QUEUE_FILE);
+        // Bind and drop to get a port nobody listens on
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let reporter = reporter(format!("http://127.0.0.1:{port}/runs"), queue_file.clone());
+        reporter.report(&run_dir).unwrap();
+
+        let queued = fs::read_to_string(&queue_file).unwrap();
+        assert_eq!(queued.lines().count(), 1);
+        let report: RunReport = serde_json::from_str(queued.trim()).unwrap();
+        assert_eq!(report.metadata.iterations.len(), 1);
+        assert!(matches!(
+            reporter.flush_queue(),
+            Err(RalphError::ReportError(_))
+        ));
+    }
+
+    #[test]
+    fn delivers_queued_reports_before_new_one() {
+        let temp_dir = TempDir::new().unwrap();
+        let run_dir = finished_run(temp_dir.path());
+        let queue_file = temp_dir.path().join(QUEUE_FILE);
+        fs::write(&queue_file, "{\"queued\":true}\n").unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/runs", listener.local_addr().unwrap());
+        let server = serve(listener, 2);
+
+        reporter(url, queue_file.clone()).report(&run_dir).unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].1, "{\"queued\":true}");
+        assert!(requests[1].0.contains("Authorization: Bearer secret"));
+        let report: RunReport = serde_json::from_str(&requests[1].1).unwrap();
+        assert_eq!(report.ralph_version, crate::VERSION);
+        assert_eq!(fs::read_to_string(&queue_file).unwrap(), "");
+    }
+}
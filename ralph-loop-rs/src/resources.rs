@@ -0,0 +1,105 @@
+//! Scheduling priority and resource caps of the agent subprocess.
+//!
+//! The limits in `[agent.resources]` are applied by running the agent through
+//! standard tools, each of which execs the next, so the spawned PID stays the
+//! agent's: `systemd-run --user --scope` puts it into a transient cgroup with
+//! `MemoryMax` and `CPUQuota`, `prlimit --as` caps its address space when
+//! cgroups are turned off, and `nice -n` lowers its priority.
+
+use crate::config::ResourceLimitsConfig;
+
+/// The program and arguments that run `program` with `args` under `limits`
+pub fn wrap_command(
+    limits: &ResourceLimitsConfig,
+    program: String,
+    args: Vec<String>,
+) -> (String, Vec<String>) {
+    let mut prefix: Vec<String> = Vec::new();
+    if limits.cgroup && (limits.max_memory_mb.is_some() || limits.max_cpu_percent.is_some()) {
+        prefix
+            .extend(["systemd-run", "--user", "--scope", "--quiet", "--collect"].map(String::from));
+        if let Some(mb) = limits.max_memory_mb {
+            prefix.extend(["-p".to_string(), format!("MemoryMax={mb}M")]);
+        }
+        if let Some(percent) = limits.max_cpu_percent {
+            prefix.extend(["-p".to_string(), format!("CPUQuota={percent}%")]);
+        }
+        prefix.push("--".to_string());
+    } else if let Some(mb) = limits.max_memory_mb {
+        prefix.extend([
+            "prlimit".to_string(),
+            format!("--as={}", mb * 1024 * 1024),
+            "--".to_string(),
+        ]);
+    }
+    if let Some(nice) = limits.nice {
+        prefix.extend([
+            "nice".to_string(),
+            "-n".to_string(),
+            nice.to_string(),
+            "--".to_string(),
+        ]);
+    }
+
+    if prefix.is_empty() {
+        return (program, args);
+    }
+    let wrapper = prefix.remove(0);
+    prefix.push(program);
+    prefix.extend(args);
+    (wrapper, prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrap(limits: &ResourceLimitsConfig) -> Vec<String> {
+        let (program, args) = wrap_command(limits, "claude".to_string(), vec!["-p".to_string()]);
+        std::iter::once(program).chain(args).collect()
+    }
+
+    #[test]
+    fn limits_wrap_the_agent_command() {
+        let mut limits = ResourceLimitsConfig::default();
+        assert_eq!(wrap(&limits), ["claude", "-p"]);
+
+        limits.nice = Some(10);
+        limits.max_memory_mb = Some(4096);
+        limits.max_cpu_percent = Some(150);
+        assert_eq!(
+            wrap(&limits).join(" "),
+            "systemd-run --user --scope --quiet --collect -p MemoryMax=4096M \
+             -p CPUQuota=150% -- nice -n 10 -- claude -p"
+        );
+
+        limits.max_cpu_percent = None;
+        limits.cgroup = false;
+        assert_eq!(
+            wrap(&limits).join(" "),
+            "prlimit --as=4294967296 -- nice -n 10 -- claude -p"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn wrapped_command_runs_with_the_niceness() {
+        let niceness = |(program, args): (String, Vec<String>)| {
+            let output = std::process::Command::new(program)
+                .args(args)
+                .output()
+                .unwrap();
+            String::from_utf8_lossy(&output.stdout)
+                .trim()
+                .parse::<i32>()
+                .unwrap()
+        };
+        let limits = ResourceLimitsConfig {
+            nice: Some(5),
+            ..ResourceLimitsConfig::default()
+        };
+        let base = niceness(("nice".to_string(), Vec::new()));
+        let wrapped = niceness(wrap_command(&limits, "nice".to_string(), Vec::new()));
+        assert_eq!(wrapped, (base + 5).min(19));
+    }
+}
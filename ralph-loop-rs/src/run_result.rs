@@ -0,0 +1,282 @@
+//! The machine-readable outcome of a run.
+//!
+//! When a run ends, `runs/<id>/result.json` records how the loop ended, the
+//! promise and summary it ended with, the run's totals and the exit code of
+//! the process, so scripts don't have to parse the metadata or stdout.
+//!
+//! The exit code tells the outcomes apart for wrappers:
+//!
+//! | Code | Outcome |
+//! |------|---------|
+//! | 0    | promise found or plan completed |
+//! | 1    | max iterations exceeded, or another error |
+//! | 2    | invalid configuration or arguments |
+//! | 3    | the agent or provider kept failing |
+//! | 4    | a budget was exceeded |
+//! | 130  | interrupted |
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{RalphError, Result};
+use crate::loop_controller::LoopResult;
+use crate::plan::PlanProgress;
+use crate::transcript::RunMetadata;
+
+/// Name of the result file inside the run directory
+pub const RESULT_FILE: &str = "result.json";
+
+/// How a run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+    /// The completion promise was found
+    PromiseFulfilled,
+    /// Every item in the plan file was checked
+    PlanCompleted,
+    /// Max iterations were exhausted without the promise
+    MaxIterationsExceeded,
+    /// The run was interrupted or cancelled
+    Interrupted,
+    /// The configuration or arguments were invalid
+    ConfigError,
+    /// The agent couldn't be run, or its iterations kept failing
+    AgentFailed,
+    /// Another error aborted the run
+    Failed,
+}
+
+impl RunOutcome {
+    /// Classify the result of a loop
+    pub fn from_result(result: &Result<LoopResult>) -> Self {
+        match result {
+            Ok(LoopResult::PromiseFulfilled { .. }) => Self::PromiseFulfilled,
+            Ok(LoopResult::PlanCompleted { .. }) => Self::PlanCompleted,
+            Ok(LoopResult::Shutdown { .. }) | Err(RalphError::ShutdownRequested) => {
+                Self::Interrupted
+            }
+            Ok(LoopResult::Failed { .. }) => Self::AgentFailed,
+            Err(e) => Self::from_error(e),
+        }
+    }
+
+    /// Classify an error that ended the run, or kept it from starting
+    pub fn from_error(error: &RalphError) -> Self {
+        match error {
+            RalphError::ShutdownRequested => Self::Interrupted,
+            RalphError::MaxIterationsExceeded(_) => Self::MaxIterationsExceeded,
+            RalphError::ConfigError(_)
+            | RalphError::PromptFileError(_)
+            | RalphError::NoPromptProvided
+            | RalphError::ConsentRequired(_)
+            | RalphError::ResumeError(_) => Self::ConfigError,
+            RalphError::ProcessSpawnError(_)
+            | RalphError::ProcessIoError(_)
+            | RalphError::IncompatibleAgent(_) => Self::AgentFailed,
+            _ => Self::Failed,
+        }
+    }
+
+    /// Exit code of the process for the outcome
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::PromiseFulfilled | Self::PlanCompleted => 0,
+            Self::MaxIterationsExceeded | Self::Failed => 1,
+            Self::ConfigError => 2,
+            Self::AgentFailed => 3,
+            Self::Interrupted => 130,
+        }
+    }
+}
+
+/// Totals across all iterations of a run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunTotals {
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    /// Cost reported by the backend, if it reported one
+    pub cost_usd: Option<f64>,
+    /// Wall-clock duration, if the run was marked complete
+    pub duration_secs: Option<i64>,
+}
+
+impl RunTotals {
+    /// Totals of the run in `metadata`
+    pub fn from_metadata(metadata: &RunMetadata) -> Self {
+        let tokens = || metadata.iterations.iter().filter_map(|i| i.tokens.as_ref());
+        Self {
+            input_tokens: tokens().map(|t| t.input).sum(),
+            output_tokens: tokens().map(|t| t.output).sum(),
+            cost_usd: metadata.total_cost_usd(),
+            duration_secs: metadata
+                .completed_at
+                .map(|completed| (completed - metadata.started_at).num_seconds()),
+        }
+    }
+}
+
+/// Contents of `result.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunResult {
+    pub run_id: String,
+    pub outcome: RunOutcome,
+    /// Exit code of the process
+    pub exit_code: i32,
+    /// Iterations run
+    pub iterations: u32,
+    /// The promise that was found
+    pub promise: Option<String>,
+    /// Summary the agent gave with the promise, or the summary of the run
+    pub summary: Option<String>,
+    /// Plan file progress when the plan completed
+    pub plan: Option<PlanProgress>,
+    /// Length of the streak of failed iterations that aborted the run
+    pub consecutive_errors: Option<u32>,
+    /// Error message for runs aborted by an error
+    pub error: Option<String>,
+    pub totals: RunTotals,
+}
+
+impl RunResult {
+    /// Summarize the result of the loop that made the run in `metadata`
+    pub fn new(result: &Result<LoopResult>, metadata: &RunMetadata, exit_code: i32) -> Self {
+        let mut run_result = Self {
+            run_id: metadata.run_id.clone(),
+            outcome: RunOutcome::from_result(result),
+            exit_code,
+            iterations: metadata.iterations.len() as u32,
+            promise: None,
+            summary: metadata
+                .promise_summary
+                .clone()
+                .or_else(|| metadata.summary.clone()),
+            plan: None,
+            consecutive_errors: None,
+            error: None,
+            totals: RunTotals::from_metadata(metadata),
+        };
+        match result {
+            Ok(LoopResult::PromiseFulfilled {
+                promise, summary, ..
+            }) => {
+                run_result.promise = Some(promise.clone());
+                run_result.summary = summary.clone().or(run_result.summary);
+            }
+            Ok(LoopResult::PlanCompleted { plan, .. }) => run_result.plan = Some(*plan),
+            Ok(LoopResult::Failed {
+                consecutive_errors, ..
+            }) => run_result.consecutive_errors = Some(*consecutive_errors),
+            Ok(LoopResult::Shutdown { .. }) => {}
+            Err(e) => run_result.error = Some(e.to_string()),
+        }
+        run_result
+    }
+
+    /// Write the result into `run_dir`
+    pub fn write(&self, run_dir: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+        std::fs::write(run_dir.join(RESULT_FILE), json)
+            .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))
+    }
+
+    /// Read the result of the run in `run_dir`
+    pub fn load(run_dir: &Path) -> Result<Self> {
+        let path = run_dir.join(RESULT_FILE);
+        let json = std::fs::read_to_string(&path)
+            .map_err(|e| RalphError::TranscriptWriteError(format!("{}: {}", path.display(), e)))?;
+        serde_json::from_str(&json)
+            .map_err(|e| RalphError::TranscriptWriteError(format!("{}: {}", path.display(), e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AgentProvider;
+    use crate::transcript::{IterationEndReason, TokenUsageRecord, TranscriptWriter};
+    use tempfile::TempDir;
+
+    #[test]
+    fn results_carry_the_outcome_and_totals() {
+        let dir = TempDir::new().unwrap();
+        let mut writer = TranscriptWriter::new(
+            dir.path(),
+            dir.path(),
+            "prompt",
+            None,
+            AgentProvider::Claude,
+            "DONE".to_string(),
+            Some("run-1".to_string()),
+        )
+        .unwrap();
+        for (input, output) in [(100, 10), (200, 20)] {
+            writer.start_iteration().unwrap();
+            writer
+                .end_iteration(
+                    IterationEndReason::Normal,
+                    "agent ended without the promise".to_string(),
+                    TokenUsageRecord {
+                        input,
+                        output,
+                        ..TokenUsageRecord::default()
+                    },
+                )
+                .unwrap();
+        }
+        let metadata = writer.metadata().clone();
+
+        let result: Result<LoopResult> = Ok(LoopResult::PromiseFulfilled {
+            iterations: 2,
+            promise: "DONE".to_string(),
+            summary: Some("Fixed it".to_string()),
+            cost_usd: None,
+        });
+        let run_result = RunResult::new(&result, &metadata, 0);
+        run_result.write(writer.run_dir()).unwrap();
+        let loaded = RunResult::load(writer.run_dir()).unwrap();
+        assert_eq!(loaded, run_result);
+        assert_eq!(loaded.outcome, RunOutcome::PromiseFulfilled);
+        assert_eq!(loaded.promise.as_deref(), Some("DONE"));
+        assert_eq!(loaded.summary.as_deref(), Some("Fixed it"));
+        assert_eq!(loaded.iterations, 2);
+        assert_eq!(
+            (loaded.totals.input_tokens, loaded.totals.output_tokens),
+            (300, 30)
+        );
+
+        let exhausted = RunResult::new(&Err(RalphError::MaxIterationsExceeded(2)), &metadata, 1);
+        assert_eq!(exhausted.outcome, RunOutcome::MaxIterationsExceeded);
+        assert!(exhausted.error.unwrap().contains("2"));
+    }
+
+    #[test]
+    fn outcomes_map_to_distinct_exit_codes() {
+        let code = |result: Result<LoopResult>| RunOutcome::from_result(&result).exit_code();
+        let plan = PlanProgress { done: 1, total: 1 };
+        assert_eq!(
+            code(Ok(LoopResult::PlanCompleted {
+                iterations: 1,
+                plan,
+                cost_usd: None
+            })),
+            0
+        );
+        assert_eq!(code(Err(RalphError::MaxIterationsExceeded(3))), 1);
+        assert_eq!(code(Err(RalphError::NoPromptProvided)), 2);
+        assert_eq!(code(Err(RalphError::ConfigError("bad".into()))), 2);
+        assert_eq!(
+            code(Ok(LoopResult::Failed {
+                iterations: 3,
+                consecutive_errors: 3,
+            })),
+            3
+        );
+        assert_eq!(code(Err(RalphError::IncompatibleAgent("old".into()))), 3);
+        assert_eq!(code(Err(RalphError::Aborted("classified".into()))), 1);
+        assert_eq!(code(Err(RalphError::Aborted("low disk space".into()))), 1);
+        assert_eq!(code(Ok(LoopResult::Shutdown { iterations: 1 })), 130);
+        assert_eq!(code(Err(RalphError::ShutdownRequested)), 130);
+    }
+}
@@ -0,0 +1,101 @@
+//! Per-iteration scratch directories.
+//!
+//! Each iteration gets a fresh `runs/<id>/scratch/NNN/` directory for
+//! temporary files, so agents don't litter the repository. The path is
+//! passed to the agent in `RALPH_SCRATCH_DIR` and substituted for
+//! `{scratch_dir}` in the prompt. Only the most recent directories are kept.
+
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{RalphError, Result};
+
+/// Name of the directory holding the scratch directories of a run
+pub const SCRATCH_DIR: &str = "scratch";
+
+/// Environment variable holding the scratch directory of the iteration
+pub const SCRATCH_ENV_VAR: &str = "RALPH_SCRATCH_DIR";
+
+/// Prompt placeholder replaced with the scratch directory of the iteration
+pub const SCRATCH_PLACEHOLDER: &str = "{scratch_dir}";
+
+/// Create the scratch directory for `iteration` inside `run_dir`
+pub fn create(run_dir: &Path, iteration: u32) -> Result<PathBuf> {
+    let dir = run_dir.join(SCRATCH_DIR).join(format!("{iteration:03}"));
+    fs::create_dir_all(&dir).map_err(RalphError::OutputDirError)?;
+    Ok(dir)
+}
+
+/// Substitute the scratch directory into `prompt`
+pub fn render_prompt<'a>(prompt: &'a str, scratch_dir: &Path) -> Cow<'a, str> {
+    if prompt.contains(SCRATCH_PLACEHOLDER) {
+        Cow::Owned(prompt.replace(SCRATCH_PLACEHOLDER, &scratch_dir.to_string_lossy()))
+    } else {
+        Cow::Borrowed(prompt)
+    }
+}
+
+/// Delete all but the `keep` most recent scratch directories of the run in
+/// `run_dir` (`0` keeps all). Returns how many were deleted.
+pub fn prune(run_dir: &Path, keep: usize) -> Result<usize> {
+    if keep == 0 {
+        return Ok(0);
+    }
+    let io_error = |e: std::io::Error| RalphError::TranscriptWriteError(e.to_string());
+    let mut dirs: Vec<(u32, PathBuf)> = match fs::read_dir(run_dir.join(SCRATCH_DIR)) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let iteration = entry.file_name().to_str()?.parse().ok()?;
+                Some((iteration, entry.path()))
+            })
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(io_error(e)),
+    };
+    dirs.sort();
+
+    let stale = dirs.len().saturating_sub(keep);
+    for (_, dir) in &dirs[..stale] {
+        fs::remove_dir_all(dir).map_err(io_error)?;
+    }
+    Ok(stale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn creates_numbered_dirs_and_prunes_oldest() {
+        let temp_dir = TempDir::new().unwrap();
+        for iteration in [1, 2, 10] {
+            let dir = create(temp_dir.path(), iteration).unwrap();
+            fs::write(dir.join("tmp.txt"), "x").unwrap();
+        }
+        assert!(temp_dir.path().join("scratch/010").is_dir());
+
+        assert_eq!(prune(temp_dir.path(), 2).unwrap(), 1);
+
+        assert!(!temp_dir.path().join("scratch/001").exists());
+        assert!(temp_dir.path().join("scratch/002").exists());
+        assert!(temp_dir.path().join("scratch/010").exists());
+        assert_eq!(prune(temp_dir.path(), 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn renders_placeholder_only_when_present() {
+        let dir = Path::new("/runs/r/scratch/001");
+
+        assert_eq!(
+            render_prompt("Put temp files in {scratch_dir}.", dir),
+            "Put temp files in /runs/r/scratch/001."
+        );
+        assert!(matches!(
+            render_prompt("No placeholder", dir),
+            Cow::Borrowed(_)
+        ));
+    }
+}
@@ -9,6 +9,8 @@ use crate::error::RalphError;
 
 const GITHUB_REPO: &str = "tomatitito/ralph";
 const BINARY_NAME: &str = "ralph-loop";
+/// Installed next to `ralph-loop` when the release archive has it
+const UMBRELLA_NAME: &str = "ralph";
 
 #[derive(Debug, Deserialize)]
 struct GitHubRelease {
@@ -22,6 +24,20 @@ struct GitHubAsset {
     browser_download_url: String,
 }
 
+/// Whether a newer release than this build is available, as a message
+pub fn check_for_update() -> Result<String, RalphError> {
+    let release = fetch_latest_release()?;
+    let release_version = normalize_version(&release.tag_name);
+    Ok(if is_newer(release_version, crate::VERSION) {
+        format!(
+            "{BINARY_NAME} {release_version} is available (installed: {}); run `{BINARY_NAME} self-update`",
+            crate::VERSION
+        )
+    } else {
+        format!("{BINARY_NAME} {} is up to date", crate::VERSION)
+    })
+}
+
 pub fn upgrade_current_binary() -> Result<String, RalphError> {
     let current_exe = std::env::current_exe().map_err(io_upgrade_error)?;
     let install_dir = current_exe.parent().ok_or_else(|| {
@@ -33,26 +49,34 @@ pub fn upgrade_current_binary() -> Result<String, RalphError> {
     let release = fetch_latest_release()?;
     let release_version = normalize_version(&release.tag_name);
 
-    if release_version == crate::VERSION {
+    if !is_newer(release_version, crate::VERSION) {
         return Ok(format!(
-            "{BINARY_NAME} {release_version} is already installed"
+            "{BINARY_NAME} {} is already installed",
+            crate::VERSION
         ));
     }
 
-    let asset = release
-        .assets
-        .iter()
-        .find(|asset| asset.name == artifact_name)
-        .ok_or_else(|| {
-            RalphError::UpgradeError(format!(
-                "could not find release artifact '{artifact_name}' in latest release"
-            ))
-        })?;
+    let find_asset = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .ok_or_else(|| {
+                RalphError::UpgradeError(format!(
+                    "could not find release artifact '{name}' in latest release"
+                ))
+            })
+    };
+    let asset = find_asset(&artifact_name)?;
+    let checksum_asset = find_asset(&checksum_name(&artifact_name))?;
 
     let temp_dir = make_temp_dir()?;
     let archive_path = temp_dir.join(&artifact_name);
 
+    let checksum_path = temp_dir.join(checksum_name(&artifact_name));
     download_file(&asset.browser_download_url, &archive_path)?;
+    download_file(&checksum_asset.browser_download_url, &checksum_path)?;
+    verify_checksum(&archive_path, &checksum_path)?;
     extract_archive(&archive_path, &temp_dir)?;
 
     let extracted_binary = temp_dir.join(BINARY_NAME);
@@ -63,6 +87,14 @@ pub fn upgrade_current_binary() -> Result<String, RalphError> {
     }
 
     install_binary(&extracted_binary, install_dir, &current_exe)?;
+    let extracted_umbrella = temp_dir.join(UMBRELLA_NAME);
+    if extracted_umbrella.exists() {
+        install_binary(
+            &extracted_umbrella,
+            install_dir,
+            &install_dir.join(UMBRELLA_NAME),
+        )?;
+    }
 
     let _ = fs::remove_dir_all(&temp_dir);
 
@@ -162,6 +194,67 @@ fn install_binary(
     Ok(())
 }
 
+/// Name of the release asset holding the SHA-256 checksum of `artifact_name`
+fn checksum_name(artifact_name: &str) -> String {
+    format!("{artifact_name}.sha256")
+}
+
+/// The checksum in a `sha256sum` style line: the first word, if it is 64
+/// hex digits
+fn parse_checksum(content: &str) -> Option<String> {
+    let checksum = content.split_whitespace().next()?;
+    (checksum.len() == 64 && checksum.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| checksum.to_ascii_lowercase())
+}
+
+/// SHA-256 of the file at `path`, from `sha256sum` or `shasum -a 256`
+fn sha256_file(path: &Path) -> Result<String, RalphError> {
+    for (program, args) in [("sha256sum", &[][..]), ("shasum", &["-a", "256"][..])] {
+        let Ok(output) = Command::new(program).args(args).arg(path).output() else {
+            continue;
+        };
+        if output.status.success() {
+            if let Some(checksum) = parse_checksum(&String::from_utf8_lossy(&output.stdout)) {
+                return Ok(checksum);
+            }
+        }
+    }
+    Err(RalphError::UpgradeError(
+        "could not compute a SHA-256 checksum: neither sha256sum nor shasum worked".to_string(),
+    ))
+}
+
+/// Check the archive against the checksum file published with it
+fn verify_checksum(archive_path: &Path, checksum_path: &Path) -> Result<(), RalphError> {
+    let content = fs::read_to_string(checksum_path).map_err(io_upgrade_error)?;
+    let expected = parse_checksum(&content).ok_or_else(|| {
+        RalphError::UpgradeError(format!(
+            "invalid checksum file '{}'",
+            checksum_path.display()
+        ))
+    })?;
+    let actual = sha256_file(archive_path)?;
+    if actual != expected {
+        return Err(RalphError::UpgradeError(format!(
+            "checksum mismatch for '{}': expected {expected}, got {actual}",
+            archive_path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Whether version `release` is newer than `current`, comparing the
+/// dot-separated numbers in order
+fn is_newer(release: &str, current: &str) -> bool {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .split(['.', '-', '+'])
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+    parts(release) > parts(current)
+}
+
 fn make_temp_dir() -> Result<PathBuf, RalphError> {
     let temp_dir = std::env::temp_dir().join(format!("ralph-loop-upgrade-{}", Uuid::new_v4()));
     fs::create_dir_all(&temp_dir).map_err(io_upgrade_error)?;
@@ -178,13 +271,32 @@ fn io_upgrade_error(err: std::io::Error) -> RalphError {
 
 #[cfg(test)]
 mod tests {
-    use super::{normalize_version, platform_suffix_for};
+    use super::{is_newer, normalize_version, parse_checksum, platform_suffix_for};
 
     #[test]
     fn strips_v_prefix_from_release_tag() {
         assert_eq!(normalize_version("v0.4.0"), "0.4.0");
     }
 
+    #[test]
+    fn compares_versions_numerically() {
+        assert!(is_newer("0.10.0", "0.9.3"));
+        assert!(is_newer("0.4.2", "0.4.1"));
+        assert!(!is_newer("0.4.1", "0.4.1"));
+        assert!(!is_newer("0.4.0", "0.4.1"));
+    }
+
+    #[test]
+    fn reads_checksums_in_sha256sum_format() {
+        let checksum = "A".repeat(64);
+        assert_eq!(
+            parse_checksum(&format!("{checksum}  ralph-loop-linux-x86_64.tar.gz\n")),
+            Some("a".repeat(64))
+        );
+        assert_eq!(parse_checksum("not-a-checksum file"), None);
+        assert_eq!(parse_checksum(""), None);
+    }
+
     #[test]
     fn leaves_plain_versions_unchanged() {
         assert_eq!(normalize_version("0.4.0"), "0.4.0");
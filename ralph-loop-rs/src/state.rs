@@ -1,19 +1,35 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Instant;
+use tokio::sync::{watch, RwLock};
 
-/// Shared state for concurrent access between the loop controller and monitors
-#[derive(Debug)]
-pub struct SharedState {
+/// Consistent point-in-time view of the shared state
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateSnapshot {
     /// Current estimated token count
-    pub token_count: RwLock<usize>,
-    /// Accumulated output from the agent
-    pub output_buffer: RwLock<String>,
-    /// Whether the completion promise has been found
-    pub promise_found: RwLock<bool>,
-    /// The promise text if found
-    pub promise_text: RwLock<Option<String>>,
+    pub token_count: usize,
     /// Current iteration number
-    pub iteration: RwLock<u32>,
+    pub iteration: u32,
+    /// The promise text if found
+    pub promise: Option<String>,
+    /// Task progress last reported by the agent, in percent
+    pub progress: Option<u8>,
+    /// When the last agent event was read
+    pub last_event_at: Option<Instant>,
+    /// Agent events parsed in this iteration
+    pub events: u64,
+    /// Tool call of the agent waiting for its result, the latest if several
+    pub current_tool: Option<String>,
+}
+
+/// Shared state for concurrent access between the loop controller and monitors.
+///
+/// All scalar state lives in a single watch channel, so every read is a
+/// consistent snapshot and observers can wait for changes. The output buffer
+/// is kept apart because it grows large and nobody needs to be notified of it.
+#[derive(Debug)]
+pub struct SharedState {
+    state: watch::Sender<StateSnapshot>,
+    output_buffer: RwLock<String>,
 }
 
 impl Default for SharedState {
@@ -26,11 +42,8 @@ impl SharedState {
     /// Create a new SharedState with default values
     pub fn new() -> Self {
         Self {
-            token_count: RwLock::new(0),
+            state: watch::Sender::new(StateSnapshot::default()),
             output_buffer: RwLock::new(String::new()),
-            promise_found: RwLock::new(false),
-            promise_text: RwLock::new(None),
-            iteration: RwLock::new(0),
         }
     }
 
@@ -41,48 +54,95 @@ impl SharedState {
 
     /// Reset the state for a new iteration
     pub async fn reset(&self) {
-        *self.token_count.write().await = 0;
-        *self.output_buffer.write().await = String::new();
-        *self.promise_found.write().await = false;
-        *self.promise_text.write().await = None;
+        self.state.send_modify(|state| {
+            state.token_count = 0;
+            state.promise = None;
+            state.events = 0;
+            state.current_tool = None;
+        });
+        self.output_buffer.write().await.clear();
+    }
+
+    /// Take a consistent snapshot of the state
+    pub async fn snapshot(&self) -> StateSnapshot {
+        self.state.borrow().clone()
+    }
+
+    /// Subscribe to state changes
+    pub fn subscribe(&self) -> watch::Receiver<StateSnapshot> {
+        self.state.subscribe()
+    }
+
+    /// Record that an agent event was just read
+    pub async fn record_event(&self) {
+        self.state
+            .send_modify(|state| state.last_event_at = Some(Instant::now()));
+    }
+
+    /// Count an agent event that was parsed
+    pub async fn count_event(&self) {
+        self.state.send_modify(|state| state.events += 1);
+    }
+
+    /// Set the tool call the agent is waiting for
+    pub async fn set_current_tool(&self, tool: Option<String>) {
+        self.state.send_modify(|state| state.current_tool = tool);
+    }
+
+    /// Record progress reported by the agent
+    pub async fn set_progress(&self, percent: u8) {
+        self.state
+            .send_modify(|state| state.progress = Some(percent));
+    }
+
+    /// Get the progress last reported by the agent
+    pub async fn get_progress(&self) -> Option<u8> {
+        self.state.borrow().progress
     }
 
     /// Increment the iteration counter
     pub async fn increment_iteration(&self) -> u32 {
-        let mut iter = self.iteration.write().await;
-        *iter += 1;
-        *iter
+        let mut iteration = 0;
+        self.state.send_modify(|state| {
+            state.iteration += 1;
+            iteration = state.iteration;
+        });
+        iteration
+    }
+
+    /// Continue counting after `iteration`, for a resumed run
+    pub async fn set_iteration(&self, iteration: u32) {
+        self.state.send_modify(|state| state.iteration = iteration);
     }
 
     /// Get the current token count
     pub async fn get_token_count(&self) -> usize {
-        *self.token_count.read().await
+        self.state.borrow().token_count
     }
 
     /// Add to the token count
     pub async fn add_tokens(&self, count: usize) {
-        *self.token_count.write().await += count;
+        self.state.send_modify(|state| state.token_count += count);
     }
 
     /// Set the token count to a specific value
     pub async fn set_tokens(&self, count: usize) {
-        *self.token_count.write().await = count;
+        self.state.send_modify(|state| state.token_count = count);
     }
 
     /// Check if the promise has been found
     pub async fn is_promise_found(&self) -> bool {
-        *self.promise_found.read().await
+        self.state.borrow().promise.is_some()
     }
 
     /// Set the promise as found with the given text
     pub async fn set_promise_found(&self, text: String) {
-        *self.promise_found.write().await = true;
-        *self.promise_text.write().await = Some(text);
+        self.state.send_modify(|state| state.promise = Some(text));
     }
 
     /// Get the promise text if found
     pub async fn get_promise_text(&self) -> Option<String> {
-        self.promise_text.read().await.clone()
+        self.state.borrow().promise.clone()
     }
 
     /// Append text to the output buffer
@@ -90,8 +150,78 @@ impl SharedState {
         self.output_buffer.write().await.push_str(text);
     }
 
+    /// Append `line` and a newline to the output buffer, taking the lock once
+    pub async fn append_line(&self, line: &str) {
+        let mut buffer = self.output_buffer.write().await;
+        buffer.reserve(line.len() + 1);
+        buffer.push_str(line);
+        buffer.push('\n');
+    }
+
     /// Get the current output buffer
     pub async fn get_output(&self) -> String {
         self.output_buffer.read().await.clone()
     }
+
+    /// Take the output buffer, leaving it empty. Saves copying the output of
+    /// a high-output agent once the iteration is over.
+    pub async fn take_output(&self) -> String {
+        std::mem::take(&mut *self.output_buffer.write().await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn snapshot_reflects_all_fields() {
+        let state = SharedState::new();
+        state.increment_iteration().await;
+        state.set_tokens(1234).await;
+        state.set_promise_found("DONE".to_string()).await;
+        state.set_progress(50).await;
+        state.record_event().await;
+        state.count_event().await;
+        state.set_current_tool(Some("Bash".to_string())).await;
+
+        let snapshot = state.snapshot().await;
+
+        assert_eq!(snapshot.token_count, 1234);
+        assert_eq!(snapshot.iteration, 1);
+        assert_eq!(snapshot.promise.as_deref(), Some("DONE"));
+        assert_eq!(snapshot.progress, Some(50));
+        assert!(snapshot.last_event_at.is_some());
+        assert_eq!(snapshot.events, 1);
+        assert_eq!(snapshot.current_tool.as_deref(), Some("Bash"));
+    }
+
+    #[tokio::test]
+    async fn subscribers_are_notified_of_changes() {
+        let state = SharedState::new();
+        let mut rx = state.subscribe();
+
+        state.add_tokens(10).await;
+        state.add_tokens(5).await;
+
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(rx.borrow_and_update().token_count, 15);
+
+        state.reset().await;
+        rx.changed().await.unwrap();
+        assert_eq!(rx.borrow().token_count, 0);
+    }
+
+    #[tokio::test]
+    async fn output_lines_are_taken_once() {
+        let state = SharedState::new();
+        state.append_line("{\"type\":\"init\"}").await;
+        state.append_line("{\"type\":\"result\"}").await;
+
+        assert_eq!(
+            state.take_output().await,
+            "{\"type\":\"init\"}\n{\"type\":\"result\"}\n"
+        );
+        assert_eq!(state.get_output().await, "");
+    }
 }
@@ -0,0 +1,569 @@
+//! Run statistics and baselines.
+//!
+//! `ralph-loop stats` lists the iterations, tokens, cost and duration of
+//! each run. A run can be marked as a named baseline with `ralph-loop
+//! baseline set`; later runs of the same prompt are then compared against
+//! it, and a metric that grew by more than its threshold is flagged as a
+//! regression. Baselines are kept in `<output-dir>/baselines.json`.
+//!
+//! With `--by tag` or `--by task`, the runs are summed up per month and
+//! tag, or per month and task (the first line of the prompt), instead, and
+//! `--csv` prints either table as CSV for spreadsheets.
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{RalphError, Result};
+use crate::run_result::RunTotals;
+use crate::summary;
+use crate::time_display::TimeDisplay;
+use crate::transcript::RunMetadata;
+
+/// Name of the baselines file in the output directory
+pub const BASELINES_FILE: &str = "baselines.json";
+
+/// Growth over the baseline, in percent, flagged unless set otherwise
+pub const DEFAULT_THRESHOLD_PERCENT: f64 = 20.0;
+
+/// A run marked as the reference for later runs of its prompt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub name: String,
+    pub run_id: String,
+    pub marked_at: DateTime<Utc>,
+}
+
+/// Baselines marked in `output_dir`, oldest first
+pub fn load_baselines(output_dir: &Path) -> Result<Vec<Baseline>> {
+    let path = output_dir.join(BASELINES_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(json) => serde_json::from_str(&json)
+            .map_err(|e| RalphError::StatsError(format!("{}: {}", path.display(), e))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(RalphError::StatsError(format!("{}: {}", path.display(), e))),
+    }
+}
+
+fn save_baselines(output_dir: &Path, baselines: &[Baseline]) -> Result<()> {
+    let path = output_dir.join(BASELINES_FILE);
+    let json = serde_json::to_string_pretty(baselines)
+        .map_err(|e| RalphError::StatsError(e.to_string()))?;
+    std::fs::write(&path, json)
+        .map_err(|e| RalphError::StatsError(format!("{}: {}", path.display(), e)))
+}
+
+/// Mark the run `run_id` as the baseline `name`, replacing an earlier
+/// baseline of that name
+pub fn set_baseline(output_dir: &Path, name: &str, run_id: &str) -> Result<Baseline> {
+    if RunMetadata::load(&output_dir.join("runs").join(run_id)).is_err() {
+        return Err(RalphError::StatsError(format!(
+            "run '{run_id}' not found in {}",
+            output_dir.join("runs").display()
+        )));
+    }
+    let mut baselines = load_baselines(output_dir)?;
+    baselines.retain(|baseline| baseline.name != name);
+    let baseline = Baseline {
+        name: name.to_string(),
+        run_id: run_id.to_string(),
+        marked_at: Utc::now(),
+    };
+    baselines.push(baseline.clone());
+    save_baselines(output_dir, &baselines)?;
+    Ok(baseline)
+}
+
+/// Remove the baseline `name`
+pub fn remove_baseline(output_dir: &Path, name: &str) -> Result<()> {
+    let mut baselines = load_baselines(output_dir)?;
+    let before = baselines.len();
+    baselines.retain(|baseline| baseline.name != name);
+    if baselines.len() == before {
+        return Err(RalphError::StatsError(format!(
+            "no baseline named '{name}'"
+        )));
+    }
+    save_baselines(output_dir, &baselines)
+}
+
+/// What is compared between runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Iterations,
+    /// Input and output tokens
+    Tokens,
+    /// Cost reported by the backend
+    Cost,
+    /// Wall-clock duration of a finished run
+    Duration,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 4] = [
+        Metric::Iterations,
+        Metric::Tokens,
+        Metric::Cost,
+        Metric::Duration,
+    ];
+
+    /// Value of the metric for the run in `metadata`, if it has one
+    pub fn value(self, metadata: &RunMetadata) -> Option<f64> {
+        let totals = RunTotals::from_metadata(metadata);
+        match self {
+            Self::Iterations => Some(metadata.iterations.len() as f64),
+            Self::Tokens => Some((totals.input_tokens + totals.output_tokens) as f64),
+            Self::Cost => totals.cost_usd,
+            Self::Duration => totals.duration_secs.map(|secs| secs as f64),
+        }
+    }
+
+    /// Format a value of the metric, or a difference of two with `signed`
+    pub fn format(self, value: f64, signed: bool, time: TimeDisplay) -> String {
+        let sign = match (signed, value < 0.0) {
+            (_, true) => "-",
+            (true, false) => "+",
+            (false, false) => "",
+        };
+        let magnitude = value.abs();
+        match self {
+            Self::Iterations | Self::Tokens => format!("{sign}{magnitude:.0}"),
+            Self::Cost => format!("{sign}${magnitude:.2}"),
+            Self::Duration => format!(
+                "{sign}{}",
+                time.duration(Duration::from_secs(magnitude as u64))
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Iterations => "iterations",
+            Self::Tokens => "tokens",
+            Self::Cost => "cost",
+            Self::Duration => "duration",
+        })
+    }
+}
+
+impl FromStr for Metric {
+    type Err = RalphError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|metric| metric.to_string() == s.trim())
+            .ok_or_else(|| {
+                RalphError::StatsError(format!(
+                    "unknown metric '{s}', expected iterations, tokens, cost or duration"
+                ))
+            })
+    }
+}
+
+/// Growth over the baseline, in percent, that is flagged for each metric
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    pub iterations: f64,
+    pub tokens: f64,
+    pub cost: f64,
+    pub duration: f64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            iterations: DEFAULT_THRESHOLD_PERCENT,
+            tokens: DEFAULT_THRESHOLD_PERCENT,
+            cost: DEFAULT_THRESHOLD_PERCENT,
+            duration: DEFAULT_THRESHOLD_PERCENT,
+        }
+    }
+}
+
+impl Thresholds {
+    /// Threshold for `metric`
+    pub fn get(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::Iterations => self.iterations,
+            Metric::Tokens => self.tokens,
+            Metric::Cost => self.cost,
+            Metric::Duration => self.duration,
+        }
+    }
+
+    /// Apply `spec`, a percentage for every metric (`10`) or for one
+    /// (`cost=10`)
+    pub fn apply(&mut self, spec: &str) -> Result<()> {
+        let (metrics, percent) = match spec.split_once('=') {
+            Some((metric, percent)) => (vec![metric.parse()?], percent),
+            None => (Metric::ALL.to_vec(), spec),
+        };
+        let percent: f64 = percent
+            .trim()
+            .trim_end_matches('%')
+            .parse()
+            .ok()
+            .filter(|percent: &f64| *percent >= 0.0)
+            .ok_or_else(|| {
+                RalphError::StatsError(format!(
+                    "invalid threshold '{spec}', expected a percentage like 20 or cost=10"
+                ))
+            })?;
+        for metric in metrics {
+            match metric {
+                Metric::Iterations => self.iterations = percent,
+                Metric::Tokens => self.tokens = percent,
+                Metric::Cost => self.cost = percent,
+                Metric::Duration => self.duration = percent,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A metric of a run next to the baseline's
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Delta {
+    pub metric: Metric,
+    pub baseline: f64,
+    pub value: f64,
+    /// Whether the metric grew by more than its threshold
+    pub regression: bool,
+}
+
+impl Delta {
+    /// Growth over the baseline in percent, unless the baseline is zero
+    pub fn percent(&self) -> Option<f64> {
+        (self.baseline != 0.0).then(|| (self.value - self.baseline) / self.baseline * 100.0)
+    }
+
+    /// The value with its difference to the baseline, e.g. `5 (+2, +67%)`
+    pub fn render(&self, time: TimeDisplay) -> String {
+        let mut text = format!(
+            "{} ({}",
+            self.metric.format(self.value, false, time),
+            self.metric.format(self.value - self.baseline, true, time)
+        );
+        if let Some(percent) = self.percent() {
+            text.push_str(&format!(", {percent:+.0}%"));
+        }
+        text.push(')');
+        text
+    }
+}
+
+/// Compare `run` with `baseline` on every metric both have
+pub fn compare(baseline: &RunMetadata, run: &RunMetadata, thresholds: &Thresholds) -> Vec<Delta> {
+    Metric::ALL
+        .into_iter()
+        .filter_map(|metric| {
+            let (baseline, value) = (metric.value(baseline)?, metric.value(run)?);
+            let delta = Delta {
+                metric,
+                baseline,
+                value,
+                regression: false,
+            };
+            let regression = delta
+                .percent()
+                .is_some_and(|percent| percent > thresholds.get(metric));
+            Some(Delta {
+                regression,
+                ..delta
+            })
+        })
+        .collect()
+}
+
+/// A baseline and the later runs of its prompt
+#[derive(Debug, Clone)]
+pub struct Comparison<'a> {
+    pub baseline: &'a Baseline,
+    pub baseline_run: &'a RunMetadata,
+    /// Each later run with its metrics next to the baseline's
+    pub runs: Vec<(&'a RunMetadata, Vec<Delta>)>,
+}
+
+/// Compare each of `baselines` whose run is among `runs` with the runs of
+/// the same prompt that started after it
+pub fn comparisons<'a>(
+    baselines: &'a [Baseline],
+    runs: &'a [RunMetadata],
+    thresholds: &Thresholds,
+) -> Vec<Comparison<'a>> {
+    baselines
+        .iter()
+        .filter_map(|baseline| {
+            let baseline_run = runs.iter().find(|run| run.run_id == baseline.run_id)?;
+            let later = runs
+                .iter()
+                .filter(|run| {
+                    run.started_at > baseline_run.started_at && baseline_run.same_prompt(run)
+                })
+                .map(|run| (run, compare(baseline_run, run, thresholds)))
+                .collect();
+            Some(Comparison {
+                baseline,
+                baseline_run,
+                runs: later,
+            })
+        })
+        .collect()
+}
+
+/// What runs are summed up by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GroupBy {
+    /// Each tag of the run; runs without tags are `untagged`
+    Tag,
+    /// The first line of the prompt
+    Task,
+}
+
+/// Group of runs that runs without tags fall into
+pub const UNTAGGED: &str = "untagged";
+
+/// Totals of the runs of one group in one month
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupTotals {
+    /// Month the runs started in, e.g. `2026-03`
+    pub month: String,
+    pub group: String,
+    pub runs: u32,
+    pub iterations: u32,
+    /// Input and output tokens
+    pub tokens: usize,
+    /// Cost reported by the backend; runs that reported none add nothing
+    pub cost_usd: f64,
+}
+
+/// The groups `run` is counted in
+fn groups(run: &RunMetadata, group_by: GroupBy) -> Vec<String> {
+    match group_by {
+        GroupBy::Tag if run.tags.is_empty() => vec![UNTAGGED.to_string()],
+        GroupBy::Tag => run.tags.clone(),
+        GroupBy::Task => vec![summary::task(run)],
+    }
+}
+
+/// Totals of `runs` per month and group, latest month first and the most
+/// expensive group first within a month. A run with several tags counts
+/// toward each.
+pub fn by_group(runs: &[RunMetadata], group_by: GroupBy, time: TimeDisplay) -> Vec<GroupTotals> {
+    let mut totals: Vec<GroupTotals> = Vec::new();
+    for run in runs {
+        let month = time.month(run.started_at);
+        let run_totals = RunTotals::from_metadata(run);
+        for group in groups(run, group_by) {
+            let index = match totals
+                .iter()
+                .position(|t| t.month == month && t.group == group)
+            {
+                Some(index) => index,
+                None => {
+                    totals.push(GroupTotals {
+                        month: month.clone(),
+                        group,
+                        runs: 0,
+                        iterations: 0,
+                        tokens: 0,
+                        cost_usd: 0.0,
+                    });
+                    totals.len() - 1
+                }
+            };
+            let entry = &mut totals[index];
+            entry.runs += 1;
+            entry.iterations += run.iterations.len() as u32;
+            entry.tokens += run_totals.input_tokens + run_totals.output_tokens;
+            entry.cost_usd += run_totals.cost_usd.unwrap_or(0.0);
+        }
+    }
+    totals.sort_by(|a, b| {
+        b.month
+            .cmp(&a.month)
+            .then(b.cost_usd.total_cmp(&a.cost_usd))
+            .then(a.group.cmp(&b.group))
+    });
+    totals
+}
+
+/// `rows` under `header` as CSV, quoting cells that need it
+pub fn csv(header: &[&str], rows: &[Vec<String>]) -> String {
+    let line = |cells: &mut dyn Iterator<Item = &str>| {
+        cells
+            .map(|cell| {
+                if cell.contains([',', '"', '\n', '\r']) {
+                    format!("\"{}\"", cell.replace('"', "\"\""))
+                } else {
+                    cell.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let mut output = line(&mut header.iter().copied());
+    for row in rows {
+        output.push('\n');
+        output.push_str(&line(&mut row.iter().map(String::as_str)));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AgentProvider;
+    use crate::transcript::{IterationEndReason, TokenUsageRecord, TranscriptWriter};
+    use tempfile::TempDir;
+
+    #[test]
+    fn runs_are_summed_up_per_month_and_tag() {
+        let dir = TempDir::new().unwrap();
+        let mut runs = vec![
+            run(dir.path(), "a", "fix it", &[100]),
+            run(dir.path(), "b", "fix it", &[100, 100]),
+            run(dir.path(), "c", "write docs\nin detail", &[50]),
+        ];
+        runs[0].tags = vec!["bugfix".to_string(), "backend".to_string()];
+        runs[1].tags = vec!["bugfix".to_string()];
+        runs[0].iterations[0].cost_usd = Some(0.5);
+        runs[1].iterations[0].cost_usd = Some(1.0);
+        let time = TimeDisplay {
+            zone: crate::time_display::TimeZoneChoice::Utc,
+            iso_8601: false,
+        };
+        let month = time.month(runs[0].started_at);
+
+        let by_tag = by_group(&runs, GroupBy::Tag, time);
+        let summary: Vec<_> = by_tag
+            .iter()
+            .map(|t| (t.group.as_str(), t.runs, t.iterations, t.tokens, t.cost_usd))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("bugfix", 2, 3, 300, 1.5),
+                ("backend", 1, 1, 100, 0.5),
+                (UNTAGGED, 1, 1, 50, 0.0),
+            ]
+        );
+        assert!(by_tag.iter().all(|t| t.month == month));
+
+        let by_task = by_group(&runs, GroupBy::Task, time);
+        let tasks: Vec<_> = by_task.iter().map(|t| (t.group.as_str(), t.runs)).collect();
+        assert_eq!(tasks, [("fix it", 2), ("write docs", 1)]);
+    }
+
+    #[test]
+    fn csv_quotes_cells_that_need_it() {
+        let rows = vec![vec!["fix, then test".to_string(), "say \"hi\"".to_string()]];
+        assert_eq!(
+            csv(&["task", "note"], &rows),
+            "task,note\n\"fix, then test\",\"say \"\"hi\"\"\""
+        );
+    }
+
+    fn run(dir: &Path, run_id: &str, prompt: &str, tokens: &[usize]) -> RunMetadata {
+        let mut writer = TranscriptWriter::new(
+            dir,
+            dir,
+            prompt,
+            None,
+            AgentProvider::Claude,
+            "DONE".to_string(),
+            Some(run_id.to_string()),
+        )
+        .unwrap();
+        for &input in tokens {
+            writer.start_iteration().unwrap();
+            writer
+                .end_iteration(
+                    IterationEndReason::Normal,
+                    "agent ended without the promise".to_string(),
+                    TokenUsageRecord {
+                        input,
+                        ..TokenUsageRecord::default()
+                    },
+                )
+                .unwrap();
+        }
+        writer.metadata().clone()
+    }
+
+    #[test]
+    fn baselines_are_replaced_by_name() {
+        let dir = TempDir::new().unwrap();
+        run(dir.path(), "run-1", "fix it", &[100]);
+        run(dir.path(), "run-2", "fix it", &[100]);
+
+        assert!(set_baseline(dir.path(), "main", "missing").is_err());
+        set_baseline(dir.path(), "main", "run-1").unwrap();
+        set_baseline(dir.path(), "main", "run-2").unwrap();
+        let baselines = load_baselines(dir.path()).unwrap();
+        assert_eq!(baselines.len(), 1);
+        assert_eq!(baselines[0].run_id, "run-2");
+
+        remove_baseline(dir.path(), "main").unwrap();
+        assert!(load_baselines(dir.path()).unwrap().is_empty());
+        assert!(remove_baseline(dir.path(), "main").is_err());
+    }
+
+    #[test]
+    fn thresholds_apply_to_all_metrics_or_one() {
+        let mut thresholds = Thresholds::default();
+        thresholds.apply("10").unwrap();
+        thresholds.apply("cost=5%").unwrap();
+        assert_eq!(thresholds.get(Metric::Tokens), 10.0);
+        assert_eq!(thresholds.get(Metric::Cost), 5.0);
+        assert!(thresholds.apply("speed=5").is_err());
+        assert!(thresholds.apply("-3").is_err());
+    }
+
+    #[test]
+    fn later_runs_of_the_same_prompt_are_compared() {
+        let dir = TempDir::new().unwrap();
+        let runs = vec![
+            run(dir.path(), "before", "fix it", &[100]),
+            run(dir.path(), "base", "fix it", &[100, 100]),
+            run(dir.path(), "other", "write docs", &[500, 500, 500]),
+            run(dir.path(), "same", "fix it", &[100]),
+            run(dir.path(), "worse", "fix it", &[100, 100, 100]),
+        ];
+        let baselines = vec![Baseline {
+            name: "main".to_string(),
+            run_id: "base".to_string(),
+            marked_at: Utc::now(),
+        }];
+
+        let comparisons = comparisons(&baselines, &runs, &Thresholds::default());
+        assert_eq!(comparisons.len(), 1);
+        let compared: Vec<&str> = comparisons[0]
+            .runs
+            .iter()
+            .map(|(run, _)| run.run_id.as_str())
+            .collect();
+        assert_eq!(compared, ["same", "worse"]);
+
+        let deltas = &comparisons[0].runs[1].1;
+        let iterations = deltas[0];
+        assert_eq!(iterations.metric, Metric::Iterations);
+        assert!(iterations.regression);
+        assert_eq!(iterations.render(TimeDisplay::default()), "3 (+1, +50%)");
+        assert!(comparisons[0].runs[0]
+            .1
+            .iter()
+            .all(|delta| !delta.regression));
+        assert_eq!(
+            comparisons[0].runs[0].1[1].render(TimeDisplay::default()),
+            "100 (-100, -50%)"
+        );
+    }
+}
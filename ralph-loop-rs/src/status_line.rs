@@ -0,0 +1,192 @@
+//! Status line shown while the agent runs.
+//!
+//! When stderr is a terminal, a single line with a spinner, the time the
+//! agent has been running, the events parsed, the tool it is running and
+//! its token count is redrawn in place, so long iterations don't look hung.
+//! When earlier iterations give an estimate, the time left in the iteration
+//! and until max iterations is shown too.
+//! Log lines are written through [`LogWriter`], which clears the status
+//! line first; the next redraw puts it back underneath them.
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::task::JoinHandle;
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::banner::format_duration;
+use crate::config::Config;
+use crate::eta::IterationEta;
+use crate::state::{SharedState, StateSnapshot};
+use crate::table::terminal_width;
+use crate::text::elide_end;
+
+/// How often the status line is redrawn
+const REDRAW_INTERVAL: Duration = Duration::from_millis(125);
+
+/// Spinner frames, one per redraw
+const SPINNER: &[char] = &['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Erases the current terminal line and returns to its start
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+/// Whether a status line is on screen. Held while writing to the terminal,
+/// so a log line and a redraw never interleave.
+static SHOWING: Mutex<bool> = Mutex::new(false);
+
+/// Estimate for the iteration being run, set by the loop
+static ETA: Mutex<Option<IterationEta>> = Mutex::new(None);
+
+/// Set the estimate shown for the iterations that start from now on
+pub fn set_eta(eta: Option<IterationEta>) {
+    *ETA.lock().unwrap_or_else(|e| e.into_inner()) = eta;
+}
+
+/// Text of the status line, cut to `width` columns if given
+pub fn render(
+    snapshot: &StateSnapshot,
+    elapsed: Duration,
+    eta: Option<&IterationEta>,
+    frame: usize,
+    width: Option<usize>,
+) -> String {
+    let mut line = format!(
+        "{} {}",
+        SPINNER[frame % SPINNER.len()],
+        format_duration(elapsed)
+    );
+    if let Some(eta) = eta {
+        line.push_str(&format!(" · {}", eta.render(elapsed)));
+    }
+    line.push_str(&format!(" · {} events", snapshot.events));
+    if let Some(ref tool) = snapshot.current_tool {
+        line.push_str(&format!(" · {tool}"));
+    }
+    if snapshot.token_count > 0 {
+        line.push_str(&format!(" · ~{} tokens", snapshot.token_count));
+    }
+    match width {
+        Some(width) => elide_end(&line, width.saturating_sub(1)).into_owned(),
+        None => line,
+    }
+}
+
+/// Background task redrawing the status line; cleared when dropped
+pub struct StatusLine {
+    handle: JoinHandle<()>,
+}
+
+impl StatusLine {
+    /// Start drawing the status line for the agent whose events update
+    /// `state`, if enabled and stderr is a terminal
+    pub fn start(config: &Config, state: &Arc<SharedState>) -> Option<Self> {
+        if !config.progress || !io::stderr().is_terminal() {
+            return None;
+        }
+        let state = Arc::clone(state);
+        let started = Instant::now();
+        let width = terminal_width();
+        let eta = *ETA.lock().unwrap_or_else(|e| e.into_inner());
+        let handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(REDRAW_INTERVAL);
+            for frame in 0.. {
+                ticker.tick().await;
+                let line = render(
+                    &state.snapshot().await,
+                    started.elapsed(),
+                    eta.as_ref(),
+                    frame,
+                    width,
+                );
+                let mut showing = SHOWING.lock().unwrap_or_else(|e| e.into_inner());
+                let mut stderr = io::stderr().lock();
+                let _ = write!(stderr, "{CLEAR_LINE}{line}");
+                let _ = stderr.flush();
+                *showing = true;
+            }
+        });
+        Some(Self { handle })
+    }
+}
+
+impl Drop for StatusLine {
+    fn drop(&mut self) {
+        self.handle.abort();
+        clear();
+    }
+}
+
+/// Erase the status line, if one is on screen
+fn clear() {
+    let mut showing = SHOWING.lock().unwrap_or_else(|e| e.into_inner());
+    if *showing {
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "{CLEAR_LINE}");
+        let _ = stderr.flush();
+        *showing = false;
+    }
+}
+
+/// Writer for the terminal log, erasing the status line before each line
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut showing = SHOWING.lock().unwrap_or_else(|e| e.into_inner());
+        if *showing {
+            // Both streams go to the same terminal, so this clears the line
+            io::stderr().write_all(CLEAR_LINE.as_bytes())?;
+            *showing = false;
+        }
+        io::stdout().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogWriter {
+    type Writer = LogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        *self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_shows_what_the_agent_is_doing() {
+        let mut snapshot = StateSnapshot {
+            events: 42,
+            ..StateSnapshot::default()
+        };
+        assert_eq!(
+            render(&snapshot, Duration::from_secs(64), None, 0, None),
+            "⠋ 1m 04s · 42 events"
+        );
+        snapshot.current_tool = Some("Bash".to_string());
+        snapshot.token_count = 12_345;
+        assert_eq!(
+            render(&snapshot, Duration::from_secs(5), None, 2, None),
+            "⠹ 5s · 42 events · Bash · ~12345 tokens"
+        );
+        assert_eq!(
+            render(&snapshot, Duration::from_secs(5), None, 2, Some(21)),
+            "⠹ 5s · 42 events ..."
+        );
+        let eta = IterationEta {
+            expected: Duration::from_secs(60),
+            remaining_iterations: Some(2),
+        };
+        assert_eq!(
+            render(&snapshot, Duration::from_secs(5), Some(&eta), 2, None),
+            "⠹ 5s · ETA 55s · max iterations in ~2m 55s · 42 events · Bash · ~12345 tokens"
+        );
+    }
+}
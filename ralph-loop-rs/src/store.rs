@@ -0,0 +1,196 @@
+//! Storage backends for run artifacts.
+//!
+//! A finished run directory can be published to one or more [`ArtifactStore`]s
+//! so its transcripts and reports outlive the machine that produced them:
+//! a local or mounted directory, or an S3-compatible bucket.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::JobConfig;
+use crate::error::{RalphError, Result};
+
+/// A place run directories can be published to and fetched from
+pub trait ArtifactStore: Send + Sync {
+    /// Location of the run called `name` in this store
+    fn location(&self, name: &str) -> String;
+
+    /// Copy `run_dir` into the store as `name`, returning its location
+    fn upload(&self, run_dir: &Path, name: &str) -> Result<String>;
+
+    /// Copy the run called `name` out of the store into `dest`
+    fn download(&self, name: &str, dest: &Path) -> Result<()>;
+}
+
+/// Stores runs as directories below `root`, e.g. a mounted volume
+#[derive(Debug, Clone)]
+pub struct FilesystemStore {
+    root: PathBuf,
+}
+
+impl FilesystemStore {
+    /// Create a store rooted at `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl ArtifactStore for FilesystemStore {
+    fn location(&self, name: &str) -> String {
+        self.root.join(name).display().to_string()
+    }
+
+    fn upload(&self, run_dir: &Path, name: &str) -> Result<String> {
+        copy_dir(run_dir, &self.root.join(name))?;
+        Ok(self.location(name))
+    }
+
+    fn download(&self, name: &str, dest: &Path) -> Result<()> {
+        let source = self.root.join(name);
+        if !source.is_dir() {
+            return Err(RalphError::TranscriptWriteError(format!(
+                "run {} not found in {}",
+                name,
+                self.root.display()
+            )));
+        }
+        copy_dir(&source, dest)
+    }
+}
+
+/// Stores runs below an `s3://bucket/prefix` URL using the AWS CLI, which
+/// also takes care of credentials
+#[derive(Debug, Clone)]
+pub struct S3Store {
+    url: String,
+    endpoint_url: Option<String>,
+}
+
+impl S3Store {
+    /// `endpoint_url` points at an S3-compatible service such as MinIO
+    pub fn new(url: impl Into<String>, endpoint_url: Option<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            endpoint_url,
+        }
+    }
+
+    fn copy(&self, from: &str, to: &str) -> Result<()> {
+        let mut command = Command::new("aws");
+        command
+            .arg("s3")
+            .arg("cp")
+            .arg(from)
+            .arg(to)
+            .arg("--recursive")
+            .arg("--only-show-errors");
+        if let Some(ref endpoint_url) = self.endpoint_url {
+            command.arg("--endpoint-url").arg(endpoint_url);
+        }
+
+        let output = command
+            .output()
+            .map_err(|e| RalphError::TranscriptWriteError(format!("failed to run aws: {e}")))?;
+        if !output.status.success() {
+            return Err(RalphError::TranscriptWriteError(format!(
+                "aws s3 cp failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl ArtifactStore for S3Store {
+    fn location(&self, name: &str) -> String {
+        format!("{}/{}", self.url, name)
+    }
+
+    fn upload(&self, run_dir: &Path, name: &str) -> Result<String> {
+        let target = self.location(name);
+        self.copy(&run_dir.to_string_lossy(), &target)?;
+        Ok(target)
+    }
+
+    fn download(&self, name: &str, dest: &Path) -> Result<()> {
+        self.copy(&self.location(name), &dest.to_string_lossy())
+    }
+}
+
+/// The stores configured in `[job]`
+pub fn configured(config: &JobConfig) -> Vec<Box<dyn ArtifactStore>> {
+    let mut stores: Vec<Box<dyn ArtifactStore>> = Vec::new();
+    if let Some(ref artifacts_dir) = config.artifacts_dir {
+        stores.push(Box::new(FilesystemStore::new(artifacts_dir)));
+    }
+    if let Some(ref s3_url) = config.s3_url {
+        stores.push(Box::new(S3Store::new(
+            s3_url,
+            config.s3_endpoint_url.clone(),
+        )));
+    }
+    stores
+}
+
+/// Recursively copy `from` into `to`
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    let io_error = |e: std::io::Error| RalphError::TranscriptWriteError(e.to_string());
+    fs::create_dir_all(to).map_err(io_error)?;
+    for entry in fs::read_dir(from).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        let target = to.join(entry.file_name());
+        if entry.file_type().map_err(io_error)?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target).map_err(io_error)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn filesystem_store_roundtrips_run_dir() {
+        let temp_dir = TempDir::new().unwrap();
+        let run_dir = temp_dir.path().join("run");
+        fs::create_dir_all(run_dir.join("iterations")).unwrap();
+        fs::write(run_dir.join("iterations").join("1.jsonl"), "{}").unwrap();
+        let store = FilesystemStore::new(temp_dir.path().join("store"));
+
+        let location = store.upload(&run_dir, "run-1").unwrap();
+        assert_eq!(location, store.location("run-1"));
+
+        let restored = temp_dir.path().join("restored");
+        store.download("run-1", &restored).unwrap();
+        assert_eq!(
+            fs::read_to_string(restored.join("iterations").join("1.jsonl")).unwrap(),
+            "{}"
+        );
+        assert!(store.download("missing", &restored).is_err());
+    }
+
+    #[test]
+    fn configured_stores_follow_job_config() {
+        let config = JobConfig {
+            artifacts_dir: Some(PathBuf::from("/artifacts")),
+            s3_url: Some("s3://bucket/ralph/".to_string()),
+            s3_endpoint_url: None,
+        };
+
+        let locations: Vec<String> = configured(&config)
+            .iter()
+            .map(|store| store.location("run-1"))
+            .collect();
+
+        assert_eq!(
+            locations,
+            vec!["/artifacts/run-1", "s3://bucket/ralph/run-1"]
+        );
+        assert!(configured(&JobConfig::default()).is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+//! Summary of a successful run, usable as a commit message or changelog entry.
+//!
+//! When `[summary] enabled = true`, a summary is generated once the promise is
+//! fulfilled or the plan completed and stored in the run metadata. It is
+//! either rendered from a template or written by a short, non-interactive call
+//! to the configured agent, falling back to the template if that call fails.
+
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::audit::SubprocessRecord;
+use crate::config::{AgentProvider, Config, SummaryGenerator};
+use crate::transcript::RunMetadata;
+
+/// Template used when `[summary] template` is not set.
+/// Placeholders: `{task}`, `{promise}`, `{iterations}`, `{notes}`
+pub const DEFAULT_TEMPLATE: &str =
+    "{task}\n\n{notes}\n\nCompleted by ralph-loop in {iterations} iteration(s).";
+
+/// How long the agent may take to write a summary
+const AGENT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// First line of the prompt, shortened to fit a commit subject
+pub fn task(metadata: &RunMetadata) -> String {
+    let line = metadata
+        .prompt_preview
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("ralph-loop run");
+    if line.chars().count() > 72 {
+        format!("{}...", line.chars().take(69).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+/// Notes the agent recorded, one bullet per note
+fn notes(metadata: &RunMetadata) -> String {
+    metadata
+        .iterations
+        .iter()
+        .flat_map(|iteration| &iteration.notes)
+        .map(|note| format!("- {note}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Render `template` for a finished run
+pub fn render_template(template: &str, metadata: &RunMetadata, promise: &str) -> String {
+    let rendered = template
+        .replace("{task}", &task(metadata))
+        .replace("{promise}", promise)
+        .replace("{iterations}", &metadata.iterations.len().to_string())
+        .replace("{notes}", &notes(metadata));
+    // Drop the blank lines left behind by empty placeholders
+    let mut text = String::new();
+    for paragraph in rendered
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+    {
+        if !text.is_empty() {
+            text.push_str("\n\n");
+        }
+        text.push_str(paragraph);
+    }
+    text
+}
+
+/// Prompt asking the agent to summarize the run
+pub fn agent_prompt(metadata: &RunMetadata, promise: &str) -> String {
+    let mut prompt = format!(
+        "Write a git commit message for the work an automated coding loop just finished. \
+         Use a subject line of at most 72 characters, a blank line and a short body. \
+         Reply with the commit message only.\n\nTask:\n{}\n\nThe task was completed \
+         ({}) after {} iteration(s).",
+        metadata.prompt_preview,
+        promise,
+        metadata.iterations.len()
+    );
+    let notes = notes(metadata);
+    if !notes.is_empty() {
+        prompt.push_str("\n\nNotes recorded during the run:\n");
+        prompt.push_str(&notes);
+    }
+    prompt
+}
+
+/// Generate the summary for a finished run. Returns the summary and the
+/// agent subprocess, if one was spawned.
+pub async fn generate(
+    config: &Config,
+    metadata: &RunMetadata,
+    promise: &str,
+) -> (String, Option<SubprocessRecord>) {
+    let template = config
+        .summary
+        .template
+        .as_deref()
+        .unwrap_or(DEFAULT_TEMPLATE);
+    let mut args = match (config.summary.generator, config.agent_provider()) {
+        (SummaryGenerator::Agent, AgentProvider::Claude) => vec!["--print".to_string()],
+        (SummaryGenerator::Agent, AgentProvider::Codex) => vec!["exec".to_string()],
+        // The API backends have no one-shot CLI to ask
+        (SummaryGenerator::Template, _)
+        | (_, AgentProvider::Openai)
+        | (_, AgentProvider::Anthropic)
+        | (_, AgentProvider::Ollama) => {
+            return (render_template(template, metadata, promise), None);
+        }
+    };
+    let program = config.agent_path();
+    args.push(agent_prompt(metadata, promise));
+
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let output = tokio::time::timeout(
+        AGENT_TIMEOUT,
+        Command::new(&program)
+            .args(&args)
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await;
+    let exit_code = match output {
+        Ok(Ok(ref output)) => output.status.code(),
+        _ => None,
+    };
+    let subprocess = SubprocessRecord {
+        kind: "summary".to_string(),
+        program,
+        args,
+        pid: None,
+        started_at,
+        exit_code,
+        duration_ms: started.elapsed().as_millis() as u64,
+    };
+
+    let summary = match output {
+        Ok(Ok(output)) if output.status.success() => {
+            let text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            (!text.is_empty()).then_some(text)
+        }
+        Ok(Ok(output)) => {
+            warn!(
+                "Summary agent exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            None
+        }
+        Ok(Err(e)) => {
+            warn!("Failed to run summary agent: {}", e);
+            None
+        }
+        Err(_) => {
+            warn!("Summary agent timed out after {:?}", AGENT_TIMEOUT);
+            None
+        }
+    };
+    let summary = summary.unwrap_or_else(|| {
+        info!("Falling back to the summary template");
+        render_template(template, metadata, promise)
+    });
+    (summary, Some(subprocess))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SummaryConfig;
+    use crate::transcript::IterationMetadata;
+
+    fn metadata(prompt: &str, notes: &[&str]) -> RunMetadata {
+        let mut metadata = RunMetadata::new(
+            "run-1".to_string(),
+            "/project".to_string(),
+            prompt,
+            None,
+            AgentProvider::Claude,
+            "DONE".to_string(),
+        );
+        metadata.iterations = vec![IterationMetadata {
+            iteration: 1,
+            session_id: None,
+            started_at: Utc::now(),
+            ended_at: None,
+            end_reason: None,
+            reason_detail: None,
+            tokens: None,
+            cost_usd: None,
+            timings: None,
+            plan: None,
+            progress_percent: None,
+            notes: notes.iter().map(|note| note.to_string()).collect(),
+            stderr_file: None,
+            unparsed_lines: None,
+            limits: None,
+            probe_complete: None,
+            extra: serde_json::Map::new(),
+        }];
+        metadata
+    }
+
+    #[test]
+    fn default_template_lists_notes() {
+        let metadata = metadata(
+            "Fix the parser\nmore details",
+            &["split lexer", "add tests"],
+        );
+
+        assert_eq!(
+            render_template(DEFAULT_TEMPLATE, &metadata, "DONE"),
+            "Fix the parser\n\n- split lexer\n- add tests\n\n\
+             Completed by ralph-loop in 1 iteration(s)."
+        );
+    }
+
+    #[test]
+    fn default_template_without_notes_has_no_blank_paragraph() {
+        let metadata = metadata("Fix the parser", &[]);
+
+        assert_eq!(
+            render_template(DEFAULT_TEMPLATE, &metadata, "DONE"),
+            "Fix the parser\n\nCompleted by ralph-loop in 1 iteration(s)."
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_agent_falls_back_to_template() {
+        let mut config = Config {
+            summary: SummaryConfig {
+                enabled: true,
+                generator: SummaryGenerator::Agent,
+                template: Some("{task}: {promise}".to_string()),
+            },
+            ..Config::default()
+        };
+        config.agent.path = Some("false".to_string());
+
+        let (summary, subprocess) = generate(&config, &metadata("Fix it", &[]), "DONE").await;
+
+        assert_eq!(summary, "Fix it: DONE");
+        assert_eq!(subprocess.unwrap().exit_code, Some(1));
+    }
+}
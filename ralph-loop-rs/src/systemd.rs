@@ -0,0 +1,236 @@
+//! systemd integration for unattended server deployments.
+//!
+//! `ralph-loop systemd install` renders a user unit that runs a configured
+//! loop with `Type=notify`. When started by systemd, ralph reports readiness
+//! and pings the watchdog through `sd_notify`, so a hung process is restarted.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::{debug, warn};
+
+use crate::error::{RalphError, Result};
+
+/// Watchdog timeout written into generated units
+pub const WATCHDOG_SEC: u64 = 60;
+
+/// Everything needed to render a unit file
+#[derive(Debug, Clone)]
+pub struct UnitSpec {
+    /// Task name; the unit is called `ralph-<name>.service`
+    pub name: String,
+    /// Path to the ralph-loop executable
+    pub executable: PathBuf,
+    /// Project directory the loop runs in
+    pub working_dir: PathBuf,
+    /// Extra arguments for the loop; consent to skipping permissions is
+    /// only given if they include `--yes`
+    pub args: Vec<String>,
+}
+
+impl UnitSpec {
+    /// File name of the unit
+    pub fn unit_name(&self) -> String {
+        format!("ralph-{}.service", self.name)
+    }
+
+    /// Render the unit file
+    pub fn render(&self) -> String {
+        let mut exec_start = vec![quote(&self.executable.to_string_lossy())];
+        exec_start.extend(self.args.iter().map(|arg| quote(arg)));
+        format!(
+            "[Unit]\n\
+             Description=ralph-loop task {name}\n\
+             After=network-online.target\n\
+             \n\
+             [Service]\n\
+             Type=notify\n\
+             NotifyAccess=main\n\
+             WatchdogSec={WATCHDOG_SEC}\n\
+             WorkingDirectory={working_dir}\n\
+             ExecStart={exec_start}\n\
+             Restart=on-failure\n\
+             RestartSec=30\n\
+             \n\
+             [Install]\n\
+             WantedBy=default.target\n",
+            name = self.name,
+            // Paths aren't split on whitespace, but specifiers still expand
+            working_dir = self.working_dir.to_string_lossy().replace('%', "%%"),
+            exec_start = exec_start.join(" "),
+        )
+    }
+}
+
+/// Quote a word for a unit file command line if needed
+fn quote(word: &str) -> String {
+    if !word.is_empty()
+        && !word
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | '%' | '$'))
+    {
+        return word.to_string();
+    }
+    let escaped = word
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{escaped}\"")
+}
+
+/// Directory user units are installed into
+fn user_unit_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|config| config.join("systemd").join("user"))
+}
+
+/// Write the user unit for `spec`, refusing to overwrite an existing one
+pub fn install(spec: &UnitSpec, unit_dir: Option<&Path>) -> Result<String> {
+    if spec.name.is_empty()
+        || !spec
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(RalphError::ConfigError(format!(
+            "invalid task name '{}': use letters, digits, '-', '_' and '.'",
+            spec.name
+        )));
+    }
+
+    let unit_dir = match unit_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => user_unit_dir().ok_or_else(|| {
+            RalphError::ConfigError("no user config directory for systemd units".to_string())
+        })?,
+    };
+    std::fs::create_dir_all(&unit_dir).map_err(RalphError::OutputDirError)?;
+
+    let path = unit_dir.join(spec.unit_name());
+    if path.exists() {
+        return Err(RalphError::ConfigError(format!(
+            "{} already exists",
+            path.display()
+        )));
+    }
+    std::fs::write(&path, spec.render()).map_err(RalphError::OutputDirError)?;
+
+    Ok(format!(
+        "wrote {}\nstart it with: systemctl --user daemon-reload && systemctl --user enable --now {}",
+        path.display(),
+        spec.unit_name()
+    ))
+}
+
+/// Send a state update to systemd; a no-op when not started with `Type=notify`
+pub fn notify(state: &str) {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send(&socket.to_string_lossy(), state) {
+        warn!("Failed to notify systemd: {}", e);
+    } else {
+        debug!("sd_notify: {}", state);
+    }
+}
+
+#[cfg(unix)]
+fn send(socket: &str, state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let datagram = UnixDatagram::unbound()?;
+    #[cfg(target_os = "linux")]
+    if let Some(name) = socket.strip_prefix('@') {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        datagram.send_to_addr(state.as_bytes(), &addr)?;
+        return Ok(());
+    }
+    datagram.send_to(state.as_bytes(), socket)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_socket: &str, _state: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// How often to ping the watchdog: half the timeout systemd configured
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// Report readiness and keep the watchdog fed for the life of the process
+pub fn start() {
+    notify("READY=1");
+    if let Some(interval) = watchdog_interval() {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                notify("WATCHDOG=1");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn spec(name: &str) -> UnitSpec {
+        UnitSpec {
+            name: name.to_string(),
+            executable: PathBuf::from("/usr/local/bin/ralph-loop"),
+            working_dir: PathBuf::from("/srv/my project"),
+            args: vec!["-m".to_string(), "20".to_string()],
+        }
+    }
+
+    #[test]
+    fn renders_notify_unit() {
+        let unit = spec("docs").render();
+
+        assert!(unit.contains("Type=notify\n"));
+        assert!(unit.contains("WorkingDirectory=/srv/my project\n"));
+        assert!(unit.contains("ExecStart=/usr/local/bin/ralph-loop -m 20\n"));
+        assert!(unit.contains(&format!("WatchdogSec={WATCHDOG_SEC}\n")));
+    }
+
+    #[test]
+    fn quotes_special_characters() {
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("50% done"), "\"50%% done\"");
+        assert_eq!(quote(""), "\"\"");
+    }
+
+    #[test]
+    fn install_writes_unit_once() {
+        let temp_dir = TempDir::new().unwrap();
+
+        install(&spec("docs"), Some(temp_dir.path())).unwrap();
+        assert!(temp_dir.path().join("ralph-docs.service").exists());
+
+        let error = install(&spec("docs"), Some(temp_dir.path())).unwrap_err();
+        assert!(error.to_string().contains("already exists"));
+        assert!(install(&spec("../etc"), Some(temp_dir.path())).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn notify_sends_datagram() {
+        use std::os::unix::net::UnixDatagram;
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("notify.sock");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        send(&path.to_string_lossy(), "READY=1").unwrap();
+
+        let mut buf = [0u8; 16];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+    }
+}
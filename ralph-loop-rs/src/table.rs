@@ -0,0 +1,230 @@
+//! Laying out listings like `ralph-loop runs` to fit the terminal.
+//!
+//! Columns are aligned to their widest cell. When the rows don't fit the
+//! terminal, the columns that may be elided are shortened, widest first,
+//! down to their minimum width. Below the sum of the minimums, each row is
+//! stacked instead: the first cell on its own line and the others indented
+//! underneath with their column name.
+
+use std::fs::File;
+use std::io::IsTerminal;
+use std::process::{Command, Stdio};
+
+use crate::text::{display_width, elide_end, elide_start};
+
+/// Spaces between two columns
+const GAP: usize = 2;
+
+/// Which end of a cell is cut off when its column has to be shortened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Elide {
+    /// The column is never shortened
+    Never,
+    /// Keep the start, e.g. for statuses
+    End,
+    /// Keep the end, e.g. for paths
+    Start,
+}
+
+/// A column of a table
+#[derive(Debug, Clone, Copy)]
+pub struct Column {
+    /// Name shown in front of the cell in the stacked layout
+    pub name: &'static str,
+    pub elide: Elide,
+    /// Width the column is never shortened below
+    pub min_width: usize,
+}
+
+impl Column {
+    pub const fn new(name: &'static str, elide: Elide, min_width: usize) -> Self {
+        Self {
+            name,
+            elide,
+            min_width,
+        }
+    }
+}
+
+/// Width of the terminal output goes to: `COLUMNS` if set, otherwise the
+/// size of the terminal if stdout is one. `None` for pipes and files, whose
+/// lines are never shortened.
+pub fn terminal_width() -> Option<usize> {
+    if let Some(columns) = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.trim().parse().ok())
+        .filter(|&columns: &usize| columns > 0)
+    {
+        return Some(columns);
+    }
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    // `stty size` prints "<rows> <columns>" for the terminal on its stdin
+    let output = Command::new("stty")
+        .arg("size")
+        .stdin(File::open("/dev/tty").ok()?)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+        .filter(|&columns: &usize| columns > 0)
+}
+
+/// Render `rows` of `columns` to fit `width`, or at their natural width if
+/// it is `None`
+pub fn render(columns: &[Column], rows: &[Vec<String>], width: Option<usize>) -> String {
+    layout(columns, rows, width, false)
+}
+
+/// Like [`render`], with a first line naming the columns unless the rows
+/// are stacked
+pub fn render_with_header(
+    columns: &[Column],
+    rows: &[Vec<String>],
+    width: Option<usize>,
+) -> String {
+    layout(columns, rows, width, true)
+}
+
+fn layout(columns: &[Column], rows: &[Vec<String>], width: Option<usize>, header: bool) -> String {
+    let header_row: Vec<String> = columns.iter().map(|c| c.name.to_string()).collect();
+    let mut widths: Vec<usize> = (0..columns.len())
+        .map(|i| {
+            rows.iter()
+                .chain(header.then_some(&header_row))
+                .map(|row| row.get(i).map_or(0, |cell| display_width(cell)))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    if let Some(width) = width {
+        let gaps = GAP * columns.len().saturating_sub(1);
+        let total = |widths: &[usize]| widths.iter().sum::<usize>() + gaps;
+        while total(&widths) > width {
+            // Shorten the widest column that can still give up some width
+            let Some(i) = (0..columns.len())
+                .filter(|&i| columns[i].elide != Elide::Never && widths[i] > columns[i].min_width)
+                .max_by_key(|&i| widths[i])
+            else {
+                return stacked(columns, rows, width);
+            };
+            widths[i] -= (total(&widths) - width).min(widths[i] - columns[i].min_width);
+        }
+    }
+
+    header
+        .then_some(&header_row)
+        .into_iter()
+        .chain(rows)
+        .map(|row| {
+            let last = row.len().saturating_sub(1);
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate() {
+                let cell = fit(cell, columns[i].elide, widths[i]);
+                line.push_str(&cell);
+                if i < last {
+                    line.push_str(&" ".repeat(widths[i] - display_width(&cell) + GAP));
+                }
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `cell` shortened to `width` the way its column allows
+fn fit(cell: &str, elide: Elide, width: usize) -> String {
+    match elide {
+        Elide::Never => cell.to_string(),
+        Elide::End => elide_end(cell, width).into_owned(),
+        Elide::Start => elide_start(cell, width).into_owned(),
+    }
+}
+
+/// Each row as its first cell followed by one indented `name: cell` line per
+/// other column, shortened to `width`
+fn stacked(columns: &[Column], rows: &[Vec<String>], width: usize) -> String {
+    rows.iter()
+        .map(|row| {
+            let mut lines = Vec::with_capacity(row.len());
+            for (i, cell) in row.iter().enumerate() {
+                if i == 0 {
+                    lines.push(fit(cell, columns[i].elide, width));
+                    continue;
+                }
+                let label = format!("  {}: ", columns[i].name);
+                let room = width.saturating_sub(display_width(&label));
+                lines.push(format!("{label}{}", fit(cell, columns[i].elide, room)));
+            }
+            lines.join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLUMNS: [Column; 3] = [
+        Column::new("run", Elide::Never, 0),
+        Column::new("status", Elide::End, 6),
+        Column::new("project", Elide::Start, 10),
+    ];
+
+    fn rows() -> Vec<Vec<String>> {
+        vec![
+            vec![
+                "run-1".into(),
+                "paused: quiet hours".into(),
+                "/home/me/projects/app".into(),
+            ],
+            vec!["run-2".into(), "completed".into(), "/srv/日本語".into()],
+        ]
+    }
+
+    #[test]
+    fn columns_are_aligned_and_elided_to_fit() {
+        assert_eq!(
+            render(&COLUMNS, &rows(), None),
+            "run-1  paused: quiet hours  /home/me/projects/app\n\
+             run-2  completed            /srv/日本語"
+        );
+        // The project column is widest, so it gives up width first
+        assert_eq!(
+            render(&COLUMNS, &rows(), Some(40)),
+            "run-1  paused: quiet hours  ...jects/app\n\
+             run-2  completed            /srv/日本語"
+        );
+        assert_eq!(
+            render(&COLUMNS, &rows(), Some(30)),
+            "run-1  paused: ...  ...cts/app\n\
+             run-2  completed    .../日本語"
+        );
+    }
+
+    #[test]
+    fn headers_name_the_columns() {
+        assert_eq!(
+            render_with_header(&COLUMNS, &rows()[1..], None),
+            "run    status     project\n\
+             run-2  completed  /srv/日本語"
+        );
+        assert!(!render_with_header(&COLUMNS, &rows(), Some(22)).starts_with("run "));
+    }
+
+    #[test]
+    fn narrow_terminals_get_a_stacked_layout() {
+        assert_eq!(
+            render(&COLUMNS, &rows(), Some(22)),
+            "run-1\n  status: paused: q...\n  project: ...ects/app\n\
+             run-2\n  status: completed\n  project: /srv/日本語"
+        );
+    }
+}
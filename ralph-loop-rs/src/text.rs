@@ -0,0 +1,221 @@
+//! Cutting text for display and storage without splitting characters, and
+//! the plain-text prompt previews shown in run listings.
+//!
+//! Slicing a `str` at a byte index panics inside a multi-byte character, and
+//! even a cut between characters can separate an accent from its letter or
+//! break up an emoji sequence or a flag. The cuts here happen only between
+//! extended grapheme clusters, and widths are those a terminal shows.
+
+use std::borrow::Cow;
+
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Characters of the prompt kept in the run metadata's `prompt_preview` by default
+pub const DEFAULT_PROMPT_PREVIEW_CHARS: usize = 100;
+
+/// Byte index after the first `max_chars` characters of `text`, moved to
+/// the end of the grapheme the last of them belongs to; `None` if `text`
+/// isn't longer
+pub fn cut_index(text: &str, max_chars: usize) -> Option<usize> {
+    let (index, _) = text.char_indices().nth(max_chars)?;
+    let end = text
+        .grapheme_indices(true)
+        .map(|(start, _)| start)
+        .find(|&start| start >= index)
+        .unwrap_or(text.len());
+    (end < text.len()).then_some(end)
+}
+
+/// The longest prefix of `text` of at most `max_bytes` bytes that doesn't
+/// split a character or grapheme
+pub fn prefix_within_bytes(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let end = text
+        .grapheme_indices(true)
+        .map(|(start, grapheme)| start + grapheme.len())
+        .take_while(|&end| end <= max_bytes)
+        .last()
+        .unwrap_or(0);
+    &text[..end]
+}
+
+/// The extended grapheme clusters of `text`
+fn graphemes(text: &str) -> impl Iterator<Item = &str> {
+    text.graphemes(true)
+}
+
+/// Columns a grapheme takes up in a terminal
+fn grapheme_width(grapheme: &str) -> usize {
+    match grapheme.chars().next() {
+        Some(c) if c.is_control() => 0,
+        Some(_) => grapheme.width(),
+        None => 0,
+    }
+}
+
+/// Columns `text` takes up in a terminal
+pub fn display_width(text: &str) -> usize {
+    graphemes(text).map(grapheme_width).sum()
+}
+
+/// `text` cut to at most `width` columns, ending in `...` if anything was
+/// left out
+pub fn elide_end(text: &str, width: usize) -> Cow<'_, str> {
+    if display_width(text) <= width {
+        return Cow::Borrowed(text);
+    }
+    let mut kept = String::new();
+    let mut used = 0;
+    for grapheme in graphemes(text) {
+        used += grapheme_width(grapheme);
+        if used + 3 > width {
+            break;
+        }
+        kept.push_str(grapheme);
+    }
+    Cow::Owned(format!("{kept}{}", &"..."[..width.min(3)]))
+}
+
+/// `text` cut to at most `width` columns, starting with `...` if anything
+/// was left out; for paths, whose end tells them apart
+pub fn elide_start(text: &str, width: usize) -> Cow<'_, str> {
+    if display_width(text) <= width {
+        return Cow::Borrowed(text);
+    }
+    let kept: Vec<&str> = graphemes(text).collect();
+    let mut start = kept.len();
+    let mut used = 0;
+    while start > 0 {
+        used += grapheme_width(kept[start - 1]);
+        if used + 3 > width {
+            break;
+        }
+        start -= 1;
+    }
+    Cow::Owned(format!(
+        "{}{}",
+        &"..."[..width.min(3)],
+        kept[start..].concat()
+    ))
+}
+
+/// `text` cut after about `max_chars` characters with `...` appended
+pub fn preview(text: &str, max_chars: usize) -> Cow<'_, str> {
+    match cut_index(text, max_chars) {
+        Some(end) => Cow::Owned(format!("{}...", &text[..end])),
+        None => Cow::Borrowed(text),
+    }
+}
+
+/// A plain-text preview of a markdown prompt for listings: terminal escapes,
+/// control characters, code fences, markdown markup and blank lines are
+/// removed, and the result is cut after about `max_chars` characters. The
+/// first line stays the prompt's first line, which summaries use as the task.
+pub fn prompt_preview(prompt: &str, max_chars: usize) -> String {
+    let escapes = Regex::new(r"\x1b\[[0-9;?]*[ -/]*[@-~]").expect("Invalid escape regex");
+    let block_markers = Regex::new(r"^(#{1,6}\s+|>\s*|[-*+]\s+(\[[ xX]\]\s+)?|\d+[.)]\s+)+")
+        .expect("Invalid marker regex");
+    let links = Regex::new(r"!?\[([^\]]*)\]\([^)]*\)").expect("Invalid link regex");
+    let emphasis = Regex::new(r"\*\*|__|`").expect("Invalid emphasis regex");
+
+    let prompt = escapes.replace_all(prompt, "");
+    let lines: Vec<String> = prompt
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("```") && !line.starts_with("~~~"))
+        .map(|line| {
+            let line = block_markers.replace(line, "");
+            let line = links.replace_all(&line, "$1");
+            let line = emphasis.replace_all(&line, "");
+            line.chars()
+                .map(|c| if c.is_control() { ' ' } else { c })
+                .collect::<String>()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|line| !line.is_empty())
+        .collect();
+    preview(&lines.join("\n"), max_chars).into_owned()
+}
+
+/// `text` cut after about `max_chars` characters, noting how many were left out
+pub fn shorten(text: &str, max_chars: usize) -> Cow<'_, str> {
+    match cut_index(text, max_chars) {
+        Some(end) => {
+            let omitted = text[end..].chars().count();
+            Cow::Owned(format!("{}... ({omitted} more chars)", &text[..end]))
+        }
+        None => Cow::Borrowed(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_ascii_text_is_cut_between_graphemes() {
+        assert_eq!(preview("short", 10), "short");
+        assert_eq!(preview("修复解析器中的错误", 4), "修复解析...");
+        // "e" followed by a combining acute accent
+        assert_eq!(preview("cafe\u{301} au lait", 4), "cafe\u{301}...");
+        let family = "👨\u{200D}👩\u{200D}👧";
+        assert_eq!(preview(&format!("{family} ok"), 1), format!("{family}..."));
+        assert_eq!(preview("👍🏽 done", 1), "👍🏽...");
+        assert_eq!(preview("🇩🇪🇫🇷", 1), "🇩🇪...");
+        // Hangul jamo and a Devanagari cluster with a spacing mark
+        assert_eq!(
+            preview("\u{1100}\u{1161}\u{11A8} ok", 1),
+            "\u{1100}\u{1161}\u{11A8}..."
+        );
+        assert_eq!(preview("\u{0915}\u{093F} ok", 1), "\u{0915}\u{093F}...");
+
+        assert_eq!(shorten("naïve café", 4), "naïv... (6 more chars)");
+        assert_eq!(shorten("", 0), "");
+
+        assert_eq!(prefix_within_bytes("日本語", 4), "日");
+        assert_eq!(prefix_within_bytes("日本語", 9), "日本語");
+        assert_eq!(prefix_within_bytes(family, 6), "");
+        assert_eq!(prefix_within_bytes("cafe\u{301}", 4), "caf");
+        assert_eq!(prefix_within_bytes("🇩🇪🇫🇷", 12), "🇩🇪");
+    }
+
+    #[test]
+    fn width_counts_terminal_columns() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("日本語"), 6);
+        assert_eq!(display_width("cafe\u{301}"), 4);
+        assert_eq!(display_width("👨\u{200D}👩\u{200D}👧!"), 3);
+        assert_eq!(display_width("🚀 ship"), 7);
+        assert_eq!(display_width("🇩🇪"), 2);
+        assert_eq!(display_width("🪄"), 2);
+        assert_eq!(elide_end("🚀🚀🚀🚀", 7), "🚀🚀...");
+
+        assert_eq!(elide_end("completed", 20), "completed");
+        assert_eq!(elide_end("paused: quiet hours", 10), "paused:...");
+        assert_eq!(elide_end("日本語のテキスト", 8), "日本...");
+        assert_eq!(elide_end("abcdef", 2), "..");
+        assert_eq!(elide_start("/home/me/projects/app", 16), ".../projects/app");
+        assert_eq!(elide_start("/home/me/日本語", 10), ".../日本語");
+    }
+
+    #[test]
+    fn prompt_preview_is_plain_text() {
+        let prompt = "# Fix the **parser**\n\n\
+                      > Context: see [the issue](https://example.com/1)\n\
+                      ```rust\nfn main() {}\n```\n\
+                      - [ ] add `tests`\r\n\
+                      1. run \x1b[1mcargo\x1b[0m\ttest\u{7}\n";
+        assert_eq!(
+            prompt_preview(prompt, 100),
+            "Fix the parser\nContext: see the issue\nfn main() {}\nadd tests\nrun cargo test"
+        );
+        assert_eq!(prompt_preview(prompt, 7), "Fix the...");
+        assert_eq!(prompt_preview("🔧 修复解析器", 3), "🔧 修...");
+    }
+}
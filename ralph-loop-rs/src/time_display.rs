@@ -0,0 +1,109 @@
+//! How timestamps and durations are shown on the terminal.
+//!
+//! Timestamps are shown in local time by default, always with their offset,
+//! so output copied between time zones stays unambiguous. `--utc` shows them
+//! in UTC, and `--iso-8601` switches timestamps to RFC 3339 and durations to
+//! ISO 8601 (`PT1H2M3S`) for output that is parsed by scripts.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+
+use crate::banner;
+
+/// Time zone timestamps are shown in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeZoneChoice {
+    #[default]
+    Local,
+    Utc,
+}
+
+/// Display options for timestamps and durations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeDisplay {
+    pub zone: TimeZoneChoice,
+    /// RFC 3339 timestamps and ISO 8601 durations
+    pub iso_8601: bool,
+}
+
+impl TimeDisplay {
+    /// Format `at`, e.g. `2026-03-01 14:05:09 +01:00` or `2026-03-01T13:05:09Z`
+    pub fn timestamp(&self, at: DateTime<Utc>) -> String {
+        match (self.zone, self.iso_8601) {
+            (TimeZoneChoice::Utc, true) => at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            (TimeZoneChoice::Utc, false) => at.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+            (TimeZoneChoice::Local, true) => at
+                .with_timezone(&Local)
+                .to_rfc3339_opts(SecondsFormat::Secs, false),
+            (TimeZoneChoice::Local, false) => at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S %:z")
+                .to_string(),
+        }
+    }
+
+    /// The month of `at`, e.g. `2026-03`
+    pub fn month(&self, at: DateTime<Utc>) -> String {
+        match self.zone {
+            TimeZoneChoice::Utc => at.format("%Y-%m").to_string(),
+            TimeZoneChoice::Local => at.with_timezone(&Local).format("%Y-%m").to_string(),
+        }
+    }
+
+    /// Format `duration`, e.g. `4m 05s` or `PT4M5S`
+    pub fn duration(&self, duration: Duration) -> String {
+        if !self.iso_8601 {
+            return banner::format_duration(duration);
+        }
+        let secs = duration.as_secs();
+        let (hours, minutes, seconds) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+        let mut iso = "PT".to_string();
+        if hours > 0 {
+            iso.push_str(&format!("{hours}H"));
+        }
+        if minutes > 0 {
+            iso.push_str(&format!("{minutes}M"));
+        }
+        if seconds > 0 || secs == 0 {
+            iso.push_str(&format!("{seconds}S"));
+        }
+        iso
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn timestamps_and_durations_follow_the_options() {
+        let at = Utc.with_ymd_and_hms(2026, 3, 1, 13, 5, 9).unwrap();
+        let utc = TimeDisplay {
+            zone: TimeZoneChoice::Utc,
+            iso_8601: false,
+        };
+        let iso = TimeDisplay {
+            iso_8601: true,
+            ..utc
+        };
+        assert_eq!(utc.timestamp(at), "2026-03-01 13:05:09 UTC");
+        assert_eq!(iso.timestamp(at), "2026-03-01T13:05:09Z");
+        let local = TimeDisplay::default().timestamp(at);
+        assert!(
+            local.ends_with(
+                &Local
+                    .from_utc_datetime(&at.naive_utc())
+                    .format("%:z")
+                    .to_string()
+            ),
+            "{local}"
+        );
+
+        assert_eq!(utc.duration(Duration::from_secs(245)), "4m 05s");
+        assert_eq!(iso.duration(Duration::from_secs(245)), "PT4M5S");
+        assert_eq!(iso.duration(Duration::from_secs(3600)), "PT1H");
+        assert_eq!(iso.duration(Duration::ZERO), "PT0S");
+    }
+}
@@ -0,0 +1,287 @@
+//! Timeline of the decisions the loop made during a run.
+//!
+//! Each run directory gets a `timeline.jsonl` with one line per decision:
+//! when the run and each iteration started, on which output line the promise
+//! or a milestone was detected, when the agent was told to stop and why, and
+//! how iterations and the run ended. Line numbers count the lines of the
+//! iteration's agent output, starting at 1.
+//!
+//! Decisions made while the agent runs are also written into its kept
+//! output as marker lines, `{"type":"ralph_marker",...}` right after the
+//! line they were made at, so the transcript shows them inline. Marker
+//! lines don't count as output lines and are skipped when the output is
+//! replayed.
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{RalphError, Result};
+use crate::transcript::{ExitReason, IterationEndReason};
+
+/// Name of the timeline inside each run directory
+pub const TIMELINE_FILE: &str = "timeline.jsonl";
+
+/// Start of every marker line in the kept agent output
+const MARKER_PREFIX: &str = r#"{"type":"ralph_marker","#;
+
+/// Why the monitor asked for the agent to be stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KillReason {
+    /// The context limit was reached
+    ContextLimit,
+    /// The next turn was projected to exceed the context limit
+    ProjectedContextLimit,
+    /// The promise was found and `stop_on_promise` is set
+    Promise,
+    /// The agent asked for a fresh context
+    RestartRequested,
+}
+
+impl fmt::Display for KillReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ContextLimit => "context limit",
+            Self::ProjectedContextLimit => "projected context limit",
+            Self::Promise => "promise",
+            Self::RestartRequested => "restart requested",
+        })
+    }
+}
+
+/// A decision of the loop controller or the monitor
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TimelineEvent {
+    /// The loop started
+    RunStarted,
+    /// A stopped run was continued after `after_iteration`
+    RunResumed { after_iteration: u32 },
+    /// An iteration started
+    IterationStarted,
+    /// A milestone was detected in the agent output
+    MilestoneReached { promise: String, line: u64 },
+    /// The promise was detected and accepted
+    PromiseDetected { line: u64 },
+    /// The promise was detected but doesn't count yet
+    PromiseIgnored { line: u64, reason: String },
+    /// The monitor asked for the agent to be stopped
+    KillSent {
+        reason: KillReason,
+        line: u64,
+        /// Context size the decision was based on, if it was about context
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tokens: Option<usize>,
+    },
+    /// The agent's context reached `context_limit.warning_threshold`
+    ContextWarning {
+        tokens: usize,
+        threshold: usize,
+        line: u64,
+    },
+    /// The agent's context filled past `percent` of `context_limit.max_tokens`
+    ContextFilled {
+        percent: u8,
+        tokens: usize,
+        line: u64,
+    },
+    /// An iteration failed because the network is down; the loop waits for it
+    NetworkLost { error: String },
+    /// The network is reachable again after `offline_secs`
+    NetworkRestored { offline_secs: u64 },
+    /// An iteration ended
+    IterationEnded { reason: IterationEndReason },
+    /// The run ended
+    RunCompleted { exit_reason: ExitReason },
+}
+
+impl fmt::Display for TimelineEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RunStarted => write!(f, "run started"),
+            Self::RunResumed { after_iteration } => {
+                write!(f, "run resumed after iteration {after_iteration}")
+            }
+            Self::IterationStarted => write!(f, "iteration started"),
+            Self::MilestoneReached { promise, line } => {
+                write!(f, "line {line}: milestone \"{promise}\" reached")
+            }
+            Self::PromiseDetected { line } => write!(f, "line {line}: promise detected"),
+            Self::PromiseIgnored { line, reason } => {
+                write!(f, "line {line}: promise ignored, {reason}")
+            }
+            Self::KillSent {
+                reason,
+                line,
+                tokens,
+            } => {
+                write!(f, "line {line}: stop requested ({reason}")?;
+                if let Some(tokens) = tokens {
+                    write!(f, ", {tokens} tokens")?;
+                }
+                write!(f, ")")
+            }
+            Self::ContextWarning {
+                tokens,
+                threshold,
+                line,
+            } => write!(
+                f,
+                "line {line}: context warning ({tokens} tokens, threshold {threshold})"
+            ),
+            Self::ContextFilled {
+                percent,
+                tokens,
+                line,
+            } => write!(f, "line {line}: context {percent}% full ({tokens} tokens)"),
+            Self::NetworkLost { error } => write!(f, "network lost: {error}"),
+            Self::NetworkRestored { offline_secs } => {
+                write!(f, "network back after {offline_secs}s")
+            }
+            Self::IterationEnded { reason } => write!(f, "iteration ended ({reason:?})"),
+            Self::RunCompleted { exit_reason } => write!(f, "run completed ({exit_reason:?})"),
+        }
+    }
+}
+
+/// One line of `timeline.jsonl`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimelineEntry {
+    pub at: DateTime<Utc>,
+    /// Iteration the decision belongs to, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iteration: Option<u32>,
+    #[serde(flatten)]
+    pub event: TimelineEvent,
+}
+
+impl TimelineEntry {
+    /// An entry for `event` happening now
+    pub fn now(event: TimelineEvent) -> Self {
+        Self {
+            at: Utc::now(),
+            iteration: None,
+            event,
+        }
+    }
+
+    /// Marker line for the kept agent output, with the decision described
+    /// in `message` for reading the transcript
+    pub fn marker(&self) -> String {
+        let entry = serde_json::to_string(self).expect("timeline entries serialize to JSON");
+        let message =
+            serde_json::to_string(&self.event.to_string()).expect("strings serialize to JSON");
+        format!("{MARKER_PREFIX}\"message\":{message},{}", &entry[1..])
+    }
+}
+
+/// Whether `line` of agent output is a marker written by ralph-loop
+pub fn is_marker(line: &str) -> bool {
+    line.trim_start().starts_with(MARKER_PREFIX)
+}
+
+/// Append entries to the timeline in `run_dir`
+pub fn append(run_dir: &Path, entries: &[TimelineEntry]) -> Result<()> {
+    let mut lines = String::new();
+    for entry in entries {
+        lines.push_str(
+            &serde_json::to_string(entry)
+                .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?,
+        );
+        lines.push('\n');
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(run_dir.join(TIMELINE_FILE))
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+    file.write_all(lines.as_bytes())
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))
+}
+
+/// The timeline of the run in `run_dir`; empty if nothing was recorded
+pub fn load(run_dir: &Path) -> Result<Vec<TimelineEntry>> {
+    let path = run_dir.join(TIMELINE_FILE);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(RalphError::TranscriptWriteError(format!(
+                "{}: {}",
+                path.display(),
+                e
+            )))
+        }
+    };
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .map_err(|e| RalphError::TranscriptWriteError(format!("{}: {}", path.display(), e)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn entries_round_trip_through_the_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut kill = TimelineEntry::now(TimelineEvent::KillSent {
+            reason: KillReason::ContextLimit,
+            line: 42,
+            tokens: Some(180_512),
+        });
+        kill.iteration = Some(2);
+        let entries = vec![
+            TimelineEntry::now(TimelineEvent::RunStarted),
+            kill,
+            TimelineEntry::now(TimelineEvent::RunCompleted {
+                exit_reason: ExitReason::PromiseFulfilled,
+            }),
+        ];
+
+        assert!(load(temp_dir.path()).unwrap().is_empty());
+        append(temp_dir.path(), &entries[..2]).unwrap();
+        append(temp_dir.path(), &entries[2..]).unwrap();
+
+        assert_eq!(load(temp_dir.path()).unwrap(), entries);
+        let content = std::fs::read_to_string(temp_dir.path().join(TIMELINE_FILE)).unwrap();
+        let kill_line = content.lines().nth(1).unwrap();
+        assert!(kill_line.contains(r#""iteration":2"#), "{kill_line}");
+        assert!(
+            kill_line.contains(r#""event":"kill_sent","reason":"context_limit","line":42"#),
+            "{kill_line}"
+        );
+    }
+
+    #[test]
+    fn markers_are_recognized_and_carry_the_decision() {
+        let entry = TimelineEntry::now(TimelineEvent::ContextWarning {
+            tokens: 150_200,
+            threshold: 150_000,
+            line: 12,
+        });
+        let marker = entry.marker();
+        assert!(is_marker(&marker));
+        assert!(!is_marker(r#"{"type":"assistant","message":{}}"#));
+
+        let value: serde_json::Value = serde_json::from_str(&marker).unwrap();
+        assert_eq!(value["type"], "ralph_marker");
+        assert_eq!(value["event"], "context_warning");
+        assert_eq!(value["line"], 12);
+        assert_eq!(
+            value["message"],
+            "line 12: context warning (150200 tokens, threshold 150000)"
+        );
+    }
+}
@@ -1,38 +1,90 @@
+//! Token estimation for prompts and for agents that don't report usage.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, OnceLock};
+
 use crate::config::TokenEstimationMethod;
 
-/// Token counter for estimating context size
+/// Claude's tokenizer produces somewhat more tokens than cl100k_base for
+/// typical code and prose; scale up so estimates err towards restarting early
+const CLAUDE_TOKEN_RATIO: f64 = 1.15;
+
+/// Number of recently counted strings to remember
+const CACHE_CAPACITY: usize = 256;
+
+/// Token counter for estimating context size. The tokenizer is loaded on
+/// first use, so creating a counter is cheap.
 pub struct TokenCounter {
     method: TokenEstimationMethod,
-    bpe: Option<tiktoken_rs::CoreBPE>,
+    bpe: OnceLock<Option<tiktoken_rs::CoreBPE>>,
+    cache: Mutex<CountCache>,
 }
 
 impl TokenCounter {
     /// Create a new TokenCounter with the specified estimation method
     pub fn new(method: TokenEstimationMethod) -> Self {
-        let bpe = if method == TokenEstimationMethod::Tiktoken {
-            tiktoken_rs::cl100k_base().ok()
-        } else {
-            None
-        };
-
-        Self { method, bpe }
+        Self {
+            method,
+            bpe: OnceLock::new(),
+            cache: Mutex::new(CountCache::default()),
+        }
     }
 
     /// Estimate the token count for the given text
     pub fn count(&self, text: &str) -> usize {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        self.count_cached(&mut cache, text)
+    }
+
+    /// Estimate the token counts of several texts at once
+    pub fn count_many<'a>(&self, texts: impl IntoIterator<Item = &'a str>) -> Vec<usize> {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        texts
+            .into_iter()
+            .map(|text| self.count_cached(&mut cache, text))
+            .collect()
+    }
+
+    fn count_cached(&self, cache: &mut CountCache, text: &str) -> usize {
+        let key = cache_key(text);
+        if let Some(count) = cache.get(key) {
+            return count;
+        }
+        let count = self.estimate(text);
+        cache.insert(key, count);
+        count
+    }
+
+    fn estimate(&self, text: &str) -> usize {
         match self.method {
-            TokenEstimationMethod::Tiktoken => {
-                if let Some(ref bpe) = self.bpe {
-                    bpe.encode_with_special_tokens(text).len()
-                } else {
-                    // Fallback to byte ratio if tiktoken fails to initialize
-                    text.len() / 4
-                }
+            TokenEstimationMethod::Tiktoken => self.cl100k(text),
+            TokenEstimationMethod::Claude => {
+                (self.cl100k(text) as f64 * CLAUDE_TOKEN_RATIO).ceil() as usize
             }
             TokenEstimationMethod::ByteRatio => text.len() / 4,
             TokenEstimationMethod::CharRatio => text.chars().count() / 4,
         }
     }
+
+    fn cl100k(&self, text: &str) -> usize {
+        if let Some(bpe) = self.bpe.get_or_init(|| tiktoken_rs::cl100k_base().ok()) {
+            bpe.encode_with_special_tokens(text).len()
+        } else {
+            // Fallback to byte ratio if tiktoken fails to initialize
+            text.len() / 4
+        }
+    }
+}
+
+impl fmt::Debug for TokenCounter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenCounter")
+            .field("method", &self.method)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Default for TokenCounter {
@@ -41,6 +93,48 @@ impl Default for TokenCounter {
     }
 }
 
+fn cache_key(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Small LRU cache of counts, keyed by text hash
+#[derive(Default)]
+struct CountCache {
+    counts: HashMap<u64, usize>,
+    /// Keys from least to most recently used
+    order: VecDeque<u64>,
+}
+
+impl CountCache {
+    fn get(&mut self, key: u64) -> Option<usize> {
+        let count = *self.counts.get(&key)?;
+        self.touch(key);
+        Some(count)
+    }
+
+    fn insert(&mut self, key: u64, count: usize) {
+        if self.counts.insert(key, count).is_some() {
+            self.touch(key);
+            return;
+        }
+        self.order.push_back(key);
+        if self.order.len() > CACHE_CAPACITY {
+            if let Some(evicted) = self.order.pop_front() {
+                self.counts.remove(&evicted);
+            }
+        }
+    }
+
+    fn touch(&mut self, key: u64) {
+        if let Some(position) = self.order.iter().position(|k| *k == key) {
+            self.order.remove(position);
+        }
+        self.order.push_back(key);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -68,6 +162,39 @@ mod tests {
         assert!(count < 10);
     }
 
+    #[test]
+    fn test_claude_estimation_exceeds_cl100k() {
+        let tiktoken = TokenCounter::new(TokenEstimationMethod::Tiktoken);
+        let claude = TokenCounter::new(TokenEstimationMethod::Claude);
+        let text = "fn main() { println!(\"Hello, world!\"); }";
+
+        assert!(claude.count(text) > tiktoken.count(text));
+    }
+
+    #[test]
+    fn test_count_many_matches_count() {
+        let counter = TokenCounter::new(TokenEstimationMethod::ByteRatio);
+        let texts = ["12345678", "1234567890123456", "12345678"];
+
+        assert_eq!(counter.count_many(texts), vec![2, 4, 2]);
+        assert_eq!(counter.count("1234567890123456"), 4);
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let mut cache = CountCache::default();
+        for key in 0..CACHE_CAPACITY as u64 {
+            cache.insert(key, 1);
+        }
+        assert_eq!(cache.get(0), Some(1));
+
+        cache.insert(u64::MAX, 2);
+
+        assert_eq!(cache.get(0), Some(1));
+        assert_eq!(cache.get(1), None);
+        assert_eq!(cache.counts.len(), CACHE_CAPACITY);
+    }
+
     #[test]
     fn test_estimates_within_range() {
         let tiktoken = TokenCounter::new(TokenEstimationMethod::Tiktoken);
@@ -0,0 +1,440 @@
+//! Tool calls of the agent, each paired with its result.
+//!
+//! The agent's output interleaves tool requests with their results, and with
+//! parallel calls a result seldom follows its request. The monitor pairs them
+//! by tool use ID, and each run directory gets a `tool_calls.jsonl` with one
+//! line per call: the tool, the output lines of the request and of the
+//! result, how long the call took and whether it failed. A call the agent was
+//! stopped during has no result line.
+//!
+//! `ralph-loop tools <run-id>` sums the calls up per tool, showing where a
+//! run spent its time, such as running the test suite over and over. With
+//! `--print-paths` or `--edit` it lists or opens the files the calls named.
+//! `--calls` lists every call instead, with the calls of a sub-agent started
+//! by the Task tool indented under that Task call.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{RalphError, Result};
+use crate::json_events::{ToolResultRef, ToolUse};
+
+/// Name of the tool call log inside each run directory
+pub const TOOL_CALLS_FILE: &str = "tool_calls.jsonl";
+
+/// A tool call from the agent's request to its result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolSpan {
+    /// Iteration the call was made in, set when it is written
+    #[serde(default)]
+    pub iteration: u32,
+    /// ID pairing the request with its result
+    pub id: String,
+    /// Name of the tool
+    pub name: String,
+    /// File the tool works on, as the agent named it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// ID of the Task call whose sub-agent made this call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Output line of the request, counted from 1
+    pub line: u64,
+    /// Output line of the result; `None` if the agent stopped before it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result_line: Option<u64>,
+    /// Time from the request to the result, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    /// Whether the result reported a failure
+    #[serde(default)]
+    pub is_error: bool,
+    /// Size of the result's text, in bytes
+    #[serde(default)]
+    pub output_bytes: u64,
+}
+
+impl fmt::Display for ToolSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.result_line, self.duration_ms) {
+            (Some(result_line), Some(ms)) => write!(
+                f,
+                "lines {}-{}: {} ({:.1}s{})",
+                self.line,
+                result_line,
+                self.name,
+                ms as f64 / 1000.0,
+                if self.is_error { ", failed" } else { "" }
+            ),
+            _ => write!(f, "line {}: {} (no result)", self.line, self.name),
+        }
+    }
+}
+
+/// Pairs the tool requests in an agent's output with their results
+#[derive(Debug, Default)]
+pub struct ToolTracker {
+    /// Calls in the order they were requested
+    spans: Vec<ToolSpan>,
+    /// Index into `spans` and request time of the calls without a result, by ID
+    pending: HashMap<String, (usize, Instant)>,
+}
+
+impl ToolTracker {
+    /// Record `tool_use` requested on output line `line`
+    pub fn requested(&mut self, tool_use: &ToolUse, line: u64, at: Instant) {
+        self.pending
+            .insert(tool_use.id.clone(), (self.spans.len(), at));
+        self.spans.push(ToolSpan {
+            iteration: 0,
+            id: tool_use.id.clone(),
+            name: tool_use.name.clone(),
+            path: tool_use.path.clone(),
+            parent: tool_use.parent.clone(),
+            line,
+            result_line: None,
+            duration_ms: None,
+            is_error: false,
+            output_bytes: 0,
+        });
+    }
+
+    /// Record `result` on output line `line`; the finished call, or `None`
+    /// if the request it belongs to isn't pending
+    pub fn finished(
+        &mut self,
+        result: &ToolResultRef,
+        line: u64,
+        at: Instant,
+    ) -> Option<&ToolSpan> {
+        let (index, requested_at) = self.pending.remove(&result.tool_use_id)?;
+        let span = &mut self.spans[index];
+        span.result_line = Some(line);
+        span.duration_ms = Some(at.duration_since(requested_at).as_millis() as u64);
+        span.is_error = result.is_error;
+        span.output_bytes = result.text.len() as u64;
+        Some(span)
+    }
+
+    /// Name of the latest call still waiting for its result
+    pub fn running(&self) -> Option<&str> {
+        self.pending
+            .values()
+            .map(|&(index, _)| index)
+            .max()
+            .map(|index| self.spans[index].name.as_str())
+    }
+
+    /// All calls so far, in the order they were requested
+    pub fn spans(&self) -> &[ToolSpan] {
+        &self.spans
+    }
+}
+
+/// Usage of one tool over a run
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ToolStats {
+    pub name: String,
+    pub calls: u64,
+    /// Calls that got a result; only they have a duration
+    pub finished: u64,
+    /// Time from request to result summed over the finished calls
+    pub total_ms: u64,
+    /// Calls whose result reported a failure
+    pub failures: u64,
+    /// Bytes of result text
+    pub output_bytes: u64,
+}
+
+impl ToolStats {
+    /// Mean time from request to result, in milliseconds
+    pub fn average_ms(&self) -> u64 {
+        self.total_ms.checked_div(self.finished).unwrap_or(0)
+    }
+}
+
+/// Usage per tool, the tools the most time went to first
+pub fn stats(spans: &[ToolSpan]) -> Vec<ToolStats> {
+    let mut by_name: BTreeMap<&str, ToolStats> = BTreeMap::new();
+    for span in spans {
+        let stats = by_name.entry(&span.name).or_insert_with(|| ToolStats {
+            name: span.name.clone(),
+            ..ToolStats::default()
+        });
+        stats.calls += 1;
+        if let Some(ms) = span.duration_ms {
+            stats.finished += 1;
+            stats.total_ms += ms;
+        }
+        stats.failures += u64::from(span.is_error);
+        stats.output_bytes += span.output_bytes;
+    }
+    let mut stats: Vec<_> = by_name.into_values().collect();
+    stats.sort_by(|a, b| b.total_ms.cmp(&a.total_ms).then(b.calls.cmp(&a.calls)));
+    stats
+}
+
+/// The files the calls named, in the order they were first named, with
+/// relative paths resolved against `project_path`
+pub fn referenced_paths(spans: &[ToolSpan], project_path: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for path in spans.iter().filter_map(|span| span.path.as_deref()) {
+        let path = project_path.join(path);
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+
+/// Every call on its own line, grouped by iteration. The calls of a
+/// sub-agent follow the Task call that started it, indented one level deeper.
+pub fn render_calls(spans: &[ToolSpan]) -> String {
+    let mut lines = Vec::new();
+    let mut iteration = None;
+    for (index, span) in spans.iter().enumerate() {
+        if iteration != Some(span.iteration) {
+            iteration = Some(span.iteration);
+            lines.push(format!("iteration {}:", span.iteration));
+        }
+        let is_root = span.parent.as_ref().is_none_or(|parent| {
+            !spans[..index]
+                .iter()
+                .any(|other| other.iteration == span.iteration && &other.id == parent)
+        });
+        if is_root {
+            push_call(spans, index, 1, &mut lines);
+        }
+    }
+    lines.join("\n")
+}
+
+/// Add the call at `index` and the calls of its sub-agent to `lines`
+fn push_call(spans: &[ToolSpan], index: usize, depth: usize, lines: &mut Vec<String>) {
+    let span = &spans[index];
+    lines.push(format!("{}{}", "  ".repeat(depth), span));
+    for (child, other) in spans.iter().enumerate().skip(index + 1) {
+        if other.iteration == span.iteration && other.parent.as_ref() == Some(&span.id) {
+            push_call(spans, child, depth + 1, lines);
+        }
+    }
+}
+
+/// Append the tool calls of `iteration` to the log in `run_dir`
+pub fn append(run_dir: &Path, iteration: u32, spans: &[ToolSpan]) -> Result<()> {
+    let mut lines = String::new();
+    for span in spans {
+        let span = ToolSpan {
+            iteration,
+            ..span.clone()
+        };
+        lines.push_str(
+            &serde_json::to_string(&span)
+                .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?,
+        );
+        lines.push('\n');
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(run_dir.join(TOOL_CALLS_FILE))
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+    file.write_all(lines.as_bytes())
+        .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))
+}
+
+/// The tool calls of the run in `run_dir`; empty if none were recorded
+pub fn load(run_dir: &Path) -> Result<Vec<ToolSpan>> {
+    let path = run_dir.join(TOOL_CALLS_FILE);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(RalphError::TranscriptWriteError(format!(
+                "{}: {}",
+                path.display(),
+                e
+            )))
+        }
+    };
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .map_err(|e| RalphError::TranscriptWriteError(format!("{}: {}", path.display(), e)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn tool_use(id: &str, name: &str) -> ToolUse {
+        ToolUse {
+            id: id.to_string(),
+            name: name.to_string(),
+            path: None,
+            parent: None,
+        }
+    }
+
+    fn result(id: &str, is_error: bool, output_bytes: usize) -> ToolResultRef {
+        ToolResultRef {
+            tool_use_id: id.to_string(),
+            is_error,
+            text: "x".repeat(output_bytes),
+        }
+    }
+
+    #[test]
+    fn parallel_calls_are_paired_by_id() {
+        let start = Instant::now();
+        let mut tracker = ToolTracker::default();
+        tracker.requested(&tool_use("a", "Bash"), 3, start);
+        tracker.requested(&tool_use("b", "Read"), 3, start);
+        tracker.requested(&tool_use("c", "Grep"), 4, start);
+
+        let read = tracker
+            .finished(
+                &result("b", false, 10),
+                5,
+                start + Duration::from_millis(200),
+            )
+            .unwrap();
+        assert_eq!(read.to_string(), "lines 3-5: Read (0.2s)");
+        let bash = tracker
+            .finished(
+                &result("a", true, 20),
+                6,
+                start + Duration::from_millis(1500),
+            )
+            .unwrap();
+        assert_eq!(bash.to_string(), "lines 3-6: Bash (1.5s, failed)");
+        assert!(tracker.finished(&result("a", false, 0), 7, start).is_none());
+
+        let names: Vec<_> = tracker.spans().iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["Bash", "Read", "Grep"]);
+        assert_eq!(tracker.spans()[2].to_string(), "line 4: Grep (no result)");
+
+        let temp_dir = TempDir::new().unwrap();
+        assert!(load(temp_dir.path()).unwrap().is_empty());
+        append(temp_dir.path(), 2, tracker.spans()).unwrap();
+        let loaded = load(temp_dir.path()).unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert!(loaded.iter().all(|span| span.iteration == 2));
+        assert_eq!(loaded[1].duration_ms, Some(200));
+        assert_eq!(loaded[1].output_bytes, 10);
+    }
+
+    #[test]
+    fn stats_sum_up_calls_per_tool() {
+        let span = |name: &str, duration_ms: Option<u64>, is_error: bool| ToolSpan {
+            iteration: 1,
+            id: String::new(),
+            name: name.to_string(),
+            path: None,
+            parent: None,
+            line: 1,
+            result_line: duration_ms.map(|_| 2),
+            duration_ms,
+            is_error,
+            output_bytes: 100,
+        };
+        let spans = [
+            span("Read", Some(100), false),
+            span("Bash", Some(60_000), true),
+            span("Bash", Some(30_000), false),
+            span("Bash", None, false),
+        ];
+
+        let stats = stats(&spans);
+        assert_eq!(
+            stats[0],
+            ToolStats {
+                name: "Bash".to_string(),
+                calls: 3,
+                finished: 2,
+                total_ms: 90_000,
+                failures: 1,
+                output_bytes: 300,
+            }
+        );
+        assert_eq!(stats[0].average_ms(), 45_000);
+        assert_eq!(stats[1].name, "Read");
+        assert_eq!(ToolStats::default().average_ms(), 0);
+    }
+
+    #[test]
+    fn sub_agent_calls_are_listed_under_their_task_call() {
+        let start = Instant::now();
+        let mut tracker = ToolTracker::default();
+        let nested = |id: &str, name: &str, parent: &str| ToolUse {
+            parent: Some(parent.to_string()),
+            ..tool_use(id, name)
+        };
+        tracker.requested(&tool_use("task", "Task"), 2, start);
+        tracker.requested(&tool_use("bash", "Bash"), 2, start);
+        tracker.requested(&nested("grep", "Grep", "task"), 3, start);
+        tracker.requested(&nested("inner", "Task", "task"), 4, start);
+        tracker.requested(&nested("read", "Read", "inner"), 5, start);
+        tracker.requested(&nested("lost", "Read", "gone"), 6, start);
+        tracker.finished(&result("task", false, 0), 7, start + Duration::from_secs(3));
+
+        let mut spans = tracker.spans().to_vec();
+        spans.push(ToolSpan {
+            iteration: 2,
+            ..spans[1].clone()
+        });
+        assert_eq!(
+            render_calls(&spans),
+            "iteration 0:\n\
+             \x20 lines 2-7: Task (3.0s)\n\
+             \x20   line 3: Grep (no result)\n\
+             \x20   line 4: Task (no result)\n\
+             \x20     line 5: Read (no result)\n\
+             \x20 line 2: Bash (no result)\n\
+             \x20 line 6: Read (no result)\n\
+             iteration 2:\n\
+             \x20 line 2: Bash (no result)"
+        );
+    }
+
+    #[test]
+    fn referenced_paths_are_resolved_against_the_project() {
+        let span = |path: Option<&str>| ToolSpan {
+            iteration: 1,
+            id: String::new(),
+            name: "Read".to_string(),
+            path: path.map(str::to_string),
+            parent: None,
+            line: 1,
+            result_line: None,
+            duration_ms: None,
+            is_error: false,
+            output_bytes: 0,
+        };
+        let spans = [
+            span(Some("src/lib.rs")),
+            span(None),
+            span(Some("/etc/hosts")),
+            span(Some("src/lib.rs")),
+        ];
+        assert_eq!(
+            referenced_paths(&spans, Path::new("/home/me/app")),
+            [
+                PathBuf::from("/home/me/app/src/lib.rs"),
+                PathBuf::from("/etc/hosts")
+            ]
+        );
+    }
+}
@@ -0,0 +1,447 @@
+//! Local tools for the API-based agents.
+//!
+//! The CLI agents edit files and run commands themselves. The API backends
+//! only return text, so ralph-loop offers them a minimal set of tools and
+//! executes the calls locally: `read_file`, `write_file` and `bash`. Which
+//! tools are offered follows `permissions`: `default` allows reading only,
+//! `acceptEdits` adds writing, and `skip` adds running shell commands. Paths
+//! must stay inside the directory the agent runs in. Commands run in a
+//! process group of their own, killed as a whole when the call ends, and
+//! without the API keys of ralph-loop's providers in their environment.
+
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::process::Command;
+
+use crate::config::{PermissionMode, ToolsConfig};
+use crate::text::prefix_within_bytes;
+
+/// A complete tool call requested by the model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    /// ID the result has to reference
+    pub id: String,
+    /// Name of the tool
+    pub name: String,
+    /// JSON-encoded arguments
+    pub arguments: String,
+}
+
+impl ToolCall {
+    /// The arguments as JSON, or an empty object if they don't parse
+    pub fn input(&self) -> Value {
+        serde_json::from_str(&self.arguments).unwrap_or_else(|_| json!({}))
+    }
+}
+
+/// Output of an executed tool call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolOutput {
+    /// Text handed back to the model
+    pub content: String,
+    /// Whether the call failed or was refused
+    pub is_error: bool,
+}
+
+impl ToolOutput {
+    fn ok(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            is_error: false,
+        }
+    }
+
+    fn error(content: impl Into<String>) -> Self {
+        Self {
+            content: content.into(),
+            is_error: true,
+        }
+    }
+}
+
+/// A tool offered to the model, with a JSON schema for its arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolDefinition {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub parameters: Value,
+}
+
+/// Executes tool calls inside a root directory
+#[derive(Debug, Clone)]
+pub struct ToolExecutor {
+    root: PathBuf,
+    permissions: PermissionMode,
+    config: ToolsConfig,
+    /// Environment variables removed for commands
+    hidden_env: Vec<String>,
+}
+
+impl ToolExecutor {
+    /// Create an executor confined to `root`
+    pub fn new(root: &Path, permissions: PermissionMode, config: ToolsConfig) -> Self {
+        Self {
+            root: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+            permissions,
+            config,
+            hidden_env: Vec::new(),
+        }
+    }
+
+    /// Run commands without the environment variables `names`
+    pub fn hiding_env(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.hidden_env.extend(names);
+        self
+    }
+
+    fn allows(&self, name: &str) -> bool {
+        match name {
+            "read_file" => true,
+            "write_file" => self.permissions != PermissionMode::Default,
+            "bash" => self.permissions == PermissionMode::Skip,
+            _ => false,
+        }
+    }
+
+    /// Tools offered to the model under the configured permissions
+    pub fn definitions(&self) -> Vec<ToolDefinition> {
+        let all = [
+            ToolDefinition {
+                name: "read_file",
+                description: "Read a text file. Paths are relative to the project directory.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": { "path": { "type": "string" } },
+                    "required": ["path"],
+                }),
+            },
+            ToolDefinition {
+                name: "write_file",
+                description: "Create or overwrite a text file with the given content. \
+                              Paths are relative to the project directory.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string" },
+                        "content": { "type": "string" },
+                    },
+                    "required": ["path", "content"],
+                }),
+            },
+            ToolDefinition {
+                name: "bash",
+                description: "Run a shell command in the project directory and return its \
+                              combined output and exit code.",
+                parameters: json!({
+                    "type": "object",
+                    "properties": { "command": { "type": "string" } },
+                    "required": ["command"],
+                }),
+            },
+        ];
+        all.into_iter()
+            .filter(|definition| self.allows(definition.name))
+            .collect()
+    }
+
+    /// Execute one call. Failures are reported to the model, not returned.
+    pub async fn execute(&self, call: &ToolCall, env: &[(String, String)]) -> ToolOutput {
+        if !self.allows(&call.name) {
+            return ToolOutput::error(format!(
+                "tool `{}` is not available with the current permissions",
+                call.name
+            ));
+        }
+        let input = call.input();
+        let arg = |key: &str| input.get(key).and_then(|v| v.as_str());
+
+        match (
+            call.name.as_str(),
+            arg("path"),
+            arg("content"),
+            arg("command"),
+        ) {
+            ("read_file", Some(path), _, _) => self.read_file(path),
+            ("write_file", Some(path), Some(content), _) => self.write_file(path, content),
+            ("bash", _, _, Some(command)) => self.bash(command, env).await,
+            (name, ..) => ToolOutput::error(format!("missing arguments for `{name}`")),
+        }
+    }
+
+    /// Resolve `path` inside the root, following symlinks of the parts
+    /// that exist, or explain why it is outside
+    fn resolve(&self, path: &str) -> std::result::Result<PathBuf, String> {
+        let mut resolved = self.root.clone();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(part) => resolved.push(part),
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                Component::RootDir | Component::Prefix(_) => resolved = PathBuf::from("/"),
+                Component::CurDir => {}
+            }
+        }
+        if !resolved.starts_with(&self.root) {
+            return Err(format!("{path} is outside the project directory"));
+        }
+        // A symlink may still point elsewhere
+        let existing = resolved
+            .ancestors()
+            .find(|ancestor| ancestor.exists())
+            .and_then(|ancestor| ancestor.canonicalize().ok());
+        match existing {
+            Some(existing) if !existing.starts_with(&self.root) => {
+                Err(format!("{path} is outside the project directory"))
+            }
+            _ => Ok(resolved),
+        }
+    }
+
+    fn read_file(&self, path: &str) -> ToolOutput {
+        let resolved = match self.resolve(path) {
+            Ok(resolved) => resolved,
+            Err(e) => return ToolOutput::error(e),
+        };
+        match std::fs::read_to_string(&resolved) {
+            Ok(content) => ToolOutput::ok(self.truncate(content)),
+            Err(e) => ToolOutput::error(format!("failed to read {path}: {e}")),
+        }
+    }
+
+    fn write_file(&self, path: &str, content: &str) -> ToolOutput {
+        let resolved = match self.resolve(path) {
+            Ok(resolved) => resolved,
+            Err(e) => return ToolOutput::error(e),
+        };
+        if let Some(parent) = resolved.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                return ToolOutput::error(format!("failed to create {}: {e}", parent.display()));
+            }
+        }
+        match std::fs::write(&resolved, content) {
+            Ok(()) => ToolOutput::ok(format!("wrote {} bytes to {path}", content.len())),
+            Err(e) => ToolOutput::error(format!("failed to write {path}: {e}")),
+        }
+    }
+
+    async fn bash(&self, command: &str, env: &[(String, String)]) -> ToolOutput {
+        let timeout = Duration::from_secs(self.config.bash_timeout_secs);
+        let mut shell = Command::new("sh");
+        shell
+            .arg("-c")
+            .arg(command)
+            .current_dir(&self.root)
+            .envs(env.iter().map(|(key, value)| (key, value)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true);
+        for name in &self.hidden_env {
+            shell.env_remove(name);
+        }
+        let child = match shell.spawn() {
+            Ok(child) => child,
+            Err(e) => return ToolOutput::error(format!("failed to run command: {e}")),
+        };
+        // Whatever the command started in the background goes with it
+        let _group = child.id().map(ProcessGroup);
+        let output = tokio::time::timeout(timeout, child.wait_with_output()).await;
+        match output {
+            Ok(Ok(output)) => {
+                let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+                text.push_str(&String::from_utf8_lossy(&output.stderr));
+                let code = output
+                    .status
+                    .code()
+                    .map_or("killed".to_string(), |c| c.to_string());
+                let content = format!("{}\n[exit code: {code}]", self.truncate(text));
+                if output.status.success() {
+                    ToolOutput::ok(content)
+                } else {
+                    ToolOutput::error(content)
+                }
+            }
+            Ok(Err(e)) => ToolOutput::error(format!("failed to run command: {e}")),
+            Err(_) => ToolOutput::error(format!("command timed out after {timeout:?}")),
+        }
+    }
+
+    /// Cut `text` to the configured maximum, keeping the beginning
+    fn truncate(&self, text: String) -> String {
+        let max = self.config.max_output_bytes;
+        if text.len() <= max {
+            return text;
+        }
+        let kept = prefix_within_bytes(&text, max);
+        format!(
+            "{}\n[truncated {} of {} bytes]",
+            kept,
+            text.len() - kept.len(),
+            text.len()
+        )
+    }
+}
+
+/// A process group, killed when dropped
+struct ProcessGroup(u32);
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        let _ = std::process::Command::new("kill")
+            .args(["-s", "KILL", "--", &format!("-{}", self.0)])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn call(name: &str, arguments: Value) -> ToolCall {
+        ToolCall {
+            id: "call_1".to_string(),
+            name: name.to_string(),
+            arguments: arguments.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_and_reads_inside_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let executor = ToolExecutor::new(
+            temp_dir.path(),
+            PermissionMode::AcceptEdits,
+            ToolsConfig::default(),
+        );
+
+        let written = executor
+            .execute(
+                &call(
+                    "write_file",
+                    json!({ "path": "src/a.txt", "content": "hello" }),
+                ),
+                &[],
+            )
+            .await;
+        let read = executor
+            .execute(&call("read_file", json!({ "path": "./src/a.txt" })), &[])
+            .await;
+
+        assert!(!written.is_error, "{}", written.content);
+        assert_eq!(read, ToolOutput::ok("hello"));
+    }
+
+    #[tokio::test]
+    async fn refuses_paths_outside_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path().join("project");
+        std::fs::create_dir(&root).unwrap();
+        let executor = ToolExecutor::new(&root, PermissionMode::Skip, ToolsConfig::default());
+
+        for path in ["../secret.txt", "/etc/passwd"] {
+            let output = executor
+                .execute(&call("read_file", json!({ "path": path })), &[])
+                .await;
+            assert!(
+                output.is_error && output.content.contains("outside"),
+                "{path}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn permissions_limit_the_tools() {
+        let temp_dir = TempDir::new().unwrap();
+        let read_only = ToolExecutor::new(
+            temp_dir.path(),
+            PermissionMode::Default,
+            ToolsConfig::default(),
+        );
+
+        let names: Vec<_> = read_only.definitions().iter().map(|d| d.name).collect();
+        assert_eq!(names, ["read_file"]);
+        let output = read_only
+            .execute(&call("bash", json!({ "command": "true" })), &[])
+            .await;
+        assert!(output.is_error);
+    }
+
+    #[tokio::test]
+    async fn bash_reports_output_and_exit_code() {
+        let temp_dir = TempDir::new().unwrap();
+        let executor = ToolExecutor::new(
+            temp_dir.path(),
+            PermissionMode::Skip,
+            ToolsConfig::default(),
+        );
+
+        let output = executor
+            .execute(
+                &call("bash", json!({ "command": "echo $GREETING; exit 3" })),
+                &[("GREETING".to_string(), "hi".to_string())],
+            )
+            .await;
+
+        assert!(output.is_error);
+        assert_eq!(output.content, "hi\n\n[exit code: 3]");
+    }
+
+    #[tokio::test]
+    async fn bash_hides_the_api_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let executor = ToolExecutor::new(
+            temp_dir.path(),
+            PermissionMode::Skip,
+            ToolsConfig::default(),
+        )
+        .hiding_env(["RALPH_TEST_KEY".to_string()]);
+
+        let output = executor
+            .execute(
+                &call("bash", json!({ "command": "echo \"[$RALPH_TEST_KEY]\"" })),
+                &[("RALPH_TEST_KEY".to_string(), "secret".to_string())],
+            )
+            .await;
+
+        assert_eq!(output.content, "[]\n\n[exit code: 0]");
+    }
+
+    #[tokio::test]
+    async fn bash_timeout_kills_background_processes() {
+        let temp_dir = TempDir::new().unwrap();
+        let executor = ToolExecutor::new(
+            temp_dir.path(),
+            PermissionMode::Skip,
+            ToolsConfig {
+                bash_timeout_secs: 1,
+                ..ToolsConfig::default()
+            },
+        );
+
+        let output = executor
+            .execute(
+                &call(
+                    "bash",
+                    json!({ "command": "sleep 30 & echo $! > pid; wait" }),
+                ),
+                &[],
+            )
+            .await;
+
+        assert!(output.content.contains("timed out"));
+        let pid = std::fs::read_to_string(temp_dir.path().join("pid")).unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        // A killed process may linger as a zombie until it is reaped
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid.trim()));
+        assert!(stat.map_or(true, |stat| stat.contains(") Z ")));
+    }
+}
@@ -8,12 +8,42 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+use crate::audit::{AuditEntry, SubprocessRecord};
+use crate::build_info::BuildInfo;
 use crate::config::AgentProvider;
+use crate::disk_space::LowDiskSpaceRecord;
+use crate::environment::EnvironmentSnapshot;
 use crate::error::{RalphError, Result};
+use crate::git::{FilesChanged, GitSnapshot};
+use crate::monitor::{UnknownEvent, UnparsedLine};
+use crate::plan::PlanProgress;
+use crate::text;
+use crate::timeline::{self, TimelineEntry, TimelineEvent};
+use crate::tool_spans::{self, ToolSpan};
+use crate::warnings::RunWarning;
+
+/// Name of the metadata file inside each run directory
+pub const META_FILE: &str = ".ralph-meta.json";
+
+/// Name of the directory holding the agent stderr of each iteration
+pub const STDERR_DIR: &str = "stderr";
+
+/// Name of the file collecting agent output lines that couldn't be parsed
+pub const UNPARSED_FILE: &str = "unparsed.jsonl";
+
+/// Name of the file collecting agent events of types ralph-loop doesn't handle
+pub const UNKNOWN_EVENTS_FILE: &str = "unknown_events.jsonl";
+
+/// Version of the records in `unknown_events.jsonl`
+pub const UNKNOWN_EVENTS_VERSION: u32 = 1;
+
+/// Name of the marker file requesting a running loop to stop
+pub const CANCEL_FILE: &str = ".ralph-cancel";
 
 /// Status of a run
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -21,7 +51,7 @@ use crate::error::{RalphError, Result};
 pub enum RunStatus {
     /// Run is currently active
     Running,
-    /// Run completed successfully (promise found)
+    /// Run completed successfully (promise found or plan completed)
     Completed,
     /// Run failed (max iterations, error, etc.)
     Failed,
@@ -35,6 +65,8 @@ pub enum RunStatus {
 pub enum ExitReason {
     /// Completion promise was found
     PromiseFulfilled,
+    /// Every item in the plan file was checked
+    PlanCompleted,
     /// Max iterations exceeded
     MaxIterationsExceeded,
     /// User interrupted (Ctrl+C)
@@ -55,6 +87,8 @@ pub enum IterationEndReason {
     PromiseFound,
     /// Process exited normally
     Normal,
+    /// The agent asked for a fresh context
+    RestartRequested,
     /// Process was interrupted
     Interrupted,
     /// Error occurred
@@ -76,16 +110,133 @@ pub struct IterationMetadata {
     /// Why this iteration ended
     #[serde(skip_serializing_if = "Option::is_none")]
     pub end_reason: Option<IterationEndReason>,
+    /// Why this iteration ended, in words, e.g. `killed: 182,340 tokens ≥ limit 180,000 at line 412`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason_detail: Option<String>,
     /// Token usage for this iteration
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tokens: Option<TokenUsageRecord>,
+    /// Cost of this iteration as reported by the agent backend
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    /// Timing of the iteration's phases
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timings: Option<IterationTimings>,
+    /// Plan file progress after this iteration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<PlanProgress>,
+    /// Task progress last reported by the agent, in percent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress_percent: Option<u8>,
+    /// Notes the agent recorded with `<ralph:cmd>note ...</ralph:cmd>`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+    /// Agent stderr of this iteration, relative to the run directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stderr_file: Option<String>,
+    /// Output lines that couldn't be parsed as events, the first of which
+    /// are kept in `unparsed.jsonl`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unparsed_lines: Option<u32>,
+    /// Context limits the iteration ran with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<IterationLimits>,
+    /// Whether the completion probe judged the task complete
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub probe_complete: Option<bool>,
+    /// Fields written by other versions, preserved on rewrite
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Context limits in effect for an iteration, which differ for the first
+/// iteration with `first_iteration_max_tokens`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IterationLimits {
+    pub max_tokens: usize,
+    pub warning_threshold: usize,
+}
+
+/// Timing of the phases of a single iteration, in milliseconds.
+///
+/// Event times are measured from the moment the agent process was spawned, so
+/// `first_event_ms` is model/startup latency and the gap to `last_event_ms` is
+/// the agent's own work. `drain_ms` is how long ralph spent waiting for its
+/// monitors after the process ended.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IterationTimings {
+    /// Time taken to spawn the agent process
+    pub spawn_ms: u64,
+    /// Time from spawn until the first output line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_event_ms: Option<u64>,
+    /// Time from spawn until the last output line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_event_ms: Option<u64>,
+    /// Time from process exit until the monitors finished draining
+    pub drain_ms: u64,
 }
 
 /// Token usage record for an iteration
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TokenUsageRecord {
+    /// Uncached input tokens; with `output` what the limit counts by default
     pub input: usize,
     pub output: usize,
+    /// Input tokens read from the prompt cache
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub cache_read: usize,
+    /// Input tokens written to the prompt cache
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub cache_creation: usize,
+    /// The part of `input` and `output` used by sub-agents the agent started
+    /// with the Task tool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subagents: Option<SubAgentTokens>,
+}
+
+impl TokenUsageRecord {
+    /// Tokens that occupied the context: all input, cached or not, and output
+    pub fn context(&self) -> usize {
+        self.input + self.cache_read + self.cache_creation + self.output
+    }
+}
+
+fn is_zero(tokens: &usize) -> bool {
+    *tokens == 0
+}
+
+/// Tokens used by the sub-agents of an iteration
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubAgentTokens {
+    /// Number of sub-agents that reported usage
+    pub count: u32,
+    pub input: usize,
+    pub output: usize,
+}
+
+/// A line of `unknown_events.jsonl`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnknownEventRecord {
+    /// Record format version, [`UNKNOWN_EVENTS_VERSION`] when written
+    pub version: u32,
+    /// Iteration the event was emitted in
+    pub iteration: u32,
+    /// The event's type
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// The event as the agent sent it, with secrets redacted
+    pub event: serde_json::Value,
+}
+
+/// A milestone promise the agent gave, see `milestones` in the config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MilestoneRecord {
+    /// The milestone's promise text
+    pub promise: String,
+    /// Iteration in which it was given
+    pub iteration: u32,
+    pub reached_at: DateTime<Utc>,
 }
 
 /// Metadata about a run stored in .ralph-meta.json
@@ -100,8 +251,11 @@ pub struct RunMetadata {
     /// When the run completed (if finished)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub completed_at: Option<DateTime<Utc>>,
-    /// Absolute path to the project
+    /// Absolute path to the project root
     pub project_path: String,
+    /// Directory ralph was started from, when different from the project root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
     /// Path to the prompt file (if used)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub prompt_file: Option<String>,
@@ -114,8 +268,53 @@ pub struct RunMetadata {
     /// Why the run ended (if finished)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub exit_reason: Option<ExitReason>,
+    /// Why the run ended, in words, e.g. `no promise in 10 iteration(s)`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason_detail: Option<String>,
     /// Per-iteration metadata with session ID mappings
     pub iterations: Vec<IterationMetadata>,
+    /// PID of the ralph-loop process driving this run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// Environment the run started in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<EnvironmentSnapshot>,
+    /// Commit checked out in the project when the run started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_base: Option<String>,
+    /// Files with uncommitted changes when the run started
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub git_dirty: Vec<String>,
+    /// Commit message style summary of a successful run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// Summary the agent gave with the promise, `<promise name="...">summary</promise>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub promise_summary: Option<String>,
+    /// Milestones reached so far, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub milestones: Vec<MilestoneRecord>,
+    /// Times the free disk space was below `[disk_space] min_free_mb`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub low_disk_space: Vec<LowDiskSpaceRecord>,
+    /// Why a running loop is waiting instead of iterating, e.g. `quiet hours`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paused: Option<String>,
+    /// Warnings logged during the run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<RunWarning>,
+    /// Files changed in the project since the run started
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files_changed: Option<FilesChanged>,
+    /// Tags given for the run, for grouping runs in `ralph-loop stats`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Build of ralph-loop that started the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<BuildInfo>,
+    /// Fields written by other versions, preserved on rewrite
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
 impl RunMetadata {
@@ -128,11 +327,17 @@ impl RunMetadata {
         agent_provider: AgentProvider,
         completion_promise: String,
     ) -> Self {
-        let prompt_preview = if prompt.len() > 100 {
-            format!("{}...", &prompt[..100])
-        } else {
-            prompt.to_string()
-        };
+        let prompt_preview = text::prompt_preview(prompt, text::DEFAULT_PROMPT_PREVIEW_CHARS);
+
+        let working_dir = std::env::current_dir()
+            .ok()
+            .map(|cwd| {
+                cwd.canonicalize()
+                    .unwrap_or(cwd)
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .filter(|cwd| *cwd != project_path);
 
         Self {
             run_id,
@@ -140,20 +345,108 @@ impl RunMetadata {
             started_at: Utc::now(),
             completed_at: None,
             project_path,
+            working_dir,
             prompt_file,
             prompt_preview,
             agent_provider,
             completion_promise,
             exit_reason: None,
+            reason_detail: None,
             iterations: Vec::new(),
+            pid: Some(std::process::id()),
+            environment: None,
+            git_base: None,
+            git_dirty: Vec::new(),
+            summary: None,
+            promise_summary: None,
+            milestones: Vec::new(),
+            low_disk_space: Vec::new(),
+            paused: None,
+            warnings: Vec::new(),
+            files_changed: None,
+            tags: Vec::new(),
+            build: Some(BuildInfo::current()),
+            extra: serde_json::Map::new(),
         }
     }
 
+    /// Load metadata from a run directory's .ralph-meta.json
+    pub fn load(run_dir: &Path) -> Result<Self> {
+        let meta_path = run_dir.join(META_FILE);
+        let content = fs::read_to_string(&meta_path).map_err(|e| {
+            RalphError::TranscriptWriteError(format!("{}: {}", meta_path.display(), e))
+        })?;
+        serde_json::from_str(&content).map_err(|e| {
+            RalphError::TranscriptWriteError(format!("{}: {}", meta_path.display(), e))
+        })
+    }
+
+    /// Store `warnings` in the metadata of the run in `run_dir`
+    pub fn record_warnings(run_dir: &Path, warnings: Vec<RunWarning>) -> Result<()> {
+        Self::update(run_dir, |metadata| metadata.warnings = warnings)
+    }
+
+    /// Store the files changed during the run in `run_dir` in its metadata
+    pub fn record_files_changed(run_dir: &Path, files_changed: FilesChanged) -> Result<()> {
+        Self::update(run_dir, |metadata| {
+            metadata.files_changed = Some(files_changed)
+        })
+    }
+
+    /// Apply `change` to the metadata of the run in `run_dir` on disk
+    fn update(run_dir: &Path, change: impl FnOnce(&mut Self)) -> Result<()> {
+        let mut metadata = Self::load(run_dir)?;
+        change(&mut metadata);
+        let json = serde_json::to_string_pretty(&metadata)
+            .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+        fs::write(run_dir.join(META_FILE), json)
+            .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))
+    }
+
     /// Get the current iteration number
     pub fn current_iteration(&self) -> u32 {
         self.iterations.len() as u32
     }
 
+    /// Commit the run started from; runs recorded before it was kept on its
+    /// own only have it in the environment snapshot
+    pub fn base_commit(&self) -> Option<&str> {
+        self.git_base.as_deref().or_else(|| {
+            self.environment
+                .as_ref()
+                .and_then(|environment| environment.git_head.as_deref())
+        })
+    }
+
+    /// A note for looking at the run elsewhere than where it was made: on
+    /// another machine than `hostname`, or with its project path missing.
+    /// Paths the run names may not resolve then; its kept output in the run
+    /// directory is all there is to look at.
+    pub fn origin_notice(&self, hostname: Option<&str>) -> Option<String> {
+        let made_on = self
+            .environment
+            .as_ref()
+            .and_then(|environment| environment.hostname.as_deref());
+        let other_host = match (made_on, hostname) {
+            (Some(made_on), Some(hostname)) if made_on != hostname => Some(made_on),
+            _ => None,
+        };
+        let missing_project = !Path::new(&self.project_path).is_dir();
+        let place = match (other_host, missing_project) {
+            (None, false) => return None,
+            (Some(host), false) => format!("on {host}"),
+            (None, true) => format!("in {}, which doesn't exist here", self.project_path),
+            (Some(host), true) => format!(
+                "on {host} in {}, which doesn't exist here",
+                self.project_path
+            ),
+        };
+        Some(format!(
+            "note: run {} was made {place}; paths it names may not resolve",
+            self.run_id
+        ))
+    }
+
     /// Get total tokens across all iterations
     pub fn total_tokens(&self) -> usize {
         self.iterations
@@ -162,6 +455,32 @@ impl RunMetadata {
             .map(|t| t.input + t.output)
             .sum()
     }
+
+    /// Get the number of output lines across all iterations that couldn't be parsed
+    pub fn unparsed_lines(&self) -> u32 {
+        self.iterations
+            .iter()
+            .filter_map(|i| i.unparsed_lines)
+            .sum()
+    }
+
+    /// Get total cost across all iterations, if any iteration reported one
+    pub fn total_cost_usd(&self) -> Option<f64> {
+        self.iterations
+            .iter()
+            .filter_map(|i| i.cost_usd)
+            .reduce(|a, b| a + b)
+    }
+
+    /// Whether `other` ran the same prompt: the same prompt file, or the
+    /// same inline prompt
+    pub fn same_prompt(&self, other: &RunMetadata) -> bool {
+        match (&self.prompt_file, &other.prompt_file) {
+            (Some(file), Some(other_file)) => file == other_file,
+            (None, None) => self.prompt_preview == other.prompt_preview,
+            _ => false,
+        }
+    }
 }
 
 /// Manages run metadata for a single run.
@@ -223,12 +542,72 @@ impl TranscriptWriter {
         // Write initial metadata
         writer.write_metadata()?;
 
+        // Record the run in the output directory's registry
+        crate::registry::register_run(output_dir, &writer.metadata)?;
+
         // Update latest symlink
         writer.update_latest_symlink()?;
 
         Ok(writer)
     }
 
+    /// Reopen the stopped run `run_id` to continue it in this process.
+    ///
+    /// An iteration that was cut short is recorded as interrupted, and the run
+    /// is marked running again under the current process ID.
+    pub fn open(output_dir: &Path, run_id: &str) -> Result<Self> {
+        let run_dir = output_dir.join("runs").join(run_id);
+        if !run_dir.is_dir() {
+            return Err(RalphError::ResumeError(format!(
+                "run '{run_id}' not found in {}",
+                output_dir.join("runs").display()
+            )));
+        }
+
+        let mut metadata = RunMetadata::load(&run_dir)?;
+        match metadata.status {
+            RunStatus::Completed => {
+                return Err(RalphError::ResumeError(format!(
+                    "run '{run_id}' already completed"
+                )));
+            }
+            RunStatus::Running if metadata.pid.is_some_and(crate::cancel::process_alive) => {
+                return Err(RalphError::ResumeError(format!(
+                    "run '{run_id}' is still running"
+                )));
+            }
+            _ => {}
+        }
+
+        if let Some(iteration) = metadata.iterations.last_mut() {
+            if iteration.ended_at.is_none() {
+                iteration.ended_at = Some(Utc::now());
+                iteration.end_reason = Some(IterationEndReason::Interrupted);
+                iteration.reason_detail =
+                    Some("interrupted: ralph-loop stopped during the iteration".to_string());
+            }
+        }
+        metadata.status = RunStatus::Running;
+        metadata.completed_at = None;
+        metadata.exit_reason = None;
+        metadata.reason_detail = None;
+        metadata.paused = None;
+        metadata.pid = Some(std::process::id());
+
+        // A cancel request applied to the previous process
+        let _ = fs::remove_file(run_dir.join(CANCEL_FILE));
+
+        let writer = Self {
+            output_dir: output_dir.to_path_buf(),
+            run_dir,
+            metadata,
+        };
+        writer.write_metadata()?;
+        writer.update_latest_symlink()?;
+
+        Ok(writer)
+    }
+
     /// Get the run ID
     pub fn run_id(&self) -> &str {
         &self.metadata.run_id
@@ -249,15 +628,87 @@ impl TranscriptWriter {
             started_at: Utc::now(),
             ended_at: None,
             end_reason: None,
+            reason_detail: None,
             tokens: None,
+            cost_usd: None,
+            timings: None,
+            plan: None,
+            progress_percent: None,
+            notes: Vec::new(),
+            stderr_file: None,
+            unparsed_lines: None,
+            limits: None,
+            probe_complete: None,
+            extra: serde_json::Map::new(),
         };
 
         self.metadata.iterations.push(iteration);
         self.write_metadata()?;
+        self.record(TimelineEvent::IterationStarted)?;
 
         Ok(iteration_num)
     }
 
+    /// Replace the prompt preview, e.g. with one of a configured length
+    pub fn set_prompt_preview(&mut self, prompt_preview: String) -> Result<()> {
+        self.metadata.prompt_preview = prompt_preview;
+        self.write_metadata()
+    }
+
+    /// Record the tags given for the run
+    pub fn set_tags(&mut self, tags: Vec<String>) -> Result<()> {
+        self.metadata.tags = tags;
+        self.write_metadata()
+    }
+
+    /// Record the state of the project's repository when the run started
+    pub fn set_git_start(&mut self, snapshot: GitSnapshot) -> Result<()> {
+        self.metadata.git_base = Some(snapshot.head);
+        self.metadata.git_dirty = snapshot.dirty;
+        self.write_metadata()
+    }
+
+    /// Record the environment the run started in
+    pub fn set_environment(&mut self, environment: EnvironmentSnapshot) -> Result<()> {
+        self.metadata.environment = Some(environment);
+        self.write_metadata()
+    }
+
+    /// Record the summary of the run
+    pub fn set_summary(&mut self, summary: String) -> Result<()> {
+        self.metadata.summary = Some(summary);
+        self.write_metadata()
+    }
+
+    /// Record the summary the agent gave with the promise
+    pub fn set_promise_summary(&mut self, summary: String) -> Result<()> {
+        self.metadata.promise_summary = Some(summary);
+        self.write_metadata()
+    }
+
+    /// Record that the milestone `promise` was reached in the current iteration
+    pub fn record_milestone(&mut self, promise: String) -> Result<()> {
+        let iteration = self.metadata.current_iteration();
+        self.metadata.milestones.push(MilestoneRecord {
+            promise,
+            iteration,
+            reached_at: Utc::now(),
+        });
+        self.write_metadata()
+    }
+
+    /// Record that a directory had less free disk space than required
+    pub fn record_low_disk_space(&mut self, record: LowDiskSpaceRecord) -> Result<()> {
+        self.metadata.low_disk_space.push(record);
+        self.write_metadata()
+    }
+
+    /// Record why the loop is paused, or clear it when it resumes
+    pub fn set_paused(&mut self, reason: Option<String>) -> Result<()> {
+        self.metadata.paused = reason;
+        self.write_metadata()
+    }
+
     /// Set the session ID for the current iteration
     pub fn set_session_id(&mut self, session_id: String) -> Result<()> {
         if let Some(iteration) = self.metadata.iterations.last_mut() {
@@ -267,36 +718,183 @@ impl TranscriptWriter {
         Ok(())
     }
 
-    /// End the current iteration with the given reason and token usage
+    /// Set the cost reported for the current iteration
+    pub fn set_cost_usd(&mut self, cost_usd: f64) -> Result<()> {
+        if let Some(iteration) = self.metadata.iterations.last_mut() {
+            iteration.cost_usd = Some(cost_usd);
+            self.write_metadata()?;
+        }
+        Ok(())
+    }
+
+    /// Set the phase timings for the current iteration
+    pub fn set_timings(&mut self, timings: IterationTimings) -> Result<()> {
+        if let Some(iteration) = self.metadata.iterations.last_mut() {
+            iteration.timings = Some(timings);
+            self.write_metadata()?;
+        }
+        Ok(())
+    }
+
+    /// Set the context limits of the current iteration
+    pub fn set_limits(&mut self, limits: IterationLimits) -> Result<()> {
+        if let Some(iteration) = self.metadata.iterations.last_mut() {
+            iteration.limits = Some(limits);
+            self.write_metadata()?;
+        }
+        Ok(())
+    }
+
+    /// Set the completion probe's answer for the current iteration
+    pub fn set_probe_complete(&mut self, complete: bool) -> Result<()> {
+        if let Some(iteration) = self.metadata.iterations.last_mut() {
+            iteration.probe_complete = Some(complete);
+            self.write_metadata()?;
+        }
+        Ok(())
+    }
+
+    /// Set the plan file progress for the current iteration
+    pub fn set_plan_progress(&mut self, plan: PlanProgress) -> Result<()> {
+        if let Some(iteration) = self.metadata.iterations.last_mut() {
+            iteration.plan = Some(plan);
+            self.write_metadata()?;
+        }
+        Ok(())
+    }
+
+    /// Set the progress the agent reported during the current iteration
+    pub fn set_progress(&mut self, percent: u8) -> Result<()> {
+        if let Some(iteration) = self.metadata.iterations.last_mut() {
+            iteration.progress_percent = Some(percent);
+            self.write_metadata()?;
+        }
+        Ok(())
+    }
+
+    /// Store the agent's stderr of the current iteration in the run directory
+    pub fn write_stderr(&mut self, stderr: &str) -> Result<()> {
+        let Some(iteration) = self.metadata.iterations.last_mut() else {
+            return Ok(());
+        };
+        let dir = self.run_dir.join(STDERR_DIR);
+        fs::create_dir_all(&dir).map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+        let file = format!("iteration_{:03}.log", iteration.iteration);
+        fs::write(dir.join(&file), stderr)
+            .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+        iteration.stderr_file = Some(format!("{STDERR_DIR}/{file}"));
+        self.write_metadata()
+    }
+
+    /// Append output lines of the current iteration that couldn't be parsed
+    /// to `unparsed.jsonl`, as evidence when the agent's output format
+    /// changes, followed by the count of the `dropped` ones not kept
+    pub fn write_unparsed(&mut self, lines: &[UnparsedLine], dropped: u64) -> Result<()> {
+        let Some(iteration) = self.metadata.iterations.last_mut() else {
+            return Ok(());
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.run_dir.join(UNPARSED_FILE))
+            .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+        for unparsed in lines {
+            let record = serde_json::json!({
+                "iteration": iteration.iteration,
+                "error": unparsed.error,
+                "line": unparsed.line,
+            });
+            writeln!(file, "{record}")
+                .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+        }
+        if dropped > 0 {
+            let record = serde_json::json!({
+                "iteration": iteration.iteration,
+                "dropped": dropped,
+            });
+            writeln!(file, "{record}")
+                .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+        }
+        let count = iteration.unparsed_lines.unwrap_or(0) + lines.len() as u32 + dropped as u32;
+        iteration.unparsed_lines = Some(count);
+        self.write_metadata()
+    }
+
+    /// Append events of the current iteration that ralph-loop doesn't handle
+    /// to `unknown_events.jsonl`, so new event types of the agent are visible
+    pub fn write_unknown_events(&self, events: &[UnknownEvent]) -> Result<()> {
+        let Some(iteration) = self.metadata.iterations.last() else {
+            return Ok(());
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.run_dir.join(UNKNOWN_EVENTS_FILE))
+            .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+        for event in events {
+            let record = UnknownEventRecord {
+                version: UNKNOWN_EVENTS_VERSION,
+                iteration: iteration.iteration,
+                event_type: event.event_type.clone(),
+                event: event.raw.clone(),
+            };
+            let line = serde_json::to_string(&record)
+                .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+            writeln!(file, "{line}")
+                .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Append the tool calls of the current iteration to `tool_calls.jsonl`
+    pub fn write_tool_spans(&self, spans: &[ToolSpan]) -> Result<()> {
+        let Some(iteration) = self.metadata.iterations.last() else {
+            return Ok(());
+        };
+        tool_spans::append(&self.run_dir, iteration.iteration, spans)
+    }
+
+    /// Append a note the agent recorded during the current iteration
+    pub fn add_note(&mut self, note: String) -> Result<()> {
+        if let Some(iteration) = self.metadata.iterations.last_mut() {
+            iteration.notes.push(note);
+            self.write_metadata()?;
+        }
+        Ok(())
+    }
+
+    /// End the current iteration with the given reason, explained in
+    /// `reason_detail`, and token usage
     pub fn end_iteration(
         &mut self,
         end_reason: IterationEndReason,
-        input_tokens: usize,
-        output_tokens: usize,
+        reason_detail: String,
+        tokens: TokenUsageRecord,
     ) -> Result<()> {
         if let Some(iteration) = self.metadata.iterations.last_mut() {
             iteration.ended_at = Some(Utc::now());
-            iteration.end_reason = Some(end_reason);
-            iteration.tokens = Some(TokenUsageRecord {
-                input: input_tokens,
-                output: output_tokens,
-            });
+            iteration.end_reason = Some(end_reason.clone());
+            iteration.reason_detail = Some(reason_detail);
+            iteration.tokens = Some(tokens);
             self.write_metadata()?;
+            self.record(TimelineEvent::IterationEnded { reason: end_reason })?;
         }
         Ok(())
     }
 
-    /// Mark the run as completed
-    pub fn complete(&mut self, exit_reason: ExitReason) -> Result<()> {
+    /// Mark the run as completed for `exit_reason`, explained in `reason_detail`
+    pub fn complete(&mut self, exit_reason: ExitReason, reason_detail: String) -> Result<()> {
         self.metadata.status = match exit_reason {
-            ExitReason::PromiseFulfilled => RunStatus::Completed,
+            ExitReason::PromiseFulfilled | ExitReason::PlanCompleted => RunStatus::Completed,
             ExitReason::UserInterrupt => RunStatus::Interrupted,
             _ => RunStatus::Failed,
         };
         self.metadata.completed_at = Some(Utc::now());
-        self.metadata.exit_reason = Some(exit_reason);
+        self.metadata.exit_reason = Some(exit_reason.clone());
+        self.metadata.reason_detail = Some(reason_detail);
 
-        self.write_metadata()
+        self.write_metadata()?;
+        self.record(TimelineEvent::RunCompleted { exit_reason })
     }
 
     /// Get a reference to the metadata
@@ -304,9 +902,33 @@ impl TranscriptWriter {
         &self.metadata
     }
 
+    /// Append a subprocess spawned by ralph to the run's audit log
+    pub fn audit(&self, iteration: Option<u32>, record: SubprocessRecord) -> Result<()> {
+        crate::audit::append(&self.run_dir, &AuditEntry { iteration, record })
+    }
+
+    /// Append a decision of the loop to the run's timeline
+    pub fn record(&self, event: TimelineEvent) -> Result<()> {
+        self.write_timeline(vec![TimelineEntry::now(event)])
+    }
+
+    /// Append decisions made during the current iteration to the run's timeline
+    pub fn write_timeline(&self, mut entries: Vec<TimelineEntry>) -> Result<()> {
+        let iteration = self.metadata.current_iteration();
+        for entry in &mut entries {
+            entry.iteration = (iteration > 0).then_some(iteration);
+        }
+        timeline::append(&self.run_dir, &entries)
+    }
+
+    /// Check whether `ralph-loop cancel` has asked this run to stop
+    pub fn cancel_requested(&self) -> bool {
+        self.run_dir.join(CANCEL_FILE).exists()
+    }
+
     /// Write metadata to .ralph-meta.json
     fn write_metadata(&self) -> Result<()> {
-        let meta_path = self.run_dir.join(".ralph-meta.json");
+        let meta_path = self.run_dir.join(META_FILE);
         let json = serde_json::to_string_pretty(&self.metadata)
             .map_err(|e| RalphError::TranscriptWriteError(e.to_string()))?;
         fs::write(&meta_path, json).map_err(|e| RalphError::TranscriptWriteError(e.to_string()))
@@ -466,7 +1088,17 @@ mod tests {
         writer.start_iteration().unwrap();
         writer.set_session_id("session-xyz".to_string()).unwrap();
         writer
-            .end_iteration(IterationEndReason::ContextLimit, 1000, 500)
+            .end_iteration(
+                IterationEndReason::ContextLimit,
+                "killed: context limit reached".to_string(),
+                TokenUsageRecord {
+                    input: 1000,
+                    output: 500,
+                    cache_read: 20_000,
+                    cache_creation: 0,
+                    subagents: None,
+                },
+            )
             .unwrap();
 
         let iteration = &writer.metadata().iterations[0];
@@ -474,6 +1106,77 @@ mod tests {
         assert_eq!(iteration.end_reason, Some(IterationEndReason::ContextLimit));
         assert_eq!(iteration.tokens.as_ref().unwrap().input, 1000);
         assert_eq!(iteration.tokens.as_ref().unwrap().output, 500);
+        assert_eq!(iteration.tokens.as_ref().unwrap().context(), 21_500);
+    }
+
+    #[test]
+    fn test_transcript_writer_appends_unparsed_lines() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let mut writer = TranscriptWriter::new(
+            temp_dir.path(),
+            temp_dir.path(),
+            "Test prompt",
+            None,
+            AgentProvider::Claude,
+            "TASK COMPLETE".to_string(),
+            Some("test-run-unparsed".to_string()),
+        )
+        .unwrap();
+
+        let unparsed = |line: &str| UnparsedLine {
+            line: line.to_string(),
+            error: "expected value".to_string(),
+        };
+        writer.start_iteration().unwrap();
+        writer.write_unparsed(&[unparsed("oops")], 0).unwrap();
+        writer.start_iteration().unwrap();
+        writer
+            .write_unparsed(&[unparsed("{\"type\":"), unparsed("Error: x")], 5)
+            .unwrap();
+
+        let content = fs::read_to_string(writer.run_dir().join(UNPARSED_FILE)).unwrap();
+        let records: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0]["iteration"], 1);
+        assert_eq!(records[1]["iteration"], 2);
+        assert_eq!(records[1]["line"], "{\"type\":");
+        assert_eq!(records[2]["error"], "expected value");
+        assert_eq!(records[3]["iteration"], 2);
+        assert_eq!(records[3]["dropped"], 5);
+        assert_eq!(writer.metadata().iterations[1].unparsed_lines, Some(7));
+        assert_eq!(writer.metadata().unparsed_lines(), 8);
+    }
+
+    #[test]
+    fn test_transcript_writer_records_timings() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let mut writer = TranscriptWriter::new(
+            temp_dir.path(),
+            temp_dir.path(),
+            "Test prompt",
+            None,
+            AgentProvider::Claude,
+            "TASK COMPLETE".to_string(),
+            Some("test-run-timings".to_string()),
+        )
+        .unwrap();
+
+        writer.start_iteration().unwrap();
+        let timings = IterationTimings {
+            spawn_ms: 4,
+            first_event_ms: Some(1200),
+            last_event_ms: Some(9000),
+            drain_ms: 2,
+        };
+        writer.set_timings(timings.clone()).unwrap();
+
+        let metadata = RunMetadata::load(writer.run_dir()).unwrap();
+        assert_eq!(metadata.iterations[0].timings, Some(timings));
     }
 
     #[test]
@@ -490,6 +1193,19 @@ mod tests {
         assert_eq!(metadata.status, RunStatus::Running);
         assert!(metadata.prompt_preview.ends_with("..."));
         assert!(metadata.prompt_preview.len() <= 103); // 100 + "..."
+
+        // Three bytes per character, so byte 100 is inside one
+        let prompt = "修复解析器中的错误并添加测试。".repeat(10);
+        let metadata = RunMetadata::new(
+            "test-run".to_string(),
+            "/home/test/project".to_string(),
+            &prompt,
+            None,
+            AgentProvider::Claude,
+            "DONE".to_string(),
+        );
+        assert_eq!(metadata.prompt_preview.chars().count(), 103);
+        assert!(prompt.starts_with(metadata.prompt_preview.trim_end_matches("...")));
         assert_eq!(metadata.project_path, "/home/test/project");
         assert_eq!(metadata.agent_provider, AgentProvider::Claude);
         assert!(metadata.iterations.is_empty());
@@ -500,6 +1216,37 @@ mod tests {
         assert!(json.contains("/home/test/project"));
     }
 
+    #[test]
+    fn runs_made_elsewhere_get_a_notice() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut metadata = RunMetadata::new(
+            "run-1".to_string(),
+            temp_dir.path().display().to_string(),
+            "prompt",
+            None,
+            AgentProvider::Claude,
+            "DONE".to_string(),
+        );
+        assert_eq!(metadata.origin_notice(Some("laptop")), None);
+
+        metadata.environment = Some(EnvironmentSnapshot {
+            hostname: Some("ci-runner".to_string()),
+            ..EnvironmentSnapshot::default()
+        });
+        assert_eq!(metadata.origin_notice(Some("ci-runner")), None);
+        assert_eq!(
+            metadata.origin_notice(Some("laptop")).unwrap(),
+            "note: run run-1 was made on ci-runner; paths it names may not resolve"
+        );
+
+        metadata.project_path = "/nonexistent/app".to_string();
+        assert_eq!(
+            metadata.origin_notice(None).unwrap(),
+            "note: run run-1 was made in /nonexistent/app, which doesn't exist here; \
+             paths it names may not resolve"
+        );
+    }
+
     #[test]
     fn test_run_metadata_total_tokens() {
         let mut metadata = RunMetadata::new(
@@ -513,6 +1260,7 @@ mod tests {
 
         // No iterations = 0 tokens
         assert_eq!(metadata.total_tokens(), 0);
+        assert_eq!(metadata.total_cost_usd(), None);
 
         // Add iterations with tokens
         metadata.iterations.push(IterationMetadata {
@@ -521,10 +1269,24 @@ mod tests {
             started_at: Utc::now(),
             ended_at: None,
             end_reason: None,
+            reason_detail: None,
             tokens: Some(TokenUsageRecord {
                 input: 1000,
                 output: 500,
+                cache_read: 0,
+                cache_creation: 0,
+                subagents: None,
             }),
+            cost_usd: Some(0.25),
+            timings: None,
+            plan: None,
+            progress_percent: None,
+            notes: Vec::new(),
+            stderr_file: None,
+            unparsed_lines: None,
+            limits: None,
+            probe_complete: None,
+            extra: serde_json::Map::new(),
         });
 
         metadata.iterations.push(IterationMetadata {
@@ -533,12 +1295,27 @@ mod tests {
             started_at: Utc::now(),
             ended_at: None,
             end_reason: None,
+            reason_detail: None,
             tokens: Some(TokenUsageRecord {
                 input: 2000,
                 output: 1000,
+                cache_read: 0,
+                cache_creation: 0,
+                subagents: None,
             }),
+            cost_usd: Some(0.5),
+            timings: None,
+            plan: None,
+            progress_percent: None,
+            notes: Vec::new(),
+            stderr_file: None,
+            unparsed_lines: None,
+            limits: None,
+            probe_complete: None,
+            extra: serde_json::Map::new(),
         });
 
         assert_eq!(metadata.total_tokens(), 4500); // 1500 + 3000
+        assert_eq!(metadata.total_cost_usd(), Some(0.75));
     }
 }
@@ -0,0 +1,233 @@
+//! Finding the agent output of a past run's iterations.
+//!
+//! Transcripts are looked for in order: the copy a run kept in its own
+//! directory (`output/iteration_NNN.jsonl`), then the session file Claude
+//! keeps under `~/.claude/projects/`, then each directory of
+//! `transcript_search_path`. The source each transcript came from is kept
+//! with it, and a transcript that can't be found is reported with every
+//! place that was tried.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::classify::{iteration_file, OUTPUT_DIR};
+use crate::error::{RalphError, Result};
+use crate::transcript::{IterationEndReason, IterationMetadata, RunMetadata};
+
+/// A place iteration transcripts are looked for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscriptSource {
+    /// The output a run kept in its own directory
+    RunDir,
+    /// Claude's session files, under `$CLAUDE_CONFIG_DIR` or `~/.claude`
+    ClaudeProjects,
+    /// A directory from `transcript_search_path`
+    SearchPath(PathBuf),
+}
+
+impl fmt::Display for TranscriptSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RunDir => write!(f, "run directory"),
+            Self::ClaudeProjects => write!(f, "Claude project directory"),
+            Self::SearchPath(dir) => write!(f, "search path {}", dir.display()),
+        }
+    }
+}
+
+impl TranscriptSource {
+    /// The sources in the order they are tried, with `search_path` last
+    pub fn ordered(search_path: &[PathBuf]) -> Vec<Self> {
+        let mut sources = vec![Self::RunDir, Self::ClaudeProjects];
+        sources.extend(search_path.iter().cloned().map(Self::SearchPath));
+        sources
+    }
+
+    /// The files that may hold the transcript of `iteration` of the run in
+    /// `run_dir`
+    fn candidates(
+        &self,
+        run_dir: &Path,
+        metadata: &RunMetadata,
+        iteration: &IterationMetadata,
+    ) -> Vec<PathBuf> {
+        let file = iteration_file(iteration.iteration);
+        let session_file = iteration
+            .session_id
+            .as_ref()
+            .map(|session| format!("{session}.jsonl"));
+        match self {
+            Self::RunDir => vec![run_dir.join(OUTPUT_DIR).join(file)],
+            Self::ClaudeProjects => claude_projects_dir()
+                .zip(session_file)
+                .map(|(dir, session_file)| {
+                    dir.join(claude_project_name(&metadata.project_path))
+                        .join(session_file)
+                })
+                .into_iter()
+                .collect(),
+            Self::SearchPath(dir) => {
+                let mut candidates = vec![dir.join(&metadata.run_id).join(OUTPUT_DIR).join(file)];
+                candidates.extend(session_file.map(|session_file| dir.join(session_file)));
+                candidates
+            }
+        }
+    }
+}
+
+/// The transcript found for an iteration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedTranscript {
+    pub iteration: u32,
+    pub path: PathBuf,
+    pub source: TranscriptSource,
+}
+
+/// The transcript of each iteration of the run in `run_dir`, from the first
+/// of `sources` that has it. Iterations that ended in an error, were
+/// interrupted or never ended are left out if none has it.
+pub fn resolve(
+    sources: &[TranscriptSource],
+    run_dir: &Path,
+    metadata: &RunMetadata,
+) -> Result<Vec<ResolvedTranscript>> {
+    let mut resolved = Vec::with_capacity(metadata.iterations.len());
+    for iteration in &metadata.iterations {
+        let mut tried = Vec::new();
+        let found = sources.iter().find_map(|source| {
+            let candidates = source.candidates(run_dir, metadata, iteration);
+            let found = candidates.iter().find(|path| path.is_file()).cloned();
+            tried.extend(candidates.into_iter().map(|path| (source, path)));
+            found.map(|path| (path, source.clone()))
+        });
+        match found {
+            Some((path, source)) => resolved.push(ResolvedTranscript {
+                iteration: iteration.iteration,
+                path,
+                source,
+            }),
+            // Iterations that failed or were cut short may have left no output
+            None if !matches!(
+                iteration.end_reason,
+                Some(
+                    IterationEndReason::ContextLimit
+                        | IterationEndReason::PromiseFound
+                        | IterationEndReason::Normal
+                        | IterationEndReason::RestartRequested
+                )
+            ) => {}
+            None => {
+                let tried: Vec<String> = tried
+                    .iter()
+                    .map(|(source, path)| format!("  {source}: {}", path.display()))
+                    .collect();
+                return Err(RalphError::ReplayError(format!(
+                    "no transcript found for iteration {} of run '{}'{}; tried:\n{}",
+                    iteration.iteration,
+                    metadata.run_id,
+                    iteration
+                        .session_id
+                        .as_ref()
+                        .map(|session| format!(" (session {session})"))
+                        .unwrap_or_default(),
+                    tried.join("\n")
+                )));
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+/// Where Claude keeps its session files, one directory per project
+fn claude_projects_dir() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("CLAUDE_CONFIG_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".claude")))?;
+    Some(config_dir.join("projects"))
+}
+
+/// Claude's directory name for the project at `project_path`: the path
+/// with everything but letters and digits replaced by `-`
+fn claude_project_name(project_path: &str) -> String {
+    project_path
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AgentProvider;
+    use crate::transcript::{TokenUsageRecord, TranscriptWriter};
+    use tempfile::TempDir;
+
+    #[test]
+    fn claude_project_names_replace_separators() {
+        assert_eq!(claude_project_name("/home/me/my.app"), "-home-me-my-app");
+    }
+
+    #[test]
+    fn transcripts_come_from_the_first_source_that_has_them() {
+        let dir = TempDir::new().unwrap();
+        let mut writer = TranscriptWriter::new(
+            dir.path(),
+            dir.path(),
+            "prompt",
+            None,
+            AgentProvider::Claude,
+            "DONE".to_string(),
+            Some("run-1".to_string()),
+        )
+        .unwrap();
+        for session in ["sess-1", "sess-2", "sess-3", "sess-4"] {
+            writer.start_iteration().unwrap();
+            writer.set_session_id(session.to_string()).unwrap();
+            if session != "sess-4" {
+                writer
+                    .end_iteration(
+                        IterationEndReason::Normal,
+                        "agent ended without the promise".to_string(),
+                        TokenUsageRecord::default(),
+                    )
+                    .unwrap();
+            }
+        }
+        let run_dir = writer.run_dir().to_path_buf();
+        let metadata = writer.metadata().clone();
+
+        std::fs::create_dir_all(run_dir.join(OUTPUT_DIR)).unwrap();
+        std::fs::write(run_dir.join(OUTPUT_DIR).join(iteration_file(1)), "").unwrap();
+        let search = dir.path().join("copied");
+        std::fs::create_dir_all(&search).unwrap();
+        std::fs::write(search.join("sess-1.jsonl"), "").unwrap();
+        std::fs::write(search.join("sess-2.jsonl"), "").unwrap();
+
+        let sources = TranscriptSource::ordered(std::slice::from_ref(&search));
+        let error = resolve(&sources, &run_dir, &metadata)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("no transcript found for iteration 3") && error.contains("sess-3"),
+            "{error}"
+        );
+        assert!(
+            error.contains(&format!("run directory: {}", run_dir.display())),
+            "{error}"
+        );
+
+        std::fs::write(search.join("sess-3.jsonl"), "").unwrap();
+        let resolved = resolve(&sources, &run_dir, &metadata).unwrap();
+        let found: Vec<_> = resolved.iter().map(|t| t.source.clone()).collect();
+        assert_eq!(
+            found,
+            [
+                TranscriptSource::RunDir,
+                TranscriptSource::SearchPath(search.clone()),
+                TranscriptSource::SearchPath(search.clone()),
+            ]
+        );
+        assert_eq!(resolved[1].path, search.join("sess-2.jsonl"));
+    }
+}
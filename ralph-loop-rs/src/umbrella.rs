@@ -0,0 +1,94 @@
+//! The `ralph` entry point.
+//!
+//! `ralph` groups the commands of `ralph-loop` under short verbs: `ralph run`
+//! starts a loop, `ralph status` lists runs, `ralph view` replays a run,
+//! `ralph stats` and `ralph clean` are the commands of the same name.
+//! Anything else is passed on unchanged, so `ralph resume <id>` or
+//! `ralph -p "..."` work too. The `ralph-loop` binary installed next to
+//! `ralph` is run in its place, keeping its signals and exit code.
+
+use std::ffi::OsString;
+
+/// Name of the binary the commands are run by
+pub const LOOP_BINARY: &str = "ralph-loop";
+
+/// Help printed by `ralph`, `ralph help` and `ralph --help`
+pub const USAGE: &str = "\
+Run coding agents in a loop
+
+Usage: ralph <COMMAND> [ARGS]...
+
+Commands:
+  run     Run the loop (ralph-loop [OPTIONS])
+  status  List recorded runs (ralph-loop runs)
+  view    Replay the decisions of a recorded run (ralph-loop replay)
+  stats   Show iterations, tokens, cost and duration per run (ralph-loop stats)
+  clean   Delete the run directories of old runs (ralph-loop clean)
+
+Any other command or option is passed to ralph-loop unchanged, e.g.
+`ralph resume <RUN_ID>` or `ralph -p \"...\"`. See `ralph-loop --help`.";
+
+/// What `ralph` does with its arguments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Invocation {
+    /// Print [`USAGE`]
+    Help,
+    /// Run `ralph-loop` with these arguments
+    Loop(Vec<OsString>),
+}
+
+/// Map the arguments of `ralph`, without the program name, onto `ralph-loop`
+pub fn invocation(args: Vec<OsString>) -> Invocation {
+    let Some(first) = args.first().and_then(|arg| arg.to_str()) else {
+        return if args.is_empty() {
+            Invocation::Help
+        } else {
+            Invocation::Loop(args)
+        };
+    };
+    let command = match first {
+        "help" | "-h" | "--help" => return Invocation::Help,
+        "run" => None,
+        "status" => Some("runs"),
+        "view" => Some("replay"),
+        _ => return Invocation::Loop(args),
+    };
+    let mut mapped: Vec<OsString> = command.map(OsString::from).into_iter().collect();
+    mapped.extend(args.into_iter().skip(1));
+    Invocation::Loop(mapped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn verbs_map_onto_ralph_loop_commands() {
+        assert_eq!(invocation(args(&[])), Invocation::Help);
+        assert_eq!(invocation(args(&["--help"])), Invocation::Help);
+        assert_eq!(
+            invocation(args(&["run", "-p", "fix it"])),
+            Invocation::Loop(args(&["-p", "fix it"]))
+        );
+        assert_eq!(
+            invocation(args(&["status", "--all-projects"])),
+            Invocation::Loop(args(&["runs", "--all-projects"]))
+        );
+        assert_eq!(
+            invocation(args(&["view", "run-1"])),
+            Invocation::Loop(args(&["replay", "run-1"]))
+        );
+        assert_eq!(
+            invocation(args(&["stats", "--by", "tag"])),
+            Invocation::Loop(args(&["stats", "--by", "tag"]))
+        );
+        assert_eq!(
+            invocation(args(&["resume", "run-1"])),
+            Invocation::Loop(args(&["resume", "run-1"]))
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! Usage summary printed at the end of a run.
+//!
+//! A short block with the iterations, wall time, tokens, cache hit ratio,
+//! cost and files changed of the run, made from its metadata. Nothing is
+//! sent anywhere; `--summary-format md` or `json` print it for pasting into
+//! standup notes or for scripts.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::time_display::TimeDisplay;
+use crate::transcript::RunMetadata;
+
+/// How the usage summary is printed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SummaryFormat {
+    /// Indented lines under a `USAGE:` heading
+    #[default]
+    Text,
+    /// A Markdown list
+    Md,
+    /// A JSON object
+    Json,
+}
+
+/// Usage of a finished run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageSummary {
+    pub run_id: String,
+    pub iterations: usize,
+    pub wall_time_secs: u64,
+    /// Uncached input tokens
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub cache_read_tokens: usize,
+    pub cache_creation_tokens: usize,
+    /// Share of the input tokens read from the prompt cache, if there was input
+    pub cache_hit_ratio: Option<f64>,
+    /// Cost reported by the backend, if it reported one
+    pub cost_usd: Option<f64>,
+    /// Files changed in the project, if they were recorded
+    pub files_changed: Option<usize>,
+}
+
+impl UsageSummary {
+    /// Usage of the run in `metadata`, which took `wall_time`
+    pub fn from_metadata(metadata: &RunMetadata, wall_time: Duration) -> Self {
+        let tokens = || metadata.iterations.iter().filter_map(|i| i.tokens.as_ref());
+        let input_tokens = tokens().map(|t| t.input).sum();
+        let cache_read_tokens = tokens().map(|t| t.cache_read).sum();
+        let cache_creation_tokens = tokens().map(|t| t.cache_creation).sum();
+        let all_input = input_tokens + cache_read_tokens + cache_creation_tokens;
+        Self {
+            run_id: metadata.run_id.clone(),
+            iterations: metadata.iterations.len(),
+            wall_time_secs: wall_time.as_secs(),
+            input_tokens,
+            output_tokens: tokens().map(|t| t.output).sum(),
+            cache_read_tokens,
+            cache_creation_tokens,
+            cache_hit_ratio: (all_input > 0).then(|| cache_read_tokens as f64 / all_input as f64),
+            cost_usd: metadata.total_cost_usd(),
+            files_changed: metadata
+                .files_changed
+                .as_ref()
+                .map(|files| files.files.len()),
+        }
+    }
+
+    /// Label and value of each line, in the order they are printed
+    fn lines(&self, time: TimeDisplay) -> Vec<(&'static str, String)> {
+        let mut lines = vec![
+            ("Run", self.run_id.clone()),
+            ("Iterations", self.iterations.to_string()),
+            (
+                "Wall time",
+                time.duration(Duration::from_secs(self.wall_time_secs)),
+            ),
+            (
+                "Tokens",
+                format!("{} in, {} out", self.input_tokens, self.output_tokens),
+            ),
+        ];
+        if let Some(ratio) = self.cache_hit_ratio {
+            lines.push((
+                "Cache hit ratio",
+                format!(
+                    "{:.0}% ({} tokens read from the cache)",
+                    ratio * 100.0,
+                    self.cache_read_tokens
+                ),
+            ));
+        }
+        if let Some(cost) = self.cost_usd {
+            lines.push(("Cost", format!("${cost:.2}")));
+        }
+        if let Some(files) = self.files_changed {
+            lines.push(("Files changed", files.to_string()));
+        }
+        lines
+    }
+
+    /// The summary in `format`, without a trailing newline
+    pub fn render(&self, format: SummaryFormat, time: TimeDisplay) -> String {
+        match format {
+            SummaryFormat::Text => self
+                .lines(time)
+                .into_iter()
+                .map(|(label, value)| format!("  {label}: {value}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            SummaryFormat::Md => self
+                .lines(time)
+                .into_iter()
+                .map(|(label, value)| format!("- **{label}:** {value}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            SummaryFormat::Json => {
+                serde_json::to_string_pretty(self).expect("usage summary serializes to JSON")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AgentProvider;
+    use crate::time_display::TimeZoneChoice;
+    use crate::transcript::{IterationEndReason, TokenUsageRecord, TranscriptWriter};
+    use tempfile::TempDir;
+
+    fn time() -> TimeDisplay {
+        TimeDisplay {
+            zone: TimeZoneChoice::Utc,
+            iso_8601: false,
+        }
+    }
+
+    fn summary(tokens: &[TokenUsageRecord]) -> UsageSummary {
+        let dir = TempDir::new().unwrap();
+        let mut writer = TranscriptWriter::new(
+            dir.path(),
+            dir.path(),
+            "fix the tests",
+            None,
+            AgentProvider::Claude,
+            "DONE".to_string(),
+            Some("run-1".to_string()),
+        )
+        .unwrap();
+        for usage in tokens {
+            writer.start_iteration().unwrap();
+            writer
+                .end_iteration(
+                    IterationEndReason::Normal,
+                    "agent ended without the promise".to_string(),
+                    usage.clone(),
+                )
+                .unwrap();
+        }
+        UsageSummary::from_metadata(writer.metadata(), Duration::from_secs(245))
+    }
+
+    #[test]
+    fn sums_up_the_tokens_of_all_iterations() {
+        let usage = TokenUsageRecord {
+            input: 100,
+            output: 50,
+            cache_read: 300,
+            ..TokenUsageRecord::default()
+        };
+        let summary = summary(&[usage.clone(), usage]);
+        assert_eq!(summary.iterations, 2);
+        assert_eq!(summary.input_tokens, 200);
+        assert_eq!(summary.output_tokens, 100);
+        assert_eq!(summary.cache_hit_ratio, Some(0.75));
+
+        assert_eq!(
+            summary.render(SummaryFormat::Text, time()),
+            "  Run: run-1\n  Iterations: 2\n  Wall time: 4m 05s\n  Tokens: 200 in, 100 out\n  \
+             Cache hit ratio: 75% (600 tokens read from the cache)"
+        );
+        assert!(summary
+            .render(SummaryFormat::Md, time())
+            .contains("- **Tokens:** 200 in, 100 out"));
+        let json: serde_json::Value =
+            serde_json::from_str(&summary.render(SummaryFormat::Json, time())).unwrap();
+        assert_eq!(json["wall_time_secs"], 245);
+    }
+
+    #[test]
+    fn runs_without_input_have_no_cache_hit_ratio() {
+        let summary = summary(&[]);
+        assert_eq!(summary.cache_hit_ratio, None);
+        assert!(!summary
+            .render(SummaryFormat::Text, time())
+            .contains("Cache"));
+    }
+}
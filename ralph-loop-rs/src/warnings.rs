@@ -0,0 +1,145 @@
+//! Warnings collected during a run.
+//!
+//! Failed metadata writes, unparsable agent output and similar problems are
+//! logged as warnings and don't stop the loop, so they easily scroll away.
+//! A tracing layer collects every warning ralph-loop logs; the binary prints
+//! them in a section at the end of the run and stores them in the run
+//! metadata, which puts them in the report as well.
+
+use std::fmt::{self, Write as _};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// A distinct warning and how often it was logged
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunWarning {
+    pub message: String,
+    pub count: u32,
+}
+
+/// Shared list of the warnings logged so far
+#[derive(Debug, Clone, Default)]
+pub struct WarningCollector {
+    warnings: Arc<Mutex<Vec<RunWarning>>>,
+}
+
+impl WarningCollector {
+    /// Create an empty collector
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `message`, counting repeats of the same text
+    pub fn record(&self, message: String) {
+        let mut warnings = self.warnings.lock().unwrap_or_else(|e| e.into_inner());
+        match warnings.iter_mut().find(|w| w.message == message) {
+            Some(warning) => warning.count += 1,
+            None => warnings.push(RunWarning { message, count: 1 }),
+        }
+    }
+
+    /// The warnings recorded so far, in the order they first occurred
+    pub fn warnings(&self) -> Vec<RunWarning> {
+        self.warnings
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Layer recording the warnings ralph-loop logs into this collector
+    pub fn layer(&self) -> WarningLayer {
+        WarningLayer {
+            collector: self.clone(),
+        }
+    }
+}
+
+/// Tracing layer feeding a [`WarningCollector`]
+pub struct WarningLayer {
+    collector: WarningCollector,
+}
+
+impl<S: Subscriber> Layer<S> for WarningLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() != Level::WARN || !metadata.target().starts_with("ralph_loop") {
+            return;
+        }
+        let mut visitor = MessageVisitor(String::new());
+        event.record(&mut visitor);
+        self.collector.record(visitor.0);
+    }
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{value:?}");
+        }
+    }
+}
+
+/// Warnings section for the end of the run, empty if there were none
+pub fn render(warnings: &[RunWarning]) -> String {
+    let mut section = String::new();
+    if warnings.is_empty() {
+        return section;
+    }
+    let _ = writeln!(section, "{} warning(s) during the run:", warnings.len());
+    for warning in warnings {
+        match warning.count {
+            1 => {
+                let _ = writeln!(section, "  - {}", warning.message);
+            }
+            count => {
+                let _ = writeln!(section, "  - {} ({count} times)", warning.message);
+            }
+        }
+    }
+    section
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn collects_ralph_warnings_with_counts() {
+        let collector = WarningCollector::new();
+        let subscriber = tracing_subscriber::registry().with(collector.layer());
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!("Failed to set session ID: {}", "disk full");
+            tracing::info!("not a warning");
+            tracing::warn!(target: "other_crate", "not ours");
+            tracing::warn!("Failed to set session ID: {}", "disk full");
+            tracing::warn!("Agent reported no session ID");
+        });
+
+        assert_eq!(
+            collector.warnings(),
+            vec![
+                RunWarning {
+                    message: "Failed to set session ID: disk full".to_string(),
+                    count: 2,
+                },
+                RunWarning {
+                    message: "Agent reported no session ID".to_string(),
+                    count: 1,
+                },
+            ]
+        );
+        assert_eq!(
+            render(&collector.warnings()),
+            "2 warning(s) during the run:\n  - Failed to set session ID: disk full (2 times)\n  \
+             - Agent reported no session ID\n"
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Shared helpers for integration tests.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One step of a fake agent iteration
+#[allow(dead_code)]
+pub enum Step {
+    /// Print a line of stream-json to stdout
+    Emit(String),
+    /// Sleep for the given number of milliseconds
+    Sleep(u64),
+    /// Print a line to stderr
+    Stderr(String),
+    /// Exit with the given status
+    Exit(i32),
+    /// On SIGINT, run the given steps and exit with 130
+    OnInterrupt(Vec<Step>),
+}
+
+/// A generated `fake-claude` shell script.
+///
+/// Each invocation consumes the prompt from stdin and plays back the steps of
+/// the next scripted iteration; once exhausted, the last iteration repeats.
+pub struct FakeClaude {
+    path: PathBuf,
+}
+
+impl FakeClaude {
+    /// Generate a fake agent in `dir` that plays `iterations` in order
+    pub fn new(dir: &Path, iterations: &[Vec<Step>]) -> Self {
+        assert!(!iterations.is_empty(), "fake-claude needs an iteration");
+        let counter = dir.join("fake-claude.count");
+
+        let mut script = String::from(FAKE_HELP);
+        script.push_str("cat > /dev/null\n");
+        script.push_str(&format!(
+            "n=$(cat {counter} 2>/dev/null || echo 0)\nn=$((n + 1))\necho $n > {counter}\n",
+            counter = quote(&counter.to_string_lossy())
+        ));
+        script.push_str("case $n in\n");
+        for (index, steps) in iterations.iter().enumerate() {
+            let pattern = if index + 1 == iterations.len() {
+                "*".to_string()
+            } else {
+                (index + 1).to_string()
+            };
+            script.push_str(&format!("{pattern})\n"));
+            for step in steps {
+                script.push_str(&format!("  {}\n", command(step)));
+            }
+            script.push_str("  ;;\n");
+        }
+        script.push_str("esac\n");
+
+        let path = dir.join("fake-claude");
+        fs::write(&path, script).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        Self { path }
+    }
+
+    /// Path to the generated executable
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Shell command playing back `step`
+fn command(step: &Step) -> String {
+    match step {
+        Step::Emit(line) => format!("printf '%s\\n' {}", quote(line)),
+        // Detach sleep from the pipes so killing the script closes them, and
+        // wait for it in the background so a trap runs as soon as it's signalled
+        Step::Sleep(ms) => format!(
+            "sleep {}.{:03} </dev/null >/dev/null 2>&1 & wait $!",
+            ms / 1000,
+            ms % 1000
+        ),
+        Step::Stderr(line) => format!("printf '%s\\n' {} >&2", quote(line)),
+        Step::Exit(code) => format!("exit {code}"),
+        Step::OnInterrupt(steps) => {
+            let mut handler: Vec<String> = steps.iter().map(command).collect();
+            handler.push("exit 130".to_string());
+            format!("trap {} INT", quote(&handler.join("; ")))
+        }
+    }
+}
+
+/// Script header answering the capability probes like the real CLI
+const FAKE_HELP: &str = "#!/bin/sh
+case \"$1\" in
+  --help)
+    echo 'Usage: claude [options] [command] [prompt]'
+    echo '  -p, --print  --output-format <format> (\"text\", \"json\", \"stream-json\")'
+    echo '  --verbose  --permission-mode <mode>  --dangerously-skip-permissions'
+    exit 0 ;;
+  --version)
+    echo '0.0.0 (fake-claude)'
+    exit 0 ;;
+esac
+";
+
+/// Quote a string for use as a single POSIX shell word
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Claude stream-json line for session start
+#[allow(dead_code)]
+pub fn init(session_id: &str) -> Step {
+    Step::Emit(format!(
+        r#"{{"type":"system","subtype":"init","session_id":"{session_id}"}}"#
+    ))
+}
+
+/// Claude stream-json line for an assistant text message
+#[allow(dead_code)]
+pub fn assistant(text: &str) -> Step {
+    let content = serde_json::json!([{ "type": "text", "text": text }]);
+    Step::Emit(
+        serde_json::json!({ "type": "assistant", "message": { "content": content } }).to_string(),
+    )
+}
+
+/// Claude stream-json line for the final result with token usage
+#[allow(dead_code)]
+pub fn result(session_id: &str, input_tokens: usize, output_tokens: usize) -> Step {
+    Step::Emit(format!(
+        r#"{{"type":"result","session_id":"{session_id}","usage":{{"input_tokens":{input_tokens},"output_tokens":{output_tokens}}},"total_cost_usd":0.01}}"#
+    ))
+}
@@ -0,0 +1,1235 @@
+//! End-to-end tests running the real ralph-loop binary against a scripted
+//! fake agent that emits Claude stream-json.
+
+#![cfg(unix)]
+
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+use common::{assistant, init, result, FakeClaude, Step};
+use ralph_loop::run_result::{RunOutcome, RunResult};
+use ralph_loop::timeline::{KillReason, TimelineEvent};
+use ralph_loop::transcript::{
+    ExitReason, IterationEndReason, RunMetadata, RunStatus, UnknownEventRecord,
+};
+use tempfile::TempDir;
+
+fn run_ralph(dir: &Path, agent: &FakeClaude, extra_args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .current_dir(dir)
+        .arg("--agent-path")
+        .arg(agent.path())
+        .args(["--yes", "-p", "do the task", "-o"])
+        .arg(dir.join("out"))
+        .args(extra_args)
+        .output()
+        .expect("Failed to execute ralph-loop")
+}
+
+fn latest_metadata(dir: &Path) -> RunMetadata {
+    RunMetadata::load(&dir.join("out").join("latest")).unwrap()
+}
+
+#[test]
+fn promise_in_second_iteration_completes_run() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[
+            vec![
+                init("sess-1"),
+                assistant("still working"),
+                result("sess-1", 100, 10),
+            ],
+            vec![
+                init("sess-2"),
+                Step::Sleep(50),
+                assistant("done <promise>TASK COMPLETE</promise>"),
+                result("sess-2", 200, 20),
+            ],
+        ],
+    );
+
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "5"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "stdout: {stdout}");
+    assert!(
+        stdout.contains("fulfilled after 2 iteration(s)"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("USAGE:"), "{stdout}");
+    assert!(stdout.contains("  Tokens: 300 in, 30 out"), "{stdout}");
+
+    let metadata = latest_metadata(temp_dir.path());
+    assert_eq!(metadata.status, RunStatus::Completed);
+    assert_eq!(metadata.iterations.len(), 2);
+    assert_eq!(metadata.iterations[0].session_id.as_deref(), Some("sess-1"));
+    assert_eq!(metadata.iterations[1].session_id.as_deref(), Some("sess-2"));
+    assert_eq!(
+        metadata.iterations[1].end_reason,
+        Some(IterationEndReason::PromiseFound)
+    );
+    assert_eq!(metadata.total_tokens(), 330);
+    assert_eq!(
+        metadata.build.map(|build| build.version).as_deref(),
+        Some(env!("CARGO_PKG_VERSION"))
+    );
+
+    let audit = std::fs::read_to_string(
+        temp_dir
+            .path()
+            .join("out")
+            .join("latest")
+            .join(ralph_loop::audit::AUDIT_FILE),
+    )
+    .unwrap();
+    let entries: Vec<ralph_loop::audit::AuditEntry> = audit
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    // Not a git repository, so reading the starting commit failed
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].record.kind, "git");
+    assert_eq!(entries[0].record.exit_code, Some(128));
+    assert_eq!(entries[2].iteration, Some(2));
+    assert_eq!(entries[2].record.kind, "agent");
+    assert_eq!(Path::new(&entries[2].record.program), agent.path());
+    assert!(entries[2].record.args.contains(&"stream-json".to_string()));
+
+    let timeline = ralph_loop::timeline::load(&temp_dir.path().join("out").join("latest")).unwrap();
+    let events: Vec<_> = timeline
+        .iter()
+        .map(|entry| (entry.iteration, entry.event.clone()))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            (None, TimelineEvent::RunStarted),
+            (Some(1), TimelineEvent::IterationStarted),
+            (
+                Some(1),
+                TimelineEvent::IterationEnded {
+                    reason: IterationEndReason::Normal
+                }
+            ),
+            (Some(2), TimelineEvent::IterationStarted),
+            (Some(2), TimelineEvent::PromiseDetected { line: 2 }),
+            (
+                Some(2),
+                TimelineEvent::KillSent {
+                    reason: KillReason::Promise,
+                    line: 2,
+                    tokens: None
+                }
+            ),
+            (
+                Some(2),
+                TimelineEvent::IterationEnded {
+                    reason: IterationEndReason::PromiseFound
+                }
+            ),
+            (
+                Some(2),
+                TimelineEvent::RunCompleted {
+                    exit_reason: ExitReason::PromiseFulfilled
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn context_limit_kills_agent_and_loop_continues() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[vec![
+            init("sess-big"),
+            result("sess-big", 500, 10),
+            Step::Sleep(30_000),
+        ]],
+    );
+
+    let started = Instant::now();
+    let output = run_ralph(
+        temp_dir.path(),
+        &agent,
+        &["-m", "2", "--context-limit", "400"],
+    );
+
+    assert!(
+        started.elapsed() < Duration::from_secs(20),
+        "agent not killed"
+    );
+    assert_eq!(output.status.code(), Some(1));
+
+    let metadata = latest_metadata(temp_dir.path());
+    assert_eq!(metadata.status, RunStatus::Failed);
+    assert_eq!(metadata.iterations.len(), 2);
+    for iteration in &metadata.iterations {
+        assert_eq!(iteration.end_reason, Some(IterationEndReason::ContextLimit));
+        assert_eq!(
+            iteration.reason_detail.as_deref(),
+            Some("killed: 510 tokens ≥ limit 400 at line 2")
+        );
+    }
+    let timeline = ralph_loop::timeline::load(&temp_dir.path().join("out").join("latest")).unwrap();
+    assert!(timeline.iter().any(|entry| entry.iteration == Some(2)
+        && entry.event
+            == TimelineEvent::KillSent {
+                reason: KillReason::ContextLimit,
+                line: 2,
+                tokens: Some(510),
+            }));
+}
+
+#[test]
+fn stopped_agent_is_interrupted_first_and_its_usage_kept() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[vec![
+            Step::OnInterrupt(vec![result("sess-stop", 300, 30)]),
+            init("sess-stop"),
+            assistant("done <promise>TASK COMPLETE</promise>"),
+            Step::Sleep(30_000),
+        ]],
+    );
+
+    let started = Instant::now();
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "1"]);
+
+    assert!(
+        started.elapsed() < Duration::from_secs(20),
+        "agent not stopped"
+    );
+    assert_eq!(output.status.code(), Some(0));
+
+    let metadata = latest_metadata(temp_dir.path());
+    let iteration = &metadata.iterations[0];
+    assert_eq!(iteration.end_reason, Some(IterationEndReason::PromiseFound));
+    assert_eq!(iteration.session_id.as_deref(), Some("sess-stop"));
+    let tokens = iteration
+        .tokens
+        .as_ref()
+        .expect("usage of the result event");
+    assert_eq!((tokens.input, tokens.output), (300, 30));
+    assert_eq!(iteration.cost_usd, Some(0.01));
+}
+
+#[test]
+fn global_store_collects_runs_and_lists_them() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("ralph-home");
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[vec![
+            init("sess"),
+            assistant("<promise>TASK COMPLETE</promise>"),
+            result("sess", 10, 1),
+        ]],
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .current_dir(temp_dir.path())
+        .env("RALPH_HOME", &home)
+        .arg("--agent-path")
+        .arg(agent.path())
+        .args(["--yes", "-p", "do the task", "--global"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(!temp_dir.path().join(".ralph-loop-output").exists());
+
+    let metadata = RunMetadata::load(&home.join("latest")).unwrap();
+    assert_eq!(
+        Path::new(&metadata.project_path),
+        temp_dir.path().canonicalize().unwrap()
+    );
+
+    let listing = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .current_dir(temp_dir.path())
+        .env("RALPH_HOME", &home)
+        .args(["runs", "--global", "--all-projects"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&listing.stdout);
+    assert!(stdout.contains(&metadata.run_id), "{stdout}");
+    assert!(stdout.contains("completed"), "{stdout}");
+
+    let listing = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .current_dir(temp_dir.path())
+        .env("RALPH_HOME", &home)
+        .args(["runs", "--global", "--utc", "--iso-8601"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&listing.stdout);
+    let started = metadata
+        .started_at
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    assert!(stdout.contains(&started), "{stdout}");
+
+    // Too narrow for the columns, so each run is stacked
+    let listing = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .current_dir(temp_dir.path())
+        .env("RALPH_HOME", &home)
+        .env("COLUMNS", "40")
+        .args(["runs", "--global", "--all-projects"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&listing.stdout);
+    assert!(
+        stdout.contains("\n  status: completed: promise"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.lines().all(|line| line.chars().count() <= 40),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn unexpected_output_is_kept_and_summarized() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[vec![
+            Step::Emit("not json".to_string()),
+            Step::Emit("{broken".to_string()),
+            Step::Emit(r#"{"type":"rate_limit_event","retry_after":3}"#.to_string()),
+            assistant("done <promise>TASK COMPLETE</promise>"),
+        ]],
+    );
+
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "1"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "stdout: {stdout}");
+    assert!(stdout.contains("WARNINGS:"), "{stdout}");
+    assert!(
+        stdout.contains("2 line(s) of agent output could not be parsed"),
+        "{stdout}"
+    );
+
+    let metadata = latest_metadata(temp_dir.path());
+    let messages: Vec<&str> = metadata
+        .warnings
+        .iter()
+        .map(|w| w.message.as_str())
+        .collect();
+    assert_eq!(metadata.iterations[0].unparsed_lines, Some(2));
+    let unparsed = std::fs::read_to_string(
+        temp_dir
+            .path()
+            .join("out/latest")
+            .join(ralph_loop::transcript::UNPARSED_FILE),
+    )
+    .unwrap();
+    assert!(unparsed.contains(r#""line":"not json""#), "{unparsed}");
+
+    let unknown = std::fs::read_to_string(
+        temp_dir
+            .path()
+            .join("out/latest")
+            .join(ralph_loop::transcript::UNKNOWN_EVENTS_FILE),
+    )
+    .unwrap();
+    let record: UnknownEventRecord = serde_json::from_str(unknown.trim()).unwrap();
+    assert_eq!(
+        record.version,
+        ralph_loop::transcript::UNKNOWN_EVENTS_VERSION
+    );
+    assert_eq!(record.iteration, 1);
+    assert_eq!(record.event_type, "rate_limit_event");
+    assert_eq!(record.event["retry_after"], 3);
+    assert!(messages.ends_with(&[
+        "2 line(s) of agent output could not be parsed",
+        "Agent reported no session ID"
+    ]));
+}
+
+#[test]
+fn project_config_is_discovered_from_subdirectory() {
+    let temp_dir = TempDir::new().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+    let subdir = root.join("src").join("nested");
+    std::fs::create_dir_all(&subdir).unwrap();
+    std::fs::write(
+        root.join(".ralph.toml"),
+        "skip_permissions_consent = true\ncompletion_promise = \"SHIPPED\"\nmax_iterations = 1\n",
+    )
+    .unwrap();
+    let agent = FakeClaude::new(
+        &root,
+        &[vec![
+            init("sess"),
+            assistant("<promise>SHIPPED</promise>"),
+            result("sess", 10, 1),
+        ]],
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .current_dir(&subdir)
+        .arg("--agent-path")
+        .arg(agent.path())
+        .args(["-p", "do the task"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    let metadata = RunMetadata::load(&root.join(".ralph-loop-output").join("latest")).unwrap();
+    assert_eq!(metadata.completion_promise, "SHIPPED");
+    assert_eq!(Path::new(&metadata.project_path), root);
+    assert_eq!(
+        metadata.working_dir.as_deref().map(Path::new),
+        Some(subdir.as_path())
+    );
+}
+
+#[test]
+fn skip_permissions_without_consent_is_refused_when_not_interactive() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = FakeClaude::new(temp_dir.path(), &[vec![init("sess")]]);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .current_dir(temp_dir.path())
+        .arg("--agent-path")
+        .arg(agent.path())
+        .args(["-p", "do the task"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    // Logs go to stdout
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("confirmation required"), "{stdout}");
+    assert!(!temp_dir.path().join(".ralph-loop-output").exists());
+}
+
+#[test]
+fn max_iterations_without_promise_fails() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[vec![init("sess"), assistant("nope"), result("sess", 10, 1)]],
+    );
+
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "1"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains("Max iterations (1) exceeded"), "{stdout}");
+
+    let metadata = latest_metadata(temp_dir.path());
+    assert_eq!(metadata.status, RunStatus::Failed);
+    assert_eq!(
+        metadata.iterations[0].end_reason,
+        Some(IterationEndReason::Normal)
+    );
+    assert_eq!(
+        metadata.iterations[0].reason_detail.as_deref(),
+        Some("agent exited with code 0 without the promise")
+    );
+    assert_eq!(
+        metadata.reason_detail.as_deref(),
+        Some("no promise in max iterations (1)")
+    );
+    let result = RunResult::load(&temp_dir.path().join("out").join("latest")).unwrap();
+    assert_eq!(result.outcome, RunOutcome::MaxIterationsExceeded);
+    assert_eq!((result.exit_code, result.iterations), (1, 1));
+}
+
+#[test]
+fn exit_codes_tell_failure_modes_apart() {
+    let run_with_config = |config: &str| {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::write(temp_dir.path().join(".ralph.toml"), config).unwrap();
+        let agent = FakeClaude::new(
+            temp_dir.path(),
+            &[vec![init("sess"), assistant("nope"), result("sess", 10, 1)]],
+        );
+        let output = run_ralph(temp_dir.path(), &agent, &["-m", "1"]);
+        let result = RunResult::load(&temp_dir.path().join("out").join("latest"));
+        (
+            output.status.code(),
+            result.ok().map(|result| result.outcome),
+        )
+    };
+
+    assert_eq!(run_with_config("not_a_setting = 1\n"), (Some(2), None));
+    assert_eq!(
+        run_with_config("[disk_space]\nmin_free_mb = 1000000000\naction = \"abort\"\n"),
+        (Some(1), Some(RunOutcome::Failed))
+    );
+    assert_eq!(
+        run_with_config(""),
+        (Some(1), Some(RunOutcome::MaxIterationsExceeded))
+    );
+
+    let temp_dir = TempDir::new().unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .current_dir(temp_dir.path())
+        .args(["--yes", "-p", "do the task", "--agent-path"])
+        .arg(temp_dir.path().join("missing-agent"))
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3), "{output:?}");
+}
+
+#[test]
+fn quiet_runs_print_only_the_outcome() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[vec![
+            init("sess"),
+            assistant("<promise>TASK COMPLETE</promise>"),
+            result("sess", 10, 1),
+        ]],
+    );
+
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "1", "--quiet"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "ralph-loop: promise fulfilled after 1 iteration(s)\n"
+    );
+
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "1", "--silent"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        output.stdout.is_empty() && output.stderr.is_empty(),
+        "{output:?}"
+    );
+
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "1", "--no-banner"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Starting iteration"), "{stdout}");
+    assert!(!stdout.contains("SUCCESS:"), "{stdout}");
+}
+
+#[test]
+fn run_result_is_written_when_another_run_moves_latest() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = |name: &str, steps: Vec<Step>| {
+        let dir = temp_dir.path().join(name);
+        std::fs::create_dir(&dir).unwrap();
+        FakeClaude::new(&dir, &[steps])
+    };
+    let slow = agent(
+        "slow",
+        vec![init("slow"), Step::Sleep(3000), result("slow", 10, 1)],
+    );
+    let fast = agent("fast", vec![init("fast"), result("fast", 10, 1)]);
+    let start = |agent: &FakeClaude| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_ralph-loop"));
+        command
+            .current_dir(temp_dir.path())
+            .arg("--agent-path")
+            .arg(agent.path())
+            .args(["--yes", "-p", "do the task", "-m", "1", "-o"])
+            .arg(temp_dir.path().join("out"))
+            .stdout(Stdio::null());
+        command
+    };
+
+    let mut first = start(&slow).spawn().unwrap();
+    let latest = temp_dir.path().join("out").join("latest");
+    let started = Instant::now();
+    while !latest.exists() {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "run never started"
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let first_dir = latest.canonicalize().unwrap();
+    // A second run in the same output directory moves `latest` meanwhile
+    assert_eq!(start(&fast).status().unwrap().code(), Some(1));
+    assert_ne!(latest.canonicalize().unwrap(), first_dir);
+    assert_eq!(first.wait().unwrap().code(), Some(1));
+
+    let result = RunResult::load(&first_dir).unwrap();
+    assert_eq!(result.outcome, RunOutcome::MaxIterationsExceeded);
+}
+
+#[test]
+fn job_mode_reads_env_and_publishes_artifacts() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[vec![init("sess"), assistant("nope"), result("sess", 10, 1)]],
+    );
+    let artifacts = temp_dir.path().join("artifacts");
+    let job = || {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_ralph-loop"));
+        command
+            .current_dir(temp_dir.path())
+            .arg("job")
+            .env("RALPH_AGENT_PATH", agent.path())
+            .env("RALPH_PROMPT", "do the task")
+            .env("RALPH_MAX_ITERATIONS", "1")
+            .env("RALPH_OUTPUT_DIR", temp_dir.path().join("out"))
+            .env("RALPH_ARTIFACTS_DIR", &artifacts)
+            .stdin(Stdio::null());
+        command
+    };
+
+    // Being unattended is no consent to running without permission checks
+    let output = job().output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(2), "stdout: {stdout}");
+    assert!(stdout.contains("pass --yes"), "stdout: {stdout}");
+
+    let output = job().env("RALPH_YES", "1").output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "stdout: {stdout}");
+
+    let run_id = latest_metadata(temp_dir.path()).run_id;
+    let published = artifacts.join(&run_id);
+    assert!(published.join(ralph_loop::transcript::META_FILE).exists());
+    let summary: ralph_loop::job::JobResult = serde_json::from_str(
+        &std::fs::read_to_string(published.join(ralph_loop::job::JOB_RESULT_FILE)).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        summary.outcome,
+        ralph_loop::job::JobOutcome::MaxIterationsExceeded
+    );
+    assert_eq!(summary.run_id, Some(run_id));
+}
+
+#[test]
+fn config_show_reports_the_origin_of_each_value() {
+    let temp_dir = TempDir::new().unwrap();
+    let xdg = temp_dir.path().join("xdg");
+    std::fs::create_dir_all(xdg.join("ralph")).unwrap();
+    std::fs::write(
+        xdg.join("ralph").join("config.toml"),
+        "max_iterations = 2\nstop_on_promise = false\n",
+    )
+    .unwrap();
+    std::fs::write(temp_dir.path().join(".ralph.toml"), "max_iterations = 3\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .current_dir(temp_dir.path())
+        .args(["config", "show", "--origin", "-c", "DONE"])
+        .env("XDG_CONFIG_HOME", &xdg)
+        .env("RALPH_MAX_ITERATIONS", "4")
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "stdout: {stdout}");
+    let line = |key: &str| {
+        stdout
+            .lines()
+            .find(|line| line.starts_with(&format!("{key} = ")))
+            .unwrap_or_else(|| panic!("no {key} in {stdout}"))
+            .to_string()
+    };
+    assert!(line("max_iterations").ends_with("# env RALPH_MAX_ITERATIONS"));
+    assert!(line("stop_on_promise").contains("# user ("));
+    assert!(line("completion_promise").ends_with("# command line"));
+    assert!(line("promise_confirmations").ends_with("# default"));
+}
+
+#[test]
+fn subcommand_output_is_redacted_on_request() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(
+        temp_dir.path().join(".ralph.toml"),
+        "completion_promise = \"DONE internal-1234\"\n\n[redaction]\nenabled = false\npatterns = [\"internal-[0-9]+\"]\n",
+    )
+    .unwrap();
+    let show = |extra_args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+            .current_dir(temp_dir.path())
+            .args(["config", "show"])
+            .args(extra_args)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0), "{output:?}");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    assert!(show(&[]).contains("\"DONE internal-1234\""));
+    let stdout = show(&["--redact"]);
+    assert!(stdout.contains("\"DONE [REDACTED]\""), "{stdout}");
+    assert!(!stdout.contains("internal-1234"), "{stdout}");
+}
+
+#[test]
+fn api_agent_executes_tool_calls_locally() {
+    let temp_dir = TempDir::new().unwrap();
+    let chunk = |delta: &str, finish: &str| {
+        Step::Emit(format!(
+            r#"data: {{"id":"c1","choices":[{{"index":0,"delta":{delta},"finish_reason":{finish}}}]}}"#
+        ))
+    };
+    // Stands in for curl streaming an OpenAI-compatible response
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[
+            vec![
+                chunk(
+                    r#"{"tool_calls":[{"index":0,"id":"call_1","type":"function","function":{"name":"write_file","arguments":"{\"path\":\"notes.txt\","}}]}"#,
+                    "null",
+                ),
+                chunk(
+                    r#"{"tool_calls":[{"index":0,"function":{"arguments":"\"content\":\"hello\"}"}}]}"#,
+                    "null",
+                ),
+                chunk("{}", r#""tool_calls""#),
+                Step::Emit("data: [DONE]".to_string()),
+            ],
+            vec![
+                chunk(
+                    r#"{"content":"done <promise>TASK COMPLETE</promise>"}"#,
+                    "null",
+                ),
+                chunk("{}", r#""stop""#),
+                Step::Emit("data: [DONE]".to_string()),
+            ],
+        ],
+    );
+    std::fs::write(
+        temp_dir.path().join(".ralph.toml"),
+        "permissions = \"acceptEdits\"\n\n[agent]\nprovider = \"openai\"\n\n\
+         [api]\nmodel = \"test-model\"\nkey_env = \"RALPH_TEST_API_KEY\"\n",
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .current_dir(temp_dir.path())
+        .env("RALPH_TEST_API_KEY", "test-key")
+        .arg("--agent-path")
+        .arg(agent.path())
+        .args(["--yes", "-p", "write notes", "-m", "1", "-o"])
+        .arg(temp_dir.path().join("out"))
+        .output()
+        .expect("Failed to execute ralph-loop");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "stdout: {stdout}");
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("notes.txt")).unwrap(),
+        "hello"
+    );
+    // The executed call is tracked like the tool calls of the CLI agents
+    let spans = ralph_loop::tool_spans::load(&temp_dir.path().join("out/latest")).unwrap();
+    let calls: Vec<_> = spans
+        .iter()
+        .map(|span| {
+            (
+                span.id.as_str(),
+                span.name.as_str(),
+                span.path.as_deref(),
+                span.result_line.is_some(),
+                span.is_error,
+            )
+        })
+        .collect();
+    assert_eq!(
+        calls,
+        [("call_1", "write_file", Some("notes.txt"), true, false)]
+    );
+}
+
+#[test]
+fn resume_continues_a_stopped_run_in_the_same_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[
+            vec![init("sess-1"), assistant("nope"), result("sess-1", 10, 1)],
+            vec![
+                init("sess-2"),
+                assistant("<promise>TASK COMPLETE</promise>"),
+                result("sess-2", 10, 1),
+            ],
+        ],
+    );
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "1"]);
+    assert_eq!(output.status.code(), Some(1));
+    let run_id = latest_metadata(temp_dir.path()).run_id;
+
+    // The prompt comes from the checkpoint; the budget is raised
+    let output = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .current_dir(temp_dir.path())
+        .args(["resume", &run_id, "--yes", "-m", "3", "--agent-path"])
+        .arg(agent.path())
+        .arg("-o")
+        .arg(temp_dir.path().join("out"))
+        .output()
+        .expect("Failed to execute ralph-loop");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "stdout: {stdout}");
+    assert!(
+        stdout.contains("fulfilled after 2 iteration(s)"),
+        "{stdout}"
+    );
+    let metadata = latest_metadata(temp_dir.path());
+    assert_eq!(metadata.run_id, run_id);
+    assert_eq!(metadata.status, RunStatus::Completed);
+    assert_eq!(metadata.iterations.len(), 2);
+}
+
+#[test]
+fn failing_agent_aborts_after_consecutive_errors() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(
+        temp_dir.path().join(".ralph.toml"),
+        "max_consecutive_errors = 2\n",
+    )
+    .unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[vec![
+            init("sess"),
+            Step::Stderr("cc: command not found".to_string()),
+            Step::Exit(1),
+        ]],
+    );
+
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "5"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(3), "stdout: {stdout}");
+    assert!(stdout.contains("the last 2 ended in an error"), "{stdout}");
+    let metadata = latest_metadata(temp_dir.path());
+    assert_eq!(metadata.status, RunStatus::Failed);
+    assert_eq!(metadata.iterations.len(), 2);
+    assert_eq!(
+        metadata.iterations[1].end_reason,
+        Some(IterationEndReason::Error)
+    );
+    let stderr_file = metadata.iterations[1].stderr_file.as_deref().unwrap();
+    assert_eq!(stderr_file, "stderr/iteration_002.log");
+    let stderr =
+        std::fs::read_to_string(temp_dir.path().join("out/latest").join(stderr_file)).unwrap();
+    assert_eq!(stderr, "cc: command not found\n");
+}
+
+#[test]
+fn assistant_text_is_logged_when_configured() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(
+        temp_dir.path().join(".ralph.toml"),
+        "log_assistant_text = true\nlog_assistant_text_max_chars = 20\n",
+    )
+    .unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[vec![
+            init("sess"),
+            assistant("Reading the failing test first"),
+            assistant("done <promise>TASK COMPLETE</promise>"),
+            result("sess", 10, 1),
+        ]],
+    );
+
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "1"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "stdout: {stdout}");
+    assert!(
+        stdout.contains("Reading the failing ... (10 more chars)"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn files_changed_during_the_run_are_recorded() {
+    let temp_dir = TempDir::new().unwrap();
+    let dir = temp_dir.path();
+    let agent = FakeClaude::new(
+        dir,
+        &[vec![
+            init("sess"),
+            assistant("done <promise>TASK COMPLETE</promise>"),
+            result("sess", 10, 1),
+        ]],
+    );
+    let git = |args: &[&str]| {
+        let status = Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {args:?}");
+    };
+    // The starting commit is recorded without the environment snapshot
+    std::fs::write(dir.join(".ralph.toml"), "[environment]\nenabled = false\n").unwrap();
+    git(&["init", "-q"]);
+    git(&["add", "fake-claude", ".ralph.toml"]);
+    git(&["commit", "-qm", "init"]);
+
+    let output = run_ralph(dir, &agent, &["-m", "1", "--summary-format", "md"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "stdout: {stdout}");
+    let metadata = latest_metadata(dir);
+    assert!(metadata.environment.is_none());
+    assert!(metadata.git_base.is_some());
+    // The fake agent's counter is the only change; the run output is left out
+    let files_changed = metadata.files_changed.unwrap();
+    assert_eq!(files_changed.added, 1);
+    assert_eq!(files_changed.files[0].path, "fake-claude.count");
+    assert!(stdout.contains("1 file(s) changed: 1 added"), "{stdout}");
+    assert!(stdout.contains("- **Files changed:** 1\n"), "{stdout}");
+}
+
+#[test]
+fn summary_given_with_the_promise_is_surfaced() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[vec![
+            init("sess"),
+            assistant(
+                "All green.\n<promise name=\"TASK COMPLETE\">Fixed the flaky parser test</promise>",
+            ),
+            result("sess", 10, 1),
+        ]],
+    );
+
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "1"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(0), "stdout: {stdout}");
+    assert!(stdout.contains("Fixed the flaky parser test"), "{stdout}");
+    assert_eq!(
+        latest_metadata(temp_dir.path()).promise_summary.as_deref(),
+        Some("Fixed the flaky parser test")
+    );
+    let result = RunResult::load(&temp_dir.path().join("out").join("latest")).unwrap();
+    assert_eq!(result.outcome, RunOutcome::PromiseFulfilled);
+    assert_eq!(result.exit_code, 0);
+    assert_eq!(result.promise.as_deref(), Some("TASK COMPLETE"));
+    assert_eq!(
+        result.summary.as_deref(),
+        Some("Fixed the flaky parser test")
+    );
+    assert_eq!(
+        (result.totals.input_tokens, result.totals.output_tokens),
+        (10, 1)
+    );
+}
+
+#[test]
+fn replay_reruns_the_decisions_on_the_kept_output() {
+    let temp_dir = TempDir::new().unwrap();
+    std::fs::write(temp_dir.path().join(".ralph.toml"), "keep_output = true\n").unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[
+            vec![init("sess-1"), assistant("nope"), result("sess-1", 10, 1)],
+            vec![
+                init("sess-2"),
+                assistant("<promise>TASK COMPLETE</promise>"),
+                result("sess-2", 10, 1),
+            ],
+        ],
+    );
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "3"]);
+    assert_eq!(output.status.code(), Some(0));
+    let run_id = latest_metadata(temp_dir.path()).run_id;
+
+    let replay = |extra_args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+            .current_dir(temp_dir.path())
+            .args(["replay", &run_id, "-o"])
+            .arg(temp_dir.path().join("out"))
+            .args(extra_args)
+            .output()
+            .expect("Failed to execute ralph-loop");
+        assert_eq!(output.status.code(), Some(0), "{output:?}");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let stdout = replay(&[]);
+    assert!(
+        stdout.contains("Iteration 1 (run directory: ")
+            && stdout.contains("iteration_001.jsonl): 3 events"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("  line 2: promise detected\n"), "{stdout}");
+    assert!(
+        stdout.contains("Promise \"TASK COMPLETE\" fulfilled after 2 iteration(s)"),
+        "{stdout}"
+    );
+    // A different promise reproduces a run that never ends
+    let stdout = replay(&["-c", "OTHER"]);
+    assert!(stdout.contains("No promise in 2 iteration(s)"), "{stdout}");
+}
+
+#[test]
+fn capture_debug_writes_a_trace_log_into_the_run_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[vec![
+            init("sess"),
+            assistant("<promise>TASK COMPLETE</promise>"),
+            result("sess", 10, 1),
+        ]],
+    );
+
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "1", "--capture-debug"]);
+
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+    let log = std::fs::read_to_string(temp_dir.path().join("out/latest/debug.log")).unwrap();
+    // Logged before the run directory existed
+    assert!(log.contains("Starting ralph-loop"), "{log}");
+    assert!(log.contains("stdout monitor: read line 2"), "{log}");
+    // The terminal keeps its usual level
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("stdout monitor: read line"), "{stderr}");
+}
+
+#[test]
+fn tool_calls_are_paired_and_summed_up_per_tool() {
+    let temp_dir = TempDir::new().unwrap();
+    let tool_use = |id: &str, name: &str, input: &str| {
+        Step::Emit(format!(
+            r#"{{"type":"assistant","message":{{"content":[{{"type":"tool_use","id":"{id}","name":"{name}","input":{input}}}]}}}}"#
+        ))
+    };
+    let tool_result = |id: &str, is_error: bool| {
+        Step::Emit(format!(
+            r#"{{"type":"user","message":{{"content":[{{"type":"tool_result","tool_use_id":"{id}","is_error":{is_error},"content":"output"}}]}}}}"#
+        ))
+    };
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[vec![
+            init("sess"),
+            tool_use("a", "Bash", r#"{"command":"cargo test"}"#),
+            tool_use("b", "Read", r#"{"file_path":"src/main.rs"}"#),
+            tool_result("b", false),
+            tool_result("a", true),
+            tool_use("c", "Bash", "{}"),
+            tool_result("c", false),
+            // A sub-agent's usage counts toward the iteration
+            Step::Emit(
+                r#"{"type":"assistant","parent_tool_use_id":"a","message":{"id":"m","content":[],"usage":{"input_tokens":100,"output_tokens":7}}}"#
+                    .to_string(),
+            ),
+            assistant("<promise>TASK COMPLETE</promise>"),
+            result("sess", 10, 1),
+        ]],
+    );
+
+    let output = run_ralph(temp_dir.path(), &agent, &["-m", "1"]);
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+
+    let run_dir = temp_dir.path().join("out/latest");
+    let spans = ralph_loop::tool_spans::load(&run_dir).unwrap();
+    let pairs: Vec<_> = spans
+        .iter()
+        .map(|span| (span.name.as_str(), span.line, span.result_line))
+        .collect();
+    assert_eq!(
+        pairs,
+        [
+            ("Bash", 2, Some(5)),
+            ("Read", 3, Some(4)),
+            ("Bash", 6, Some(7))
+        ]
+    );
+
+    let metadata = RunMetadata::load(&run_dir).unwrap();
+    let tokens = metadata.iterations[0].tokens.clone().unwrap();
+    assert_eq!((tokens.input, tokens.output), (110, 8));
+    let subagents = tokens.subagents.unwrap();
+    assert_eq!(
+        (subagents.count, subagents.input, subagents.output),
+        (1, 100, 7)
+    );
+
+    let run_id = metadata.run_id;
+    let stats = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .current_dir(temp_dir.path())
+        .args(["tools", &run_id, "-o", "out"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&stats.stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert!(lines[0].starts_with("tool"), "{stdout}");
+    let bash: Vec<_> = lines[1..]
+        .iter()
+        .find(|line| line.starts_with("Bash"))
+        .unwrap()
+        .split_whitespace()
+        .collect();
+    // Two calls, one failed, 12 bytes of output
+    assert_eq!(bash[1], "2", "{stdout}");
+    assert_eq!(bash[4], "1", "{stdout}");
+    assert_eq!(bash[5..], ["12", "B"], "{stdout}");
+
+    let paths = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .current_dir(temp_dir.path())
+        .args(["tools", &run_id, "-o", "out", "--print-paths"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&paths.stdout);
+    let project = temp_dir.path().canonicalize().unwrap();
+    assert_eq!(
+        stdout.trim(),
+        project.join("src/main.rs").display().to_string()
+    );
+}
+
+#[test]
+fn stats_flag_regressions_against_a_baseline() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[
+            vec![
+                init("sess-1"),
+                assistant("<promise>TASK COMPLETE</promise>"),
+                result("sess-1", 100, 10),
+            ],
+            vec![
+                init("sess-2"),
+                assistant("still working"),
+                result("sess-2", 100, 10),
+            ],
+            vec![
+                init("sess-3"),
+                assistant("<promise>TASK COMPLETE</promise>"),
+                result("sess-3", 100, 10),
+            ],
+        ],
+    );
+    let stats = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+            .current_dir(temp_dir.path())
+            .args(args)
+            .args(["-o", "out"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    assert_eq!(
+        run_ralph(temp_dir.path(), &agent, &[]).status.code(),
+        Some(0)
+    );
+    let baseline = latest_metadata(temp_dir.path()).run_id;
+    stats(&["baseline", "set", "main", &baseline]);
+    assert_eq!(
+        run_ralph(temp_dir.path(), &agent, &["--tag", "bugfix"])
+            .status
+            .code(),
+        Some(0)
+    );
+    let later = latest_metadata(temp_dir.path()).run_id;
+
+    let stdout = stats(&["stats"]);
+    assert!(
+        stdout.contains(&format!("baseline 'main': run {baseline}")),
+        "{stdout}"
+    );
+    let row = stdout
+        .lines()
+        .rfind(|line| line.starts_with(&later))
+        .unwrap();
+    assert!(row.contains("2 (+1, +100%)"), "{stdout}");
+    assert!(row.contains("regression: iterations, tokens"), "{stdout}");
+
+    let stdout = stats(&["stats", "--threshold", "200"]);
+    let row = stdout
+        .lines()
+        .rfind(|line| line.starts_with(&later))
+        .unwrap();
+    assert!(row.ends_with("ok"), "{stdout}");
+
+    let stdout = stats(&["stats", "--by", "tag", "--csv"]);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(lines[0], "month,tag,runs,iterations,tokens,cost");
+    assert!(
+        lines[1..]
+            .iter()
+            .any(|line| line.contains(",bugfix,1,2,220,")),
+        "{stdout}"
+    );
+    assert!(
+        lines[1..]
+            .iter()
+            .any(|line| line.contains(",untagged,1,1,110,")),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn ralph_runs_the_loop_commands_and_cleans_old_runs() {
+    let temp_dir = TempDir::new().unwrap();
+    let agent = FakeClaude::new(
+        temp_dir.path(),
+        &[vec![
+            init("sess"),
+            assistant("<promise>TASK COMPLETE</promise>"),
+            result("sess", 10, 1),
+        ]],
+    );
+    let ralph = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_ralph"))
+            .current_dir(temp_dir.path())
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let agent_path = agent.path().to_string_lossy().to_string();
+    let run = [
+        "run",
+        "--agent-path",
+        &agent_path,
+        "--yes",
+        "-p",
+        "do the task",
+    ];
+
+    assert_eq!(ralph(&run).status.code(), Some(0));
+    let first = RunMetadata::load(&temp_dir.path().join(".ralph-loop-output/latest"))
+        .unwrap()
+        .run_id;
+    assert_eq!(ralph(&run).status.code(), Some(0));
+    let second = RunMetadata::load(&temp_dir.path().join(".ralph-loop-output/latest"))
+        .unwrap()
+        .run_id;
+
+    let status = ralph(&["status"]);
+    let stdout = String::from_utf8_lossy(&status.stdout);
+    assert!(
+        stdout.contains(&first) && stdout.contains(&second),
+        "{stdout}"
+    );
+
+    // The latest run is kept
+    let clean = ralph(&["clean", "--older-than", "0", "--dry-run"]);
+    assert_eq!(
+        String::from_utf8_lossy(&clean.stdout).trim(),
+        format!("would delete {first}")
+    );
+    let runs = temp_dir.path().join(".ralph-loop-output/runs");
+    assert!(runs.join(&first).exists());
+    ralph(&["clean", "--older-than", "0"]);
+    assert!(!runs.join(&first).exists());
+    assert!(runs.join(&second).exists());
+
+    let help = ralph(&[]);
+    assert!(String::from_utf8_lossy(&help.stdout).contains("Usage: ralph <COMMAND>"));
+}
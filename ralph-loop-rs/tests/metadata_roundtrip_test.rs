@@ -0,0 +1,424 @@
+//! Property-based round-trip tests for the metadata and event formats shared
+//! between the loop and tools that read its output.
+
+use chrono::{DateTime, TimeZone, Utc};
+use proptest::prelude::*;
+use serde_json::{json, Map, Value};
+
+use ralph_loop::build_info::BuildInfo;
+use ralph_loop::config::AgentProvider;
+use ralph_loop::environment::EnvironmentSnapshot;
+use ralph_loop::git::{ChangedFile, FileChange, FilesChanged};
+use ralph_loop::json_events::{AgentEvent, TokenUsage};
+use ralph_loop::plan::PlanProgress;
+use ralph_loop::transcript::{
+    ExitReason, IterationEndReason, IterationLimits, IterationMetadata, IterationTimings,
+    MilestoneRecord, RunMetadata, RunStatus, TokenUsageRecord,
+};
+use ralph_loop::warnings::RunWarning;
+
+fn timestamp() -> impl Strategy<Value = DateTime<Utc>> {
+    (0i64..4_102_444_800, 0u32..1_000_000_000)
+        .prop_map(|(secs, nanos)| Utc.timestamp_opt(secs, nanos).unwrap())
+}
+
+fn cost() -> impl Strategy<Value = f64> {
+    (0u32..1_000_000).prop_map(|c| c as f64 / 10_000.0)
+}
+
+/// Extra fields as a newer version might write them. Keys are prefixed so
+/// they never collide with known fields.
+fn extra() -> impl Strategy<Value = Map<String, Value>> {
+    prop::collection::btree_map("x_[a-z]{1,8}", any::<i64>(), 0..3).prop_map(|fields| {
+        fields
+            .into_iter()
+            .map(|(k, v)| (k, Value::from(v)))
+            .collect()
+    })
+}
+
+fn end_reason() -> impl Strategy<Value = IterationEndReason> {
+    prop_oneof![
+        Just(IterationEndReason::ContextLimit),
+        Just(IterationEndReason::PromiseFound),
+        Just(IterationEndReason::Normal),
+        Just(IterationEndReason::RestartRequested),
+        Just(IterationEndReason::Interrupted),
+        Just(IterationEndReason::Error),
+    ]
+}
+
+fn iteration() -> impl Strategy<Value = IterationMetadata> {
+    (
+        1u32..1000,
+        proptest::option::of("[a-f0-9-]{8,36}"),
+        timestamp(),
+        proptest::option::of(timestamp()),
+        proptest::option::of(end_reason()),
+        proptest::option::of((any::<u32>(), any::<u32>())),
+        proptest::option::of(cost()),
+        proptest::option::of((
+            any::<u32>(),
+            proptest::option::of(any::<u32>()),
+            any::<u32>(),
+        )),
+        proptest::option::of((0usize..100, 0usize..100)),
+        proptest::option::of(0u8..=100),
+        (
+            prop::collection::vec("[ -~]{1,40}", 0..3),
+            proptest::option::of("stderr/iteration_[0-9]{3}\\.log"),
+            proptest::option::of(1u32..1000),
+            proptest::option::of((1usize..1_000_000, 1usize..1_000_000)),
+            proptest::option::of(any::<bool>()),
+            proptest::option::of("[ -~]{1,80}"),
+        ),
+        extra(),
+    )
+        .prop_map(
+            |(
+                iteration,
+                session_id,
+                started_at,
+                ended_at,
+                end_reason,
+                tokens,
+                cost_usd,
+                timings,
+                plan,
+                progress_percent,
+                (notes, stderr_file, unparsed_lines, limits, probe_complete, reason_detail),
+                extra,
+            )| {
+                IterationMetadata {
+                    iteration,
+                    session_id,
+                    started_at,
+                    ended_at,
+                    end_reason,
+                    reason_detail,
+                    tokens: tokens.map(|(input, output)| TokenUsageRecord {
+                        input: input as usize,
+                        output: output as usize,
+                        cache_read: 0,
+                        cache_creation: 0,
+                        subagents: None,
+                    }),
+                    cost_usd,
+                    timings: timings.map(|(spawn, first, drain)| IterationTimings {
+                        spawn_ms: spawn as u64,
+                        first_event_ms: first.map(u64::from),
+                        last_event_ms: first.map(|f| f as u64 + 1),
+                        drain_ms: drain as u64,
+                    }),
+                    plan: plan.map(|(done, todo)| PlanProgress {
+                        done,
+                        total: done + todo,
+                    }),
+                    progress_percent,
+                    notes,
+                    stderr_file,
+                    unparsed_lines,
+                    limits: limits.map(|(max_tokens, warning_threshold)| IterationLimits {
+                        max_tokens,
+                        warning_threshold,
+                    }),
+                    probe_complete,
+                    extra,
+                }
+            },
+        )
+}
+
+fn environment() -> impl Strategy<Value = EnvironmentSnapshot> {
+    (
+        proptest::option::of("[0-9a-f]{40}"),
+        proptest::option::of(0usize..1000),
+        "[a-z]{3,8}",
+        proptest::option::of("[ -~]{0,40}"),
+        proptest::option::of("[a-z0-9-]{1,20}"),
+        prop::collection::btree_map("[a-z]{1,8}", "[ -~]{1,40}", 0..3),
+    )
+        .prop_map(
+            |(git_head, git_dirty_files, os, os_version, hostname, tools)| EnvironmentSnapshot {
+                git_head,
+                git_dirty_files,
+                os,
+                arch: "x86_64".to_string(),
+                os_version,
+                hostname,
+                tools,
+            },
+        )
+}
+
+fn files_changed() -> impl Strategy<Value = FilesChanged> {
+    prop::collection::vec(
+        (
+            "[a-z/]{1,20}\\.rs",
+            prop_oneof![
+                Just(FileChange::Added),
+                Just(FileChange::Modified),
+                Just(FileChange::Deleted),
+            ],
+        ),
+        0..4,
+    )
+    .prop_map(|files| {
+        let count = |change| files.iter().filter(|(_, c)| *c == change).count();
+        FilesChanged {
+            added: count(FileChange::Added),
+            modified: count(FileChange::Modified),
+            deleted: count(FileChange::Deleted),
+            files: files
+                .iter()
+                .map(|(path, change)| ChangedFile {
+                    path: path.clone(),
+                    change: *change,
+                })
+                .collect(),
+        }
+    })
+}
+
+fn build_info() -> impl Strategy<Value = BuildInfo> {
+    (
+        "[0-9]\\.[0-9]\\.[0-9]",
+        proptest::option::of("[a-f0-9]{40}"),
+        proptest::option::of("20[0-9]{2}-[01][0-9]-[0-3][0-9]"),
+        prop::collection::vec("[a-z-]{1,12}", 0..3),
+        "[a-z0-9_]{1,10}-[a-z]{1,10}-[a-z]{1,10}",
+    )
+        .prop_map(
+            |(version, git_sha, build_date, features, target)| BuildInfo {
+                version,
+                git_sha,
+                build_date,
+                features,
+                target,
+            },
+        )
+}
+
+fn run_metadata() -> impl Strategy<Value = RunMetadata> {
+    (
+        "[0-9]{8}-[0-9]{6}-[a-f0-9]{8}",
+        "/[a-z/]{1,30}",
+        "[ -~]{0,150}",
+        proptest::option::of("[a-z]{1,10}\\.md"),
+        prop_oneof![Just(AgentProvider::Claude), Just(AgentProvider::Codex)],
+        proptest::option::of(prop_oneof![
+            Just(ExitReason::PromiseFulfilled),
+            Just(ExitReason::PlanCompleted),
+            Just(ExitReason::MaxIterationsExceeded),
+            Just(ExitReason::UserInterrupt),
+            Just(ExitReason::ContextLimit),
+            Just(ExitReason::Error),
+        ]),
+        prop::collection::vec(iteration(), 0..4),
+        proptest::option::of(any::<u32>()),
+        proptest::option::of(environment()),
+        proptest::option::of("[ -~\n]{0,200}"),
+        (
+            proptest::option::of("[a-z ]{1,20}"),
+            prop::collection::vec(("[ -~]{1,60}", 1u32..100), 0..3),
+            proptest::option::of(files_changed()),
+            proptest::option::of("[ -~\n]{1,100}"),
+            prop::collection::vec(("[A-Z ]{1,20}", 1u32..100, timestamp()), 0..3),
+            prop::collection::vec("[a-z-]{1,12}", 0..3),
+            proptest::option::of(build_info()),
+            proptest::option::of("[ -~]{1,80}"),
+        ),
+        extra(),
+    )
+        .prop_map(
+            |(
+                run_id,
+                project,
+                prompt,
+                prompt_file,
+                provider,
+                exit_reason,
+                iterations,
+                pid,
+                environment,
+                summary,
+                (
+                    paused,
+                    warnings,
+                    files_changed,
+                    promise_summary,
+                    milestones,
+                    tags,
+                    build,
+                    reason_detail,
+                ),
+                extra,
+            )| {
+                let mut metadata = RunMetadata::new(
+                    run_id,
+                    project,
+                    &prompt,
+                    prompt_file,
+                    provider,
+                    "TASK COMPLETE".to_string(),
+                );
+                if exit_reason.is_some() {
+                    metadata.status = RunStatus::Failed;
+                }
+                metadata.exit_reason = exit_reason;
+                metadata.reason_detail = reason_detail;
+                metadata.iterations = iterations;
+                metadata.pid = pid;
+                metadata.environment = environment;
+                metadata.summary = summary;
+                metadata.paused = paused;
+                metadata.warnings = warnings
+                    .into_iter()
+                    .map(|(message, count)| RunWarning { message, count })
+                    .collect();
+                metadata.files_changed = files_changed;
+                metadata.promise_summary = promise_summary;
+                metadata.milestones = milestones
+                    .into_iter()
+                    .map(|(promise, iteration, reached_at)| MilestoneRecord {
+                        promise,
+                        iteration,
+                        reached_at,
+                    })
+                    .collect();
+                metadata.tags = tags;
+                metadata.build = build;
+                metadata.extra = extra;
+                metadata
+            },
+        )
+}
+
+fn round_trip<T>(value: &T) -> Value
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    let json = serde_json::to_string(value).unwrap();
+    let parsed: T = serde_json::from_str(&json).unwrap();
+    serde_json::to_value(parsed).unwrap()
+}
+
+proptest! {
+    #[test]
+    fn iteration_metadata_round_trips(iteration in iteration()) {
+        prop_assert_eq!(round_trip(&iteration), serde_json::to_value(&iteration).unwrap());
+    }
+
+    #[test]
+    fn run_metadata_round_trips(metadata in run_metadata()) {
+        prop_assert_eq!(round_trip(&metadata), serde_json::to_value(&metadata).unwrap());
+    }
+
+    #[test]
+    fn token_usage_round_trips(input in any::<u32>(), output in any::<u32>(), cached in any::<u32>()) {
+        let usage = TokenUsage {
+            input_tokens: input as usize,
+            output_tokens: output as usize,
+            cached_input_tokens: cached as usize,
+            ..TokenUsage::default()
+        };
+        prop_assert_eq!(round_trip(&usage), serde_json::to_value(&usage).unwrap());
+    }
+
+    #[test]
+    fn claude_result_event_parses_usage(
+        input in any::<u32>(),
+        output in any::<u32>(),
+        session in "[a-f0-9-]{8,36}",
+        unknown in any::<i64>(),
+    ) {
+        let line = json!({
+            "type": "result",
+            "session_id": session,
+            "usage": { "input_tokens": input, "output_tokens": output, "x_new": unknown },
+            "x_future_field": unknown,
+        })
+        .to_string();
+
+        match AgentEvent::parse(AgentProvider::Claude, &line).unwrap() {
+            AgentEvent::Result { session_id, usage, .. } => {
+                prop_assert_eq!(session_id, Some(session));
+                prop_assert_eq!(usage.total(), input as usize + output as usize);
+            }
+            other => prop_assert!(false, "expected result event, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn claude_assistant_event_preserves_text(text in ".{0,200}") {
+        let line = json!({
+            "type": "assistant",
+            "message": { "content": [
+                { "type": "text", "text": text },
+                { "type": "some_future_block", "data": 1 },
+            ]},
+        })
+        .to_string();
+
+        let event = AgentEvent::parse(AgentProvider::Claude, &line).unwrap();
+        prop_assert_eq!(event.extract_text(), Some(text.as_str()));
+    }
+}
+
+#[test]
+fn unknown_fields_survive_a_rewrite() {
+    let json = r#"{
+        "run_id": "20250101-000000-abcdef12",
+        "status": "running",
+        "started_at": "2025-01-01T00:00:00Z",
+        "project_path": "/p",
+        "prompt_preview": "p",
+        "agent_provider": "claude",
+        "completion_promise": "DONE",
+        "iterations": [
+            {"iteration": 1, "session_id": null, "started_at": "2025-01-01T00:00:00Z", "viewer_note": "keep me"}
+        ],
+        "labels": ["nightly"]
+    }"#;
+
+    let metadata: RunMetadata = serde_json::from_str(json).unwrap();
+    let rewritten = serde_json::to_value(&metadata).unwrap();
+
+    assert_eq!(rewritten["labels"], json!(["nightly"]));
+    assert_eq!(rewritten["iterations"][0]["viewer_note"], json!("keep me"));
+}
+
+#[test]
+fn metadata_written_by_older_versions_still_parses() {
+    // Format written by 0.4.x, before pid, cost and timing fields existed
+    let json = r#"{
+        "run_id": "20250101-000000-abcdef12",
+        "status": "completed",
+        "started_at": "2025-01-01T00:00:00Z",
+        "completed_at": "2025-01-01T00:10:00Z",
+        "project_path": "/home/user/project",
+        "prompt_preview": "Fix the bug",
+        "agent_provider": "claude",
+        "completion_promise": "TASK COMPLETE",
+        "exit_reason": "promise_fulfilled",
+        "iterations": [
+            {
+                "iteration": 1,
+                "session_id": "sess-1",
+                "started_at": "2025-01-01T00:00:00Z",
+                "ended_at": "2025-01-01T00:10:00Z",
+                "end_reason": "promise_found",
+                "tokens": {"input": 1000, "output": 200}
+            }
+        ]
+    }"#;
+
+    let metadata: RunMetadata = serde_json::from_str(json).unwrap();
+
+    assert_eq!(metadata.status, RunStatus::Completed);
+    assert_eq!(metadata.pid, None);
+    assert_eq!(metadata.total_tokens(), 1200);
+    assert_eq!(metadata.total_cost_usd(), None);
+    assert!(metadata.extra.is_empty());
+    assert!(metadata.iterations[0].timings.is_none());
+}
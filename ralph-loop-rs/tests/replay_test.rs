@@ -0,0 +1,111 @@
+//! Replays recorded agent captures from tests/fixtures through the monitor
+//! to catch parser and promise-detection regressions against new CLI versions.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use ralph_loop::agent::ExitReason;
+use ralph_loop::config::{AgentProvider, Config};
+use ralph_loop::loop_controller::{LoopController, LoopResult};
+use ralph_loop::replay::ReplayAgent;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+fn config(provider: AgentProvider) -> Arc<Config> {
+    let mut config = Config {
+        prompt: "test prompt".to_string(),
+        ..Config::default()
+    };
+    config.agent.provider = provider;
+    Arc::new(config)
+}
+
+async fn replay(provider: AgentProvider, name: &str) -> ralph_loop::AgentResult {
+    let capture = std::fs::read_to_string(fixture(name)).unwrap();
+    ReplayAgent::replay(config(provider), &capture)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn claude_capture_with_promise_is_detected() {
+    let result = replay(AgentProvider::Claude, "claude_promise.jsonl").await;
+
+    assert_eq!(result.promise_found.as_deref(), Some("TASK COMPLETE"));
+    assert_eq!(result.exit_reason, ExitReason::PromiseFound);
+    assert_eq!(
+        result.session_id.as_deref(),
+        Some("5f0c1f1e-2b8a-4f7e-9d55-1a2b3c4d5e6f")
+    );
+    assert_eq!(result.token_usage.unwrap().total(), 82);
+    assert_eq!(result.cost_usd, Some(0.0421));
+}
+
+#[tokio::test]
+async fn claude_capture_without_promise_is_not_fulfilled() {
+    let result = replay(AgentProvider::Claude, "claude_no_promise.jsonl").await;
+
+    assert!(!result.is_fulfilled());
+    assert_eq!(result.exit_reason, ExitReason::Natural);
+}
+
+#[tokio::test]
+async fn claude_capture_over_context_limit_requests_kill() {
+    let result = replay(AgentProvider::Claude, "claude_context_limit.jsonl").await;
+
+    assert!(!result.is_fulfilled());
+    assert_eq!(result.exit_reason, ExitReason::ContextLimit);
+    assert_eq!(result.token_count, 182_200);
+}
+
+#[tokio::test]
+async fn codex_capture_with_promise_is_detected() {
+    let result = replay(AgentProvider::Codex, "codex_promise.jsonl").await;
+
+    assert!(result.is_fulfilled());
+    assert_eq!(
+        result.session_id.as_deref(),
+        Some("0199a213-81c0-7800-8aa1-bbab2a035a53")
+    );
+    assert_eq!(result.token_usage.unwrap().total(), 17_770);
+}
+
+#[tokio::test]
+async fn loop_replays_captures_until_promise() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let config = Config {
+        prompt: "test prompt".to_string(),
+        max_iterations: Some(5),
+        output_dir: temp_dir.path().to_path_buf(),
+        ..Config::default()
+    };
+    let agent = ReplayAgent::new(
+        Arc::new(config.clone()),
+        vec![
+            fixture("claude_no_promise.jsonl"),
+            fixture("claude_context_limit.jsonl"),
+            fixture("claude_promise.jsonl"),
+        ],
+    );
+
+    let controller =
+        LoopController::with_transcript_writer(config, agent, temp_dir.path()).unwrap();
+    let result = controller.run().await.unwrap();
+
+    match result {
+        LoopResult::PromiseFulfilled {
+            iterations,
+            cost_usd,
+            ..
+        } => {
+            assert_eq!(iterations, 3);
+            assert!((cost_usd.unwrap() - 1.9734).abs() < 1e-9);
+        }
+        other => panic!("Expected PromiseFulfilled, got {:?}", other),
+    }
+}
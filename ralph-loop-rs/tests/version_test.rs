@@ -77,3 +77,23 @@ fn upgrade_help_is_available_without_prompt() {
         stdout.trim()
     );
 }
+
+#[test]
+fn build_info_is_printed_as_json() {
+    let output = Command::new(env!("CARGO_BIN_EXE_ralph-loop"))
+        .arg("--build-info")
+        .output()
+        .expect("Failed to execute ralph-loop --build-info");
+
+    assert!(
+        output.status.success(),
+        "ralph-loop --build-info should exit successfully"
+    );
+    let info: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("--build-info should print JSON");
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert!(info["target"]
+        .as_str()
+        .is_some_and(|target| !target.is_empty()));
+    assert!(info["features"].is_array());
+}